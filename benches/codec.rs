@@ -0,0 +1,36 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use gbps::{Message, Peer};
+
+fn message(view_size: u16) -> Message {
+    let view = (0..view_size).map(|i| Peer::new(format!("node-{}.gossip.example.com:9000", i))).collect();
+    Message::new_request("127.0.0.1:9000".to_owned(), Some(view))
+}
+
+fn encode(c: &mut Criterion) {
+    let mut group = c.benchmark_group("Message encode");
+    for &view_size in &[10, 50, 255] {
+        let message = message(view_size);
+        group.bench_with_input(BenchmarkId::new("plain", view_size), &message, |b, message| b.iter(|| message.as_bytes()));
+        #[cfg(feature = "compression")]
+        group.bench_with_input(BenchmarkId::new("compressed", view_size), &message, |b, message| b.iter(|| message.as_compressed_bytes()));
+    }
+    group.finish();
+}
+
+fn decode(c: &mut Criterion) {
+    let mut group = c.benchmark_group("Message decode");
+    for &view_size in &[10, 50, 255] {
+        let message = message(view_size);
+        let plain = message.as_bytes();
+        group.bench_with_input(BenchmarkId::new("plain", view_size), &plain, |b, bytes| b.iter(|| Message::from_bytes(bytes).unwrap()));
+        #[cfg(feature = "compression")]
+        {
+            let compressed = message.as_compressed_bytes();
+            group.bench_with_input(BenchmarkId::new("compressed", view_size), &compressed, |b, bytes| b.iter(|| Message::from_bytes(bytes).unwrap()));
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, encode, decode);
+criterion_main!(benches);
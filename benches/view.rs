@@ -0,0 +1,46 @@
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use gbps::{Peer, View};
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+
+fn peers(first_port: u16, count: u16) -> Vec<Peer> {
+    (first_port..first_port + count).map(|port| Peer::with_age(format!("127.0.0.1:{}", port), port % 16)).collect()
+}
+
+fn select(c: &mut Criterion) {
+    let mut group = c.benchmark_group("View::select");
+    // (c, h, s, buffer size)
+    for &(view_size, healing, swapping, buffer_size) in &[(20, 1, 9, 10), (20, 9, 1, 10), (30, 5, 10, 15), (100, 10, 40, 50)] {
+        let parameters = format!("c={} h={} s={} buffer={}", view_size, healing, swapping, buffer_size);
+        let view = peers(10_000, view_size as u16);
+        // half of the buffer overlaps with the view
+        let buffer = peers(10_000 + view_size as u16 / 2, buffer_size as u16);
+        group.bench_with_input(BenchmarkId::from_parameter(parameters), &buffer, |b, buffer| {
+            let mut rng = StdRng::seed_from_u64(0);
+            b.iter_batched(
+                || View::with_peers("127.0.0.1:9000".to_owned(), view.clone()),
+                |mut view| view.select_with(view_size, healing, swapping, buffer, &mut rng),
+                BatchSize::SmallInput);
+        });
+    }
+    group.finish();
+}
+
+fn build_buffer(c: &mut Criterion) {
+    let mut group = c.benchmark_group("View buffer");
+    for &view_size in &[20, 100] {
+        group.bench_with_input(BenchmarkId::from_parameter(view_size), &view_size, |b, &view_size| {
+            let mut rng = StdRng::seed_from_u64(0);
+            let mut view = View::with_peers("127.0.0.1:9000".to_owned(), peers(10_000, view_size as u16));
+            b.iter(|| {
+                view.permute_with(&mut rng);
+                view.move_oldest_to_end(view_size / 10);
+                view.head(view_size / 2)
+            });
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, select, build_buffer);
+criterion_main!(benches);
@@ -0,0 +1,13 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+use gbps::Message;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(message) = Message::from_bytes(data) {
+        // a decoded message must survive a round trip
+        let decoded = Message::from_bytes(&message.as_bytes()).expect("re-encoded message must decode");
+        assert_eq!(decoded.sender(), message.sender());
+        assert_eq!(decoded.nonce(), message.nonce());
+    }
+});
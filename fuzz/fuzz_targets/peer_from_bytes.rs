@@ -0,0 +1,10 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+use gbps::Peer;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(peer) = Peer::from_bytes(data) {
+        assert_eq!(peer.as_bytes(), data);
+    }
+});
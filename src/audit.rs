@@ -0,0 +1,166 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::mpsc::{Receiver, Sender};
+use std::time::SystemTime;
+
+/// Why a peer entry was not merged into the view, see [crate::Config::with_audit]
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[non_exhaustive]
+pub enum RejectionReason {
+    /// The entry is the address of the node itself
+    OwnAddress,
+    /// The entry was sent by a quarantined peer, see [crate::Config::with_quarantine]
+    Quarantined,
+    /// The network of the entry already has as many peers in the view as allowed, see [crate::Config::with_prefix_quota]
+    PrefixQuota,
+    /// The locality of the entry already has as many peers in the view as allowed, see [crate::Config::with_locality_diversity]
+    LocalityQuota,
+    /// The entry exceeded the maximum size of a received buffer, see [crate::Config::with_max_received_buffer_size]
+    OversizedBuffer,
+    /// The entry was pushed by an unknown sender without proof of work, see [crate::Config::with_proof_of_work]
+    NoProofOfWork,
+    /// The entry was sent in a replayed or expired message, see [crate::Config::with_message_ttl]
+    Replayed,
+    /// The entry was sent in a message that could not be decoded, or larger than the maximum frame size
+    Malformed,
+    /// The entry was sent in a response that does not answer a recent request, see [crate::Config::with_strict_responses]
+    UnsolicitedResponse,
+}
+
+impl fmt::Display for RejectionReason {
+    /// Writes the reason code, such as `own_address`
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let code = match self {
+            RejectionReason::OwnAddress => "own_address",
+            RejectionReason::Quarantined => "quarantined",
+            RejectionReason::PrefixQuota => "prefix_quota",
+            RejectionReason::LocalityQuota => "locality_quota",
+            RejectionReason::OversizedBuffer => "oversized_buffer",
+            RejectionReason::NoProofOfWork => "no_proof_of_work",
+            RejectionReason::Replayed => "replayed",
+            RejectionReason::Malformed => "malformed",
+            RejectionReason::UnsolicitedResponse => "unsolicited_response",
+        };
+        write!(f, "{}", code)
+    }
+}
+
+/// A peer entry that was not merged into the view
+#[derive(Clone, Debug, PartialEq)]
+pub struct Rejection {
+    /// Address of the rejected peer, or the IP address of the sender when the message could not be decoded.
+    /// For the messages dropped as a whole, the address of their sender.
+    peer: String,
+    /// Why the entry was rejected
+    reason: RejectionReason,
+    /// When the entry was rejected
+    time: SystemTime,
+}
+
+impl Rejection {
+    pub fn peer(&self) -> &str {
+        &self.peer
+    }
+
+    pub fn reason(&self) -> RejectionReason {
+        self.reason
+    }
+
+    pub fn time(&self) -> SystemTime {
+        self.time
+    }
+}
+
+/// The last rejected peer entries of a node, for security audits
+pub(crate) struct AuditLog {
+    /// Maximum number of rejections kept, 0 when auditing is disabled
+    capacity: usize,
+    /// The last rejections, oldest first
+    rejections: VecDeque<Rejection>,
+    /// Receivers of the rejections
+    subscribers: Vec<Sender<Rejection>>,
+    /// The rejections not yet sent to monitoring, if forwarded
+    unreported: Option<Vec<Rejection>>,
+}
+
+impl AuditLog {
+    /// Creates an audit log
+    ///
+    /// # Arguments
+    ///
+    /// * `capacity` - Maximum number of rejections kept, 0 when auditing is disabled
+    /// * `forward` - Keep the rejections until they are sent to monitoring
+    pub(crate) fn new(capacity: usize, forward: bool) -> AuditLog {
+        AuditLog {
+            capacity,
+            rejections: VecDeque::new(),
+            subscribers: vec![],
+            unreported: if capacity > 0 && forward { Some(vec![]) } else { None },
+        }
+    }
+
+    /// Returns a receiver notified with the next rejections
+    pub(crate) fn subscribe(&mut self) -> Receiver<Rejection> {
+        let (tx, rx) = std::sync::mpsc::channel();
+        self.subscribers.push(tx);
+        rx
+    }
+
+    /// Records a rejected peer entry, if auditing is enabled
+    ///
+    /// # Arguments
+    ///
+    /// * `peer` - Address of the rejected peer
+    /// * `reason` - Why the entry was rejected
+    pub(crate) fn reject(&mut self, peer: &str, reason: RejectionReason) {
+        if self.capacity == 0 {
+            return;
+        }
+        let rejection = Rejection { peer: peer.to_owned(), reason, time: SystemTime::now() };
+        crate::sampler::notify(&mut self.subscribers, &rejection);
+        if let Some(unreported) = self.unreported.as_mut() {
+            // bounded like the log, in case monitoring is not reached
+            if unreported.len() < self.capacity {
+                unreported.push(rejection.clone());
+            }
+        }
+        if self.rejections.len() == self.capacity {
+            self.rejections.pop_front();
+        }
+        self.rejections.push_back(rejection);
+    }
+
+    /// Returns the last rejections, oldest first
+    pub(crate) fn rejections(&self) -> Vec<Rejection> {
+        self.rejections.iter().cloned().collect()
+    }
+
+    /// Returns the rejections not yet sent to monitoring, and forgets them
+    pub(crate) fn take_unreported(&mut self) -> Vec<Rejection> {
+        self.unreported.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_last_rejections() {
+        let mut audit = AuditLog::new(2, true);
+        let subscriber = audit.subscribe();
+        audit.reject("127.0.0.1:9000", RejectionReason::OwnAddress);
+        audit.reject("127.0.0.1:9001", RejectionReason::PrefixQuota);
+        audit.reject("10.0.0.1", RejectionReason::Malformed);
+
+        let reasons = audit.rejections().iter().map(|rejection| rejection.reason().to_string()).collect::<Vec<String>>();
+        assert_eq!(reasons, vec!["prefix_quota", "malformed"]);
+        assert_eq!(subscriber.try_iter().count(), 3);
+        assert_eq!(audit.take_unreported().len(), 2);
+        assert!(audit.take_unreported().is_empty());
+
+        let mut disabled = AuditLog::new(0, true);
+        disabled.reject("127.0.0.1:9000", RejectionReason::OwnAddress);
+        assert!(disabled.rejections().is_empty());
+    }
+}
@@ -0,0 +1,126 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use rand::Rng;
+
+// Maximum number of peers whose failures are remembered
+const MAX_PEERS: usize = 1024;
+
+/// Failures of the sends to a peer
+struct Failures {
+    /// Number of consecutive failures
+    count: u32,
+    /// No send is attempted before this instant
+    retry_at: Instant,
+}
+
+/// Delays the sends to the peers whose connections repeatedly failed, the delay doubling
+/// at each consecutive failure up to a maximum, with jitter so that the nodes that lost
+/// the same peer do not retry it at the same time
+pub struct SendBackoff {
+    /// Delay after the first failure, zero to disable the backoff
+    base: Duration,
+    /// Maximum delay
+    max: Duration,
+    /// Failures of the peers that could not be reached
+    failures: HashMap<String, Failures>,
+}
+
+impl SendBackoff {
+    /// Creates a new backoff
+    ///
+    /// # Arguments
+    ///
+    /// * `base` - Delay after the first failure, zero to disable the backoff
+    /// * `max` - Maximum delay
+    pub fn new(base: Duration, max: Duration) -> SendBackoff {
+        SendBackoff {
+            base,
+            max,
+            failures: HashMap::new(),
+        }
+    }
+
+    /// Returns true if a send can be attempted to a peer
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - Address of the peer
+    pub fn allows(&self, address: &str) -> bool {
+        self.allows_at(address, Instant::now())
+    }
+
+    fn allows_at(&self, address: &str, now: Instant) -> bool {
+        self.failures.get(address).is_none_or(|failures| failures.retry_at <= now)
+    }
+
+    /// Records the outcome of a send to a peer.
+    /// Returns the delay before the next attempt if the send failed.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - Address of the peer
+    /// * `failed` - Whether the send failed
+    pub fn record(&mut self, address: &str, failed: bool) -> Option<Duration> {
+        self.record_at(address, failed, Instant::now(), &mut rand::thread_rng())
+    }
+
+    fn record_at<R: Rng>(&mut self, address: &str, failed: bool, now: Instant, rng: &mut R) -> Option<Duration> {
+        if self.base.is_zero() {
+            return None;
+        }
+        if !failed {
+            self.failures.remove(address);
+            return None;
+        }
+        // senders are not authenticated, so that their number is bounded
+        if self.failures.len() >= MAX_PEERS && !self.failures.contains_key(address) {
+            self.failures.retain(|_, failures| failures.retry_at > now);
+            if self.failures.len() >= MAX_PEERS {
+                return None;
+            }
+        }
+        let count = self.failures.get(address).map_or(1, |failures| failures.count.saturating_add(1));
+        let delay = self.base.saturating_mul(2u32.saturating_pow(count - 1)).min(self.max);
+        // between half of the delay and the whole delay
+        let delay = delay.mul_f64(rng.gen_range(0.5..=1.0));
+        self.failures.insert(address.to_owned(), Failures { count, retry_at: now + delay });
+        Some(delay)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    #[test]
+    fn delays_double_up_to_maximum() {
+        let mut backoff = SendBackoff::new(Duration::from_secs(1), Duration::from_secs(5));
+        let mut rng = StdRng::seed_from_u64(7);
+        let start = Instant::now();
+        let delays = (0..5)
+            .map(|_| backoff.record_at("127.0.0.1:9001", true, start, &mut rng).unwrap())
+            .collect::<Vec<Duration>>();
+        for (delay, expected) in delays.iter().zip([1, 2, 4, 5, 5]) {
+            let expected = Duration::from_secs(expected);
+            assert!(*delay >= expected / 2 && *delay <= expected, "{:?} not within {:?}", delay, expected);
+        }
+        assert!(!backoff.allows_at("127.0.0.1:9001", start + delays[4] - Duration::from_millis(1)));
+        assert!(backoff.allows_at("127.0.0.1:9001", start + delays[4]));
+        assert!(backoff.allows_at("127.0.0.1:9002", start));
+
+        // a successful send resets the delay
+        assert!(backoff.record_at("127.0.0.1:9001", false, start, &mut rng).is_none());
+        assert!(backoff.allows_at("127.0.0.1:9001", start));
+        assert!(backoff.record_at("127.0.0.1:9001", true, start, &mut rng).unwrap() <= Duration::from_secs(1));
+    }
+
+    #[test]
+    fn zero_base_disables_backoff() {
+        let mut backoff = SendBackoff::new(Duration::ZERO, Duration::from_secs(5));
+        assert!(backoff.record("127.0.0.1:9001", true).is_none());
+        assert!(backoff.allows("127.0.0.1:9001"));
+    }
+}
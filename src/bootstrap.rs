@@ -0,0 +1,324 @@
+use std::collections::HashSet;
+use std::net::ToSocketAddrs;
+use std::path::PathBuf;
+use std::sync::mpsc::Receiver;
+use std::time::SystemTime;
+
+use crate::peer::Peer;
+
+/// A source of contact peers for joining the overlay.
+///
+/// The source is queried when the service is initialized, and again at each cycle
+/// for as long as the view is empty, so that a source that is temporarily unavailable
+/// is retried until the node joins the overlay.
+///
+/// Any closure returning `Option<Vec<Peer>>` is a source, as well as the receiving end of
+/// a channel, to which peers can be sent by a background task querying a remote source.
+pub trait Bootstrap: Send {
+    /// Returns the contact peers, or None if none is available yet
+    fn peers(&mut self) -> Option<Vec<Peer>>;
+
+    /// Returns the contact peers that were added to the source since it was last queried.
+    /// Queried at each cycle even when the view is not empty, the peers being merged into the view.
+    fn updates(&mut self) -> Option<Vec<Peer>> {
+        None
+    }
+}
+
+impl<F> Bootstrap for F
+    where F: FnMut() -> Option<Vec<Peer>> + Send {
+    fn peers(&mut self) -> Option<Vec<Peer>> {
+        self()
+    }
+}
+
+impl Bootstrap for Receiver<Vec<Peer>> {
+    /// Returns all the peers received since the last call, without waiting
+    fn peers(&mut self) -> Option<Vec<Peer>> {
+        let mut peers = vec![];
+        while let Ok(mut received) = self.try_recv() {
+            peers.append(&mut received);
+        }
+        if peers.is_empty() { None } else { Some(peers) }
+    }
+}
+
+/// A built-in source of contact peers, selected in the configuration of the node with [crate::Config::with_bootstrap]
+/// and queried by [crate::PeerSamplingService::init_configured]
+#[derive(Clone)]
+pub enum BootstrapProvider {
+    /// The addresses resolved from a DNS name, see [DnsBootstrap]
+    Dns(String),
+    /// The addresses read from a file, see [FileBootstrap]
+    File(PathBuf),
+    /// The addresses of a JSON list served over HTTP, see [HttpBootstrap]
+    #[cfg(feature = "http-bootstrap")]
+    Http(String),
+    /// The ready pods of a Kubernetes service, see [crate::KubernetesBootstrap]
+    #[cfg(feature = "kubernetes-bootstrap")]
+    Kubernetes(crate::kubernetes::KubernetesBootstrap),
+    /// The running EC2 instances with a tag, see [crate::Ec2Bootstrap]
+    #[cfg(feature = "ec2-bootstrap")]
+    Ec2(crate::ec2::Ec2Bootstrap),
+}
+
+impl BootstrapProvider {
+    /// Returns a new source of contact peers from the provider
+    pub fn source(&self) -> Box<dyn Bootstrap> {
+        match self {
+            BootstrapProvider::Dns(name) => Box::new(DnsBootstrap::new(name.clone())),
+            BootstrapProvider::File(path) => Box::new(FileBootstrap::new(path.clone())),
+            #[cfg(feature = "http-bootstrap")]
+            BootstrapProvider::Http(url) => Box::new(HttpBootstrap::new(url.clone())),
+            #[cfg(feature = "kubernetes-bootstrap")]
+            BootstrapProvider::Kubernetes(kubernetes) => Box::new(kubernetes.clone()),
+            #[cfg(feature = "ec2-bootstrap")]
+            BootstrapProvider::Ec2(ec2) => Box::new(ec2.clone()),
+        }
+    }
+}
+
+/// Contact peers read from a file, one address per line, such as `bootstrap.peers`.
+///
+/// Empty lines and lines starting with `#` are ignored. The file is checked for changes
+/// at each cycle, and the addresses added to it are merged into the view,
+/// which lets operators reconnect a partitioned overlay without restarting the nodes.
+pub struct FileBootstrap {
+    /// Path of the file
+    path: PathBuf,
+    /// Modification time of the file when it was last read
+    modified: Option<SystemTime>,
+    /// The addresses read from the file
+    addresses: HashSet<String>,
+}
+
+impl FileBootstrap {
+    /// Creates a source reading a file
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path of the file
+    pub fn new<P: Into<PathBuf>>(path: P) -> FileBootstrap {
+        FileBootstrap {
+            path: path.into(),
+            modified: None,
+            addresses: HashSet::new(),
+        }
+    }
+
+    /// Reads the file if it was modified since it was last read,
+    /// and returns the addresses that were not in the file before
+    fn read_new_addresses(&mut self) -> Vec<Peer> {
+        let modified = match std::fs::metadata(&self.path).and_then(|metadata| metadata.modified()) {
+            Ok(modified) => modified,
+            Err(_) => return vec![],
+        };
+        if self.modified == Some(modified) {
+            return vec![];
+        }
+        let content = match std::fs::read_to_string(&self.path) {
+            Ok(content) => content,
+            Err(_) => return vec![],
+        };
+        self.modified = Some(modified);
+
+        let addresses = content.lines()
+            .map(|line| line.trim())
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| line.to_owned())
+            .collect::<Vec<String>>();
+        let added = addresses.iter()
+            .filter(|address| !self.addresses.contains(*address))
+            .map(|address| Peer::new(address.clone()))
+            .collect::<Vec<Peer>>();
+        self.addresses = addresses.into_iter().collect();
+        added
+    }
+}
+
+impl Bootstrap for FileBootstrap {
+    /// Returns all the addresses of the file
+    fn peers(&mut self) -> Option<Vec<Peer>> {
+        self.read_new_addresses();
+        if self.addresses.is_empty() {
+            None
+        }
+        else {
+            Some(self.addresses.iter().map(|address| Peer::new(address.clone())).collect())
+        }
+    }
+
+    /// Returns the addresses added to the file since it was last read
+    fn updates(&mut self) -> Option<Vec<Peer>> {
+        let added = self.read_new_addresses();
+        if added.is_empty() { None } else { Some(added) }
+    }
+}
+
+/// Contact peers resolved from a DNS name.
+///
+/// In Kubernetes, the name of a headless service resolves to the addresses of its pods,
+/// so that the nodes deployed behind the service discover each other.
+pub struct DnsBootstrap {
+    /// The name to resolve and the port of the nodes, such as `gossip.default.svc.cluster.local:9000`
+    name: String,
+}
+
+impl DnsBootstrap {
+    /// Creates a source resolving a name
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name to resolve and the port of the nodes
+    pub fn new(name: String) -> DnsBootstrap {
+        DnsBootstrap { name }
+    }
+}
+
+impl Bootstrap for DnsBootstrap {
+    fn peers(&mut self) -> Option<Vec<Peer>> {
+        let peers = self.name.to_socket_addrs().ok()?
+            .map(|address| Peer::new(address.to_string()))
+            .collect::<Vec<Peer>>();
+        if peers.is_empty() { None } else { Some(peers) }
+    }
+}
+
+/// Contact peers retrieved from a JSON list of addresses, such as `["10.0.0.1:9000", "10.0.0.2:9000"]`,
+/// served over plain HTTP.
+#[cfg(feature = "http-bootstrap")]
+pub struct HttpBootstrap {
+    /// The URL of the list, such as `http://discovery:8080/peers`
+    url: String,
+}
+
+#[cfg(feature = "http-bootstrap")]
+impl HttpBootstrap {
+    /// Creates a source retrieving the list from a URL
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - The `http://` URL of the list
+    pub fn new(url: String) -> HttpBootstrap {
+        HttpBootstrap { url }
+    }
+
+    fn fetch(&self) -> Result<Vec<Peer>, Box<dyn std::error::Error>> {
+        use std::io::{Read, Write};
+
+        let location = self.url.strip_prefix("http://").ok_or("only http:// URLs are supported")?;
+        let (host, path) = match location.find('/') {
+            Some(index) => location.split_at(index),
+            None => (location, "/"),
+        };
+        let address = if host.contains(':') { host.to_owned() } else { format!("{}:80", host) };
+        let address = address.to_socket_addrs()?.next().ok_or("could not resolve host")?;
+
+        let timeout = std::time::Duration::from_secs(5);
+        let mut stream = std::net::TcpStream::connect_timeout(&address, timeout)?;
+        stream.set_read_timeout(Some(timeout))?;
+        // HTTP/1.0 so that the body is neither chunked nor kept alive
+        write!(stream, "GET {} HTTP/1.0\r\nHost: {}\r\nAccept: application/json\r\n\r\n", path, host)?;
+        let mut response = vec![];
+        stream.read_to_end(&mut response)?;
+
+        let separator = response.windows(4).position(|bytes| bytes == b"\r\n\r\n").ok_or("invalid HTTP response")?;
+        let status_line = String::from_utf8_lossy(&response[..separator]).lines().next().unwrap_or("").to_owned();
+        if status_line.split_whitespace().nth(1) != Some("200") {
+            Err(format!("unexpected HTTP status: {}", status_line))?
+        }
+        let addresses: Vec<String> = serde_json::from_slice(&response[separator + 4..])?;
+        Ok(addresses.into_iter().map(Peer::new).collect())
+    }
+}
+
+#[cfg(feature = "http-bootstrap")]
+impl Bootstrap for HttpBootstrap {
+    fn peers(&mut self) -> Option<Vec<Peer>> {
+        self.fetch().ok().filter(|peers| !peers.is_empty())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn closure_is_polled_repeatedly() {
+        let mut attempts = 0;
+        let mut bootstrap: Box<dyn Bootstrap> = Box::new(move || {
+            attempts += 1;
+            if attempts < 3 { None } else { Some(vec![Peer::new("127.0.0.1:9000".to_owned())]) }
+        });
+        assert!(bootstrap.peers().is_none());
+        assert!(bootstrap.peers().is_none());
+        assert_eq!(bootstrap.peers().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn channel_returns_peers_sent_since_last_poll() {
+        let (tx, rx) = std::sync::mpsc::channel();
+        let mut bootstrap: Box<dyn Bootstrap> = Box::new(rx);
+        assert!(bootstrap.peers().is_none());
+
+        tx.send(vec![Peer::new("127.0.0.1:9000".to_owned())]).unwrap();
+        tx.send(vec![Peer::new("127.0.0.1:9001".to_owned())]).unwrap();
+        assert_eq!(bootstrap.peers().unwrap().len(), 2);
+        assert!(bootstrap.peers().is_none());
+    }
+
+    #[test]
+    fn dns_resolves_all_addresses() {
+        let peers = DnsBootstrap::new("localhost:9000".to_owned()).peers().unwrap();
+        assert!(peers.iter().all(|peer| peer.address().ends_with(":9000")));
+        assert!(DnsBootstrap::new("invalid.invalid:9000".to_owned()).peers().is_none());
+    }
+
+    #[cfg(feature = "http-bootstrap")]
+    #[test]
+    fn http_reads_json_list() {
+        use std::io::{Read, Write};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/peers", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0u8; 1024];
+            let length = stream.read(&mut request).unwrap();
+            assert!(request[..length].starts_with(b"GET /peers HTTP/1.0\r\n"));
+            stream.write_all(b"HTTP/1.0 200 OK\r\nContent-Type: application/json\r\n\r\n[\"10.0.0.1:9000\", \"10.0.0.2:9000\"]").unwrap();
+        });
+        let peers = HttpBootstrap::new(url).peers().unwrap();
+        server.join().unwrap();
+        assert_eq!(peers, vec![Peer::new("10.0.0.1:9000".to_owned()), Peer::new("10.0.0.2:9000".to_owned())]);
+    }
+
+    #[test]
+    fn providers_return_their_source() {
+        let path = std::env::temp_dir().join(format!("gbps-provider-{}.peers", std::process::id()));
+        std::fs::write(&path, "127.0.0.1:9001\n").unwrap();
+        let provider = BootstrapProvider::File(path.clone());
+        assert_eq!(provider.source().peers().unwrap(), vec![Peer::new("127.0.0.1:9001".to_owned())]);
+        std::fs::remove_file(&path).unwrap();
+        assert!(BootstrapProvider::Dns("localhost:9000".to_owned()).source().peers().is_some());
+    }
+
+    #[test]
+    fn file_updates_return_added_addresses() {
+        let path = std::env::temp_dir().join(format!("gbps-bootstrap-{}.peers", std::process::id()));
+        std::fs::write(&path, "# seeds\n127.0.0.1:9001\n\n127.0.0.1:9002\n").unwrap();
+        let mut bootstrap = FileBootstrap::new(&path);
+        assert_eq!(bootstrap.peers().unwrap().len(), 2);
+        assert!(bootstrap.updates().is_none());
+
+        std::fs::write(&path, "127.0.0.1:9001\n127.0.0.1:9003\n").unwrap();
+        // make sure the modification time changes
+        let file = std::fs::File::options().write(true).open(&path).unwrap();
+        file.set_modified(SystemTime::now() + std::time::Duration::from_secs(1)).unwrap();
+        assert_eq!(bootstrap.updates().unwrap(), vec![Peer::new("127.0.0.1:9003".to_owned())]);
+        assert!(bootstrap.updates().is_none());
+
+        std::fs::remove_file(&path).unwrap();
+        assert!(bootstrap.updates().is_none());
+    }
+}
@@ -0,0 +1,123 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// State of the circuit of a peer
+enum Circuit {
+    /// Exchanges are attempted, counting the consecutive timeouts
+    Closed(usize),
+    /// Exchanges are not attempted until the end of the cooldown
+    Open(Instant),
+}
+
+/// Stops attempting exchanges with the peers that repeatedly time out, for a cooldown period
+pub struct CircuitBreaker {
+    /// Number of consecutive timeouts opening the circuit, 0 to disable the breaker
+    threshold: usize,
+    /// Duration during which no exchange is attempted with a peer once its circuit is open
+    cooldown: Duration,
+    /// Circuits of the peers that timed out
+    circuits: HashMap<String, Circuit>,
+}
+
+impl CircuitBreaker {
+    /// Creates a new circuit breaker
+    ///
+    /// # Arguments
+    ///
+    /// * `threshold` - Number of consecutive timeouts opening the circuit, 0 to disable the breaker
+    /// * `cooldown` - Duration during which a peer is not attempted after its circuit opened
+    pub fn new(threshold: usize, cooldown: Duration) -> CircuitBreaker {
+        CircuitBreaker {
+            threshold,
+            cooldown,
+            circuits: HashMap::new(),
+        }
+    }
+
+    /// Returns true if an exchange can be attempted with a peer.
+    /// Once the cooldown is over, a single exchange is attempted: the circuit
+    /// is closed if it succeeds and opened again if it times out.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - Address of the peer
+    pub fn allows(&self, address: &str) -> bool {
+        self.allows_at(address, Instant::now())
+    }
+
+    fn allows_at(&self, address: &str, now: Instant) -> bool {
+        match self.circuits.get(address) {
+            Some(Circuit::Open(until)) => *until <= now,
+            _ => true,
+        }
+    }
+
+    /// Records the outcome of an exchange with a peer.
+    /// Returns true if the circuit of the peer has just been opened.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - Address of the peer
+    /// * `timed_out` - Whether the exchange timed out
+    pub fn record(&mut self, address: &str, timed_out: bool) -> bool {
+        self.record_at(address, timed_out, Instant::now())
+    }
+
+    fn record_at(&mut self, address: &str, timed_out: bool, now: Instant) -> bool {
+        if self.threshold == 0 {
+            return false;
+        }
+        if !timed_out {
+            self.circuits.remove(address);
+            return false;
+        }
+        let timeouts = match self.circuits.get(address) {
+            Some(Circuit::Closed(timeouts)) => timeouts + 1,
+            // the attempt after the cooldown timed out
+            Some(Circuit::Open(_)) => self.threshold,
+            None => 1,
+        };
+        if timeouts >= self.threshold {
+            self.circuits.insert(address.to_owned(), Circuit::Open(now + self.cooldown));
+            true
+        }
+        else {
+            self.circuits.insert(address.to_owned(), Circuit::Closed(timeouts));
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn opens_after_consecutive_timeouts() {
+        let mut breaker = CircuitBreaker::new(2, Duration::from_secs(10));
+        let start = Instant::now();
+        assert!(!breaker.record_at("127.0.0.1:9001", true, start));
+        // a successful exchange resets the count
+        assert!(!breaker.record_at("127.0.0.1:9001", false, start));
+        assert!(!breaker.record_at("127.0.0.1:9001", true, start));
+        assert!(breaker.allows_at("127.0.0.1:9001", start));
+        assert!(breaker.record_at("127.0.0.1:9001", true, start));
+        assert!(!breaker.allows_at("127.0.0.1:9001", start + Duration::from_secs(9)));
+        assert!(breaker.allows_at("127.0.0.1:9002", start));
+
+        // a single attempt after the cooldown
+        let end = start + Duration::from_secs(10);
+        assert!(breaker.allows_at("127.0.0.1:9001", end));
+        assert!(breaker.record_at("127.0.0.1:9001", true, end));
+        assert!(!breaker.allows_at("127.0.0.1:9001", end));
+        assert!(!breaker.record_at("127.0.0.1:9001", false, end + Duration::from_secs(10)));
+        assert!(breaker.allows_at("127.0.0.1:9001", end));
+    }
+
+    #[test]
+    fn zero_threshold_disables_breaker() {
+        let mut breaker = CircuitBreaker::new(0, Duration::from_secs(10));
+        assert!(!breaker.record("127.0.0.1:9001", true));
+        assert!(breaker.allows("127.0.0.1:9001"));
+    }
+}
@@ -0,0 +1,299 @@
+use std::error::Error;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::cluster::Cluster;
+use crate::config::Config;
+use crate::impairment::Impairment;
+
+/// A fault injected into the nodes of a cluster, identified by their index, see [Chaos]
+#[derive(Clone, Debug, PartialEq)]
+pub enum Fault {
+    /// The node can neither connect to the other nodes nor be connected to by them,
+    /// as if it was paused, its view being kept until the fault is healed
+    Pause { node: usize },
+    /// The two nodes cannot connect to each other
+    DropLink { node: usize, other: usize },
+    /// The messages written by the node on the connections it opens are written twice
+    Duplicate { node: usize, probability: f64 },
+    /// A byte of the messages written by the node is altered
+    Corrupt { node: usize, probability: f64 },
+}
+
+/// Injects faults into the nodes of a [Cluster] according to a schedule drawn from a seed,
+/// for verifying that the overlay re-converges once they are healed, see [Cluster::await_overlay].
+///
+/// The faults are applied through the [Impairment] of each node, so the nodes must be configured
+/// with [Chaos::configure] when the cluster is started. At each step of the schedule, the faults
+/// that expired are healed, and a new fault may be injected for a random number of steps.
+pub struct Chaos {
+    /// Seed of the schedule, from which the random generators of the impairments are also seeded
+    seed: u64,
+    /// Draws the schedule
+    rng: StdRng,
+    /// Impairment of each node, sharing its faults with the configuration of the node
+    impairments: Vec<Impairment>,
+    /// Probability that a fault is injected at each step
+    fault_rate: f64,
+    /// Maximum number of steps a fault lasts
+    max_fault_steps: usize,
+    /// Probability of duplication or corruption of the messages of a faulty node
+    intensity: f64,
+    /// The injected faults, with the number of steps before they are healed
+    active: Vec<(Fault, usize)>,
+}
+
+impl Chaos {
+    /// Creates a controller injecting a fault every other step on average, for at most 3 steps
+    ///
+    /// # Arguments
+    ///
+    /// * `seed` - Seed of the schedule, the same seed giving the same faults
+    pub fn new(seed: u64) -> Chaos {
+        Chaos {
+            seed,
+            rng: StdRng::seed_from_u64(seed),
+            impairments: vec![],
+            fault_rate: 0.5,
+            max_fault_steps: 3,
+            intensity: 0.3,
+            active: vec![],
+        }
+    }
+
+    /// Sets the probability that a fault is injected at each step
+    ///
+    /// # Arguments
+    ///
+    /// * `fault_rate` - Probability between 0 and 1
+    pub fn with_fault_rate(mut self, fault_rate: f64) -> Chaos {
+        self.fault_rate = fault_rate.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Sets the maximum number of steps a fault lasts
+    ///
+    /// # Arguments
+    ///
+    /// * `steps` - Maximum number of steps, at least 1
+    pub fn with_max_fault_steps(mut self, steps: usize) -> Chaos {
+        self.max_fault_steps = steps.max(1);
+        self
+    }
+
+    /// Sets the probability that a message of a node is duplicated or corrupted while it has such a fault
+    ///
+    /// # Arguments
+    ///
+    /// * `intensity` - Probability between 0 and 1
+    pub fn with_intensity(mut self, intensity: f64) -> Chaos {
+        self.intensity = intensity.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Prepares the configuration of a node for the injection of faults, to be called from [Cluster::start_with].
+    /// The impairment of the configuration is kept if any, or a seeded impairment without delay nor loss is added.
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - Index of the node in the cluster
+    /// * `config` - Configuration of the node
+    pub fn configure(&mut self, index: usize, config: Config) -> Config {
+        let impairment = config.impairment().cloned()
+            .unwrap_or_else(|| Impairment::new().with_seed(self.seed.wrapping_add(index as u64 + 1)));
+        if self.impairments.len() <= index {
+            self.impairments.resize_with(index + 1, Impairment::new);
+        }
+        self.impairments[index] = impairment.clone();
+        config.with_impairment(impairment)
+    }
+
+    /// Returns the faults currently injected
+    pub fn active(&self) -> Vec<Fault> {
+        self.active.iter().map(|(fault, _)| fault.clone()).collect()
+    }
+
+    /// Advances the schedule by one step: the expired faults are healed, and a random fault may be injected.
+    /// Returns the injected fault, if any.
+    ///
+    /// # Arguments
+    ///
+    /// * `cluster` - The cluster whose nodes were configured with [Chaos::configure]
+    pub fn step(&mut self, cluster: &Cluster) -> Result<Option<Fault>, Box<dyn Error>> {
+        let addresses = self.addresses(cluster)?;
+        for (_, steps) in self.active.iter_mut() {
+            *steps -= 1;
+        }
+        if self.active.iter().any(|(_, steps)| *steps == 0) {
+            self.active.retain(|(_, steps)| *steps > 0);
+            // the remaining faults may share blocked links with the healed ones
+            self.apply_all(&addresses);
+        }
+        if addresses.is_empty() || !self.rng.gen_bool(self.fault_rate) {
+            return Ok(None);
+        }
+        let fault = self.draw(addresses.len());
+        let steps = self.rng.gen_range(1..=self.max_fault_steps);
+        self.apply(&fault, &addresses);
+        self.active.push((fault.clone(), steps));
+        Ok(Some(fault))
+    }
+
+    /// Injects a fault chosen by the caller, for a number of steps of the schedule
+    ///
+    /// # Arguments
+    ///
+    /// * `cluster` - The cluster whose nodes were configured with [Chaos::configure]
+    /// * `fault` - The fault
+    /// * `steps` - Number of steps before the fault is healed, at least 1
+    pub fn inject(&mut self, cluster: &Cluster, fault: Fault, steps: usize) -> Result<(), Box<dyn Error>> {
+        let addresses = self.addresses(cluster)?;
+        let nodes = match fault {
+            Fault::Pause { node } | Fault::Duplicate { node, .. } | Fault::Corrupt { node, .. } => vec![node],
+            Fault::DropLink { node, other } => vec![node, other],
+        };
+        if nodes.iter().any(|node| *node >= addresses.len()) {
+            Err("no such node in the cluster")?
+        }
+        self.apply(&fault, &addresses);
+        self.active.push((fault, steps.max(1)));
+        Ok(())
+    }
+
+    /// Runs the schedule for a number of steps, then heals all the faults.
+    /// Returns the faults injected.
+    ///
+    /// # Arguments
+    ///
+    /// * `cluster` - The cluster whose nodes were configured with [Chaos::configure]
+    /// * `steps` - Number of steps
+    /// * `interval` - Time between two steps, such as the period of the cycles of the nodes
+    pub fn run(&mut self, cluster: &Cluster, steps: usize, interval: Duration) -> Result<Vec<Fault>, Box<dyn Error>> {
+        let mut injected = vec![];
+        for _ in 0..steps {
+            injected.extend(self.step(cluster)?);
+            std::thread::sleep(interval);
+        }
+        self.heal();
+        Ok(injected)
+    }
+
+    /// Heals all the faults
+    pub fn heal(&mut self) {
+        self.active.clear();
+        for impairment in &self.impairments {
+            impairment.heal();
+        }
+    }
+
+    /// Returns the addresses of the nodes, failing if they were not all configured for the injection of faults
+    fn addresses(&self, cluster: &Cluster) -> Result<Vec<SocketAddr>, Box<dyn Error>> {
+        if cluster.len() != self.impairments.len() {
+            Err("the nodes of the cluster were not configured for the injection of faults")?
+        }
+        Ok(cluster.nodes().iter().map(|node| node.local_address()).collect())
+    }
+
+    /// Draws a random fault
+    ///
+    /// # Arguments
+    ///
+    /// * `nodes` - Number of nodes
+    fn draw(&mut self, nodes: usize) -> Fault {
+        let node = self.rng.gen_range(0..nodes);
+        let kinds = if nodes > 1 { 4 } else { 3 };
+        match self.rng.gen_range(0..kinds) {
+            0 => Fault::Pause { node },
+            1 => Fault::Duplicate { node, probability: self.intensity },
+            2 => Fault::Corrupt { node, probability: self.intensity },
+            _ => Fault::DropLink { node, other: (node + self.rng.gen_range(1..nodes)) % nodes },
+        }
+    }
+
+    /// Applies a fault to the impairments of the nodes
+    ///
+    /// # Arguments
+    ///
+    /// * `fault` - The fault
+    /// * `addresses` - The addresses of the nodes
+    fn apply(&self, fault: &Fault, addresses: &[SocketAddr]) {
+        let block = |node: usize, other: usize| {
+            self.impairments[node].block_link(addresses[other]);
+            self.impairments[other].block_link(addresses[node]);
+        };
+        match *fault {
+            Fault::Pause { node } => (0..addresses.len()).filter(|other| *other != node).for_each(|other| block(node, other)),
+            Fault::DropLink { node, other } => block(node, other),
+            Fault::Duplicate { node, probability } => self.impairments[node].set_duplication(probability),
+            Fault::Corrupt { node, probability } => self.impairments[node].set_corruption(probability),
+        }
+    }
+
+    /// Heals the impairments of the nodes and applies the active faults again
+    ///
+    /// # Arguments
+    ///
+    /// * `addresses` - The addresses of the nodes
+    fn apply_all(&self, addresses: &[SocketAddr]) {
+        for impairment in &self.impairments {
+            impairment.heal();
+        }
+        for (fault, _) in &self.active {
+            self.apply(fault, addresses);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn draws_reproducible_faults() {
+        let draws = |seed| {
+            let mut chaos = Chaos::new(seed).with_intensity(0.2);
+            (0..50).map(|_| chaos.draw(4)).collect::<Vec<Fault>>()
+        };
+        let faults = draws(11);
+        assert_eq!(draws(11), faults);
+        assert!(faults.iter().any(|fault| matches!(fault, Fault::Pause { .. })));
+        assert!(faults.iter().any(|fault| matches!(fault, Fault::Corrupt { probability, .. } if *probability == 0.2)));
+        assert!(faults.iter().all(|fault| match fault {
+            Fault::DropLink { node, other } => node != other && *other < 4,
+            Fault::Pause { node } | Fault::Duplicate { node, .. } | Fault::Corrupt { node, .. } => *node < 4,
+        }));
+        // a single node has no link
+        let mut chaos = Chaos::new(11);
+        assert!((0..50).all(|_| !matches!(chaos.draw(1), Fault::DropLink { .. })));
+    }
+
+    #[test]
+    fn applies_and_heals_faults() {
+        let addresses: Vec<SocketAddr> = (0..3).map(|port| SocketAddr::from(([127, 0, 0, 1], 9000 + port))).collect();
+        let mut chaos = Chaos::new(5);
+        let configs = (0..3).map(|index| chaos.configure(index, Config::new(addresses[index], true, true, 1, 0, 20, 2, 8, None))).collect::<Vec<Config>>();
+
+        chaos.active.push((Fault::Pause { node: 0 }, 1));
+        chaos.active.push((Fault::DropLink { node: 1, other: 2 }, 2));
+        chaos.active.push((Fault::Corrupt { node: 2, probability: 0.5 }, 1));
+        chaos.apply_all(&addresses);
+        let impairment = |index: usize| configs[index].impairment().unwrap();
+        assert!(impairment(1).is_blocked(&addresses[0]) && impairment(1).is_blocked(&addresses[2]));
+        assert_eq!(impairment(0).blocked_links().len(), 2);
+        assert_eq!(impairment(2).corruption(), 0.5);
+
+        // the link dropped by another fault stays blocked when the pause is healed
+        chaos.active.retain(|(fault, _)| matches!(fault, Fault::DropLink { .. }));
+        chaos.apply_all(&addresses);
+        assert!(impairment(0).blocked_links().is_empty());
+        assert_eq!(impairment(1).blocked_links(), vec![addresses[2]]);
+        assert_eq!(impairment(2).corruption(), 0.0);
+
+        chaos.heal();
+        assert!(chaos.active().is_empty());
+        assert!((0..3).all(|index| impairment(index).blocked_links().is_empty()));
+    }
+}
@@ -0,0 +1,206 @@
+use std::error::Error;
+use std::collections::HashSet;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+use slog::{o, Logger};
+
+use crate::config::Config;
+use crate::overlay::Overlay;
+use crate::peer::{Peer, PeerSamplingService};
+use crate::sampler::PeerSampler;
+
+/// Runs several nodes of the protocol in one process, for simulations and tests.
+///
+/// The nodes listen on sequential ports of the loopback or local interface, each node listening
+/// on the first free port after the port of the previous node, see [Config::with_port_range].
+/// The first node has no contact peer and the others bootstrap from the first node.
+/// There is no in-memory transport: the nodes exchange over TCP like separate processes.
+pub struct Cluster {
+    /// The nodes, in the order of their ports
+    nodes: Vec<PeerSamplingService>,
+}
+
+impl Cluster {
+    /// Creates and starts the nodes of a cluster with the same configuration
+    ///
+    /// # Arguments
+    ///
+    /// * `size` - Number of nodes
+    /// * `config` - Configuration of the nodes, the first node listening on the port of the configuration,
+    ///   or all the nodes on a port assigned by the system if the port is 0
+    /// * `logger` - Logger, each node logging with its index
+    pub fn start(size: usize, config: Config, logger: Logger) -> Result<Cluster, Box<dyn Error>> {
+        Cluster::start_with(size, config, logger, |_, config| config)
+    }
+
+    /// Creates and starts the nodes of a cluster, the configuration of each node being adjusted before it starts
+    ///
+    /// # Arguments
+    ///
+    /// * `size` - Number of nodes
+    /// * `config` - Configuration of the nodes, the first node listening on the port of the configuration,
+    ///   or all the nodes on a port assigned by the system if the port is 0. Additional addresses are not used.
+    /// * `logger` - Logger, each node logging with its index
+    /// * `configure` - Receives the index of a node and its configuration, and returns the configuration to use
+    pub fn start_with<F>(size: usize, config: Config, logger: Logger, mut configure: F) -> Result<Cluster, Box<dyn Error>>
+        where F: FnMut(usize, Config) -> Config
+    {
+        let mut nodes: Vec<PeerSamplingService> = Vec::with_capacity(size);
+        for index in 0..size {
+            let mut node_config = config.clone();
+            if let Some(previous) = nodes.last() {
+                node_config.set_address(Cluster::next_address(config.address(), &previous.local_address())?);
+            }
+            node_config.set_additional_addresses(vec![]);
+            let mut node = PeerSamplingService::try_new(configure(index, node_config), logger.new(o!("node" => index)))?;
+            // the port of the first node is known once it is bound
+            match nodes.first() {
+                Some(first) => {
+                    let contact = first.local_address().to_string();
+                    node.init(Box::new(move|| { Some(vec![Peer::new(contact.clone())]) }))?;
+                }
+                None => { node.init(Box::new(move|| { None }))?; }
+            }
+            nodes.push(node);
+        }
+        Ok(Cluster { nodes })
+    }
+
+    /// Returns the address of a node, with the port following the port of the previous node
+    ///
+    /// # Arguments
+    ///
+    /// * `configured` - The configured address, whose port 0 is kept
+    /// * `previous` - The address of the previous node
+    fn next_address(configured: &SocketAddr, previous: &SocketAddr) -> Result<SocketAddr, Box<dyn Error>> {
+        let mut address = *configured;
+        if address.port() != 0 {
+            address.set_port(previous.port().checked_add(1).ok_or("not enough ports for the nodes of the cluster")?);
+        }
+        Ok(address)
+    }
+
+    /// Returns the number of nodes
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Returns true if the cluster has no node
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// Returns the nodes, in the order of their ports
+    pub fn nodes(&self) -> &[PeerSamplingService] {
+        &self.nodes
+    }
+
+    /// Returns the nodes for sampling peers, in the order of their ports
+    pub fn nodes_mut(&mut self) -> &mut [PeerSamplingService] {
+        &mut self.nodes
+    }
+
+    /// Returns the addresses of the nodes
+    pub fn addresses(&self) -> Vec<String> {
+        self.nodes.iter().map(|node| node.local_address().to_string()).collect()
+    }
+
+    /// Performs one cycle on every node, and waits for all of them to complete,
+    /// see [PeerSamplingService::run_synchronized_round]
+    pub fn step(&self) -> Result<(), Box<dyn Error>> {
+        PeerSamplingService::run_synchronized_round(&self.nodes)
+    }
+
+    /// Returns a copy of the view of every node, in the order of their ports
+    pub fn views(&self) -> Vec<Vec<Peer>> {
+        self.nodes.iter().map(|node| node.view_snapshot()).collect()
+    }
+
+    /// Returns the overlay made of the views of the nodes
+    pub fn overlay(&self) -> Overlay {
+        let mut overlay = Overlay::new();
+        for (address, view) in self.addresses().into_iter().zip(self.views()) {
+            overlay.add_view(address, view);
+        }
+        overlay
+    }
+
+    /// Checks that the views of the nodes form a healthy overlay: every view is non-empty, without duplicates,
+    /// and only contains other nodes of the cluster, every node is in the view of another node, and the overlay
+    /// is connected. Returns a description of the first violation found.
+    pub fn check_overlay(&self) -> Result<(), String> {
+        let addresses = self.addresses();
+        let mut known = HashSet::new();
+        for (address, view) in addresses.iter().zip(self.views()) {
+            if view.is_empty() {
+                return Err(format!("the view of {} is empty", address));
+            }
+            let mut peers = HashSet::new();
+            for peer in &view {
+                if peer.address() == address {
+                    return Err(format!("the view of {} contains itself", address));
+                }
+                if !addresses.iter().any(|node| node == peer.address()) {
+                    return Err(format!("the view of {} contains {}, which is not a node of the cluster", address, peer.address()));
+                }
+                if !peers.insert(peer.address().to_owned()) {
+                    return Err(format!("the view of {} contains {} more than once", address, peer.address()));
+                }
+            }
+            known.extend(peers);
+        }
+        if let Some(unknown) = addresses.iter().find(|address| !known.contains(*address)) {
+            return Err(format!("{} is in no view", unknown));
+        }
+        if !self.overlay().is_connected() {
+            return Err("the overlay is partitioned".to_owned());
+        }
+        Ok(())
+    }
+
+    /// Waits until the views form a healthy overlay, see [Cluster::check_overlay], while the nodes perform
+    /// their cycles. Returns the time it took, or the last violation found if the overlay is still unhealthy
+    /// after the timeout.
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout` - Maximum time to wait
+    pub fn await_overlay(&self, timeout: Duration) -> Result<Duration, String> {
+        let start = Instant::now();
+        loop {
+            match self.check_overlay() {
+                Ok(()) => return Ok(start.elapsed()),
+                Err(e) if start.elapsed() >= timeout => return Err(format!("{} after {:?}", e, timeout)),
+                Err(_) => std::thread::sleep(Duration::from_millis(100)),
+            }
+        }
+    }
+
+    /// Shuts down every node, and returns the first error encountered
+    pub fn stop(self) -> Result<(), Box<dyn Error>> {
+        let mut result = Ok(());
+        for mut node in self.nodes {
+            if let Err(e) = node.shutdown() {
+                if result.is_ok() {
+                    result = Err(e);
+                }
+            }
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn assigns_sequential_ports() {
+        let configured: SocketAddr = "127.0.0.1:9000".parse().unwrap();
+        assert_eq!(Cluster::next_address(&configured, &"127.0.0.1:9003".parse().unwrap()).unwrap(), "127.0.0.1:9004".parse().unwrap());
+        let assigned: SocketAddr = "127.0.0.1:0".parse().unwrap();
+        assert_eq!(Cluster::next_address(&assigned, &"127.0.0.1:41000".parse().unwrap()).unwrap(), assigned);
+        assert!(Cluster::next_address(&configured, &"127.0.0.1:65535".parse().unwrap()).is_err());
+    }
+}
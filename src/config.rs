@@ -1,5 +1,100 @@
+use crate::bootstrap::BootstrapProvider;
+use crate::limit::ConnectionLimit;
 use crate::monitor::MonitoringConfig;
+use crate::policy::{SamplingPolicy, SelfAgePolicy};
+use crate::recorder::RecordingConfig;
+use crate::schedule::CycleSchedule;
+use crate::quota::PrefixQuota;
+use crate::socket::SocketConfig;
+use crate::thread::ThreadConfig;
+#[cfg(feature = "noise")]
+use crate::noise::NoiseConfig;
+#[cfg(feature = "port-mapping")]
+use crate::mapping::PortMappingConfig;
+#[cfg(feature = "impairment")]
+use crate::impairment::Impairment;
+use std::error::Error;
+use std::fmt;
 use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+// Default maximum size of a received message, above the largest valid message
+const DEFAULT_MAX_FRAME_SIZE: usize = 128 * 1024;
+
+// Maximum number of peers in a buffer, whose count is encoded on one byte
+const MAX_BUFFER_SIZE: usize = 255;
+
+/// A combination of parameters with which the protocol cannot run
+#[derive(Clone, Debug, PartialEq)]
+pub enum ConfigError {
+    /// The view size is 0
+    EmptyView,
+    /// Neither push nor pull is enabled, so that no peer is ever exchanged
+    NoExchange,
+    /// The healing and swapping factors together exceed half of the view size
+    SelectionFactors { healing_factor: usize, swapping_factor: usize, view_size: usize },
+    /// The sampling period is 0 with a periodic schedule, so that cycles never pause
+    ZeroSamplingPeriod,
+    /// The node is an observer without pull, so that it never obtains peers
+    ObserverWithoutPull,
+    /// The number of values for estimating the size of the network is not between 2 and 255
+    SizeEstimationSamples { samples: usize },
+    /// The locality label is larger than [crate::MAX_LOCALITY_SIZE] bytes
+    LocalityTooLong { size: usize },
+    /// The buffer size is 0, larger than the view size, or larger than the 255 peers a message can hold
+    BufferSizeOutOfRange { buffer_size: usize, view_size: usize },
+    /// The service runs on a single thread without exchange timeout, so that two nodes exchanging
+    /// with each other at the same time would wait for each other forever
+    #[cfg(feature = "event-loop")]
+    EventLoopWithoutTimeout,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::EmptyView => write!(f, "the view size must be at least 1"),
+            ConfigError::NoExchange => write!(f, "at least one of push and pull must be enabled"),
+            ConfigError::SelectionFactors { healing_factor, swapping_factor, view_size } =>
+                write!(f, "the healing factor {} plus the swapping factor {} exceed half of the view size {}", healing_factor, swapping_factor, view_size),
+            ConfigError::ZeroSamplingPeriod => write!(f, "the sampling period must be at least 1 second unless cycles are triggered"),
+            ConfigError::ObserverWithoutPull => write!(f, "an observer must pull views to obtain peers"),
+            ConfigError::SizeEstimationSamples { samples } => write!(f, "the size of the network is estimated from 2 to 255 values, not {}", samples),
+            ConfigError::LocalityTooLong { size } => write!(f, "the locality label has {} bytes, more than {}", size, crate::peer::MAX_LOCALITY_SIZE),
+            ConfigError::BufferSizeOutOfRange { buffer_size, view_size } =>
+                write!(f, "the buffer size {} must be between 1 and the view size {}, and at most 255", buffer_size, view_size),
+            #[cfg(feature = "event-loop")]
+            ConfigError::EventLoopWithoutTimeout => write!(f, "the event loop requires an exchange timeout"),
+        }
+    }
+}
+
+impl Error for ConfigError {}
+
+/// A combination of parameters deviating from the recommendations of the paper,
+/// with which the overlay is likely to mix poorly
+#[derive(Clone, Debug, PartialEq)]
+pub enum ConfigWarning {
+    /// Only one of push and pull is enabled: pull-only overlays tend to form clusters,
+    /// and push-only overlays recover slowly after the failure of many nodes
+    NotPushPull,
+    /// The healing factor is 0, so that the links to failed nodes are removed slowly
+    NoHealing,
+    /// The number of peers exchanged differs from half of the view size
+    BufferSize { buffer_size: usize, view_size: usize },
+}
+
+impl fmt::Display for ConfigWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigWarning::NotPushPull => write!(f, "without both push and pull, the overlay may cluster or heal slowly"),
+            ConfigWarning::NoHealing => write!(f, "without healing, the links to failed nodes are removed slowly"),
+            ConfigWarning::BufferSize { buffer_size, view_size } =>
+                write!(f, "exchanging {} peers instead of half of the view size {} slows down the mixing of the views", buffer_size, view_size),
+        }
+    }
+}
 
 /// The peer sampling parameters
 ///
@@ -20,21 +115,160 @@ pub struct Config {
     sampling_deviation: u64,
     /// The number of peers in the node's view
     view_size: usize,
+    /// The number of peers exchanged at each cycle, half of the view size if not set
+    buffer_size: Option<usize>,
     /// The number of removal at each cycle
     healing_factor: usize,
     /// The number of peer swapped at each cycle
     swapping_factor: usize,
     /// Monitoring configuration
     monitoring: MonitoringConfig,
+    /// Follow the view selection of the paper exactly
+    strict_selection: bool,
+    /// How the peers returned to the application are sampled
+    sampling_policy: SamplingPolicy,
+    /// Age of the node's own descriptor in the buffers it sends
+    self_age_policy: SelfAgePolicy,
+    /// Compress the messages sent to other peers
+    #[cfg(feature = "compression")]
+    compression: bool,
+    /// Maximum age in seconds of accepted messages, 0 to accept all messages
+    message_ttl: u64,
+    /// Maximum number of requests answered with the node's own descriptor per sampling period, 0 for no limit
+    indegree_cap: usize,
+    /// Maximum number of requests answered per second, 0 for no limit
+    max_requests_per_second: usize,
+    /// Number of cycles observed for suspecting a partition, 0 to disable the detection
+    partition_window: usize,
+    /// Distribution of the time between cycles
+    schedule: CycleSchedule,
+    /// Recording of experiment data at each cycle, none if not recorded
+    recording: Option<RecordingConfig>,
+    /// Minimum score of the peers kept in the view, 0 to keep all peers
+    score_threshold: f64,
+    /// Number of protocol violations leading to the quarantine of a peer, 0 to disable quarantine
+    quarantine_violations: usize,
+    /// Duration in seconds of the quarantine, also the period over which violations are counted
+    quarantine_duration: u64,
+    /// Maximum number of peers accepted in a received buffer, 0 for no limit
+    max_received_buffer_size: usize,
+    /// Maximum size in bytes of a received message, 0 for no limit
+    max_frame_size: usize,
+    /// Maximum duration in milliseconds of an outgoing exchange, 0 for no timeout
+    exchange_timeout: u64,
+    /// Number of consecutive timeouts after which a peer is no longer attempted, 0 to always attempt peers
+    breaker_timeouts: usize,
+    /// Duration in seconds during which a peer is not attempted after repeated timeouts
+    breaker_cooldown: u64,
+    /// Delay in milliseconds before attempting again a peer that could not be reached, 0 to attempt it at the next cycle
+    send_backoff_base: u64,
+    /// Maximum delay in milliseconds before attempting again a peer that could not be reached
+    send_backoff_max: u64,
+    /// File where the view is saved at each cycle, for recovering from an empty view, none if not saved
+    view_snapshot: Option<PathBuf>,
+    /// Built-in source of contact peers, queried by PeerSamplingService::init_configured, none if not configured
+    bootstrap: Option<BootstrapProvider>,
+    /// Number of recent message senders kept for recovery, 0 to keep none
+    recent_senders: usize,
+    /// Number of last protocol events kept, 0 to keep none
+    event_history: usize,
+    /// Number of last partners not selected again, 0 to select among all peers
+    partner_history: usize,
+    /// Whether the node only pulls views without ever advertising its own descriptor
+    observer: bool,
+    /// Number of random values gossiped for estimating the size of the network, 0 for no estimation
+    size_estimation_samples: usize,
+    /// Length in seconds of the epochs after which the estimation of the size of the network restarts
+    size_estimation_epoch: u64,
+    /// Maximum size in bytes of the application payloads attached to the exchanges, 0 for none
+    max_payload_size: usize,
+    /// Number of leading zero bits of the proof of work of the requests pushing a view, 0 for none
+    proof_of_work: u8,
+    /// Maximum number of peers of the view in the same network, none for no limit
+    prefix_quota: Option<PrefixQuota>,
+    /// Number of last rejected peer entries kept for audits, 0 for no audit
+    audit_capacity: usize,
+    /// Whether the answers must echo the nonce of the request, and not only come from the partner
+    strict_responses: bool,
+    /// Whether pings are answered on their connection instead of a connection to the sender
+    direct_answers: bool,
+    /// Whether the parameters of the view selection are advertised in the exchanges
+    advertised_parameters: bool,
+    /// Incarnation of the node advertised with its descriptor, 0 for none
+    incarnation: u32,
+    /// Unit of the ages in milliseconds when they measure time, 0 for counting cycles
+    age_unit: u64,
+    /// Locality label of the node advertised with its descriptor, if any
+    locality: Option<String>,
+    /// Maximum share in percent of the view with the same locality, 0 for no limit
+    locality_diversity: u8,
+    /// Number of samples over which the uniformity of the peers returned to the application is tested, 0 for no test
+    uniformity_window: usize,
+    /// Number of cycles at the warm-up period after the node starts, 0 to start at the normal period
+    warm_up_cycles: usize,
+    /// Period in milliseconds of the warm-up cycles
+    warm_up_period: u64,
+    /// Options of the sockets
+    socket: SocketConfig,
+    /// Options of the threads
+    threads: ThreadConfig,
+    /// Whether the service runs on a single thread driven by readiness events
+    #[cfg(feature = "event-loop")]
+    event_loop: bool,
+    /// Maximum number of outbound connections open at the same time, shared by the clones of the configuration, if limited
+    outbound_limit: Option<ConnectionLimit>,
+    /// Further addresses the node listens to, such as a LAN interface or an IPv6 address
+    additional_addresses: Vec<SocketAddr>,
+    /// Number of consecutive ports tried from the port of each address, when the previous ones are in use
+    port_range: u16,
+    /// Number of times the ports are tried again when they are all in use
+    bind_retries: u32,
+    /// Time in milliseconds between the attempts to bind the ports
+    bind_retry_interval: u64,
+    /// Address at which the node is reached from other networks, such as the address of a port
+    /// forwarded by a NAT gateway, advertised instead of the bind address of the same family.
+    /// Shared by the clones of the configuration, so that the tasks of the node advertise its updates.
+    external_address: Arc<Mutex<Option<SocketAddr>>>,
+    /// STUN servers asked for the external address of the node, as `host:port`
+    stun_servers: Vec<String>,
+    /// Time in seconds between the queries of the STUN servers once the service started, 0 for only querying them at startup
+    stun_interval: u64,
+    /// Relay through which the node reaches and is reached by the peers that do not accept connections, if any
+    relay: Option<SocketAddr>,
+    /// Time in milliseconds between the polls of the relay
+    relay_poll_interval: u64,
+    /// Maximum size in bytes of a message relayed for other nodes, 0 if the node does not relay
+    max_relayed_size: usize,
+    /// Maximum number of messages relayed for other nodes per second
+    max_relayed_per_second: usize,
+    /// Time in milliseconds without traffic after which a heartbeat is sent to a recent partner, 0 if disabled
+    heartbeat_interval: u64,
+    /// Number of recent partners sent heartbeats
+    heartbeat_partners: usize,
+    /// Maximum number of bytes of heartbeats sent per second, 0 for no limit
+    max_heartbeat_bytes_per_second: usize,
+    /// Port mapping requested from the gateway of the local network, if any
+    #[cfg(feature = "port-mapping")]
+    port_mapping: Option<PortMappingConfig>,
+    /// Synthetic network conditions applied to the written messages, if any
+    #[cfg(feature = "impairment")]
+    impairment: Option<Impairment>,
+    /// Static keys for encrypting connections, plaintext if none
+    #[cfg(feature = "noise")]
+    noise: Option<NoiseConfig>,
+    /// Key with which the saved view is encrypted, none if saved in clear
+    #[cfg(feature = "encrypted-snapshot")]
+    snapshot_key: Option<[u8; 32]>,
+    /// Previous keys with which the saved view may still be encrypted
+    #[cfg(feature = "encrypted-snapshot")]
+    previous_snapshot_keys: Vec<[u8; 32]>,
 }
 
 impl Config {
     /// Returns a configuration with specified parameters
+    #[allow(clippy::too_many_arguments)]
     pub fn new(address: SocketAddr, push: bool, pull: bool, sampling_period: u64, sampling_deviation: u64, view_size: usize, healing_factor: usize, swapping_factor: usize, monitoring_config: Option<MonitoringConfig>) -> Config {
-        let monitoring = match monitoring_config {
-            Some(config) => config,
-            None => MonitoringConfig::default(),
-        };
+        let monitoring = monitoring_config.unwrap_or_default();
 
         Config {
             address,
@@ -43,16 +277,963 @@ impl Config {
             sampling_period,
             sampling_deviation,
             view_size,
+            buffer_size: None,
             healing_factor,
             swapping_factor,
             monitoring,
+            strict_selection: true,
+            sampling_policy: SamplingPolicy::Queue,
+            self_age_policy: SelfAgePolicy::Reset,
+            #[cfg(feature = "compression")]
+            compression: false,
+            message_ttl: 0,
+            indegree_cap: 0,
+            max_requests_per_second: 0,
+            partition_window: 0,
+            schedule: CycleSchedule::Uniform,
+            recording: None,
+            score_threshold: 0.0,
+            quarantine_violations: 0,
+            quarantine_duration: 0,
+            max_received_buffer_size: 0,
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+            exchange_timeout: 0,
+            breaker_timeouts: 0,
+            breaker_cooldown: 0,
+            send_backoff_base: 0,
+            send_backoff_max: 0,
+            view_snapshot: None,
+            bootstrap: None,
+            recent_senders: 0,
+            event_history: 0,
+            partner_history: 0,
+            observer: false,
+            size_estimation_samples: 0,
+            size_estimation_epoch: 0,
+            max_payload_size: 0,
+            proof_of_work: 0,
+            prefix_quota: None,
+            audit_capacity: 0,
+            strict_responses: false,
+            direct_answers: false,
+            advertised_parameters: false,
+            incarnation: 0,
+            age_unit: 0,
+            locality: None,
+            locality_diversity: 0,
+            uniformity_window: 0,
+            warm_up_cycles: 0,
+            warm_up_period: 0,
+            socket: SocketConfig::default(),
+            threads: ThreadConfig::default(),
+            #[cfg(feature = "event-loop")]
+            event_loop: false,
+            outbound_limit: None,
+            additional_addresses: vec![],
+            port_range: 1,
+            bind_retries: 0,
+            bind_retry_interval: 0,
+            external_address: Arc::new(Mutex::new(None)),
+            stun_servers: vec![],
+            stun_interval: 0,
+            relay: None,
+            relay_poll_interval: 0,
+            max_relayed_size: 0,
+            max_relayed_per_second: 0,
+            heartbeat_interval: 0,
+            heartbeat_partners: 0,
+            max_heartbeat_bytes_per_second: 0,
+            #[cfg(feature = "port-mapping")]
+            port_mapping: None,
+            #[cfg(feature = "impairment")]
+            impairment: None,
+            #[cfg(feature = "noise")]
+            noise: None,
+            #[cfg(feature = "encrypted-snapshot")]
+            snapshot_key: None,
+            #[cfg(feature = "encrypted-snapshot")]
+            previous_snapshot_keys: vec![],
+        }
+    }
+
+    /// Sets the number of peers exchanged at each cycle, including the node's own descriptor.
+    /// Defaults to half of the view size.
+    ///
+    /// # Arguments
+    ///
+    /// * `buffer_size` - The number of peers in the exchanged buffer, from 1 to the view size and at most 255
+    pub fn with_buffer_size(mut self, buffer_size: usize) -> Config {
+        self.buffer_size = Some(buffer_size);
+        self
+    }
+
+    /// Enables or disables the strict view selection. When strict, the selection follows
+    /// the paper exactly and the order of the view is preserved when removing duplicates,
+    /// so that swapping removes the peers that were sent to the partner.
+    /// Disabling it restores the previous behavior where duplicates removal reorders the view.
+    ///
+    /// # Arguments
+    ///
+    /// * `strict_selection` - Follow the view selection of the paper, enabled by default
+    pub fn with_strict_selection(mut self, strict_selection: bool) -> Config {
+        self.strict_selection = strict_selection;
+        self
+    }
+
+    /// Sets how the peers returned to the application are sampled from the view.
+    /// By default, the peers that entered the view are returned first.
+    ///
+    /// # Arguments
+    ///
+    /// * `sampling_policy` - The sampling policy
+    pub fn with_sampling_policy(mut self, sampling_policy: SamplingPolicy) -> Config {
+        self.sampling_policy = sampling_policy;
+        self
+    }
+
+    /// Sets the age of the node's own descriptor added at the beginning of the buffers it sends.
+    /// By default, the age is reset to 0 as in the paper.
+    ///
+    /// # Arguments
+    ///
+    /// * `self_age_policy` - The policy
+    pub fn with_self_age_policy(mut self, self_age_policy: SelfAgePolicy) -> Config {
+        self.self_age_policy = self_age_policy;
+        self
+    }
+
+    /// Enables or disables the compression of outgoing messages.
+    /// Compressed messages are flagged in their header, so that peers
+    /// with compression disabled are still able to read them, provided they are built with the `compression` feature.
+    ///
+    /// # Arguments
+    ///
+    /// * `compression` - Compress outgoing messages
+    #[cfg(feature = "compression")]
+    pub fn with_compression(mut self, compression: bool) -> Config {
+        self.compression = compression;
+        self
+    }
+
+    /// Sets the maximum age of accepted messages. Older messages, messages
+    /// too far in the future and messages already received within that time
+    /// are dropped, so that replayed views cannot resurrect departed peers.
+    /// Requires the clocks of the nodes to be roughly synchronized. At most 65536 messages
+    /// are remembered, further messages being dropped until the earliest ones are too old.
+    ///
+    /// # Arguments
+    ///
+    /// * `message_ttl` - Maximum age of messages in seconds, 0 to disable the check
+    pub fn with_message_ttl(mut self, message_ttl: u64) -> Config {
+        self.message_ttl = message_ttl;
+        self
+    }
+
+    /// Limits the number of requests answered with the node's own descriptor during
+    /// each sampling period. Beyond that number, pulls are answered with an indirection
+    /// buffer containing only other peers, so that popular nodes such as bootstrap
+    /// nodes do not keep attracting new connections.
+    ///
+    /// # Arguments
+    ///
+    /// * `indegree_cap` - Maximum number of requests per sampling period, 0 for no limit
+    pub fn with_indegree_cap(mut self, indegree_cap: usize) -> Config {
+        self.indegree_cap = indegree_cap;
+        self
+    }
+
+    /// Limits the number of requests processed each second. Requests beyond that
+    /// number are refused with a busy message and their buffer is ignored, so that
+    /// contact nodes survive the mass restart of the nodes depending on them.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_requests_per_second` - Maximum number of requests per second, 0 for no limit
+    pub fn with_max_requests_per_second(mut self, max_requests_per_second: usize) -> Config {
+        self.max_requests_per_second = max_requests_per_second;
+        self
+    }
+
+    /// Enables the detection of network partitions. A partition is suspected when at least half
+    /// of the exchanges of the last cycles failed with several peers, or when the view shrank
+    /// to less than half of its size. The subscribers are then notified with an
+    /// [crate::Event::PartitionSuspected] and the node bootstraps again.
+    ///
+    /// # Arguments
+    ///
+    /// * `partition_window` - Number of cycles observed, 0 to disable the detection
+    pub fn with_partition_detection(mut self, partition_window: usize) -> Config {
+        self.partition_window = partition_window;
+        self
+    }
+
+    /// Enables the round-synchronized experiment mode. Cycles are not performed periodically
+    /// anymore but only when requested with [crate::PeerSamplingService::run_round], or for all the
+    /// nodes of an experiment with [crate::PeerSamplingService::run_synchronized_round],
+    /// so that measurements are comparable across runs.
+    ///
+    /// # Arguments
+    ///
+    /// * `synchronized_rounds` - Perform cycles only when requested
+    pub fn with_synchronized_rounds(mut self, synchronized_rounds: bool) -> Config {
+        self.schedule = if synchronized_rounds { CycleSchedule::Triggered } else { CycleSchedule::Uniform };
+        self
+    }
+
+    /// Sets the distribution of the time between cycles, which defaults to the sampling period
+    /// plus a uniform deviation. Cycles can also be triggered by the application with
+    /// [crate::PeerSamplingService::trigger_cycle], in addition to the scheduled cycles or instead of them.
+    ///
+    /// # Arguments
+    ///
+    /// * `schedule` - The distribution of the time between cycles
+    pub fn with_schedule(mut self, schedule: CycleSchedule) -> Config {
+        self.schedule = schedule;
+        self
+    }
+
+    /// Records a sample of the state of the node at the end of each cycle: its view with
+    /// the age of the peers, the peer contacted and the outcome of the exchange.
+    /// The data file of each node can then be analyzed without scraping the logs.
+    ///
+    /// # Arguments
+    ///
+    /// * `recording` - Location and format of the data file
+    pub fn with_recording(mut self, recording: RecordingConfig) -> Config {
+        self.recording = Some(recording);
+        self
+    }
+
+    /// Removes from the view the peers whose score fell below a threshold, once they were
+    /// observed a few times. The score of a peer is the ratio of exchanges it answered,
+    /// reduced by its latency and its protocol violations, see [crate::PeerScore::score].
+    ///
+    /// # Arguments
+    ///
+    /// * `score_threshold` - Minimum score between 0 and 1, 0 to keep all peers
+    pub fn with_score_threshold(mut self, score_threshold: f64) -> Config {
+        self.score_threshold = score_threshold;
+        self
+    }
+
+    /// Sets the maximum number of peers accepted in a received buffer. Larger buffers are
+    /// truncated with a warning and count as a protocol violation of their sender.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_received_buffer_size` - Maximum number of peers in a received buffer, 0 for no limit
+    pub fn with_max_received_buffer_size(mut self, max_received_buffer_size: usize) -> Config {
+        self.max_received_buffer_size = max_received_buffer_size;
+        self
+    }
+
+    /// Sets the maximum size of a received message, before decompression and after decryption.
+    /// Messages are parsed while they are read, and the connections sending more bytes or an invalid
+    /// field are closed without reading the rest. Defaults to 128 KiB, which is above the size of the
+    /// largest valid message.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_frame_size` - Maximum size in bytes of a received message, 0 for no limit
+    pub fn with_max_frame_size(mut self, max_frame_size: usize) -> Config {
+        self.max_frame_size = max_frame_size;
+        self
+    }
+
+    /// Sets the deadline of exchanges, applied to connecting, writing the message and reading the answer,
+    /// and to reading and answering the messages of accepted connections. Without timeout, the messages
+    /// of accepted connections are read within the sampling period, so that a peer connecting without
+    /// writing does not block the listener of the node.
+    ///
+    /// # Arguments
+    ///
+    /// * `exchange_timeout` - Maximum duration in milliseconds of each step of an exchange, 0 for no timeout
+    pub fn with_exchange_timeout(mut self, exchange_timeout: u64) -> Config {
+        self.exchange_timeout = exchange_timeout;
+        self
+    }
+
+    /// Stops selecting the peers whose exchanges repeatedly timed out for a cooldown period,
+    /// so that cycles are spent on healthy partners. After the cooldown, a single exchange
+    /// is attempted, and the peer is selected again if it answers. Requires an exchange timeout.
+    ///
+    /// # Arguments
+    ///
+    /// * `timeouts` - Number of consecutive timeouts, 0 to always attempt peers
+    /// * `cooldown` - Duration in seconds during which the peer is not attempted
+    pub fn with_circuit_breaker(mut self, timeouts: usize, cooldown: u64) -> Config {
+        self.breaker_timeouts = timeouts;
+        self.breaker_cooldown = cooldown;
+        self
+    }
+
+    /// Delays the exchanges with the peers that could not be reached, the delay doubling at each consecutive
+    /// failure up to a maximum, so that dead peers are not attempted at each cycle until they leave the view.
+    /// Each delay is randomly reduced by up to half, so that the nodes do not retry a peer at the same time.
+    ///
+    /// # Arguments
+    ///
+    /// * `base` - Delay in milliseconds after the first failure, 0 to attempt the peers at the next cycle
+    /// * `max` - Maximum delay in milliseconds
+    pub fn with_send_backoff(mut self, base: u64, max: u64) -> Config {
+        self.send_backoff_base = base;
+        self.send_backoff_max = max;
+        self
+    }
+
+    /// Saves the view to a file at each cycle, one address per line. When the view is empty,
+    /// at startup or after its peers were removed, and the bootstrap source has no peers,
+    /// the node recovers by contacting the peers of the last saved view.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path of the file
+    pub fn with_view_snapshot<P: Into<PathBuf>>(mut self, path: P) -> Config {
+        self.view_snapshot = Some(path.into());
+        self
+    }
+
+    /// Selects a built-in source of contact peers, such as the pods of a Kubernetes service or the EC2 instances
+    /// with a tag, so that the node is started with [crate::PeerSamplingService::init_configured] without a closure.
+    ///
+    /// # Arguments
+    ///
+    /// * `bootstrap` - The provider of the contact peers
+    pub fn with_bootstrap(mut self, bootstrap: BootstrapProvider) -> Config {
+        self.bootstrap = Some(bootstrap);
+        self
+    }
+
+    /// Encrypts the saved view, so that the disk of a node does not reveal the members of the overlay.
+    /// A saved view that is not encrypted is still read, and encrypted at the next save.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - Key of the ChaCha20-Poly1305 cipher
+    #[cfg(feature = "encrypted-snapshot")]
+    pub fn with_snapshot_key(mut self, key: [u8; 32]) -> Config {
+        self.snapshot_key = Some(key);
+        self
+    }
+
+    /// Reads a saved view encrypted with a previous key, for rotating the key of
+    /// [Config::with_snapshot_key]. The view is encrypted with the current key at the next save.
+    ///
+    /// # Arguments
+    ///
+    /// * `keys` - The previous keys
+    #[cfg(feature = "encrypted-snapshot")]
+    pub fn with_previous_snapshot_keys(mut self, keys: Vec<[u8; 32]>) -> Config {
+        self.previous_snapshot_keys = keys;
+        self
+    }
+
+    /// Sets the options of the listener and of the connections, such as the marking of the
+    /// gossip traffic or TCP keepalive
+    ///
+    /// # Arguments
+    ///
+    /// * `socket` - The socket options
+    pub fn with_socket(mut self, socket: SocketConfig) -> Config {
+        self.socket = socket;
+        self
+    }
+
+    /// Sets the options of the threads of the service, such as their names or priority,
+    /// for complying with the naming conventions and resource policies of the application
+    ///
+    /// # Arguments
+    ///
+    /// * `threads` - The thread options
+    pub fn with_threads(mut self, threads: ThreadConfig) -> Config {
+        self.threads = threads;
+        self
+    }
+
+    /// Runs the listeners, the handling of the received messages and the peer sampling on a single
+    /// thread waiting for readiness events, instead of a thread for each, for devices that cannot
+    /// afford several threads per overlay. The exchanges of the node and the reading of a received
+    /// message block that thread up to the exchange timeout, which is required, see [Config::with_exchange_timeout].
+    /// The accepted connections whose message does not start arriving within that timeout are dropped.
+    #[cfg(feature = "event-loop")]
+    pub fn with_event_loop(mut self) -> Config {
+        self.event_loop = true;
+        self
+    }
+
+    /// Limits the number of outbound connections open at the same time, such as when many peers are
+    /// contacted at once, so that a large node does not exhaust its file descriptors. Opening a connection
+    /// waits for another one to close, failing after the exchange timeout if any. The limit is shared by the
+    /// services configured with clones of this configuration.
+    ///
+    /// # Arguments
+    ///
+    /// * `max` - Maximum number of connections, 0 for no limit
+    pub fn with_max_outbound_connections(mut self, max: usize) -> Config {
+        self.outbound_limit = if max > 0 { Some(ConnectionLimit::new(max)) } else { None };
+        self
+    }
+
+    /// Listens to a further address, such as a LAN interface or an IPv6 address next to an IPv4 one.
+    /// The node advertises to each peer the first of its addresses of the same family as the peer.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - The additional bind address
+    pub fn with_additional_address(mut self, address: SocketAddr) -> Config {
+        self.additional_addresses.push(address);
+        self
+    }
+
+    /// Listens to the next port when the port of an address is in use, such as when several local nodes
+    /// or parallel test runs are configured with the same ports. The port actually bound is advertised,
+    /// see [crate::PeerSamplingService::local_address]. A port of 0 is assigned by the system and never in use.
+    ///
+    /// # Arguments
+    ///
+    /// * `ports` - Number of consecutive ports tried from the configured port, 1 to only try the configured port
+    pub fn with_port_range(mut self, ports: u16) -> Config {
+        self.port_range = ports.max(1);
+        self
+    }
+
+    /// Tries the ports again when they are all in use, such as while the previous process of the node
+    /// releases them, before failing with [crate::BindError::AddressInUse]
+    ///
+    /// # Arguments
+    ///
+    /// * `retries` - Number of further attempts
+    /// * `interval` - Time in milliseconds between the attempts
+    pub fn with_bind_retries(mut self, retries: u32, interval: u64) -> Config {
+        self.bind_retries = retries;
+        self.bind_retry_interval = interval;
+        self
+    }
+
+    /// Advertises an address at which the node is reached from other networks, such as the address
+    /// of a port forwarded by the NAT gateway of the node, instead of its bind address of the same family.
+    /// The bind address is still advertised to the peers on the loopback interface.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - The external address
+    pub fn with_external_address(mut self, address: SocketAddr) -> Config {
+        self.external_address = Arc::new(Mutex::new(Some(address)));
+        self
+    }
+
+    /// Learns the address of the node as seen from outside its network from STUN servers when the
+    /// service starts, unless a port mapping was obtained, and advertises it as with [Config::with_external_address],
+    /// which it replaces. The servers only see the address of a UDP socket, so that the port of the node
+    /// is advertised with the learned IP address: it must be forwarded or preserved by the NAT.
+    ///
+    /// # Arguments
+    ///
+    /// * `servers` - The STUN servers, as `host:port`, tried in order until one answers
+    /// * `interval` - Time in seconds between the queries once the service started, 0 for only querying them at startup
+    pub fn with_stun_servers(mut self, servers: Vec<String>, interval: u64) -> Config {
+        self.stun_servers = servers;
+        self.stun_interval = interval;
+        self
+    }
+
+    /// Exchanges through a relay with the peers that cannot be connected to, such as nodes behind a NAT.
+    /// The node polls the relay for the messages forwarded to it, and answers them through the relay.
+    /// Both nodes of an exchange must use the same relay, which must accept to relay, see [Config::with_relaying].
+    /// The poll interval should be well below the sampling period, for the responses to arrive before the next cycle.
+    ///
+    /// # Arguments
+    ///
+    /// * `relay` - Address of the relay
+    /// * `poll_interval` - Time in milliseconds between the polls of the relay
+    pub fn with_relay(mut self, relay: SocketAddr, poll_interval: u64) -> Config {
+        self.relay = Some(relay);
+        self.relay_poll_interval = poll_interval.max(1);
+        self
+    }
+
+    /// Relays the messages of the nodes configured with the node as relay, see [Config::with_relay].
+    /// The messages are held until their destination polls the relay, the oldest being dropped
+    /// when too many are waiting. Only nodes that accept connections from other networks should relay.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_message_size` - Maximum size in bytes of a relayed message, 0 for not relaying
+    /// * `max_messages_per_second` - Maximum number of messages relayed per second, the others being dropped
+    pub fn with_relaying(mut self, max_message_size: usize, max_messages_per_second: usize) -> Config {
+        self.max_relayed_size = max_message_size;
+        self.max_relayed_per_second = max_messages_per_second;
+        self
+    }
+
+    /// Sends a ping to the most recent partners of the node when there was no exchange with them for some time,
+    /// so that the NAT gateways and firewalls between them keep their bindings. The pings beyond the bandwidth
+    /// allowed to the heartbeats are postponed.
+    ///
+    /// # Arguments
+    ///
+    /// * `interval` - Time in milliseconds without exchange after which a heartbeat is sent, 0 for no heartbeats
+    /// * `partners` - Number of recent partners kept alive
+    /// * `max_bytes_per_second` - Maximum number of bytes of heartbeats sent per second, 0 for no limit
+    pub fn with_heartbeat(mut self, interval: u64, partners: usize, max_bytes_per_second: usize) -> Config {
+        self.heartbeat_interval = interval;
+        self.heartbeat_partners = partners;
+        self.max_heartbeat_bytes_per_second = max_bytes_per_second;
+        self
+    }
+
+    /// Requests a mapping of the port of the node from the gateway of the local network when the
+    /// service starts, with NAT-PMP or UPnP, and advertises the mapped external address as with
+    /// [Config::with_external_address], which it replaces. The mapping is renewed by a task of its own,
+    /// also when the service runs on an event loop, and removed when the service stops.
+    /// The node starts with its bind address if no mapping is obtained.
+    ///
+    /// # Arguments
+    ///
+    /// * `port_mapping` - Options of the mapping
+    #[cfg(feature = "port-mapping")]
+    pub fn with_port_mapping(mut self, port_mapping: PortMappingConfig) -> Config {
+        self.port_mapping = Some(port_mapping);
+        self
+    }
+
+    /// Applies synthetic latency, jitter, loss and reordering to the messages written by the node,
+    /// requests as well as answers, for evaluating the protocol under degraded network conditions
+    /// in a single process. Only meant for testing.
+    ///
+    /// # Arguments
+    ///
+    /// * `impairment` - The network conditions
+    #[cfg(feature = "impairment")]
+    pub fn with_impairment(mut self, impairment: Impairment) -> Config {
+        self.impairment = Some(impairment);
+        self
+    }
+
+    /// Performs the first cycles after the node starts at a much shorter period, so that the node
+    /// integrates into the overlay quickly, then settles to the normal schedule.
+    /// Has no effect when cycles are only triggered.
+    ///
+    /// # Arguments
+    ///
+    /// * `cycles` - Number of warm-up cycles, 0 to start at the normal period
+    /// * `period` - Period in milliseconds of the warm-up cycles
+    pub fn with_warm_up(mut self, cycles: usize, period: u64) -> Config {
+        self.warm_up_cycles = cycles;
+        self.warm_up_period = period;
+        self
+    }
+
+    /// Excludes the last partners from the selection of the next partner, which improves
+    /// the mixing of small overlays. The last partners are still selected when no other peer is.
+    ///
+    /// # Arguments
+    ///
+    /// * `partner_history` - Number of last partners excluded, 0 to select among all peers
+    pub fn with_partner_history(mut self, partner_history: usize) -> Config {
+        self.partner_history = partner_history;
+        self
+    }
+
+    /// Makes the node an observer, such as a monitoring node or a crawler: it pulls views
+    /// to obtain peers but never pushes, never includes its own descriptor in the buffers,
+    /// and marks its messages so that the receivers do not keep its address.
+    /// Requires pull to be enabled.
+    ///
+    /// # Arguments
+    ///
+    /// * `observer` - Whether the node is an observer
+    pub fn with_observer(mut self, observer: bool) -> Config {
+        self.observer = observer;
+        self
+    }
+
+    /// Estimates the number of nodes of the overlay by attaching the minima of random values to the exchanges,
+    /// see [crate::PeerSamplingService::estimated_network_size]. The estimation restarts at each epoch, derived
+    /// from the clock, so that the nodes that left are forgotten. The relative error is about 1/sqrt(samples),
+    /// and all the nodes must use the same parameters.
+    ///
+    /// # Arguments
+    ///
+    /// * `samples` - Number of random values, from 2 to 255, 0 for no estimation
+    /// * `epoch` - Length of an epoch in seconds, several times the time for the values to spread
+    pub fn with_size_estimation(mut self, samples: usize, epoch: u64) -> Config {
+        self.size_estimation_samples = samples;
+        self.size_estimation_epoch = epoch;
+        self
+    }
+
+    /// Attaches the payloads of the application to the requests and responses, see
+    /// [crate::PeerSamplingService::set_piggyback]. Larger payloads are neither sent nor accepted.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_payload_size` - Maximum size of a payload in bytes, at most 65535, 0 for no payload
+    pub fn with_piggyback(mut self, max_payload_size: usize) -> Config {
+        self.max_payload_size = max_payload_size.min(u16::MAX as usize);
+        self
+    }
+
+    /// Attaches a proof of work to the requests pushing a view, and requires one from the senders
+    /// that are not in the view, or that send from another IP address than theirs, before merging the
+    /// peers they push, which makes flooding the views with spoofed addresses costly.
+    /// All the nodes must use the same difficulty.
+    ///
+    /// # Arguments
+    ///
+    /// * `difficulty` - Number of leading zero bits of the hash, each bit doubling the work, 0 for none
+    pub fn with_proof_of_work(mut self, difficulty: u8) -> Config {
+        self.proof_of_work = difficulty;
+        self
+    }
+
+    /// Limits the number of peers of the view in the same network, such as a /24 IPv4 network,
+    /// so that an attacker controlling many addresses of a subnet cannot take over the view
+    ///
+    /// # Arguments
+    ///
+    /// * `prefix_quota` - The maximum number of peers and the length of the prefixes of the networks
+    pub fn with_prefix_quota(mut self, prefix_quota: PrefixQuota) -> Config {
+        self.prefix_quota = Some(prefix_quota);
+        self
+    }
+
+    /// Records every peer entry that was not merged into the view with the reason of the rejection,
+    /// see [crate::PeerSamplingService::rejections]. The rejections are also reported through monitoring.
+    ///
+    /// # Arguments
+    ///
+    /// * `capacity` - Number of last rejections kept, 0 for no audit
+    pub fn with_audit(mut self, capacity: usize) -> Config {
+        self.audit_capacity = capacity;
+        self
+    }
+
+    /// Requires the responses and busy messages to echo the nonce of the request they answer.
+    /// Responses are only merged when they answer a request recently sent to their sender, so that a
+    /// node cannot alter the view of another by pushing responses to it. By default, a response is accepted
+    /// from the partner of a recent request, such as from versions of the library that do not echo the nonce;
+    /// strict matching is enabled once all the nodes were upgraded.
+    ///
+    /// # Arguments
+    ///
+    /// * `strict_responses` - Whether the answers must echo the nonce of the request
+    pub fn with_strict_responses(mut self, strict_responses: bool) -> Config {
+        self.strict_responses = strict_responses;
+        self
+    }
+
+    /// Answers the pings on the connection they were received on, as the requests always are, instead of
+    /// connecting to the address advertised by their sender, and waits for the answers to the pings of the node
+    /// on their connection. Nodes that cannot accept connections, such as behind a NAT, can then probe their peers.
+    /// Versions of the library without this option answer on another connection, so that it must be enabled
+    /// on all the nodes.
+    ///
+    /// # Arguments
+    ///
+    /// * `direct_answers` - Whether pings are answered on their connection
+    pub fn with_direct_answers(mut self, direct_answers: bool) -> Config {
+        self.direct_answers = direct_answers;
+        self
+    }
+
+    /// Advertises the view size, healing and swapping parameters of the node in its exchanges, so that
+    /// nodes configured with different parameters interoperate predictably: the buffers received from nodes
+    /// with a larger view are truncated to the buffer size of the node, the answers to nodes with a smaller
+    /// view are truncated to half of their view size, and each peer advertising other parameters is reported
+    /// once with a warning and [crate::Event::ParameterMismatch]. Versions of the library without this option reject the messages
+    /// carrying the parameters, so that it must be enabled once all the nodes were upgraded.
+    ///
+    /// # Arguments
+    ///
+    /// * `advertised_parameters` - Whether the parameters are advertised
+    pub fn with_advertised_parameters(mut self, advertised_parameters: bool) -> Config {
+        self.advertised_parameters = advertised_parameters;
+        self
+    }
+
+    /// Advertises an incarnation with the descriptor of the node, which must increase each time the node
+    /// restarts, such as the time of the start in seconds or a counter persisted by the application.
+    /// When merging views, the descriptors of a later incarnation replace those of the previous lives of a node.
+    /// It is incremented by [crate::PeerSamplingService::restart].
+    ///
+    /// # Arguments
+    ///
+    /// * `incarnation` - Incarnation of the node, 0 for none
+    pub fn with_incarnation(mut self, incarnation: u32) -> Config {
+        self.incarnation = incarnation;
+        self
+    }
+
+    /// Measures the age of the descriptors as the time elapsed since their creation, in coarse units of a
+    /// monotonic clock, instead of the number of cycles, so that freshness is comparable between nodes with
+    /// very different sampling periods. Only durations are exchanged, so that the clocks need not be synchronized.
+    /// All the nodes must use the same unit, and ages saturate at 65535 units.
+    ///
+    /// # Arguments
+    ///
+    /// * `unit` - Unit of the ages in milliseconds, such as 1000, 0 for counting cycles
+    pub fn with_time_based_age(mut self, unit: u64) -> Config {
+        self.age_unit = unit;
+        self
+    }
+
+    /// Advertises a locality label with the descriptor of the node, such as its region or zone
+    ///
+    /// # Arguments
+    ///
+    /// * `locality` - Label of at most [crate::MAX_LOCALITY_SIZE] bytes
+    pub fn with_locality(mut self, locality: String) -> Config {
+        self.locality = Some(locality);
+        self
+    }
+
+    /// Limits the share of the view with the same locality, the oldest peers of a locality exceeding it
+    /// being removed when merging views, so that a partition between regions does not leave nodes without
+    /// peers in the other regions. The peers without locality are not limited.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_share` - Maximum share of the view in percent, at most 100, such as 50, 0 for no limit
+    pub fn with_locality_diversity(mut self, max_share: u8) -> Config {
+        self.locality_diversity = max_share.min(100);
+        self
+    }
+
+    /// Tests whether the peers returned to the application are uniformly distributed over the view,
+    /// reporting the score in [crate::Stats::uniformity], for detecting biased views in production
+    ///
+    /// # Arguments
+    ///
+    /// * `window` - Number of last samples tested, 0 for no test
+    pub fn with_uniformity_test(mut self, window: usize) -> Config {
+        self.uniformity_window = window;
+        self
+    }
+
+    /// Keeps the senders of the most recent messages, even those not merged into the view,
+    /// as a last resort pool of peers. They are added to the view when it is empty and the
+    /// bootstrap source has no peers, or after as many consecutive failed exchanges as peers in the view.
+    ///
+    /// # Arguments
+    ///
+    /// * `recent_senders` - Number of senders kept, 0 to keep none
+    pub fn with_recent_senders(mut self, recent_senders: usize) -> Config {
+        self.recent_senders = recent_senders;
+        self
+    }
+
+    /// Keeps the last protocol events in memory, such as the exchanges and their outcome,
+    /// so that they can be retrieved with [crate::PeerSamplingService::recent_events]
+    /// after something went wrong, without debug logging
+    ///
+    /// # Arguments
+    ///
+    /// * `event_history` - Number of events kept, 0 to keep none
+    pub fn with_event_history(mut self, event_history: usize) -> Config {
+        self.event_history = event_history;
+        self
+    }
+
+    /// Quarantines the peers that repeatedly violate the protocol, such as by sending malformed
+    /// or replayed messages. The messages of a quarantined peer are dropped and the peer is not
+    /// selected for exchanges until the quarantine ends. Violations on connections that could not
+    /// be attributed to a peer quarantine the remote IP address.
+    ///
+    /// # Arguments
+    ///
+    /// * `violations` - Number of violations within the duration leading to quarantine, 0 to disable quarantine
+    /// * `duration` - Duration in seconds of the quarantine
+    pub fn with_quarantine(mut self, violations: usize, duration: u64) -> Config {
+        self.quarantine_violations = violations;
+        self.quarantine_duration = duration;
+        self
+    }
+
+    /// Encrypts and authenticates all connections with a Noise XX handshake.
+    /// All the nodes of the overlay must use encryption.
+    ///
+    /// # Arguments
+    ///
+    /// * `noise` - The static keys of the node
+    #[cfg(feature = "noise")]
+    pub fn with_noise(mut self, noise: NoiseConfig) -> Config {
+        self.noise = Some(noise);
+        self
+    }
+
+    /// Checks that the parameters can be used for running the protocol
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.view_size == 0 {
+            Err(ConfigError::EmptyView)
+        }
+        else if !self.push && !self.pull {
+            Err(ConfigError::NoExchange)
+        }
+        else if self.healing_factor + self.swapping_factor > self.view_size / 2 {
+            Err(ConfigError::SelectionFactors { healing_factor: self.healing_factor, swapping_factor: self.swapping_factor, view_size: self.view_size })
+        }
+        else if self.sampling_period == 0 && self.schedule != CycleSchedule::Triggered {
+            Err(ConfigError::ZeroSamplingPeriod)
         }
+        else if self.observer && !self.pull {
+            Err(ConfigError::ObserverWithoutPull)
+        }
+        else if self.size_estimation_samples == 1 || self.size_estimation_samples > 255 {
+            Err(ConfigError::SizeEstimationSamples { samples: self.size_estimation_samples })
+        }
+        else if let Some(locality) = self.locality.as_ref().filter(|locality| locality.len() > crate::peer::MAX_LOCALITY_SIZE) {
+            Err(ConfigError::LocalityTooLong { size: locality.len() })
+        }
+        else if self.buffer_size() == 0 || self.buffer_size() > self.view_size || self.buffer_size() > MAX_BUFFER_SIZE {
+            Err(ConfigError::BufferSizeOutOfRange { buffer_size: self.buffer_size(), view_size: self.view_size })
+        }
+        else {
+            #[cfg(feature = "event-loop")]
+            if self.event_loop && self.exchange_timeout == 0 {
+                return Err(ConfigError::EventLoopWithoutTimeout);
+            }
+            Ok(())
+        }
+    }
+
+    /// Returns the deviations of the parameters from the recommended ranges, which do not prevent the protocol from running
+    pub fn warnings(&self) -> Vec<ConfigWarning> {
+        let mut warnings = vec![];
+        if !self.push || !self.pull {
+            warnings.push(ConfigWarning::NotPushPull);
+        }
+        if self.healing_factor == 0 {
+            warnings.push(ConfigWarning::NoHealing);
+        }
+        if self.buffer_size() != self.view_size / 2 {
+            warnings.push(ConfigWarning::BufferSize { buffer_size: self.buffer_size(), view_size: self.view_size });
+        }
+        warnings
     }
 
     pub fn address(&self) -> &SocketAddr {
         &self.address
     }
 
+    /// Replaces the bind address by the address actually bound, such as when the port was 0
+    pub(crate) fn set_address(&mut self, address: SocketAddr) {
+        self.address = address;
+    }
+
+    /// Increments the incarnation, if advertised, when the node restarts
+    pub(crate) fn next_incarnation(&mut self) {
+        if self.incarnation > 0 {
+            self.incarnation = self.incarnation.saturating_add(1);
+        }
+    }
+
+    pub fn additional_addresses(&self) -> &[SocketAddr] {
+        &self.additional_addresses
+    }
+
+    /// Replaces the additional addresses by the addresses actually bound
+    pub(crate) fn set_additional_addresses(&mut self, addresses: Vec<SocketAddr>) {
+        self.additional_addresses = addresses;
+    }
+
+    pub fn external_address(&self) -> Option<SocketAddr> {
+        *self.external_address.lock().unwrap()
+    }
+
+    /// Replaces the external address when binding, no longer shared with the previous clones of the configuration
+    pub(crate) fn set_external_address(&mut self, address: Option<SocketAddr>) {
+        self.external_address = Arc::new(Mutex::new(address));
+    }
+
+    /// Updates the external address advertised by all the clones of the configuration, such as when the
+    /// STUN servers report another address
+    pub(crate) fn update_external_address(&self, address: SocketAddr) {
+        *self.external_address.lock().unwrap() = Some(address);
+    }
+
+    pub fn stun_servers(&self) -> &[String] {
+        &self.stun_servers
+    }
+
+    pub fn stun_interval(&self) -> u64 {
+        self.stun_interval
+    }
+
+    pub fn relay(&self) -> Option<&SocketAddr> {
+        self.relay.as_ref()
+    }
+
+    pub fn relay_poll_interval(&self) -> u64 {
+        self.relay_poll_interval
+    }
+
+    pub fn max_relayed_size(&self) -> usize {
+        self.max_relayed_size
+    }
+
+    pub fn max_relayed_per_second(&self) -> usize {
+        self.max_relayed_per_second
+    }
+
+    pub fn heartbeat_interval(&self) -> u64 {
+        self.heartbeat_interval
+    }
+
+    pub fn heartbeat_partners(&self) -> usize {
+        self.heartbeat_partners
+    }
+
+    pub fn max_heartbeat_bytes_per_second(&self) -> usize {
+        self.max_heartbeat_bytes_per_second
+    }
+
+    #[cfg(feature = "port-mapping")]
+    pub fn port_mapping(&self) -> Option<&PortMappingConfig> {
+        self.port_mapping.as_ref()
+    }
+
+    #[cfg(feature = "impairment")]
+    pub fn impairment(&self) -> Option<&Impairment> {
+        self.impairment.as_ref()
+    }
+
+    pub fn port_range(&self) -> u16 {
+        self.port_range
+    }
+
+    pub fn bind_retries(&self) -> u32 {
+        self.bind_retries
+    }
+
+    pub fn bind_retry_interval(&self) -> u64 {
+        self.bind_retry_interval
+    }
+
+    /// Returns all the addresses of the node, starting with the bind address
+    pub fn addresses(&self) -> Vec<SocketAddr> {
+        std::iter::once(self.address).chain(self.additional_addresses.iter().copied()).collect()
+    }
+
+    /// Returns the address advertised to a peer: the external address if of the same family as the peer,
+    /// unless the peer is on the loopback interface, otherwise the first address of the node of the same
+    /// family as the peer, or the bind address
+    ///
+    /// # Arguments
+    ///
+    /// * `peer` - Address of the peer
+    pub fn address_for(&self, peer: &str) -> SocketAddr {
+        match crate::network::parse_address(peer) {
+            Ok(peer) => self.external_address()
+                .filter(|external| external.is_ipv6() == peer.is_ipv6() && !peer.ip().is_loopback())
+                .into_iter()
+                .chain(self.addresses())
+                .find(|address| address.is_ipv6() == peer.is_ipv6())
+                .unwrap_or(self.address),
+            Err(_) => self.address,
+        }
+    }
+
     pub fn sampling_period(&self) -> u64 {
         self.sampling_period
     }
@@ -73,6 +1254,10 @@ impl Config {
         self.view_size
     }
 
+    pub fn buffer_size(&self) -> usize {
+        self.buffer_size.unwrap_or(self.view_size / 2)
+    }
+
     pub fn is_pull(&self) -> bool {
         self.pull
     }
@@ -84,4 +1269,234 @@ impl Config {
     pub fn monitoring(&self) -> &MonitoringConfig {
         &self.monitoring
     }
+
+    pub fn is_strict_selection(&self) -> bool {
+        self.strict_selection
+    }
+
+    pub fn sampling_policy(&self) -> SamplingPolicy {
+        self.sampling_policy
+    }
+
+    pub fn self_age_policy(&self) -> SelfAgePolicy {
+        self.self_age_policy
+    }
+
+    #[cfg(feature = "compression")]
+    pub fn is_compression(&self) -> bool {
+        self.compression
+    }
+
+    pub fn message_ttl(&self) -> u64 {
+        self.message_ttl
+    }
+
+    pub fn indegree_cap(&self) -> usize {
+        self.indegree_cap
+    }
+
+    pub fn max_requests_per_second(&self) -> usize {
+        self.max_requests_per_second
+    }
+
+    pub fn partition_window(&self) -> usize {
+        self.partition_window
+    }
+
+    pub fn is_synchronized_rounds(&self) -> bool {
+        self.schedule == CycleSchedule::Triggered
+    }
+
+    pub fn schedule(&self) -> CycleSchedule {
+        self.schedule
+    }
+
+    pub fn recording(&self) -> Option<&RecordingConfig> {
+        self.recording.as_ref()
+    }
+
+    pub fn score_threshold(&self) -> f64 {
+        self.score_threshold
+    }
+
+    pub fn quarantine_violations(&self) -> usize {
+        self.quarantine_violations
+    }
+
+    pub fn quarantine_duration(&self) -> u64 {
+        self.quarantine_duration
+    }
+
+    pub fn max_received_buffer_size(&self) -> usize {
+        self.max_received_buffer_size
+    }
+
+    pub fn max_frame_size(&self) -> usize {
+        self.max_frame_size
+    }
+
+    pub fn view_snapshot(&self) -> Option<&Path> {
+        self.view_snapshot.as_deref()
+    }
+
+    pub fn bootstrap(&self) -> Option<&BootstrapProvider> {
+        self.bootstrap.as_ref()
+    }
+
+    #[cfg(feature = "encrypted-snapshot")]
+    pub fn snapshot_key(&self) -> Option<&[u8; 32]> {
+        self.snapshot_key.as_ref()
+    }
+
+    #[cfg(feature = "encrypted-snapshot")]
+    pub fn previous_snapshot_keys(&self) -> &[[u8; 32]] {
+        &self.previous_snapshot_keys
+    }
+
+    pub fn socket(&self) -> &SocketConfig {
+        &self.socket
+    }
+
+    pub fn threads(&self) -> &ThreadConfig {
+        &self.threads
+    }
+
+    #[cfg(feature = "event-loop")]
+    pub fn is_event_loop(&self) -> bool {
+        self.event_loop
+    }
+
+    /// Returns the maximum number of outbound connections open at the same time, 0 for no limit
+    pub fn max_outbound_connections(&self) -> usize {
+        self.outbound_limit.as_ref().map_or(0, ConnectionLimit::max)
+    }
+
+    pub(crate) fn outbound_limit(&self) -> Option<&ConnectionLimit> {
+        self.outbound_limit.as_ref()
+    }
+
+    pub fn warm_up_cycles(&self) -> usize {
+        self.warm_up_cycles
+    }
+
+    pub fn warm_up_period(&self) -> u64 {
+        self.warm_up_period
+    }
+
+    pub fn partner_history(&self) -> usize {
+        self.partner_history
+    }
+
+    pub fn is_observer(&self) -> bool {
+        self.observer
+    }
+
+    pub fn size_estimation_samples(&self) -> usize {
+        self.size_estimation_samples
+    }
+
+    pub fn size_estimation_epoch(&self) -> u64 {
+        self.size_estimation_epoch
+    }
+
+    pub fn max_payload_size(&self) -> usize {
+        self.max_payload_size
+    }
+
+    pub fn proof_of_work(&self) -> u8 {
+        self.proof_of_work
+    }
+
+    pub fn prefix_quota(&self) -> Option<&PrefixQuota> {
+        self.prefix_quota.as_ref()
+    }
+
+    pub fn audit_capacity(&self) -> usize {
+        self.audit_capacity
+    }
+
+    pub fn is_strict_responses(&self) -> bool {
+        self.strict_responses
+    }
+
+    pub fn is_direct_answers(&self) -> bool {
+        self.direct_answers
+    }
+
+    pub fn is_advertising_parameters(&self) -> bool {
+        self.advertised_parameters
+    }
+
+    pub fn locality(&self) -> Option<&str> {
+        self.locality.as_deref()
+    }
+
+    pub fn locality_diversity(&self) -> u8 {
+        self.locality_diversity
+    }
+
+    /// Returns the maximum number of peers of the view with the same locality, at least one, none for no limit
+    pub(crate) fn locality_quota(&self) -> Option<usize> {
+        if self.locality_diversity > 0 {
+            Some((self.view_size * self.locality_diversity as usize / 100).max(1))
+        }
+        else {
+            None
+        }
+    }
+
+    pub fn age_unit(&self) -> u64 {
+        self.age_unit
+    }
+
+    /// Returns the time corresponding to the age of a descriptor, in cycles or in units of time
+    pub(crate) fn age_duration(&self, age: u16) -> Duration {
+        if self.age_unit > 0 {
+            Duration::from_millis(self.age_unit) * age as u32
+        }
+        else {
+            Duration::from_secs(self.sampling_period) * age as u32
+        }
+    }
+
+    pub fn incarnation(&self) -> u32 {
+        self.incarnation
+    }
+
+    pub fn uniformity_window(&self) -> usize {
+        self.uniformity_window
+    }
+
+    pub fn recent_senders(&self) -> usize {
+        self.recent_senders
+    }
+
+    pub fn event_history(&self) -> usize {
+        self.event_history
+    }
+
+    pub fn exchange_timeout(&self) -> u64 {
+        self.exchange_timeout
+    }
+
+    pub fn breaker_timeouts(&self) -> usize {
+        self.breaker_timeouts
+    }
+
+    pub fn breaker_cooldown(&self) -> u64 {
+        self.breaker_cooldown
+    }
+
+    pub fn send_backoff_base(&self) -> u64 {
+        self.send_backoff_base
+    }
+
+    pub fn send_backoff_max(&self) -> u64 {
+        self.send_backoff_max
+    }
+
+    #[cfg(feature = "noise")]
+    pub fn noise(&self) -> Option<&NoiseConfig> {
+        self.noise.as_ref()
+    }
 }
\ No newline at end of file
@@ -0,0 +1,144 @@
+use std::collections::{BTreeMap, HashSet, VecDeque};
+use std::time::{Duration, Instant};
+
+use slog::{debug, info, Logger};
+
+use crate::config::Config;
+use crate::message::{Message, MessageType};
+use crate::overlay::Overlay;
+use crate::peer::Peer;
+
+/// Walks the overlay from a node by repeatedly pulling the views of the nodes discovered,
+/// for measuring the actual size and connectivity of a deployed overlay.
+///
+/// The crawler does not listen: its requests carry no buffer and are marked as coming from
+/// an observer, so that the crawled nodes neither add it to their views nor keep its address.
+pub struct Crawler {
+    /// Network parameters used for the requests, such as socket options, timeouts and encryption
+    config: Config,
+    /// Minimum time between two requests
+    interval: Duration,
+    /// Number of pulls sent to each node, each one returning part of its view
+    pulls_per_node: usize,
+    /// Number of nodes after which the crawl stops, 0 for no limit
+    max_nodes: usize,
+    /// Logger
+    logger: Logger,
+}
+
+impl Crawler {
+    /// Creates a crawler sending one request every 100 milliseconds and pulling each node once
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - Network parameters used for the requests, the address being the one reported as sender
+    /// * `logger` - Logger
+    pub fn new(config: Config, logger: Logger) -> Crawler {
+        Crawler {
+            config,
+            interval: Duration::from_millis(100),
+            pulls_per_node: 1,
+            max_nodes: 0,
+            logger,
+        }
+    }
+
+    /// Limits the rate of the requests, so that the crawl does not load the overlay
+    ///
+    /// # Arguments
+    ///
+    /// * `interval` - Minimum time in milliseconds between two requests
+    pub fn with_interval(mut self, interval: u64) -> Crawler {
+        self.interval = Duration::from_millis(interval);
+        self
+    }
+
+    /// Pulls each node several times, since a pull only returns part of the view of a node
+    ///
+    /// # Arguments
+    ///
+    /// * `pulls_per_node` - Number of pulls sent to each node
+    pub fn with_pulls_per_node(mut self, pulls_per_node: usize) -> Crawler {
+        self.pulls_per_node = pulls_per_node.max(1);
+        self
+    }
+
+    /// Stops the crawl after a number of nodes, for very large overlays
+    ///
+    /// # Arguments
+    ///
+    /// * `max_nodes` - Number of nodes pulled, 0 for no limit
+    pub fn with_max_nodes(mut self, max_nodes: usize) -> Crawler {
+        self.max_nodes = max_nodes;
+        self
+    }
+
+    /// Crawls the overlay breadth-first from a node, and returns the peers discovered in the view
+    /// of each node that answered. The nodes that could not be reached appear without a view.
+    ///
+    /// # Arguments
+    ///
+    /// * `start` - Address of the first node
+    pub fn crawl(&self, start: &str) -> Overlay {
+        let mut views: BTreeMap<String, Vec<Peer>> = BTreeMap::new();
+        let mut discovered = HashSet::from([start.to_owned()]);
+        let mut pending = VecDeque::from([start.to_owned()]);
+        let mut last_request: Option<Instant> = None;
+
+        while let Some(address) = pending.pop_front() {
+            if self.max_nodes > 0 && views.len() >= self.max_nodes {
+                break;
+            }
+            for _ in 0..self.pulls_per_node {
+                if let Some(last) = last_request {
+                    std::thread::sleep(self.interval.saturating_sub(last.elapsed()));
+                }
+                last_request = Some(Instant::now());
+                let peers = match self.pull(&address) {
+                    Some(peers) => peers,
+                    None => break,
+                };
+                let view = views.entry(address.clone()).or_default();
+                // the answer starts with the descriptor of the node itself
+                for peer in peers.into_iter().filter(|peer| peer.address() != address) {
+                    if discovered.insert(peer.address().to_owned()) {
+                        pending.push_back(peer.address().to_owned());
+                    }
+                    if !view.iter().any(|known| known.address() == peer.address()) {
+                        view.push(peer);
+                    }
+                }
+            }
+        }
+        info!(self.logger, "Crawled {} nodes, {} discovered", views.len(), discovered.len());
+
+        let mut overlay = Overlay::new();
+        for (address, peers) in views {
+            overlay.add_view(address, peers);
+        }
+        overlay
+    }
+
+    /// Pulls the view of a node, returning None if the node could not be reached or refused to answer
+    fn pull(&self, address: &str) -> Option<Vec<Peer>> {
+        let remote_address = match crate::network::parse_address(address) {
+            Ok(remote_address) => remote_address,
+            Err(e) => {
+                debug!(self.logger, "Could not parse address {}: {}", address, e);
+                return None;
+            }
+        };
+        let request = Message::new_request(self.config.address_for(address).to_string(), None).with_observer_flag();
+        match crate::network::exchange(&remote_address, request, &self.config, self.logger.clone()) {
+            Ok(Some(message)) if *message.message_type() == MessageType::Response => Some(message.view().clone().unwrap_or_default()),
+            Ok(_) => {
+                debug!(self.logger, "No view received from {}", address);
+                None
+            }
+            Err(e) => {
+                debug!(self.logger, "Could not pull the view of {}: {}", address, e);
+                None
+            }
+        }
+    }
+}
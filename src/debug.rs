@@ -0,0 +1,73 @@
+use crate::peer::Peer;
+
+// Glyphs assigned to the addresses
+const GLYPHS: [char; 32] = [
+    '🍎', '🍌', '🍒', '🍇', '🍉', '🍋', '🍑', '🍍', '🥝', '🥥', '🥕', '🌽', '🍄', '🌰', '🍞', '🧀',
+    '🐶', '🐱', '🐭', '🐰', '🦊', '🐻', '🐼', '🐨', '🐯', '🦁', '🐮', '🐷', '🐸', '🐵', '🐔', '🐧',
+];
+
+// ANSI foreground colors assigned to the addresses, without black and white
+const COLORS: [u8; 12] = [31, 32, 33, 34, 35, 36, 91, 92, 93, 94, 95, 96];
+
+/// Hashes an address with FNV-1a, which unlike the hasher of the standard library
+/// gives the same result across runs and versions
+fn hash(address: &str) -> u64 {
+    address.bytes().fold(0xcbf29ce484222325, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3))
+}
+
+/// Returns the glyph of an address, the same for every run, so that the peers
+/// of a demo can be told apart at a glance
+///
+/// # Arguments
+///
+/// * `address` - Address of the peer
+pub fn glyph(address: &str) -> char {
+    GLYPHS[(hash(address) % GLYPHS.len() as u64) as usize]
+}
+
+/// Returns the ANSI color code of an address, chosen independently of its glyph
+///
+/// # Arguments
+///
+/// * `address` - Address of the peer
+pub fn color(address: &str) -> u8 {
+    COLORS[((hash(address) / GLYPHS.len() as u64) % COLORS.len() as u64) as usize]
+}
+
+/// Formats peers as their colored glyphs, followed by their age
+///
+/// # Arguments
+///
+/// * `peers` - The peers, such as a view
+pub fn format_peers(peers: &[Peer]) -> String {
+    peers.iter()
+        .map(|peer| format!("\x1b[{}m{}{}\x1b[0m", color(peer.address()), glyph(peer.address()), peer.age()))
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+/// Prints peers as their colored glyphs, followed by their age
+///
+/// # Arguments
+///
+/// * `host_address` - Address of the node holding the peers
+/// * `peers` - The peers, such as the view of the node
+pub fn print_peers(host_address: &str, peers: &[Peer]) {
+    println!("\x1b[{}m{}\x1b[0m {}", color(host_address), glyph(host_address), format_peers(peers));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn any_address_has_a_stable_glyph() {
+        for address in ["127.0.0.1:9000", "127.0.0.1:9", "[fe80::1%eth0]:9000", "node", ""] {
+            assert_eq!(glyph(address), glyph(address));
+            assert_eq!(color(address), color(address));
+        }
+        assert_eq!(glyph("127.0.0.1:9000"), '🐻');
+        let formatted = format_peers(&[Peer::with_age("127.0.0.1:9000".to_owned(), 2)]);
+        assert!(formatted.contains("🐻2"));
+    }
+}
@@ -0,0 +1,359 @@
+use std::error::Error;
+use std::fmt::Write;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+
+use crate::bootstrap::Bootstrap;
+use crate::peer::Peer;
+
+// Version of the EC2 API
+const API_VERSION: &str = "2016-11-15";
+// Address of the instance metadata service, providing the credentials of the role of the instance
+const METADATA_URL: &str = "http://169.254.169.254";
+// Deadline of the requests to the EC2 API and to the metadata service
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Credentials of the requests to the EC2 API
+#[derive(Clone)]
+struct Credentials {
+    access_key: String,
+    secret_key: String,
+    /// Token of temporary credentials, such as those of the role of an instance
+    session_token: Option<String>,
+}
+
+/// Contact peers listed by the EC2 API, the private addresses of the running instances with a tag,
+/// such as `overlay=gossip`, the nodes listening on the same port on every instance.
+///
+/// The requests are signed with the credentials given to [Ec2Bootstrap::with_credentials], or with those
+/// of the `AWS_ACCESS_KEY_ID`, `AWS_SECRET_ACCESS_KEY` and `AWS_SESSION_TOKEN` environment variables,
+/// or else with the credentials of the role of the instance, from the instance metadata service.
+/// The credentials must allow `ec2:DescribeInstances`.
+#[derive(Clone)]
+pub struct Ec2Bootstrap {
+    /// Region of the instances, such as `eu-west-1`
+    region: String,
+    /// Key of the tag of the instances
+    tag_key: String,
+    /// Value of the tag of the instances
+    tag_value: String,
+    /// Port on which the nodes listen
+    port: u16,
+    /// URL of the EC2 API, the endpoint of the region if none
+    endpoint: Option<String>,
+    /// Credentials of the requests, from the environment or the instance metadata if none
+    credentials: Option<Credentials>,
+}
+
+impl Ec2Bootstrap {
+    /// Creates a source listing the running instances with a tag
+    ///
+    /// # Arguments
+    ///
+    /// * `region` - Region of the instances, such as `eu-west-1`
+    /// * `tag_key` - Key of the tag of the instances
+    /// * `tag_value` - Value of the tag of the instances
+    /// * `port` - Port on which the nodes listen
+    pub fn new(region: String, tag_key: String, tag_value: String, port: u16) -> Ec2Bootstrap {
+        Ec2Bootstrap {
+            region,
+            tag_key,
+            tag_value,
+            port,
+            endpoint: None,
+            credentials: None,
+        }
+    }
+
+    /// Sets the credentials of the requests
+    ///
+    /// # Arguments
+    ///
+    /// * `access_key` - Access key identifier
+    /// * `secret_key` - Secret access key
+    /// * `session_token` - Token of temporary credentials, if any
+    pub fn with_credentials(mut self, access_key: String, secret_key: String, session_token: Option<String>) -> Ec2Bootstrap {
+        self.credentials = Some(Credentials { access_key, secret_key, session_token });
+        self
+    }
+
+    /// Sets the URL of the EC2 API, such as that of an endpoint in a virtual private cloud
+    ///
+    /// # Arguments
+    ///
+    /// * `endpoint` - URL of the API, such as `https://ec2.eu-west-1.amazonaws.com`
+    pub fn with_endpoint(mut self, endpoint: String) -> Ec2Bootstrap {
+        self.endpoint = Some(endpoint);
+        self
+    }
+
+    /// Returns the credentials of the requests, from the configuration, the environment or the instance metadata
+    fn credentials(&self, agent: &ureq::Agent) -> Result<Credentials, Box<dyn Error>> {
+        if let Some(credentials) = &self.credentials {
+            return Ok(credentials.clone());
+        }
+        if let (Ok(access_key), Ok(secret_key)) = (std::env::var("AWS_ACCESS_KEY_ID"), std::env::var("AWS_SECRET_ACCESS_KEY")) {
+            return Ok(Credentials { access_key, secret_key, session_token: std::env::var("AWS_SESSION_TOKEN").ok() });
+        }
+        instance_credentials(agent, METADATA_URL)
+    }
+
+    fn fetch(&self) -> Result<Vec<Peer>, Box<dyn Error>> {
+        let agent = ureq::AgentBuilder::new().timeout(REQUEST_TIMEOUT).build();
+        let credentials = self.credentials(&agent)?;
+        let endpoint = self.endpoint.clone().unwrap_or_else(|| format!("https://ec2.{}.amazonaws.com", self.region));
+        let host = endpoint.split("://").nth(1).ok_or("invalid EC2 endpoint")?.trim_end_matches('/');
+
+        let mut parameters = [
+            ("Action", "DescribeInstances".to_owned()),
+            ("Filter.1.Name", format!("tag:{}", self.tag_key)),
+            ("Filter.1.Value.1", self.tag_value.clone()),
+            ("Filter.2.Name", "instance-state-name".to_owned()),
+            ("Filter.2.Value.1", "running".to_owned()),
+            ("Version", API_VERSION.to_owned()),
+        ];
+        parameters.sort();
+        let query = parameters.iter()
+            .map(|(name, value)| format!("{}={}", uri_encode(name), uri_encode(value)))
+            .collect::<Vec<String>>()
+            .join("&");
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        let headers = sign(&credentials, &self.region, "ec2", host, &query, &amz_date(now));
+
+        let mut request = agent.get(&format!("{}/?{}", endpoint.trim_end_matches('/'), query));
+        for (name, value) in &headers {
+            request = request.set(name, value);
+        }
+        let response = request.call()?.into_string()?;
+        Ok(private_addresses(&response).into_iter()
+            .map(|address| Peer::new(format!("{}:{}", address, self.port)))
+            .collect())
+    }
+}
+
+impl Bootstrap for Ec2Bootstrap {
+    fn peers(&mut self) -> Option<Vec<Peer>> {
+        self.fetch().ok().filter(|peers| !peers.is_empty())
+    }
+}
+
+/// Returns the temporary credentials of the role of the instance, from the instance metadata service
+///
+/// # Arguments
+///
+/// * `agent` - The HTTP agent
+/// * `metadata_url` - URL of the metadata service
+fn instance_credentials(agent: &ureq::Agent, metadata_url: &str) -> Result<Credentials, Box<dyn Error>> {
+    let token = agent.put(&format!("{}/latest/api/token", metadata_url))
+        .set("X-aws-ec2-metadata-token-ttl-seconds", "60")
+        .call()?
+        .into_string()?;
+    let roles_url = format!("{}/latest/meta-data/iam/security-credentials/", metadata_url);
+    let roles = agent.get(&roles_url).set("X-aws-ec2-metadata-token", &token).call()?.into_string()?;
+    let role = roles.lines().next().ok_or("no role attached to the instance")?;
+    let credentials = agent.get(&format!("{}{}", roles_url, role)).set("X-aws-ec2-metadata-token", &token).call()?.into_string()?;
+    let credentials: serde_json::Value = serde_json::from_str(&credentials)?;
+    Ok(Credentials {
+        access_key: credentials["AccessKeyId"].as_str().ok_or("missing access key")?.to_owned(),
+        secret_key: credentials["SecretAccessKey"].as_str().ok_or("missing secret key")?.to_owned(),
+        session_token: credentials["Token"].as_str().map(|token| token.to_owned()),
+    })
+}
+
+/// Returns the private address of each instance of a DescribeInstances response
+///
+/// # Arguments
+///
+/// * `response` - The XML response
+fn private_addresses(response: &str) -> Vec<String> {
+    // the first private address following the identifier of an instance is the primary address of the instance,
+    // those of its network interfaces being listed after it
+    response.split("<instanceId>").skip(1)
+        .filter_map(|instance| {
+            let start = instance.find("<privateIpAddress>")? + "<privateIpAddress>".len();
+            let end = start + instance[start..].find("</privateIpAddress>")?;
+            Some(instance[start..end].trim().to_owned())
+        })
+        .collect()
+}
+
+/// Returns the headers signing a GET request with the AWS signature version 4
+///
+/// # Arguments
+///
+/// * `credentials` - Credentials of the request
+/// * `region` - Region of the service
+/// * `service` - Name of the service, such as `ec2`
+/// * `host` - Host of the request, with its port if it is not the default one
+/// * `query` - Canonical query string of the request, its parameters being encoded and sorted
+/// * `amz_date` - Time of the request, such as `20150830T123600Z`
+fn sign(credentials: &Credentials, region: &str, service: &str, host: &str, query: &str, amz_date: &str) -> Vec<(&'static str, String)> {
+    let mut headers = vec![("host", host.to_owned()), ("x-amz-date", amz_date.to_owned())];
+    if let Some(token) = &credentials.session_token {
+        headers.push(("x-amz-security-token", token.clone()));
+    }
+    let canonical_headers = headers.iter().map(|(name, value)| format!("{}:{}\n", name, value)).collect::<String>();
+    let signed_headers = headers.iter().map(|(name, _)| *name).collect::<Vec<&str>>().join(";");
+    let canonical_request = format!("GET\n/\n{}\n{}\n{}\n{}", query, canonical_headers, signed_headers, hex(&Sha256::digest(b"")));
+
+    let date = &amz_date[..8];
+    let scope = format!("{}/{}/{}/aws4_request", date, region, service);
+    let string_to_sign = format!("AWS4-HMAC-SHA256\n{}\n{}\n{}", amz_date, scope, hex(&Sha256::digest(canonical_request.as_bytes())));
+    let key = [date, region, service, "aws4_request"].iter()
+        .fold(format!("AWS4{}", credentials.secret_key).into_bytes(), |key, part| hmac(&key, part.as_bytes()));
+    let signature = hex(&hmac(&key, string_to_sign.as_bytes()));
+
+    // the host header is set from the URL
+    headers.remove(0);
+    headers.push(("Authorization", format!("AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+        credentials.access_key, scope, signed_headers, signature)));
+    headers
+}
+
+fn hmac(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any size");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut hex, byte| {
+        let _ = write!(hex, "{:02x}", byte);
+        hex
+    })
+}
+
+/// Encodes a parameter of the query, only the unreserved characters being kept
+fn uri_encode(value: &str) -> String {
+    value.bytes().fold(String::new(), |mut encoded, byte| {
+        if byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'_' | b'.' | b'~') {
+            encoded.push(byte as char);
+        }
+        else {
+            let _ = write!(encoded, "%{:02X}", byte);
+        }
+        encoded
+    })
+}
+
+/// Formats a time as a signed request expects it, such as `20150830T123600Z`
+///
+/// # Arguments
+///
+/// * `seconds` - Seconds since the Unix epoch
+fn amz_date(seconds: u64) -> String {
+    // civil date of a number of days since the epoch, see http://howardhinnant.github.io/date_algorithms.html
+    let days = (seconds / 86400) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    let time = seconds % 86400;
+    format!("{:04}{:02}{:02}T{:02}{:02}{:02}Z", year, month, day, time / 3600, time / 60 % 60, time % 60)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+
+    /// Answers the requests of the connections accepted in order with the bodies, and returns the requests
+    fn serve(listener: TcpListener, bodies: Vec<&'static str>) -> std::thread::JoinHandle<Vec<String>> {
+        std::thread::spawn(move || {
+            bodies.into_iter().map(|body| {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut request = vec![];
+                let mut buffer = [0u8; 1024];
+                while !request.ends_with(b"\r\n\r\n") {
+                    let length = stream.read(&mut buffer).unwrap();
+                    assert!(length > 0);
+                    request.extend_from_slice(&buffer[..length]);
+                }
+                write!(stream, "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", body.len(), body).unwrap();
+                String::from_utf8(request).unwrap()
+            }).collect()
+        })
+    }
+
+    #[test]
+    fn signs_requests() {
+        // the get-vanilla case of the test suite of the signature version 4
+        let credentials = Credentials {
+            access_key: "AKIDEXAMPLE".to_owned(),
+            secret_key: "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY".to_owned(),
+            session_token: None,
+        };
+        let headers = sign(&credentials, "us-east-1", "service", "example.amazonaws.com", "", "20150830T123600Z");
+        assert_eq!(headers, vec![
+            ("x-amz-date", "20150830T123600Z".to_owned()),
+            ("Authorization", "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/service/aws4_request, \
+                SignedHeaders=host;x-amz-date, Signature=5fa00fa31553b73ebf1942676e86291e8372ff2a2260956d9b8aae1d763fbf31".to_owned()),
+        ]);
+    }
+
+    #[test]
+    fn formats_dates() {
+        assert_eq!(amz_date(0), "19700101T000000Z");
+        assert_eq!(amz_date(1_440_938_160), "20150830T123600Z");
+        assert_eq!(amz_date(951_782_400), "20000229T000000Z");
+    }
+
+    #[test]
+    fn encodes_query_parameters() {
+        assert_eq!(uri_encode("tag:role"), "tag%3Arole");
+        assert_eq!(uri_encode("a b/c~d"), "a%20b%2Fc~d");
+    }
+
+    #[test]
+    fn lists_the_instances_with_the_tag() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+        let server = serve(listener, vec![r#"<?xml version="1.0" encoding="UTF-8"?>
+            <DescribeInstancesResponse xmlns="http://ec2.amazonaws.com/doc/2016-11-15/">
+                <reservationSet><item><instancesSet>
+                    <item><instanceId>i-1</instanceId><privateIpAddress>10.0.0.1</privateIpAddress>
+                        <networkInterfaceSet><item><privateIpAddress>10.0.0.1</privateIpAddress></item></networkInterfaceSet></item>
+                    <item><instanceId>i-2</instanceId><privateIpAddress>10.0.0.2</privateIpAddress></item>
+                </instancesSet></item></reservationSet>
+            </DescribeInstancesResponse>"#]);
+        let mut bootstrap = Ec2Bootstrap::new("eu-west-1".to_owned(), "overlay".to_owned(), "gossip".to_owned(), 9000)
+            .with_endpoint(endpoint)
+            .with_credentials("AKID".to_owned(), "secret".to_owned(), Some("session".to_owned()));
+        assert_eq!(bootstrap.peers().unwrap(), vec![Peer::new("10.0.0.1:9000".to_owned()), Peer::new("10.0.0.2:9000".to_owned())]);
+
+        let request = server.join().unwrap().remove(0);
+        assert!(request.starts_with("GET /?Action=DescribeInstances&Filter.1.Name=tag%3Aoverlay&Filter.1.Value.1=gossip&\
+            Filter.2.Name=instance-state-name&Filter.2.Value.1=running&Version=2016-11-15 HTTP/1.1\r\n"), "{}", request);
+        let request = request.to_lowercase();
+        assert!(request.contains("authorization: aws4-hmac-sha256 credential=akid/"), "{}", request);
+        assert!(request.contains("/eu-west-1/ec2/aws4_request, signedheaders=host;x-amz-date;x-amz-security-token, signature="), "{}", request);
+        assert!(request.contains("x-amz-security-token: session\r\n"), "{}", request);
+    }
+
+    #[test]
+    fn reads_the_credentials_of_the_instance() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let metadata_url = format!("http://{}", listener.local_addr().unwrap());
+        let server = serve(listener, vec![
+            "token",
+            "gossip-role\n",
+            r#"{"Code":"Success","AccessKeyId":"ASIA","SecretAccessKey":"secret","Token":"session"}"#,
+        ]);
+        let agent = ureq::AgentBuilder::new().timeout(REQUEST_TIMEOUT).build();
+        let credentials = instance_credentials(&agent, &metadata_url).unwrap();
+        assert_eq!((credentials.access_key.as_str(), credentials.secret_key.as_str()), ("ASIA", "secret"));
+        assert_eq!(credentials.session_token.as_deref(), Some("session"));
+
+        let requests = server.join().unwrap();
+        assert!(requests[0].starts_with("PUT /latest/api/token HTTP/1.1\r\n"), "{}", requests[0]);
+        assert!(requests[2].starts_with("GET /latest/meta-data/iam/security-credentials/gossip-role HTTP/1.1\r\n"), "{}", requests[2]);
+        assert!(requests[2].to_lowercase().contains("x-aws-ec2-metadata-token: token\r\n"), "{}", requests[2]);
+    }
+}
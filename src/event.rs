@@ -0,0 +1,154 @@
+use std::collections::VecDeque;
+use std::sync::mpsc::{Receiver, Sender};
+use std::time::SystemTime;
+
+use crate::audit::AuditLog;
+use crate::parameters::ProtocolParameters;
+
+/// An event of the peer sampling protocol, notified to the subscribers of the service
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub enum Event {
+    /// The node suspects that it was partitioned from the peers it failed to reach,
+    /// and bootstraps again
+    PartitionSuspected {
+        /// Addresses of the peers that could not be reached
+        unreachable: Vec<String>,
+    },
+    /// A peer repeatedly violated the protocol and is ignored for the configured duration,
+    /// see [crate::Config::with_quarantine]
+    PeerQuarantined {
+        /// Address of the peer, or its IP address if the violations came from connections
+        /// that could not be attributed to a peer
+        peer: String,
+        /// Number of violations that led to the quarantine
+        violations: usize,
+    },
+    /// The node exchanged views with a partner, or failed to.
+    /// Only kept in the recent events, see [crate::Config::with_event_history]
+    Exchanged {
+        /// Address of the partner
+        peer: String,
+        /// Outcome of the exchange, as recorded by [crate::Config::with_recording]
+        outcome: String,
+    },
+    /// The node merged the buffer pushed by another peer into its view.
+    /// Only kept in the recent events, see [crate::Config::with_event_history]
+    Merged {
+        /// Address of the peer
+        peer: String,
+        /// Number of peers in the buffer
+        peers: usize,
+    },
+    /// A peer advertised parameters of the view selection different from those of the node,
+    /// see [crate::Config::with_advertised_parameters]. Notified once per peer, and again if it changes them.
+    ParameterMismatch {
+        /// Address of the peer
+        peer: String,
+        /// Parameters advertised by the peer
+        parameters: ProtocolParameters,
+    },
+}
+
+/// The subscribers of the events of a node, and the last events kept for post-mortem debugging
+pub(crate) struct Events {
+    /// Receivers of the events
+    subscribers: Vec<Sender<Event>>,
+    /// The last events, oldest first, with the time they occurred
+    history: VecDeque<(SystemTime, Event)>,
+    /// Maximum number of events kept, 0 for none
+    capacity: usize,
+    /// The rejected peer entries, kept along with the events so that every component reporting events can audit them
+    audit: AuditLog,
+}
+
+impl Events {
+    /// Creates the events of a node
+    ///
+    /// # Arguments
+    ///
+    /// * `capacity` - Maximum number of events kept, 0 for none
+    pub(crate) fn new(capacity: usize) -> Events {
+        Events {
+            subscribers: vec![],
+            history: VecDeque::new(),
+            capacity,
+            audit: AuditLog::new(0, false),
+        }
+    }
+
+    /// Sets the log of the rejected peer entries, see [crate::Config::with_audit]
+    ///
+    /// # Arguments
+    ///
+    /// * `audit` - The audit log
+    pub(crate) fn set_audit(&mut self, audit: AuditLog) {
+        self.audit = audit;
+    }
+
+    /// Returns the log of the rejected peer entries
+    pub(crate) fn audit(&mut self) -> &mut AuditLog {
+        &mut self.audit
+    }
+
+    /// Returns a receiver notified with the next events
+    pub(crate) fn subscribe(&mut self) -> Receiver<Event> {
+        let (tx, rx) = std::sync::mpsc::channel();
+        self.subscribers.push(tx);
+        rx
+    }
+
+    /// Notifies the subscribers of an event and keeps it, forgetting the oldest event when full
+    ///
+    /// # Arguments
+    ///
+    /// * `event` - The event
+    pub(crate) fn notify(&mut self, event: Event) {
+        crate::sampler::notify(&mut self.subscribers, &event);
+        self.record(event);
+    }
+
+    /// Keeps an event without notifying the subscribers, such as the frequent events of each cycle
+    ///
+    /// # Arguments
+    ///
+    /// * `event` - The event
+    pub(crate) fn record(&mut self, event: Event) {
+        if self.capacity > 0 {
+            if self.history.len() == self.capacity {
+                self.history.pop_front();
+            }
+            self.history.push_back((SystemTime::now(), event));
+        }
+    }
+
+    /// Returns the last events, oldest first
+    pub(crate) fn recent(&self) -> Vec<(SystemTime, Event)> {
+        self.history.iter().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_last_events() {
+        let mut events = Events::new(2);
+        let subscriber = events.subscribe();
+        for violations in 1..=3 {
+            events.notify(Event::PeerQuarantined { peer: "127.0.0.1:9001".to_owned(), violations });
+        }
+        let recent = events.recent().into_iter().map(|(_, event)| event).collect::<Vec<Event>>();
+        assert_eq!(recent, vec![
+            Event::PeerQuarantined { peer: "127.0.0.1:9001".to_owned(), violations: 2 },
+            Event::PeerQuarantined { peer: "127.0.0.1:9001".to_owned(), violations: 3 },
+        ]);
+        assert_eq!(subscriber.try_iter().count(), 3);
+
+        events.record(Event::Merged { peer: "127.0.0.1:9001".to_owned(), peers: 4 });
+        assert_eq!(events.recent()[1].1, Event::Merged { peer: "127.0.0.1:9001".to_owned(), peers: 4 });
+        assert!(subscriber.try_recv().is_err());
+        assert!(Events::new(0).recent().is_empty());
+    }
+}
@@ -0,0 +1,219 @@
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{Receiver, Sender, TryRecvError};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use mio::net::{TcpListener, TcpStream};
+use mio::{Interest, Poll, Token, Waker};
+use slog::{error, info, warn, Logger};
+
+use crate::config::Config;
+use crate::peer::{MessageHandler, SamplingActivity};
+
+// Token of the waker, notified when a cycle is triggered
+const WAKER: Token = Token(usize::MAX);
+
+// Maximum number of readiness events handled at each wake up
+const EVENTS_CAPACITY: usize = 64;
+
+// Maximum number of accepted connections waiting for their message, the next ones being dropped
+const MAX_PENDING_CONNECTIONS: usize = 256;
+
+/// Runs the listeners, the handling of the received messages and the peer sampling on a single
+/// thread, see [crate::Config::with_event_loop]. The accepted connections are only read once
+/// their message starts arriving, or dropped if it does not start arriving before the exchange timeout, and the cycles are performed when the thread wakes up at
+/// their scheduled time or when triggered.
+pub(crate) struct EventLoop {
+    poll: Poll,
+    /// The listeners of the node, whose tokens are their indices
+    listeners: Vec<TcpListener>,
+    /// Accepted connections whose message has not arrived yet, by token, with the time they were accepted
+    pending: HashMap<Token, (TcpStream, Instant)>,
+    /// Token of the next accepted connection
+    next_token: usize,
+    handler: MessageHandler,
+    activity: SamplingActivity,
+    /// The channel used for triggering cycles, closed on shutdown
+    cycle_trigger: Receiver<Sender<Option<String>>>,
+    shutdown_requested: Arc<AtomicBool>,
+    /// Maximum time for reading a received message and writing its answer
+    read_timeout: Duration,
+    logger: Logger,
+}
+
+impl EventLoop {
+    /// Creates the event loop, and returns it with the waker to be notified after a cycle is triggered
+    ///
+    /// # Arguments
+    ///
+    /// * `listeners` - The listeners, bound to the addresses of the node
+    /// * `handler` - The handler of the received messages
+    /// * `activity` - The peer sampling
+    /// * `cycle_trigger` - The channel used for triggering cycles, closed on shutdown
+    /// * `shutdown_requested` - The flag set on shutdown, before waking the listeners
+    /// * `config` - The configuration of the node, whose exchange timeout also bounds the reading of the received messages
+    pub(crate) fn new(listeners: Vec<std::net::TcpListener>, handler: MessageHandler, activity: SamplingActivity, cycle_trigger: Receiver<Sender<Option<String>>>, shutdown_requested: Arc<AtomicBool>, config: &Config, logger: Logger) -> std::io::Result<(EventLoop, Arc<Waker>)> {
+        let poll = Poll::new()?;
+        let waker = Arc::new(Waker::new(poll.registry(), WAKER)?);
+        let listeners = listeners.into_iter().enumerate()
+            .map(|(index, listener)| {
+                listener.set_nonblocking(true)?;
+                let mut listener = TcpListener::from_std(listener);
+                poll.registry().register(&mut listener, Token(index), Interest::READABLE)?;
+                Ok(listener)
+            })
+            .collect::<Result<Vec<TcpListener>, std::io::Error>>()?;
+        let next_token = listeners.len();
+        let event_loop = EventLoop {
+            poll,
+            listeners,
+            pending: HashMap::new(),
+            next_token,
+            handler,
+            activity,
+            cycle_trigger,
+            shutdown_requested,
+            read_timeout: Duration::from_millis(config.exchange_timeout()),
+            logger,
+        };
+        Ok((event_loop, waker))
+    }
+
+    /// Runs until the service is shut down, and returns the peer sampling
+    pub(crate) fn run(mut self) -> SamplingActivity {
+        info!(self.logger, "Started event loop");
+        let mut readiness = mio::Events::with_capacity(EVENTS_CAPACITY);
+        let mut scheduled = self.activity.first_cycle();
+        'running: loop {
+            // the loop also wakes up when the oldest pending connection expires
+            let deadline = match (scheduled, self.pending_deadline()) {
+                (Some(scheduled), Some(pending)) => Some(scheduled.min(pending)),
+                (scheduled, pending) => scheduled.or(pending),
+            };
+            let timeout = deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()));
+            if let Err(e) = self.poll.poll(&mut readiness, timeout) {
+                if e.kind() == ErrorKind::Interrupted {
+                    continue;
+                }
+                error!(self.logger, "Error waiting for events: {}", e);
+                break;
+            }
+
+            // check for shutdown request
+            if self.shutdown_requested.load(Ordering::SeqCst) {
+                break;
+            }
+
+            self.drop_idle_connections();
+            for event in readiness.iter() {
+                match event.token() {
+                    WAKER => (),
+                    Token(index) if index < self.listeners.len() => self.accept(index),
+                    token => self.receive(token),
+                }
+            }
+
+            // triggered cycles do not delay the scheduled cycle
+            loop {
+                match self.cycle_trigger.try_recv() {
+                    Ok(completion) => {
+                        let partner = self.activity.run_cycle();
+                        let _ = completion.send(partner);
+                    }
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => break 'running,
+                }
+            }
+            if scheduled.is_some_and(|deadline| deadline <= Instant::now()) {
+                scheduled = self.activity.following_cycle();
+                self.activity.run_cycle();
+            }
+        }
+        info!(self.logger, "Event loop exiting");
+        self.activity
+    }
+
+    /// Accepts the pending connections of a listener, which are read once their message arrives
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - Index of the listener
+    fn accept(&mut self, index: usize) {
+        loop {
+            match self.listeners[index].accept() {
+                Ok((mut stream, remote_address)) => {
+                    if self.pending.len() >= MAX_PENDING_CONNECTIONS {
+                        warn!(self.logger, "Too many connections waiting for their message, dropping connection from {}", remote_address);
+                        continue;
+                    }
+                    let token = Token(self.next_token);
+                    // the tokens of the listeners and of the waker are skipped when wrapping around
+                    self.next_token = self.next_token.checked_add(1).filter(|next| *next != WAKER.0).unwrap_or(self.listeners.len());
+                    match self.poll.registry().register(&mut stream, token, Interest::READABLE) {
+                        Ok(()) => { self.pending.insert(token, (stream, Instant::now())); },
+                        Err(e) => warn!(self.logger, "Could not wait for the message of {}: {}", remote_address, e),
+                    }
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == ErrorKind::Interrupted => (),
+                Err(e) => {
+                    warn!(self.logger, "Connection failed: {}", e);
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Returns the time at which the oldest pending connection expires, if any
+    fn pending_deadline(&self) -> Option<Instant> {
+        self.pending.values().map(|(_, accepted)| *accepted + self.read_timeout).min()
+    }
+
+    /// Drops the pending connections whose message did not start arriving before the read timeout,
+    /// so that idle connections do not fill the table of pending connections
+    fn drop_idle_connections(&mut self) {
+        let now = Instant::now();
+        let registry = self.poll.registry();
+        let read_timeout = self.read_timeout;
+        let logger = &self.logger;
+        self.pending.retain(|_, (stream, accepted)| {
+            if now.saturating_duration_since(*accepted) < read_timeout {
+                return true;
+            }
+            if let Err(e) = registry.deregister(stream) {
+                warn!(logger, "Could not stop waiting for a connection: {}", e);
+            }
+            match stream.peer_addr() {
+                Ok(remote_address) => warn!(logger, "No message received from {}, dropping connection", remote_address),
+                Err(_) => warn!(logger, "No message received, dropping connection"),
+            }
+            false
+        });
+    }
+
+    /// Reads and handles the message arriving on an accepted connection
+    ///
+    /// # Arguments
+    ///
+    /// * `token` - Token of the connection
+    fn receive(&mut self, token: Token) {
+        let mut stream = match self.pending.remove(&token) {
+            Some((stream, _)) => stream,
+            None => return,
+        };
+        if let Err(e) = self.poll.registry().deregister(&mut stream) {
+            warn!(self.logger, "Could not stop waiting for a connection: {}", e);
+        }
+        // the rest of the message is read without waiting for other events
+        let stream = std::net::TcpStream::from(stream);
+        let blocking = stream.set_nonblocking(false)
+            .and_then(|()| stream.set_read_timeout(Some(self.read_timeout)))
+            .and_then(|()| stream.set_write_timeout(Some(self.read_timeout)));
+        match blocking {
+            Ok(()) => self.handler.receive(stream),
+            Err(e) => warn!(self.logger, "Could not read connection: {}", e),
+        }
+    }
+}
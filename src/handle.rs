@@ -0,0 +1,161 @@
+use std::collections::HashMap;
+use std::sync::mpsc::{Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use crate::audit::Rejection;
+use crate::event::{Event, Events};
+use crate::peer::Peer;
+use crate::policy::SampleSource;
+use crate::sampler::PeerSampler;
+use crate::score::{PeerScore, PeerScores};
+use crate::seen::LastSeen;
+use crate::size::SizeEstimator;
+use crate::stats::Stats;
+use crate::stream::PeerIter;
+use crate::view::View;
+
+/// A cheap cloneable handle to a running service, for sampling peers from several
+/// application threads without locking the service, see [crate::PeerSamplingService::handle].
+///
+/// The handle remains usable after the service is shut down, returning the last view.
+#[derive(Clone)]
+pub struct SamplingHandle {
+    /// View of the service
+    view: Arc<Mutex<View>>,
+    /// Receivers of the view updates
+    subscribers: Arc<Mutex<Vec<Sender<Vec<Peer>>>>>,
+    /// Receivers of the protocol events, and the last events
+    events: Arc<Mutex<Events>>,
+    /// Statistics of the protocol
+    stats: Arc<Mutex<Stats>>,
+    /// Behavior observed for each peer
+    scores: Arc<Mutex<PeerScores>>,
+    /// Estimator of the number of nodes of the overlay
+    size_estimator: Arc<Mutex<SizeEstimator>>,
+    /// The last time each peer was seen
+    last_seen: Arc<Mutex<LastSeen>>,
+}
+
+impl SamplingHandle {
+    pub(crate) fn new(view: Arc<Mutex<View>>, subscribers: Arc<Mutex<Vec<Sender<Vec<Peer>>>>>, events: Arc<Mutex<Events>>, stats: Arc<Mutex<Stats>>, scores: Arc<Mutex<PeerScores>>, size_estimator: Arc<Mutex<SizeEstimator>>, last_seen: Arc<Mutex<LastSeen>>) -> SamplingHandle {
+        SamplingHandle { view, subscribers, events, stats, scores, size_estimator, last_seen }
+    }
+
+    /// Returns a random peer for the client application, see [crate::PeerSamplingService::get_peer]
+    pub fn get_peer(&self) -> Option<Peer> {
+        self.view.lock().unwrap().get_peer()
+    }
+
+    /// Returns a sampled peer along with how it was sampled, see [crate::PeerSamplingService::sample_peer]
+    pub fn sample_peer(&self) -> Option<(Peer, SampleSource)> {
+        self.view.lock().unwrap().sample_peer(&[])
+    }
+
+    /// Returns a random peer for the client application other than the excluded peers,
+    /// see [crate::PeerSamplingService::get_peer_excluding]
+    ///
+    /// # Arguments
+    ///
+    /// * `excluded` - The peers not to be returned
+    pub fn get_peer_excluding(&self, excluded: &[Peer]) -> Option<Peer> {
+        self.view.lock().unwrap().get_peer_excluding(excluded)
+    }
+
+    /// Returns a peer that is probably alive, see [crate::PeerSamplingService::get_recent_peer]
+    ///
+    /// # Arguments
+    ///
+    /// * `max_age` - Time since the peers were last seen for being considered recent
+    pub fn get_recent_peer(&self, max_age: Duration) -> Option<Peer> {
+        let view = self.view.lock().unwrap();
+        self.last_seen.lock().unwrap().recent_peer(&view, max_age)
+    }
+
+    /// Returns a random peer in a locality, see [crate::PeerSamplingService::get_peer_in]
+    ///
+    /// # Arguments
+    ///
+    /// * `locality` - The locality of the peer
+    pub fn get_peer_in(&self, locality: &str) -> Option<Peer> {
+        self.view.lock().unwrap().get_peer_in(locality)
+    }
+
+    /// Returns a random peer in another locality, see [crate::PeerSamplingService::get_peer_outside]
+    ///
+    /// # Arguments
+    ///
+    /// * `locality` - The locality the peer is not in
+    pub fn get_peer_outside(&self, locality: &str) -> Option<Peer> {
+        self.view.lock().unwrap().get_peer_outside(locality)
+    }
+
+    /// Returns an iterator over sampled peers, see [crate::PeerSamplingService::peers_iter]
+    pub fn peers_iter(&self) -> PeerIter {
+        PeerIter::new(self.view.clone())
+    }
+
+    /// Returns a copy of the current view
+    pub fn view_snapshot(&self) -> Vec<Peer> {
+        self.view.lock().unwrap().peers().to_vec()
+    }
+
+    /// Returns a receiver notified with the new view every time the view is updated
+    pub fn subscribe(&self) -> Receiver<Vec<Peer>> {
+        let (tx, rx) = std::sync::mpsc::channel();
+        self.subscribers.lock().unwrap().push(tx);
+        rx
+    }
+
+    /// Returns a receiver notified with the events of the protocol
+    pub fn subscribe_events(&self) -> Receiver<Event> {
+        self.events.lock().unwrap().subscribe()
+    }
+
+    /// Returns the last events of the protocol, see [crate::PeerSamplingService::recent_events]
+    pub fn recent_events(&self) -> Vec<(SystemTime, Event)> {
+        self.events.lock().unwrap().recent()
+    }
+
+    /// Returns the last rejected peer entries, see [crate::PeerSamplingService::rejections]
+    pub fn rejections(&self) -> Vec<Rejection> {
+        self.events.lock().unwrap().audit().rejections()
+    }
+
+    /// Returns the statistics of the protocol
+    pub fn stats(&self) -> Stats {
+        let uniformity = self.view.lock().unwrap().uniformity();
+        let mut stats = self.stats.lock().unwrap().clone();
+        stats.set_uniformity(uniformity);
+        stats
+    }
+
+    /// Returns the estimated number of nodes of the overlay, see [crate::PeerSamplingService::estimated_network_size]
+    pub fn estimated_network_size(&self) -> Option<f64> {
+        self.size_estimator.lock().unwrap().estimate()
+    }
+
+    /// Returns the last time each peer was seen, see [crate::PeerSamplingService::last_seen]
+    pub fn last_seen(&self) -> HashMap<String, SystemTime> {
+        self.last_seen.lock().unwrap().all()
+    }
+
+    /// Returns the score of each peer, see [crate::PeerSamplingService::scores]
+    pub fn scores(&self) -> HashMap<String, PeerScore> {
+        self.scores.lock().unwrap().snapshot()
+    }
+}
+
+impl PeerSampler for SamplingHandle {
+    fn get_peer(&mut self) -> Option<Peer> {
+        SamplingHandle::get_peer(self)
+    }
+
+    fn view_snapshot(&self) -> Vec<Peer> {
+        SamplingHandle::view_snapshot(self)
+    }
+
+    fn subscribe(&mut self) -> Receiver<Vec<Peer>> {
+        SamplingHandle::subscribe(self)
+    }
+}
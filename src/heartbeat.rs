@@ -0,0 +1,148 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// The most recent partners of the node, with the time of the last traffic exchanged with them,
+/// which are sent heartbeats, see [crate::Config::with_heartbeat]
+pub(crate) struct RecentPartners {
+    /// Number of partners kept
+    max: usize,
+    /// The partners, the most recent last
+    partners: VecDeque<(String, Instant)>,
+}
+
+impl RecentPartners {
+    /// Creates an empty list
+    ///
+    /// # Arguments
+    ///
+    /// * `max` - Number of partners kept, the least recent being forgotten first
+    pub(crate) fn new(max: usize) -> RecentPartners {
+        RecentPartners {
+            max,
+            partners: VecDeque::new(),
+        }
+    }
+
+    /// Records traffic exchanged with a partner
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - Address of the partner
+    pub(crate) fn record(&mut self, address: &str) {
+        self.record_at(address, Instant::now())
+    }
+
+    fn record_at(&mut self, address: &str, now: Instant) {
+        if self.max == 0 {
+            return;
+        }
+        self.partners.retain(|(partner, _)| partner != address);
+        self.partners.push_back((address.to_owned(), now));
+        if self.partners.len() > self.max {
+            self.partners.pop_front();
+        }
+    }
+
+    /// Returns the partners without traffic for an interval, the least recent first
+    ///
+    /// # Arguments
+    ///
+    /// * `interval` - Time without traffic after which a heartbeat is due
+    pub(crate) fn due(&self, interval: Duration) -> Vec<String> {
+        self.due_at(interval, Instant::now())
+    }
+
+    fn due_at(&self, interval: Duration, now: Instant) -> Vec<String> {
+        self.partners.iter()
+            .filter(|(_, last)| now.duration_since(*last) >= interval)
+            .map(|(partner, _)| partner.clone())
+            .collect()
+    }
+
+    /// Records that a heartbeat was sent to a partner, without changing the order of the partners
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - Address of the partner
+    pub(crate) fn refresh(&mut self, address: &str) {
+        let now = Instant::now();
+        if let Some((_, last)) = self.partners.iter_mut().find(|(partner, _)| partner == address) {
+            *last = now;
+        }
+    }
+}
+
+/// Bytes that may be sent, replenished at a constant rate up to one second of traffic
+pub(crate) struct ByteBudget {
+    /// Bytes allowed per second, 0 for no limit
+    rate: usize,
+    /// Bytes that may be sent now
+    available: f64,
+    /// Time the budget was last replenished
+    updated: Instant,
+}
+
+impl ByteBudget {
+    /// Creates a full budget
+    ///
+    /// # Arguments
+    ///
+    /// * `rate` - Bytes allowed per second, 0 for no limit
+    pub(crate) fn new(rate: usize) -> ByteBudget {
+        ByteBudget::new_at(rate, Instant::now())
+    }
+
+    fn new_at(rate: usize, now: Instant) -> ByteBudget {
+        ByteBudget {
+            rate,
+            available: rate as f64,
+            updated: now,
+        }
+    }
+
+    /// Takes bytes from the budget, returning false if there are not enough
+    ///
+    /// # Arguments
+    ///
+    /// * `bytes` - Number of bytes to be sent
+    pub(crate) fn spend(&mut self, bytes: usize) -> bool {
+        self.spend_at(bytes, Instant::now())
+    }
+
+    fn spend_at(&mut self, bytes: usize, now: Instant) -> bool {
+        if self.rate == 0 {
+            return true;
+        }
+        let elapsed = now.duration_since(self.updated).as_secs_f64();
+        self.available = (self.available + elapsed * self.rate as f64).min(self.rate as f64);
+        self.updated = now;
+        if self.available < bytes as f64 {
+            return false;
+        }
+        self.available -= bytes as f64;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn heartbeats_idle_partners_within_budget() {
+        let now = Instant::now();
+        let mut partners = RecentPartners::new(2);
+        partners.record_at("10.0.0.1:9000", now);
+        partners.record_at("10.0.0.2:9000", now + Duration::from_secs(1));
+        partners.record_at("10.0.0.3:9000", now + Duration::from_secs(2));
+        // the least recent partner is forgotten
+        assert_eq!(partners.due_at(Duration::from_secs(1), now + Duration::from_secs(3)), vec!["10.0.0.2:9000".to_owned(), "10.0.0.3:9000".to_owned()]);
+        assert_eq!(partners.due_at(Duration::from_secs(1), now + Duration::from_millis(2500)), vec!["10.0.0.2:9000".to_owned()]);
+
+        let mut budget = ByteBudget::new_at(100, now);
+        assert!(budget.spend_at(60, now));
+        assert!(!budget.spend_at(60, now));
+        assert!(budget.spend_at(60, now + Duration::from_millis(200)));
+        assert!(ByteBudget::new_at(0, now).spend_at(1000, now));
+    }
+}
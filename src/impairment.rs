@@ -0,0 +1,288 @@
+use std::collections::HashSet;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+/// Synthetic network conditions applied to the messages written by a node, for evaluating the protocol
+/// in-process without network emulation tools, see [crate::Config::with_impairment].
+/// Clones share the same random generator, so that a seeded experiment is reproducible.
+#[derive(Clone, Debug)]
+pub struct Impairment {
+    /// Delay added to every message
+    latency: Duration,
+    /// Maximum random delay added to the latency
+    jitter: Duration,
+    /// Probability that a message is lost
+    loss: f64,
+    /// Probability that a message is held back, so that the messages written after it overtake it
+    reordering: f64,
+    /// Source of the random delays and losses
+    rng: Arc<Mutex<StdRng>>,
+    /// Faults changed while the node runs, shared by the clones
+    faults: Arc<Mutex<Faults>>,
+}
+
+/// Faults that can be injected and healed while the node runs
+#[derive(Debug, Default)]
+struct Faults {
+    /// Addresses the node cannot connect to
+    blocked: HashSet<SocketAddr>,
+    /// Probability that a message written on an opened connection is also written on a second connection
+    duplication: f64,
+    /// Probability that a byte of a written message is altered
+    corruption: f64,
+}
+
+impl Default for Impairment {
+    fn default() -> Self {
+        Impairment {
+            latency: Duration::ZERO,
+            jitter: Duration::ZERO,
+            loss: 0.0,
+            reordering: 0.0,
+            rng: Arc::new(Mutex::new(StdRng::from_entropy())),
+            faults: Arc::new(Mutex::new(Faults::default())),
+        }
+    }
+}
+
+impl Impairment {
+    /// Creates conditions without delay nor loss
+    pub fn new() -> Impairment {
+        Impairment::default()
+    }
+
+    /// Delays every message
+    ///
+    /// # Arguments
+    ///
+    /// * `latency` - Delay in milliseconds
+    pub fn with_latency(mut self, latency: u64) -> Impairment {
+        self.latency = Duration::from_millis(latency);
+        self
+    }
+
+    /// Adds a uniformly distributed random delay to the latency of every message
+    ///
+    /// # Arguments
+    ///
+    /// * `jitter` - Maximum random delay in milliseconds
+    pub fn with_jitter(mut self, jitter: u64) -> Impairment {
+        self.jitter = Duration::from_millis(jitter);
+        self
+    }
+
+    /// Loses messages at random. A lost message is not written, the receiver finding
+    /// the connection closed without message.
+    ///
+    /// # Arguments
+    ///
+    /// * `loss` - Probability that a message is lost, between 0 and 1
+    pub fn with_loss(mut self, loss: f64) -> Impairment {
+        self.loss = loss.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Holds messages back at random for twice the maximum delay, so that the messages
+    /// written after them by the other threads of the node arrive first
+    ///
+    /// # Arguments
+    ///
+    /// * `reordering` - Probability that a message is held back, between 0 and 1
+    pub fn with_reordering(mut self, reordering: f64) -> Impairment {
+        self.reordering = reordering.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Draws the delays and losses from a generator seeded with a value, for reproducible experiments
+    ///
+    /// # Arguments
+    ///
+    /// * `seed` - The seed
+    pub fn with_seed(mut self, seed: u64) -> Impairment {
+        self.rng = Arc::new(Mutex::new(StdRng::seed_from_u64(seed)));
+        self
+    }
+
+    /// Duplicates messages at random, see [Impairment::set_duplication]
+    ///
+    /// # Arguments
+    ///
+    /// * `duplication` - Probability that a message is duplicated, between 0 and 1
+    pub fn with_duplication(self, duplication: f64) -> Impairment {
+        self.set_duplication(duplication);
+        self
+    }
+
+    /// Corrupts messages at random, see [Impairment::set_corruption]
+    ///
+    /// # Arguments
+    ///
+    /// * `corruption` - Probability that a message is corrupted, between 0 and 1
+    pub fn with_corruption(self, corruption: f64) -> Impairment {
+        self.set_corruption(corruption);
+        self
+    }
+
+    /// Prevents the node from connecting to an address, as if the link was down, until unblocked.
+    /// Takes effect on the running node, the configuration sharing the faults with this instance.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - Address of the other node
+    pub fn block_link(&self, address: SocketAddr) {
+        self.faults.lock().unwrap().blocked.insert(address);
+    }
+
+    /// Allows the node to connect again to a blocked address
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - Address of the other node
+    pub fn unblock_link(&self, address: SocketAddr) {
+        self.faults.lock().unwrap().blocked.remove(&address);
+    }
+
+    /// Changes the probability that a message written on a connection opened by the node is written again
+    /// on a second connection to the same address. Duplicates are not written on encrypted connections.
+    ///
+    /// # Arguments
+    ///
+    /// * `duplication` - Probability that a message is duplicated, between 0 and 1
+    pub fn set_duplication(&self, duplication: f64) {
+        self.faults.lock().unwrap().duplication = duplication.clamp(0.0, 1.0);
+    }
+
+    /// Changes the probability that a byte of a written message is altered,
+    /// before its encryption on encrypted connections
+    ///
+    /// # Arguments
+    ///
+    /// * `corruption` - Probability that a message is corrupted, between 0 and 1
+    pub fn set_corruption(&self, corruption: f64) {
+        self.faults.lock().unwrap().corruption = corruption.clamp(0.0, 1.0);
+    }
+
+    /// Unblocks all the links and stops duplicating and corrupting messages
+    pub fn heal(&self) {
+        *self.faults.lock().unwrap() = Faults::default();
+    }
+
+    pub fn latency(&self) -> Duration {
+        self.latency
+    }
+
+    pub fn jitter(&self) -> Duration {
+        self.jitter
+    }
+
+    pub fn loss(&self) -> f64 {
+        self.loss
+    }
+
+    pub fn reordering(&self) -> f64 {
+        self.reordering
+    }
+
+    pub fn duplication(&self) -> f64 {
+        self.faults.lock().unwrap().duplication
+    }
+
+    pub fn corruption(&self) -> f64 {
+        self.faults.lock().unwrap().corruption
+    }
+
+    pub fn blocked_links(&self) -> Vec<SocketAddr> {
+        self.faults.lock().unwrap().blocked.iter().copied().collect()
+    }
+
+    /// Returns true if the node cannot connect to an address
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - Address of the other node
+    pub(crate) fn is_blocked(&self, address: &SocketAddr) -> bool {
+        self.faults.lock().unwrap().blocked.contains(address)
+    }
+
+    /// Returns true if a message is duplicated
+    pub(crate) fn duplicates(&self) -> bool {
+        let duplication = self.faults.lock().unwrap().duplication;
+        duplication > 0.0 && self.rng.lock().unwrap().gen_bool(duplication)
+    }
+
+    /// Alters a random byte of a serialized message if it is corrupted, returning true if it was
+    ///
+    /// # Arguments
+    ///
+    /// * `bytes` - The serialized message
+    pub(crate) fn corrupt(&self, bytes: &mut [u8]) -> bool {
+        let corruption = self.faults.lock().unwrap().corruption;
+        let mut rng = self.rng.lock().unwrap();
+        if bytes.is_empty() || corruption == 0.0 || !rng.gen_bool(corruption) {
+            return false;
+        }
+        let position = rng.gen_range(0..bytes.len());
+        bytes[position] ^= rng.gen_range(1..=u8::MAX);
+        true
+    }
+
+    /// Returns the delay of a message, or None if the message is lost
+    pub(crate) fn delay(&self) -> Option<Duration> {
+        let mut rng = self.rng.lock().unwrap();
+        if self.loss > 0.0 && rng.gen_bool(self.loss) {
+            return None;
+        }
+        let jitter = if self.jitter.is_zero() { Duration::ZERO } else { self.jitter.mul_f64(rng.gen::<f64>()) };
+        let held_back = if self.reordering > 0.0 && rng.gen_bool(self.reordering) {
+            // at least a millisecond, for reordering without latency
+            ((self.latency + self.jitter) * 2).max(Duration::from_millis(1))
+        }
+        else {
+            Duration::ZERO
+        };
+        Some(self.latency + jitter + held_back)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delays_and_loses_messages() {
+        let impairment = Impairment::new().with_latency(10).with_jitter(5).with_loss(0.25).with_reordering(0.1).with_seed(7);
+        let delays = (0..1000).map(|_| impairment.delay()).collect::<Vec<Option<Duration>>>();
+        let lost = delays.iter().filter(|delay| delay.is_none()).count();
+        assert!((200..300).contains(&lost));
+        let held_back = delays.iter().flatten().filter(|delay| **delay >= Duration::from_millis(30)).count();
+        assert!((40..120).contains(&held_back));
+        assert!(delays.iter().flatten().all(|delay| *delay >= Duration::from_millis(10) && *delay <= Duration::from_millis(45)));
+
+        // the same seed gives the same conditions
+        let replayed = Impairment::new().with_latency(10).with_jitter(5).with_loss(0.25).with_reordering(0.1).with_seed(7);
+        assert_eq!((0..1000).map(|_| replayed.delay()).collect::<Vec<Option<Duration>>>(), delays);
+        assert_eq!(Impairment::new().delay(), Some(Duration::ZERO));
+    }
+
+    #[test]
+    fn injects_and_heals_faults() {
+        let impairment = Impairment::new().with_duplication(1.0).with_corruption(1.0).with_seed(3);
+        let address = "127.0.0.1:9000".parse().unwrap();
+        // the faults are shared by the clone given to the node
+        impairment.clone().block_link(address);
+        assert!(impairment.is_blocked(&address));
+        assert!(impairment.duplicates());
+        let mut bytes = vec![0u8; 16];
+        assert!(impairment.corrupt(&mut bytes));
+        assert_eq!(bytes.iter().filter(|byte| **byte != 0).count(), 1);
+
+        impairment.heal();
+        assert!(impairment.blocked_links().is_empty());
+        assert!(!impairment.duplicates());
+        assert!(!impairment.corrupt(&mut bytes));
+    }
+}
@@ -0,0 +1,203 @@
+use std::convert::TryFrom;
+use std::error::Error;
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use rustls_pki_types::pem::PemObject;
+use rustls_pki_types::CertificateDer;
+
+use crate::bootstrap::Bootstrap;
+use crate::peer::Peer;
+
+// Address of the API server from within a pod
+const IN_CLUSTER_API_URL: &str = "https://kubernetes.default.svc";
+// Directory where the credentials of the service account are mounted in a pod
+const SERVICE_ACCOUNT_DIRECTORY: &str = "/var/run/secrets/kubernetes.io/serviceaccount";
+// Deadline of the requests to the API server
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Contact peers listed by the endpoints API of Kubernetes, the ready pods of a service,
+/// such as a headless service selecting the pods of the overlay.
+///
+/// From within a pod, the API server is reached with the token and the certificate authority
+/// of the service account of the pod, which must be allowed to get the endpoints of the service.
+#[derive(Clone)]
+pub struct KubernetesBootstrap {
+    /// Namespace of the service
+    namespace: String,
+    /// Name of the service
+    service: String,
+    /// Name of the port of the service on which the nodes listen, the first port if none
+    port_name: Option<String>,
+    /// URL of the API server, such as `https://kubernetes.default.svc`
+    api_url: String,
+    /// Bearer token authenticating the requests, read from the service account if none
+    token: Option<String>,
+    /// Certificate authority of the API server in PEM format, the public authorities being trusted if none
+    ca_certificate: Option<PathBuf>,
+}
+
+impl KubernetesBootstrap {
+    /// Creates a source listing the endpoints of a service from within a pod of the cluster,
+    /// with the credentials of the service account of the pod
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace` - Namespace of the service
+    /// * `service` - Name of the service
+    pub fn new(namespace: String, service: String) -> KubernetesBootstrap {
+        KubernetesBootstrap {
+            namespace,
+            service,
+            port_name: None,
+            api_url: IN_CLUSTER_API_URL.to_owned(),
+            token: None,
+            ca_certificate: Some(PathBuf::from(SERVICE_ACCOUNT_DIRECTORY).join("ca.crt")),
+        }
+    }
+
+    /// Selects the port of the service on which the nodes listen, when the service has several ports
+    ///
+    /// # Arguments
+    ///
+    /// * `port_name` - Name of the port in the service
+    pub fn with_port_name(mut self, port_name: String) -> KubernetesBootstrap {
+        self.port_name = Some(port_name);
+        self
+    }
+
+    /// Sets the API server and the credentials, such as for running outside of the cluster
+    ///
+    /// # Arguments
+    ///
+    /// * `api_url` - URL of the API server, `http://` URLs such as that of `kubectl proxy` being accepted
+    /// * `token` - Bearer token authenticating the requests, if any
+    /// * `ca_certificate` - Certificate authority of the API server in PEM format, the public authorities being trusted if none
+    pub fn with_api_server(mut self, api_url: String, token: Option<String>, ca_certificate: Option<PathBuf>) -> KubernetesBootstrap {
+        self.api_url = api_url;
+        self.token = token;
+        self.ca_certificate = ca_certificate;
+        self
+    }
+
+    /// Returns the token of the requests, that of the service account if none was set
+    fn token(&self) -> Option<String> {
+        match &self.token {
+            Some(token) => Some(token.clone()),
+            None if self.api_url == IN_CLUSTER_API_URL => std::fs::read_to_string(PathBuf::from(SERVICE_ACCOUNT_DIRECTORY).join("token"))
+                .ok()
+                .map(|token| token.trim().to_owned()),
+            None => None,
+        }
+    }
+
+    fn fetch(&self) -> Result<Vec<Peer>, Box<dyn Error>> {
+        let mut agent = ureq::AgentBuilder::new().timeout(REQUEST_TIMEOUT);
+        if let Some(path) = &self.ca_certificate {
+            let mut roots = rustls::RootCertStore::empty();
+            for certificate in CertificateDer::pem_file_iter(path)? {
+                roots.add(certificate?)?;
+            }
+            let tls_config = rustls::ClientConfig::builder()
+                .with_root_certificates(roots)
+                .with_no_client_auth();
+            agent = agent.tls_config(Arc::new(tls_config));
+        }
+        let url = format!("{}/api/v1/namespaces/{}/endpoints/{}", self.api_url.trim_end_matches('/'), self.namespace, self.service);
+        let mut request = agent.build().get(&url).set("Accept", "application/json");
+        if let Some(token) = self.token() {
+            request = request.set("Authorization", &format!("Bearer {}", token));
+        }
+        let endpoints: serde_json::Value = serde_json::from_str(&request.call()?.into_string()?)?;
+        endpoint_addresses(&endpoints, self.port_name.as_deref())
+    }
+}
+
+impl Bootstrap for KubernetesBootstrap {
+    fn peers(&mut self) -> Option<Vec<Peer>> {
+        self.fetch().ok().filter(|peers| !peers.is_empty())
+    }
+}
+
+/// Returns the addresses of the ready pods of an endpoints object, on the selected port
+///
+/// # Arguments
+///
+/// * `endpoints` - The endpoints object returned by the API server
+/// * `port_name` - Name of the selected port, the first port of each subset if none
+fn endpoint_addresses(endpoints: &serde_json::Value, port_name: Option<&str>) -> Result<Vec<Peer>, Box<dyn Error>> {
+    let mut peers = vec![];
+    // pods that are not ready are listed separately, under notReadyAddresses
+    for subset in endpoints["subsets"].as_array().map(Vec::as_slice).unwrap_or_default() {
+        let ports = subset["ports"].as_array().map(Vec::as_slice).unwrap_or_default();
+        let port = match port_name {
+            Some(name) => ports.iter().find(|port| port["name"].as_str() == Some(name)),
+            None => ports.first(),
+        };
+        let port = match port.and_then(|port| port["port"].as_u64()) {
+            Some(port) => u16::try_from(port)?,
+            None => continue,
+        };
+        for address in subset["addresses"].as_array().map(Vec::as_slice).unwrap_or_default() {
+            let ip: IpAddr = address["ip"].as_str().ok_or("endpoint address without IP")?.parse()?;
+            peers.push(Peer::new(SocketAddr::new(ip, port).to_string()));
+        }
+    }
+    Ok(peers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lists_the_ready_addresses_on_the_selected_port() {
+        let endpoints = serde_json::json!({
+            "kind": "Endpoints",
+            "subsets": [
+                {
+                    "addresses": [{ "ip": "10.1.0.4" }, { "ip": "fd00::5" }],
+                    "notReadyAddresses": [{ "ip": "10.1.0.6" }],
+                    "ports": [{ "name": "metrics", "port": 8080 }, { "name": "gossip", "port": 9000 }]
+                },
+                { "addresses": [{ "ip": "10.1.0.7" }] }
+            ]
+        });
+        let peers = endpoint_addresses(&endpoints, Some("gossip")).unwrap();
+        assert_eq!(peers, vec![Peer::new("10.1.0.4:9000".to_owned()), Peer::new("[fd00::5]:9000".to_owned())]);
+        let peers = endpoint_addresses(&endpoints, None).unwrap();
+        assert_eq!(peers[0], Peer::new("10.1.0.4:8080".to_owned()));
+        // a service without pods has no subsets
+        assert!(endpoint_addresses(&serde_json::json!({ "kind": "Endpoints" }), None).unwrap().is_empty());
+    }
+
+    #[test]
+    fn queries_the_endpoints_of_the_service() {
+        use std::io::{Read, Write};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let api_url = format!("http://{}", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = vec![];
+            let mut buffer = [0u8; 1024];
+            // the request has no body
+            while !request.ends_with(b"\r\n\r\n") {
+                let length = stream.read(&mut buffer).unwrap();
+                assert!(length > 0);
+                request.extend_from_slice(&buffer[..length]);
+            }
+            let request = String::from_utf8(request).unwrap();
+            assert!(request.starts_with("GET /api/v1/namespaces/overlay/endpoints/gossip HTTP/1.1\r\n"), "{}", request);
+            assert!(request.to_lowercase().contains("authorization: bearer secret\r\n"), "{}", request);
+            let body = r#"{"subsets":[{"addresses":[{"ip":"10.1.0.4"}],"ports":[{"port":9000}]}]}"#;
+            write!(stream, "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}", body.len(), body).unwrap();
+        });
+        let mut bootstrap = KubernetesBootstrap::new("overlay".to_owned(), "gossip".to_owned())
+            .with_api_server(api_url, Some("secret".to_owned()), None);
+        assert_eq!(bootstrap.peers().unwrap(), vec![Peer::new("10.1.0.4:9000".to_owned())]);
+        server.join().unwrap();
+    }
+}
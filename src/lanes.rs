@@ -0,0 +1,150 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::message::{Message, MessageType};
+
+/// Lane of a received message, the messages of the control lane being handled before the others
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Lane {
+    /// Liveness and membership messages, small and quickly handled
+    Control,
+    /// Exchanges of views, and the messages relayed for other nodes
+    Exchange,
+}
+
+impl Lane {
+    /// Returns the lane of a message
+    ///
+    /// # Arguments
+    ///
+    /// * `message` - The received message
+    pub(crate) fn of(message: &Message) -> Lane {
+        match message.message_type() {
+            MessageType::Ping | MessageType::Pong | MessageType::Leave | MessageType::Busy | MessageType::Poll => Lane::Control,
+            MessageType::Request | MessageType::Response | MessageType::Relay => Lane::Exchange,
+        }
+    }
+}
+
+/// Queues of the channel, with the time each item was sent, and whether its ends are still connected
+struct Queues<T> {
+    control: VecDeque<(T, Instant)>,
+    exchange: VecDeque<(T, Instant)>,
+    /// Number of senders not dropped
+    senders: usize,
+    /// Whether the receiver was dropped
+    disconnected: bool,
+}
+
+/// State shared by the ends of the channel
+struct Shared<T> {
+    queues: Mutex<Queues<T>>,
+    /// Notified when an item is sent or when the last sender is dropped
+    available: Condvar,
+}
+
+/// Sending end of a channel with two lanes, see [channel]
+pub(crate) struct LaneSender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+/// Receiving end of a channel with two lanes, see [channel]
+pub(crate) struct LaneReceiver<T> {
+    shared: Arc<Shared<T>>,
+}
+
+/// Creates a channel like [std::sync::mpsc::channel], whose receiver returns the items sent
+/// on the control lane before those of the exchange lane, each lane being in order, with the
+/// time they waited in the channel
+pub(crate) fn channel<T>() -> (LaneSender<T>, LaneReceiver<T>) {
+    let shared = Arc::new(Shared {
+        queues: Mutex::new(Queues { control: VecDeque::new(), exchange: VecDeque::new(), senders: 1, disconnected: false }),
+        available: Condvar::new(),
+    });
+    (LaneSender { shared: shared.clone() }, LaneReceiver { shared })
+}
+
+impl<T> LaneSender<T> {
+    /// Sends an item on a lane, returning it if the receiver was dropped
+    ///
+    /// # Arguments
+    ///
+    /// * `lane` - The lane
+    /// * `item` - The item
+    pub(crate) fn send(&self, lane: Lane, item: T) -> Result<(), T> {
+        let mut queues = self.shared.queues.lock().unwrap();
+        if queues.disconnected {
+            return Err(item);
+        }
+        match lane {
+            Lane::Control => queues.control.push_back((item, Instant::now())),
+            Lane::Exchange => queues.exchange.push_back((item, Instant::now())),
+        }
+        self.shared.available.notify_one();
+        Ok(())
+    }
+}
+
+impl<T> Clone for LaneSender<T> {
+    fn clone(&self) -> Self {
+        self.shared.queues.lock().unwrap().senders += 1;
+        LaneSender { shared: self.shared.clone() }
+    }
+}
+
+impl<T> Drop for LaneSender<T> {
+    fn drop(&mut self) {
+        let mut queues = self.shared.queues.lock().unwrap();
+        queues.senders -= 1;
+        if queues.senders == 0 {
+            self.shared.available.notify_all();
+        }
+    }
+}
+
+impl<T> LaneReceiver<T> {
+    /// Waits for an item, the control lane first, and returns it with the time it waited in the channel,
+    /// or None once all the senders were dropped and the lanes are empty
+    pub(crate) fn recv(&self) -> Option<(T, Duration)> {
+        let mut queues = self.shared.queues.lock().unwrap();
+        loop {
+            if let Some((item, sent)) = queues.control.pop_front().or_else(|| queues.exchange.pop_front()) {
+                return Some((item, sent.elapsed()));
+            }
+            if queues.senders == 0 {
+                return None;
+            }
+            queues = self.shared.available.wait(queues).unwrap();
+        }
+    }
+}
+
+impl<T> Drop for LaneReceiver<T> {
+    fn drop(&mut self) {
+        self.shared.queues.lock().unwrap().disconnected = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn receives_control_lane_first() {
+        assert_eq!(Lane::of(&Message::new_ping("127.0.0.1:9000".to_owned())), Lane::Control);
+        assert_eq!(Lane::of(&Message::new_request("127.0.0.1:9000".to_owned(), None)), Lane::Exchange);
+
+        let (sender, receiver) = channel();
+        sender.send(Lane::Exchange, 1).unwrap();
+        sender.send(Lane::Exchange, 2).unwrap();
+        sender.clone().send(Lane::Control, 3).unwrap();
+        sender.send(Lane::Control, 4).unwrap();
+        drop(sender);
+        assert_eq!(std::iter::from_fn(|| receiver.recv().map(|(item, _)| item)).collect::<Vec<i32>>(), vec![3, 4, 1, 2]);
+
+        let (sender, receiver) = channel();
+        drop(receiver);
+        assert_eq!(sender.send(Lane::Control, 1), Err(1));
+    }
+}
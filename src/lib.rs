@@ -1,12 +1,194 @@
+// the view selection only depends on core and alloc, see the selection module,
+// and everything else on the standard library
+#![cfg_attr(not(feature = "std"), no_std)]
+extern crate alloc;
+
+#[cfg(feature = "std")]
+mod audit;
+#[cfg(feature = "std")]
+mod backoff;
+#[cfg(feature = "std")]
+mod bootstrap;
+#[cfg(feature = "std")]
+mod breaker;
+#[cfg(feature = "chaos")]
+mod chaos;
+#[cfg(feature = "std")]
+mod cluster;
+#[cfg(feature = "std")]
 mod config;
+#[cfg(feature = "std")]
+mod crawler;
+#[cfg(feature = "debug")]
+pub mod debug;
+#[cfg(feature = "ec2-bootstrap")]
+mod ec2;
+#[cfg(feature = "std")]
+mod event;
+#[cfg(feature = "event-loop")]
+mod event_loop;
+#[cfg(feature = "std")]
+mod handle;
+#[cfg(feature = "std")]
+mod heartbeat;
+#[cfg(feature = "impairment")]
+mod impairment;
+#[cfg(feature = "kubernetes-bootstrap")]
+mod kubernetes;
+#[cfg(feature = "std")]
+mod lanes;
+#[cfg(feature = "std")]
+mod limit;
+#[cfg(feature = "std")]
+mod load;
+#[cfg(feature = "port-mapping")]
+mod mapping;
+#[cfg(feature = "terminal-logger")]
 mod log;
+#[cfg(feature = "std")]
 mod monitor;
+#[cfg(feature = "std")]
 mod message;
+#[cfg(feature = "std")]
 mod network;
+#[cfg(feature = "noise")]
+mod noise;
+#[cfg(feature = "std")]
+mod overlay;
+#[cfg(feature = "std")]
+mod parameters;
+#[cfg(feature = "std")]
+mod partition;
+#[cfg(feature = "std")]
 mod peer;
+#[cfg(feature = "std")]
+mod pending;
+#[cfg(feature = "std")]
+mod piggyback;
+#[cfg(feature = "std")]
+mod policy;
+#[cfg(feature = "std")]
+mod pow;
+#[cfg(feature = "std")]
+mod quarantine;
+#[cfg(feature = "std")]
+mod quota;
+#[cfg(feature = "std")]
+mod recent;
+#[cfg(feature = "std")]
+mod recorder;
+#[cfg(feature = "std")]
+mod relay;
+#[cfg(feature = "std")]
+mod replay;
+#[cfg(feature = "monitoring")]
+mod report;
+#[cfg(feature = "std")]
+mod sampler;
+#[cfg(feature = "std")]
+mod schedule;
+#[cfg(feature = "std")]
+mod seen;
+#[cfg(feature = "std")]
+mod snapshot;
+#[cfg(feature = "std")]
+mod socket;
+#[cfg(feature = "std")]
+mod state;
+#[cfg(feature = "std")]
+mod stream;
+#[cfg(feature = "std")]
+mod stun;
+#[cfg(feature = "std")]
+mod score;
+pub mod selection;
+#[cfg(feature = "std")]
+mod size;
+#[cfg(feature = "std")]
+mod stats;
+#[cfg(feature = "std")]
+mod supervisor;
+#[cfg(feature = "std")]
+mod thread;
+#[cfg(feature = "std")]
+mod uniformity;
+#[cfg(feature = "std")]
+mod view;
 
-pub use crate::config::Config;
+#[cfg(feature = "std")]
+pub use crate::audit::{Rejection, RejectionReason};
+#[cfg(feature = "std")]
+pub use crate::bootstrap::{Bootstrap, BootstrapProvider, DnsBootstrap, FileBootstrap};
+#[cfg(feature = "http-bootstrap")]
+pub use crate::bootstrap::HttpBootstrap;
+#[cfg(feature = "chaos")]
+pub use crate::chaos::{Chaos, Fault};
+#[cfg(feature = "ec2-bootstrap")]
+pub use crate::ec2::Ec2Bootstrap;
+#[cfg(feature = "std")]
+pub use crate::cluster::Cluster;
+#[cfg(feature = "std")]
+pub use crate::config::{Config, ConfigError, ConfigWarning};
+#[cfg(feature = "std")]
+pub use crate::crawler::Crawler;
+#[cfg(feature = "std")]
+pub use crate::event::Event;
+#[cfg(feature = "std")]
+pub use crate::handle::SamplingHandle;
+#[cfg(feature = "impairment")]
+pub use crate::impairment::Impairment;
+#[cfg(feature = "kubernetes-bootstrap")]
+pub use crate::kubernetes::KubernetesBootstrap;
+#[cfg(feature = "port-mapping")]
+pub use crate::mapping::{MappingProtocol, PortMappingConfig};
+#[cfg(feature = "terminal-logger")]
 pub use crate::log::terminal_logger;
+#[cfg(feature = "std")]
+pub use crate::message::{Message, MessageType};
+#[cfg(feature = "std")]
 pub use crate::monitor::MonitoringConfig;
-pub use crate::peer::Peer;
+#[cfg(feature = "std")]
+pub use crate::network::BindError;
+#[cfg(feature = "noise")]
+pub use crate::noise::NoiseConfig;
+#[cfg(feature = "std")]
+pub use crate::overlay::Overlay;
+#[cfg(feature = "std")]
+pub use crate::parameters::ProtocolParameters;
+#[cfg(feature = "std")]
+pub use crate::peer::{Peer, MAX_LOCALITY_SIZE};
+#[cfg(feature = "std")]
 pub use crate::peer::PeerSamplingService;
+#[cfg(feature = "std")]
+pub use crate::piggyback::Piggyback;
+#[cfg(feature = "std")]
+pub use crate::policy::{SampleSource, SamplingPolicy, SelfAgePolicy};
+#[cfg(feature = "std")]
+pub use crate::quota::PrefixQuota;
+#[cfg(feature = "std")]
+pub use crate::recorder::{RecordFormat, RecordingConfig};
+#[cfg(feature = "monitoring")]
+pub use crate::report::{MonitoringCollector, MonitoringReport, ReportedRejection, REPORT_VERSION};
+#[cfg(feature = "std")]
+pub use crate::sampler::{MockPeerSampler, PeerSampler};
+#[cfg(feature = "std")]
+pub use crate::schedule::CycleSchedule;
+pub use crate::selection::Descriptor;
+#[cfg(feature = "std")]
+pub use crate::socket::SocketConfig;
+#[cfg(feature = "std")]
+pub use crate::state::ServiceState;
+#[cfg(feature = "std")]
+pub use crate::stream::PeerIter;
+#[cfg(feature = "stream")]
+pub use crate::stream::PeerStream;
+#[cfg(feature = "std")]
+pub use crate::score::PeerScore;
+#[cfg(feature = "std")]
+pub use crate::stats::{Histogram, Stats};
+#[cfg(feature = "std")]
+pub use crate::supervisor::{InitError, ServiceHandle, TaskExit};
+#[cfg(feature = "std")]
+pub use crate::thread::{Executor, ThreadConfig};
+#[cfg(feature = "std")]
+pub use crate::view::View;
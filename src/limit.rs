@@ -0,0 +1,92 @@
+use std::error::Error;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+/// Limits the number of outbound connections open at the same time, see [crate::Config::with_max_outbound_connections].
+/// Clones share the same connections.
+#[derive(Clone, Debug)]
+pub(crate) struct ConnectionLimit {
+    /// Maximum number of connections
+    max: usize,
+    /// Number of open connections, and the condition notified when one is closed
+    open: Arc<(Mutex<usize>, Condvar)>,
+}
+
+/// An open outbound connection, counted until dropped
+#[derive(Debug)]
+pub(crate) struct ConnectionPermit {
+    /// Number of open connections, and the condition notified when one is closed
+    open: Arc<(Mutex<usize>, Condvar)>,
+}
+
+impl ConnectionLimit {
+    /// Creates a limit
+    ///
+    /// # Arguments
+    ///
+    /// * `max` - Maximum number of connections, at least 1
+    pub(crate) fn new(max: usize) -> ConnectionLimit {
+        ConnectionLimit {
+            max: max.max(1),
+            open: Arc::new((Mutex::new(0), Condvar::new())),
+        }
+    }
+
+    pub(crate) fn max(&self) -> usize {
+        self.max
+    }
+
+    /// Waits until a connection can be opened, failing after the timeout if any
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout` - Maximum waiting time, none to wait until a connection is closed
+    pub(crate) fn acquire(&self, timeout: Option<Duration>) -> Result<ConnectionPermit, Box<dyn Error>> {
+        let (open, closed) = &*self.open;
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        let mut open = open.lock().unwrap();
+        while *open >= self.max {
+            open = match deadline {
+                Some(deadline) => {
+                    let remaining = deadline.saturating_duration_since(Instant::now());
+                    if remaining.is_zero() {
+                        Err(format!("all {} outbound connections are in use", self.max))?
+                    }
+                    closed.wait_timeout(open, remaining).unwrap().0
+                }
+                None => closed.wait(open).unwrap(),
+            };
+        }
+        *open += 1;
+        Ok(ConnectionPermit { open: self.open.clone() })
+    }
+}
+
+impl Drop for ConnectionPermit {
+    fn drop(&mut self) {
+        let (open, closed) = &*self.open;
+        *open.lock().unwrap() -= 1;
+        closed.notify_one();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn waits_for_a_closed_connection() {
+        let limit = ConnectionLimit::new(2);
+        let first = limit.acquire(None).unwrap();
+        let _second = limit.clone().acquire(None).unwrap();
+        assert!(limit.acquire(Some(Duration::from_millis(10))).is_err());
+
+        let waiting = {
+            let limit = limit.clone();
+            std::thread::spawn(move || limit.acquire(Some(Duration::from_secs(5))).is_ok())
+        };
+        std::thread::sleep(Duration::from_millis(50));
+        drop(first);
+        assert!(waiting.join().unwrap());
+    }
+}
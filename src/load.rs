@@ -0,0 +1,58 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Counts the requests received over a sliding time window
+pub struct RequestLoad {
+    /// Duration of the window
+    window: Duration,
+    /// Arrival times of the requests within the window
+    arrivals: VecDeque<Instant>,
+}
+
+impl RequestLoad {
+    /// Creates a new counter
+    ///
+    /// # Arguments
+    ///
+    /// * `window` - Duration over which requests are counted
+    pub fn new(window: Duration) -> RequestLoad {
+        RequestLoad {
+            window,
+            arrivals: VecDeque::new(),
+        }
+    }
+
+    /// Records a new request and returns the number of requests in the window, including this one
+    pub fn record(&mut self) -> usize {
+        self.record_at(Instant::now())
+    }
+
+    pub(crate) fn record_at(&mut self, now: Instant) -> usize {
+        while let Some(arrival) = self.arrivals.front() {
+            if now.duration_since(*arrival) > self.window {
+                self.arrivals.pop_front();
+            }
+            else {
+                break;
+            }
+        }
+        self.arrivals.push_back(now);
+        self.arrivals.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_requests_within_window() {
+        let start = Instant::now();
+        let mut load = RequestLoad::new(Duration::from_secs(5));
+        assert_eq!(load.record_at(start), 1);
+        assert_eq!(load.record_at(start + Duration::from_secs(2)), 2);
+        assert_eq!(load.record_at(start + Duration::from_secs(4)), 3);
+        assert_eq!(load.record_at(start + Duration::from_secs(6)), 3);
+        assert_eq!(load.record_at(start + Duration::from_secs(20)), 1);
+    }
+}
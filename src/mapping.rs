@@ -0,0 +1,508 @@
+use std::error::Error;
+use std::io::{Read, Write};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, SocketAddrV4, TcpStream, UdpSocket};
+use std::time::{Duration, Instant};
+
+/// Port of the NAT-PMP service of the gateway
+const NAT_PMP_PORT: u16 = 5351;
+/// Multicast address of the SSDP discovery of UPnP devices
+const SSDP_ADDRESS: &str = "239.255.255.250:1900";
+
+/// Protocol used for requesting a port mapping from the gateway
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MappingProtocol {
+    /// NAT Port Mapping Protocol (RFC 6886), also answered by the PCP servers of recent gateways
+    NatPmp,
+    /// Internet Gateway Device of UPnP, discovered on the local network
+    Upnp,
+}
+
+/// Options of the port mapping requested from the gateway of the local network when the
+/// service starts, see [crate::Config::with_port_mapping]
+#[derive(Clone, Debug)]
+pub struct PortMappingConfig {
+    /// Address of the gateway, none for the default gateway of the system for NAT-PMP,
+    /// and for the device found by discovery for UPnP
+    gateway: Option<Ipv4Addr>,
+    /// Protocols tried in order until one maps the port
+    protocols: Vec<MappingProtocol>,
+    /// Lifetime in seconds requested for the mapping, renewed at half its lifetime
+    lifetime: u32,
+    /// Time in milliseconds waited for the gateway by each protocol
+    timeout: u64,
+}
+
+impl Default for PortMappingConfig {
+    fn default() -> PortMappingConfig {
+        PortMappingConfig {
+            gateway: None,
+            protocols: vec![MappingProtocol::NatPmp, MappingProtocol::Upnp],
+            lifetime: 3600,
+            timeout: 2000,
+        }
+    }
+}
+
+impl PortMappingConfig {
+    /// Creates a configuration trying NAT-PMP then UPnP, with a lifetime of one hour
+    pub fn new() -> PortMappingConfig {
+        PortMappingConfig::default()
+    }
+
+    /// Sets the address of the gateway instead of the default gateway of the system
+    ///
+    /// # Arguments
+    ///
+    /// * `gateway` - Address of the gateway
+    pub fn with_gateway(mut self, gateway: Ipv4Addr) -> PortMappingConfig {
+        self.gateway = Some(gateway);
+        self
+    }
+
+    /// Sets the protocols tried in order until one maps the port
+    ///
+    /// # Arguments
+    ///
+    /// * `protocols` - The protocols
+    pub fn with_protocols(mut self, protocols: Vec<MappingProtocol>) -> PortMappingConfig {
+        self.protocols = protocols;
+        self
+    }
+
+    /// Sets the lifetime requested for the mapping, which is renewed at half its lifetime
+    ///
+    /// # Arguments
+    ///
+    /// * `lifetime` - Lifetime in seconds
+    pub fn with_lifetime(mut self, lifetime: u32) -> PortMappingConfig {
+        self.lifetime = lifetime.max(1);
+        self
+    }
+
+    /// Sets the time waited for the gateway by each protocol
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout` - Time in milliseconds
+    pub fn with_timeout(mut self, timeout: u64) -> PortMappingConfig {
+        self.timeout = timeout.max(1);
+        self
+    }
+
+    pub fn gateway(&self) -> Option<Ipv4Addr> {
+        self.gateway
+    }
+
+    pub fn protocols(&self) -> &[MappingProtocol] {
+        &self.protocols
+    }
+
+    pub fn lifetime(&self) -> u32 {
+        self.lifetime
+    }
+
+    pub fn timeout(&self) -> u64 {
+        self.timeout
+    }
+}
+
+/// The gateway that mapped the port, and how to reach it again
+#[derive(Clone, Debug)]
+enum Gateway {
+    NatPmp(SocketAddrV4),
+    Upnp {
+        /// Address of the HTTP server of the device
+        host: SocketAddr,
+        /// Path of the control URL of the connection service
+        control: String,
+        /// Type of the connection service
+        service: String,
+        /// Address of the node on the local network
+        client: Ipv4Addr,
+    },
+}
+
+/// A port mapping obtained from the gateway of the local network
+#[derive(Clone, Debug)]
+pub(crate) struct PortMapping {
+    gateway: Gateway,
+    /// Port of the node on the local network
+    internal_port: u16,
+    /// Address at which the gateway forwards connections to the node
+    external: SocketAddr,
+    /// Lifetime granted by the gateway
+    lifetime: Duration,
+    /// Time out for the requests to the gateway
+    timeout: Duration,
+}
+
+impl PortMapping {
+    /// Requests a mapping of the port of a TCP address with each protocol in turn,
+    /// and returns the first mapping obtained or the failures of all the protocols
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - Options of the mapping
+    /// * `internal` - Bound address of the node
+    pub(crate) fn request(config: &PortMappingConfig, internal: &SocketAddr) -> Result<PortMapping, Box<dyn Error>> {
+        if !internal.is_ipv4() || internal.ip().is_loopback() {
+            Err(format!("{} cannot be reached through a gateway", internal))?
+        }
+        let timeout = Duration::from_millis(config.timeout());
+        let mut failures = vec![];
+        for protocol in config.protocols() {
+            let mapping = match protocol {
+                MappingProtocol::NatPmp => match config.gateway().or_else(default_gateway) {
+                    Some(gateway) => PortMapping::nat_pmp(SocketAddrV4::new(gateway, NAT_PMP_PORT), internal.port(), config.lifetime(), timeout),
+                    None => Err("no default gateway".into()),
+                },
+                MappingProtocol::Upnp => PortMapping::upnp(config.gateway(), internal.port(), config.lifetime(), timeout),
+            };
+            match mapping {
+                Ok(mapping) => return Ok(mapping),
+                Err(e) => failures.push(format!("{:?}: {}", protocol, e)),
+            }
+        }
+        Err(format!("no port mapping obtained ({})", failures.join(", ")))?
+    }
+
+    /// Returns the address at which the gateway forwards connections to the node
+    pub(crate) fn external(&self) -> SocketAddr {
+        self.external
+    }
+
+    /// Returns the lifetime granted by the gateway
+    pub(crate) fn lifetime(&self) -> Duration {
+        self.lifetime
+    }
+
+    /// Requests the same mapping again before it expires, and returns the external address, which may have changed
+    pub(crate) fn renew(&mut self) -> Result<SocketAddr, Box<dyn Error>> {
+        let lifetime = self.lifetime.as_secs().max(1) as u32;
+        let renewed = match &self.gateway {
+            Gateway::NatPmp(gateway) => PortMapping::nat_pmp(*gateway, self.internal_port, lifetime, self.timeout)?,
+            Gateway::Upnp { host, control, service, client } => PortMapping::upnp_at(*host, control, service, *client, self.internal_port, lifetime, self.timeout)?,
+        };
+        *self = renewed;
+        Ok(self.external)
+    }
+
+    /// Removes the mapping from the gateway
+    pub(crate) fn release(&self) -> Result<(), Box<dyn Error>> {
+        match &self.gateway {
+            // a lifetime of 0 deletes the mapping
+            Gateway::NatPmp(gateway) => nat_pmp_call(*gateway, &nat_pmp_mapping_request(self.internal_port, 0, 0), self.timeout).map(|_| ()),
+            Gateway::Upnp { host, control, service, .. } => {
+                let arguments = format!("<NewRemoteHost></NewRemoteHost><NewExternalPort>{}</NewExternalPort><NewProtocol>TCP</NewProtocol>", self.external.port());
+                soap_call(*host, control, service, "DeletePortMapping", &arguments, self.timeout).map(|_| ())
+            }
+        }
+    }
+
+    /// Maps the port with NAT-PMP, requesting the same external port
+    fn nat_pmp(gateway: SocketAddrV4, port: u16, lifetime: u32, timeout: Duration) -> Result<PortMapping, Box<dyn Error>> {
+        let address = parse_nat_pmp_address(&nat_pmp_call(gateway, &[0, 0], timeout)?)?;
+        let (external_port, granted) = parse_nat_pmp_mapping(&nat_pmp_call(gateway, &nat_pmp_mapping_request(port, port, lifetime), timeout)?)?;
+        Ok(PortMapping {
+            gateway: Gateway::NatPmp(gateway),
+            internal_port: port,
+            external: SocketAddr::new(IpAddr::V4(address), external_port),
+            lifetime: Duration::from_secs(granted as u64),
+            timeout,
+        })
+    }
+
+    /// Maps the port with the UPnP Internet Gateway Device found on the local network
+    fn upnp(gateway: Option<Ipv4Addr>, port: u16, lifetime: u32, timeout: Duration) -> Result<PortMapping, Box<dyn Error>> {
+        let deadline = Instant::now() + timeout;
+        let location = ssdp_discover(gateway, timeout)?;
+        let (host, path) = parse_url(&location)?;
+        let remaining = deadline.saturating_duration_since(Instant::now()).max(Duration::from_millis(1));
+        let (_, description) = http_request(host, &format!("GET {} HTTP/1.0\r\nHost: {}\r\n\r\n", path, host), remaining)?;
+        let (service, control) = parse_connection_service(&description).ok_or("no WAN connection service")?;
+        let control = match control.strip_prefix("http://") {
+            Some(_) => parse_url(&control)?.1,
+            None => control,
+        };
+        // the address of the node as seen from the device, without sending anything
+        let socket = UdpSocket::bind("0.0.0.0:0")?;
+        socket.connect(host)?;
+        let client = match socket.local_addr()?.ip() {
+            IpAddr::V4(client) => client,
+            IpAddr::V6(_) => Err("gateway is not reachable over IPv4")?,
+        };
+        PortMapping::upnp_at(host, &control, &service, client, port, lifetime, timeout)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn upnp_at(host: SocketAddr, control: &str, service: &str, client: Ipv4Addr, port: u16, lifetime: u32, timeout: Duration) -> Result<PortMapping, Box<dyn Error>> {
+        let arguments = format!(
+            "<NewRemoteHost></NewRemoteHost><NewExternalPort>{port}</NewExternalPort><NewProtocol>TCP</NewProtocol>\
+             <NewInternalPort>{port}</NewInternalPort><NewInternalClient>{client}</NewInternalClient><NewEnabled>1</NewEnabled>\
+             <NewPortMappingDescription>gbps</NewPortMappingDescription><NewLeaseDuration>{lifetime}</NewLeaseDuration>",
+            port = port, client = client, lifetime = lifetime);
+        soap_call(host, control, service, "AddPortMapping", &arguments, timeout)?;
+        let response = soap_call(host, control, service, "GetExternalIPAddress", "", timeout)?;
+        let address: Ipv4Addr = xml_element(&response, "NewExternalIPAddress").ok_or("no external address")?.trim().parse()?;
+        Ok(PortMapping {
+            gateway: Gateway::Upnp { host, control: control.to_owned(), service: service.to_owned(), client },
+            internal_port: port,
+            external: SocketAddr::new(IpAddr::V4(address), port),
+            lifetime: Duration::from_secs(lifetime as u64),
+            timeout,
+        })
+    }
+}
+
+/// Returns the default gateway of the system, read from the routing table on Linux
+fn default_gateway() -> Option<Ipv4Addr> {
+    std::fs::read_to_string("/proc/net/route").ok().and_then(|routes| parse_default_route(&routes))
+}
+
+/// Returns the gateway of the default route of a Linux routing table, whose addresses are
+/// written as hexadecimal integers in the byte order of the system
+///
+/// # Arguments
+///
+/// * `routes` - Content of `/proc/net/route`
+fn parse_default_route(routes: &str) -> Option<Ipv4Addr> {
+    routes.lines().skip(1)
+        .map(|line| line.split_whitespace().collect::<Vec<&str>>())
+        .find(|fields| fields.len() > 2 && fields[1] == "00000000" && fields[2] != "00000000")
+        .and_then(|fields| u32::from_str_radix(fields[2], 16).ok())
+        .map(|gateway| Ipv4Addr::from(gateway.to_ne_bytes()))
+}
+
+/// Returns a NAT-PMP request mapping a TCP port
+///
+/// # Arguments
+///
+/// * `internal_port` - Port of the node
+/// * `external_port` - Suggested external port
+/// * `lifetime` - Lifetime in seconds, 0 for deleting the mapping
+fn nat_pmp_mapping_request(internal_port: u16, external_port: u16, lifetime: u32) -> [u8; 12] {
+    let mut request = [0u8; 12];
+    // version 0, opcode 2 for TCP
+    request[1] = 2;
+    request[4..6].copy_from_slice(&internal_port.to_be_bytes());
+    request[6..8].copy_from_slice(&external_port.to_be_bytes());
+    request[8..12].copy_from_slice(&lifetime.to_be_bytes());
+    request
+}
+
+/// Checks the header of a NAT-PMP response to a request
+///
+/// # Arguments
+///
+/// * `response` - The response
+/// * `opcode` - Opcode of the request
+/// * `length` - Length of a successful response
+fn check_nat_pmp_response(response: &[u8], opcode: u8, length: usize) -> Result<(), String> {
+    if response.len() < 4 || response[0] != 0 || response[1] != 128 + opcode {
+        return Err("invalid NAT-PMP response".to_owned());
+    }
+    match u16::from_be_bytes([response[2], response[3]]) {
+        0 if response.len() >= length => Ok(()),
+        0 => Err("truncated NAT-PMP response".to_owned()),
+        1 => Err("unsupported NAT-PMP version".to_owned()),
+        2 => Err("port mapping not authorized by the gateway".to_owned()),
+        3 => Err("gateway has no external address".to_owned()),
+        4 => Err("gateway is out of resources".to_owned()),
+        code => Err(format!("NAT-PMP error {}", code)),
+    }
+}
+
+/// Returns the external address of a NAT-PMP response
+fn parse_nat_pmp_address(response: &[u8]) -> Result<Ipv4Addr, String> {
+    check_nat_pmp_response(response, 0, 12)?;
+    Ok(Ipv4Addr::new(response[8], response[9], response[10], response[11]))
+}
+
+/// Returns the external port and the lifetime in seconds of a NAT-PMP response to a mapping request
+fn parse_nat_pmp_mapping(response: &[u8]) -> Result<(u16, u32), String> {
+    check_nat_pmp_response(response, 2, 16)?;
+    Ok((u16::from_be_bytes([response[10], response[11]]), u32::from_be_bytes([response[12], response[13], response[14], response[15]])))
+}
+
+/// Sends a NAT-PMP request to the gateway, retransmitted with a doubling interval as in RFC 6886,
+/// and returns the response
+///
+/// # Arguments
+///
+/// * `gateway` - Address of the NAT-PMP service
+/// * `request` - The request
+/// * `timeout` - Time waited for the response
+fn nat_pmp_call(gateway: SocketAddrV4, request: &[u8], timeout: Duration) -> Result<Vec<u8>, Box<dyn Error>> {
+    let socket = UdpSocket::bind("0.0.0.0:0")?;
+    socket.connect(gateway)?;
+    let deadline = Instant::now() + timeout;
+    let mut interval = Duration::from_millis(250);
+    let mut buffer = [0u8; 16];
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            Err("no answer from the NAT-PMP gateway")?
+        }
+        socket.send(request)?;
+        socket.set_read_timeout(Some(interval.min(remaining)))?;
+        match socket.recv(&mut buffer) {
+            // responses to previous transmissions of another request are ignored
+            Ok(length) if length >= 2 && buffer[1] == 128 + request[1] => return Ok(buffer[..length].to_vec()),
+            Ok(_) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock || e.kind() == std::io::ErrorKind::TimedOut => interval *= 2,
+            Err(e) => Err(e)?,
+        }
+    }
+}
+
+/// Discovers an Internet Gateway Device with SSDP, and returns the location of its description
+///
+/// # Arguments
+///
+/// * `gateway` - Address of the device, the first device answering if none
+/// * `timeout` - Time waited for the answer
+fn ssdp_discover(gateway: Option<Ipv4Addr>, timeout: Duration) -> Result<String, Box<dyn Error>> {
+    let socket = UdpSocket::bind("0.0.0.0:0")?;
+    let search = "M-SEARCH * HTTP/1.1\r\nHOST: 239.255.255.250:1900\r\nST: urn:schemas-upnp-org:device:InternetGatewayDevice:1\r\nMAN: \"ssdp:discover\"\r\nMX: 1\r\n\r\n";
+    socket.send_to(search.as_bytes(), SSDP_ADDRESS)?;
+    let deadline = Instant::now() + timeout;
+    let mut buffer = [0u8; 2048];
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            Err("no UPnP gateway found")?
+        }
+        socket.set_read_timeout(Some(remaining))?;
+        let (length, sender) = match socket.recv_from(&mut buffer) {
+            Ok(received) => received,
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock || e.kind() == std::io::ErrorKind::TimedOut => Err("no UPnP gateway found")?,
+            Err(e) => Err(e)?,
+        };
+        if gateway.is_none_or(|gateway| sender.ip() == IpAddr::V4(gateway)) {
+            if let Some(location) = parse_ssdp_location(&String::from_utf8_lossy(&buffer[..length])) {
+                return Ok(location);
+            }
+        }
+    }
+}
+
+/// Returns the location header of an SSDP answer
+fn parse_ssdp_location(answer: &str) -> Option<String> {
+    answer.lines()
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("location"))
+        .map(|(_, value)| value.trim().to_owned())
+}
+
+/// Returns the address of the host and the path of an `http://` URL
+fn parse_url(url: &str) -> Result<(SocketAddr, String), Box<dyn Error>> {
+    let location = url.strip_prefix("http://").ok_or("only http:// URLs are supported")?;
+    let (host, path) = match location.find('/') {
+        Some(index) => location.split_at(index),
+        None => (location, "/"),
+    };
+    let host = if host.contains(':') { host.parse()? } else { SocketAddr::new(host.parse()?, 80) };
+    Ok((host, path.to_owned()))
+}
+
+/// Returns the type and the control URL of the first WAN connection service of a device description
+fn parse_connection_service(description: &str) -> Option<(String, String)> {
+    description.split("<service>").skip(1)
+        .filter_map(|service| Some((xml_element(service, "serviceType")?, xml_element(service, "controlURL")?)))
+        .find(|(service, _)| service.contains(":WANIPConnection:") || service.contains(":WANPPPConnection:"))
+        .map(|(service, control)| (service.trim().to_owned(), control.trim().to_owned()))
+}
+
+/// Returns the text of the first element of a name, without parsing the document
+fn xml_element<'a>(xml: &'a str, name: &str) -> Option<&'a str> {
+    let start = xml.find(&format!("<{}>", name))? + name.len() + 2;
+    let length = xml[start..].find(&format!("</{}>", name))?;
+    Some(&xml[start..start + length])
+}
+
+/// Invokes an action of the connection service of a UPnP device, and returns the response
+fn soap_call(host: SocketAddr, control: &str, service: &str, action: &str, arguments: &str, timeout: Duration) -> Result<String, Box<dyn Error>> {
+    let body = format!(
+        "<?xml version=\"1.0\"?><s:Envelope xmlns:s=\"http://schemas.xmlsoap.org/soap/envelope/\" s:encodingStyle=\"http://schemas.xmlsoap.org/soap/encoding/\">\
+         <s:Body><u:{action} xmlns:u=\"{service}\">{arguments}</u:{action}></s:Body></s:Envelope>",
+        action = action, service = service, arguments = arguments);
+    let request = format!(
+        "POST {} HTTP/1.0\r\nHost: {}\r\nContent-Type: text/xml; charset=\"utf-8\"\r\nSOAPAction: \"{}#{}\"\r\nContent-Length: {}\r\n\r\n{}",
+        control, host, service, action, body.len(), body);
+    let (status, response) = http_request(host, &request, timeout)?;
+    if status != "200" {
+        let code = xml_element(&response, "errorCode").unwrap_or(&status);
+        Err(format!("{} failed with error {}", action, code))?
+    }
+    Ok(response)
+}
+
+/// Sends an HTTP/1.0 request, and returns the status code and the body of the response
+fn http_request(host: SocketAddr, request: &str, timeout: Duration) -> Result<(String, String), Box<dyn Error>> {
+    let mut stream = TcpStream::connect_timeout(&host, timeout)?;
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+    stream.write_all(request.as_bytes())?;
+    let mut response = vec![];
+    stream.read_to_end(&mut response)?;
+
+    let separator = response.windows(4).position(|bytes| bytes == b"\r\n\r\n").ok_or("invalid HTTP response")?;
+    let status = String::from_utf8_lossy(&response[..separator]).lines().next()
+        .and_then(|line| line.split_whitespace().nth(1).map(|status| status.to_owned()))
+        .ok_or("invalid HTTP response")?;
+    Ok((status, String::from_utf8_lossy(&response[separator + 4..]).into_owned()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_default_gateway() {
+        let routes = "Iface\tDestination\tGateway \tFlags\tRefCnt\tUse\tMetric\tMask\t\tMTU\tWindow\tIRTT\n\
+                      eth0\t0001A8C0\t00000000\t0001\t0\t0\t0\t00FFFFFF\t0\t0\t0\n\
+                      eth0\t00000000\t0101A8C0\t0003\t0\t0\t0\t00000000\t0\t0\t0\n";
+        let expected = Ipv4Addr::from(u32::from_str_radix("0101A8C0", 16).unwrap().to_ne_bytes());
+        assert_eq!(parse_default_route(routes), Some(expected));
+        assert_eq!(parse_default_route(routes.lines().take(2).collect::<Vec<&str>>().join("\n").as_str()), None);
+    }
+
+    #[test]
+    fn nat_pmp_maps_port() {
+        let gateway = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let address = match gateway.local_addr().unwrap() { SocketAddr::V4(address) => address, _ => unreachable!() };
+        let server = std::thread::spawn(move || {
+            let mut request = [0u8; 12];
+            let (_, client) = gateway.recv_from(&mut request).unwrap();
+            assert_eq!(&request[..2], &[0, 0]);
+            gateway.send_to(&[0, 128, 0, 0, 0, 0, 0, 1, 203, 0, 113, 7], client).unwrap();
+            let (length, client) = gateway.recv_from(&mut request).unwrap();
+            assert_eq!(&request[..length], &nat_pmp_mapping_request(9000, 9000, 3600));
+            gateway.send_to(&[0, 130, 0, 0, 0, 0, 0, 1, 0x23, 0x28, 0x23, 0x29, 0, 0, 0x0e, 0x10], client).unwrap();
+        });
+        let mapping = PortMapping::nat_pmp(address, 9000, 3600, Duration::from_secs(2)).unwrap();
+        server.join().unwrap();
+        assert_eq!(mapping.external(), "203.0.113.7:9001".parse().unwrap());
+        assert_eq!(mapping.lifetime(), Duration::from_secs(3600));
+    }
+
+    #[test]
+    fn nat_pmp_errors_are_reported() {
+        assert!(parse_nat_pmp_mapping(&[0, 130, 0, 2, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0]).unwrap_err().contains("not authorized"));
+        assert!(parse_nat_pmp_mapping(&[0, 128, 0, 0]).is_err());
+        assert!(parse_nat_pmp_address(&[0, 128, 0, 0, 0, 0]).is_err());
+    }
+
+    #[test]
+    fn finds_connection_service_of_device() {
+        assert_eq!(parse_ssdp_location("HTTP/1.1 200 OK\r\nCACHE-CONTROL: max-age=120\r\nLocation: http://192.168.1.1:5000/rootDesc.xml\r\n\r\n"),
+                   Some("http://192.168.1.1:5000/rootDesc.xml".to_owned()));
+        let description = "<root><device><serviceList>\
+            <service><serviceType>urn:schemas-upnp-org:service:Layer3Forwarding:1</serviceType><controlURL>/ctl/L3F</controlURL></service>\
+            <service><serviceType>urn:schemas-upnp-org:service:WANIPConnection:1</serviceType><controlURL>/ctl/IPConn</controlURL></service>\
+            </serviceList></device></root>";
+        assert_eq!(parse_connection_service(description), Some(("urn:schemas-upnp-org:service:WANIPConnection:1".to_owned(), "/ctl/IPConn".to_owned())));
+        let (host, path) = parse_url("http://192.168.1.1:5000/rootDesc.xml").unwrap();
+        assert_eq!((host, path.as_str()), ("192.168.1.1:5000".parse().unwrap(), "/rootDesc.xml"));
+    }
+}
@@ -1,16 +1,58 @@
+use crate::parameters::ProtocolParameters;
 use crate::peer::Peer;
 use std::error::Error;
 use std::fmt::Debug;
+use std::io::{ErrorKind, Read};
+use std::time::{SystemTime, UNIX_EPOCH};
 
-const MSG_TYPE_REQ: u8 = 0x80; // 0b1000000
+const MSG_TYPE_REQ: u8 = 0x80; // 0b10000000
 const MSG_TYPE_RESP: u8 = 0x00;
-const MASK_MSG_TYPE: u8 = 0x80; // 0b1000000
+const MSG_TYPE_BUSY: u8 = 0x10; // 0b00010000
+const MSG_TYPE_PING: u8 = 0x20; // 0b00100000
+const MSG_TYPE_PONG: u8 = 0x30; // 0b00110000
+const MSG_TYPE_LEAVE: u8 = 0x40; // 0b01000000
+const MSG_TYPE_RELAY: u8 = 0x50; // 0b01010000
+const MSG_TYPE_POLL: u8 = 0x60; // 0b01100000
+const MASK_MSG_TYPE: u8 = 0xF0; // 0b11110000
+const FLAG_COMPRESSED: u8 = 0x01; // 0b0000001
+const FLAG_OBSERVER: u8 = 0x02; // 0b0000010
+const FLAG_SIZE_ESTIMATE: u8 = 0x04; // 0b0000100
+const FLAG_PAYLOAD: u8 = 0x08; // 0b0001000
+
+// message type(1) + timestamp(8) + nonce(8)
+const HEADER_SIZE: usize = 17;
+
+#[cfg(feature = "compression")]
+// Upper bound of the size of a decompressed message:
+// header(17) + sender size(1) + sender(255) + view size(1) + 255 * (peer size(1) + peer(255))
+// + epoch(8) + number of minima(1) + 255 * minimum(8) + payload size(2) + payload(65535) + parameters(6)
+const MAX_UNCOMPRESSED_SIZE: usize = HEADER_SIZE + 1 + 255 + 1 + 255 * 256 + 8 + 1 + 255 * 8 + 2 + 65535 + PARAMETERS_SIZE;
+
+#[cfg(feature = "compression")]
+// Upper bound of the size of a compressed message without its first byte:
+// uncompressed size(4) + the worst case expansion of LZ4 for the largest message
+const MAX_COMPRESSED_SIZE: usize = 4 + lz4_flex::block::get_maximum_output_size(MAX_UNCOMPRESSED_SIZE);
+
+// view size(2) + healing factor(2) + swapping factor(2), the optional last field of a message
+const PARAMETERS_SIZE: usize = 6;
 
 /// The message type
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 pub enum MessageType {
     Request,
-    Response
+    Response,
+    /// Refusal to answer a request because the node is overloaded
+    Busy,
+    /// Liveness probe
+    Ping,
+    /// Answer to a liveness probe
+    Pong,
+    /// Announcement of the departure of the sender
+    Leave,
+    /// Message forwarded by a relay to a node that does not accept connections
+    Relay,
+    /// Request of the messages held by a relay for the sender
+    Poll,
 }
 
 /// A peer sampling protocol message
@@ -20,8 +62,20 @@ pub struct Message {
     sender: String,
     /// Type of the message
     message_type: MessageType,
+    /// Creation time of the message in milliseconds since the Unix epoch
+    timestamp: u64,
+    /// Random value identifying the message
+    nonce: u64,
     /// The view of the sender
     view: Option<Vec<Peer>>,
+    /// Whether the sender is an observer, which must not be added to the views of the receivers
+    observer: bool,
+    /// The epoch and the minima of the random values for estimating the size of the network, if any
+    size_estimate: Option<(u64, Vec<f64>)>,
+    /// Data of the application attached to the message, if any
+    payload: Option<Vec<u8>>,
+    /// Parameters of the view selection of the sender, if advertised
+    parameters: Option<ProtocolParameters>,
 }
 
 impl Message {
@@ -36,14 +90,95 @@ impl Message {
         Self::new(sender, MessageType::Response, view)
     }
 
+    /// Creates a new message of type [MessageType::Busy], refusing a request
+    pub fn new_busy(sender: String) -> Message {
+        Self::new(sender, MessageType::Busy, None)
+    }
+
+    /// Creates a new message of type [MessageType::Ping], probing the liveness of a peer
+    pub fn new_ping(sender: String) -> Message {
+        Self::new(sender, MessageType::Ping, None)
+    }
+
+    /// Creates a new message of type [MessageType::Pong], answering a ping.
+    /// The nonce of the ping is echoed so that the answer can be matched with the probe.
+    ///
+    /// # Arguments
+    ///
+    /// * `sender` - Address of the sender
+    /// * `ping_nonce` - Nonce of the ping being answered
+    pub fn new_pong(sender: String, ping_nonce: u64) -> Message {
+        let mut message = Self::new(sender, MessageType::Pong, None);
+        message.nonce = ping_nonce;
+        message
+    }
+
+    /// Creates a new message of type [MessageType::Leave], announcing the departure of the sender
+    pub fn new_leave(sender: String) -> Message {
+        Self::new(sender, MessageType::Leave, None)
+    }
+
+    /// Creates a new message of type [MessageType::Relay], forwarding a message to a destination
+    /// through a relay. Fails if the destination or the serialized message are too large.
+    ///
+    /// # Arguments
+    ///
+    /// * `sender` - Address of the sender
+    /// * `destination` - Address of the node the message is forwarded to
+    /// * `message` - The forwarded message
+    pub fn new_relay(sender: String, destination: &str, message: &Message) -> Result<Message, Box<dyn Error>> {
+        let bytes = message.as_bytes();
+        if destination.is_empty() || destination.len() > u8::MAX as usize || 1 + destination.len() + bytes.len() > u16::MAX as usize {
+            Err("message too large to be relayed")?
+        }
+        // destination size(1) + destination + forwarded message, carried as the payload
+        let mut payload = Vec::with_capacity(1 + destination.len() + bytes.len());
+        payload.push(destination.len() as u8);
+        payload.extend_from_slice(destination.as_bytes());
+        payload.extend_from_slice(&bytes);
+        Ok(Self::new(sender, MessageType::Relay, None).with_payload(payload))
+    }
+
+    /// Creates a new message of type [MessageType::Poll], requesting a message held by a relay for the sender
+    pub fn new_poll(sender: String) -> Message {
+        Self::new(sender, MessageType::Poll, None)
+    }
+
     fn new(sender: String, message_type: MessageType, view: Option<Vec<Peer>>) -> Message {
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_millis() as u64)
+            .unwrap_or(0);
         Message{
             sender,
             message_type,
-            view
+            timestamp,
+            nonce: rand::random(),
+            view,
+            observer: false,
+            size_estimate: None,
+            payload: None,
+            parameters: None,
         }
     }
 
+    /// Echoes the nonce of the request being answered, as a response or a busy message,
+    /// so that the answer can be matched with the request, see [crate::Config::with_strict_responses]
+    ///
+    /// # Arguments
+    ///
+    /// * `request_nonce` - Nonce of the request being answered
+    pub fn in_reply_to(mut self, request_nonce: u64) -> Message {
+        self.nonce = request_nonce;
+        self
+    }
+
+    /// Marks the sender of the message as an observer, so that the receiver neither adds it
+    /// to its view nor keeps it among the recent senders
+    pub fn with_observer_flag(mut self) -> Message {
+        self.observer = true;
+        self
+    }
+
     /// Returns the message sender
     pub fn sender(&self) -> &str {
         &self.sender
@@ -54,38 +189,212 @@ impl Message {
         &self.message_type
     }
 
+    /// Returns the creation time of the message in milliseconds since the Unix epoch
+    pub fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
+    /// Returns the random value identifying the message
+    pub fn nonce(&self) -> u64 {
+        self.nonce
+    }
+
     /// Returns the view contained in the message
     pub fn view(&self) -> &Option<Vec<Peer>> {
         &self.view
     }
 
+    /// Returns whether the sender is an observer
+    pub fn is_observer(&self) -> bool {
+        self.observer
+    }
+
+    /// Attaches the values of the sender for estimating the size of the network, at most 255 minima
+    ///
+    /// # Arguments
+    ///
+    /// * `epoch` - Epoch of the minima
+    /// * `minima` - The minima of the random values known to the sender
+    pub fn with_size_estimate(mut self, epoch: u64, minima: Vec<f64>) -> Message {
+        self.size_estimate = Some((epoch, minima));
+        self
+    }
+
+    /// Returns the epoch and the minima attached for estimating the size of the network, if any
+    pub fn size_estimate(&self) -> Option<(u64, &[f64])> {
+        self.size_estimate.as_ref().map(|(epoch, minima)| (*epoch, minima.as_slice()))
+    }
+
+    /// Attaches data of the application to the message, at most 65535 bytes
+    ///
+    /// # Arguments
+    ///
+    /// * `payload` - The data of the application
+    pub fn with_payload(mut self, payload: Vec<u8>) -> Message {
+        self.payload = Some(payload);
+        self
+    }
+
+    /// Returns the data of the application attached to the message, if any
+    pub fn payload(&self) -> Option<&[u8]> {
+        self.payload.as_deref()
+    }
+
+    /// Returns the destination and the serialized forwarded message of a message of type [MessageType::Relay],
+    /// or None if the message forwards nothing
+    pub fn relayed(&self) -> Option<(&str, &[u8])> {
+        if self.message_type != MessageType::Relay {
+            return None;
+        }
+        let payload = self.payload.as_deref()?;
+        let size = *payload.first()? as usize;
+        let destination = std::str::from_utf8(payload.get(1..1 + size)?).ok()?;
+        Some((destination, &payload[1 + size..]))
+    }
+
+    /// Advertises the parameters of the view selection of the sender. Having no flag left in the
+    /// first byte, they are the last field of the message, present when bytes remain after the payload.
+    ///
+    /// # Arguments
+    ///
+    /// * `parameters` - Parameters of the sender
+    pub fn with_parameters(mut self, parameters: ProtocolParameters) -> Message {
+        self.parameters = Some(parameters);
+        self
+    }
+
+    /// Returns the parameters of the view selection of the sender, if advertised
+    pub fn parameters(&self) -> Option<ProtocolParameters> {
+        self.parameters
+    }
+
+    /// Chooses the nonce of the message so that it carries a proof of work over its content,
+    /// which makes flooding the views of other nodes costly. Must be called after the content is complete.
+    ///
+    /// # Arguments
+    ///
+    /// * `difficulty` - Number of leading zero bits of the hash, each bit doubling the work
+    pub fn with_proof_of_work(mut self, difficulty: u8) -> Message {
+        let digest = self.content_digest();
+        self.nonce = crate::pow::solve(&digest, self.nonce, difficulty);
+        self
+    }
+
+    /// Returns whether the nonce of the message is a proof of work over its content of at least a difficulty
+    ///
+    /// # Arguments
+    ///
+    /// * `difficulty` - Number of leading zero bits of the hash required
+    pub fn has_proof_of_work(&self, difficulty: u8) -> bool {
+        crate::pow::is_solved(&self.content_digest(), self.nonce, difficulty)
+    }
+
+    /// Returns the hash of the serialized message with a zero nonce
+    fn content_digest(&self) -> Vec<u8> {
+        let mut bytes = self.as_bytes();
+        // the nonce follows the message type and the timestamp
+        bytes[9..HEADER_SIZE].fill(0);
+        crate::pow::digest(&bytes)
+    }
+
     /// Serializes the message to a vector of bytes
     pub fn as_bytes(&self) -> Vec<u8> {
         let mut buffer = vec![];
+        let payload = self.write_head(&mut buffer);
+        buffer.extend_from_slice(payload);
+        self.write_tail(&mut buffer);
+        buffer
+    }
+
+    /// Serializes the message up to the data of the application at the end of a buffer,
+    /// and returns the data of the application, so that it is written without being copied
+    ///
+    /// # Arguments
+    ///
+    /// * `buffer` - The buffer, such as a buffer reused for each message
+    pub(crate) fn write_head(&self, buffer: &mut Vec<u8>) -> &[u8] {
+        let start = buffer.len();
         // first byte: message type
         match self.message_type {
             MessageType::Request => buffer.push(MSG_TYPE_REQ),
             MessageType::Response => buffer.push(MSG_TYPE_RESP),
+            MessageType::Busy => buffer.push(MSG_TYPE_BUSY),
+            MessageType::Ping => buffer.push(MSG_TYPE_PING),
+            MessageType::Pong => buffer.push(MSG_TYPE_PONG),
+            MessageType::Leave => buffer.push(MSG_TYPE_LEAVE),
+            MessageType::Relay => buffer.push(MSG_TYPE_RELAY),
+            MessageType::Poll => buffer.push(MSG_TYPE_POLL),
+        }
+        if self.observer {
+            buffer[start] |= FLAG_OBSERVER;
+        }
+        if self.size_estimate.is_some() {
+            buffer[start] |= FLAG_SIZE_ESTIMATE;
+        }
+        if self.payload.is_some() {
+            buffer[start] |= FLAG_PAYLOAD;
         }
+        // timestamp and nonce
+        buffer.extend_from_slice(&self.timestamp.to_be_bytes());
+        buffer.extend_from_slice(&self.nonce.to_be_bytes());
         // sender
-        buffer.push(self.sender.as_bytes().len() as u8);
+        buffer.push(self.sender.len() as u8);
         self.sender.as_bytes().iter().for_each(|byte| buffer.push(*byte));
         // view
         if let Some(peers) = &self.view {
             // view size in number of peers
             buffer.push(peers.len() as u8);
             // rest of bytes: peers
-            peers.iter().map(|p| { p.as_bytes() }).for_each(|mut bytes| {
-                // length of peer data in bytes
-                buffer.push(bytes.len() as u8);
+            for peer in peers {
+                // length of peer data in bytes, known once written
+                let length_index = buffer.len();
+                buffer.push(0);
                 // peer data
-                buffer.append(&mut bytes);
-            });
+                peer.write_bytes(buffer);
+                buffer[length_index] = (buffer.len() - length_index - 1) as u8;
+            }
         }
         else {
             // empty set
             buffer.push(0);
         }
+        // epoch and minima for estimating the size of the network
+        if let Some((epoch, minima)) = &self.size_estimate {
+            buffer.extend_from_slice(&epoch.to_be_bytes());
+            buffer.push(minima.len() as u8);
+            minima.iter().for_each(|minimum| buffer.extend_from_slice(&minimum.to_bits().to_be_bytes()));
+        }
+        // data of the application, written by the caller
+        match &self.payload {
+            Some(payload) => {
+                buffer.extend_from_slice(&(payload.len() as u16).to_be_bytes());
+                payload
+            }
+            None => &[],
+        }
+    }
+
+    /// Serializes the message after the data of the application at the end of a buffer
+    ///
+    /// # Arguments
+    ///
+    /// * `buffer` - The buffer
+    pub(crate) fn write_tail(&self, buffer: &mut Vec<u8>) {
+        // parameters of the sender
+        if let Some(parameters) = &self.parameters {
+            for value in [parameters.view_size(), parameters.healing_factor(), parameters.swapping_factor()] {
+                buffer.extend_from_slice(&(value.min(u16::MAX as usize) as u16).to_be_bytes());
+            }
+        }
+    }
+
+    /// Serializes the message to a vector of bytes where everything following
+    /// the first byte is compressed. The compression flag is set in the first byte.
+    #[cfg(feature = "compression")]
+    pub fn as_compressed_bytes(&self) -> Vec<u8> {
+        let bytes = self.as_bytes();
+        let mut buffer = vec![bytes[0] | FLAG_COMPRESSED];
+        buffer.append(&mut lz4_flex::compress_prepend_size(&bytes[1..]));
         buffer
     }
 
@@ -96,57 +405,394 @@ impl Message {
     /// * `bytes` - A message serialized as bytes
     pub fn from_bytes(bytes: &[u8]) -> Result<Message, Box<dyn Error>> {
 
-        // message type(1) + sender size(1) + one byte for sender(>=1) + view size(1)
-        if bytes.len() < 4 {
+        // message type(1) + at least one byte
+        if bytes.len() < 2 {
+            Err("invalid message")?
+        }
+
+        // header(17) + sender size(1) + one byte for sender(>=1) + view size(1)
+        if bytes[0] & FLAG_COMPRESSED == 0 && bytes.len() < HEADER_SIZE + 3 {
             Err("invalid message")?
         }
 
-        // message type
-        let message_type = match bytes[0] & MASK_MSG_TYPE {
+        Self::read_from(bytes)?.ok_or_else(|| "invalid message".into())
+    }
+
+    /// Deserializes a message while it is read from a stream, until the end of the stream.
+    /// Reading stops at the first invalid field, so that the rest of an invalid message is never read.
+    /// Returns None if the stream ends before the first byte.
+    ///
+    /// # Arguments
+    ///
+    /// * `reader` - The stream, whose errors other than its early end are returned as [std::io::Error]
+    pub fn read_from<R: Read>(reader: R) -> Result<Option<Message>, Box<dyn Error>> {
+
+        let mut decoder = Decoder::new(reader);
+
+        // message type and flags
+        let first_byte = match decoder.read_first()? {
+            Some(first_byte) => first_byte,
+            None => return Ok(None),
+        };
+
+        // compressed message, decompressed whole
+        #[cfg(not(feature = "compression"))]
+        if first_byte & FLAG_COMPRESSED != 0 {
+            Err("compressed messages are not supported")?
+        }
+        #[cfg(feature = "compression")]
+        if first_byte & FLAG_COMPRESSED != 0 {
+            let compressed = decoder.read_rest(MAX_COMPRESSED_SIZE)?;
+            if compressed.is_empty() {
+                Err("invalid message")?
+            }
+            if compressed.len() > MAX_COMPRESSED_SIZE || lz4_flex::block::uncompressed_size(&compressed)?.0 > MAX_UNCOMPRESSED_SIZE {
+                Err("invalid compressed message size")?
+            }
+            let mut decompressed = vec![first_byte & !FLAG_COMPRESSED];
+            decompressed.append(&mut lz4_flex::decompress_size_prepended(&compressed)?);
+            return Self::from_bytes(&decompressed).map(Some);
+        }
+
+        let observer = first_byte & FLAG_OBSERVER != 0;
+        let message_type = match first_byte & MASK_MSG_TYPE {
             MSG_TYPE_REQ => MessageType::Request,
             MSG_TYPE_RESP => MessageType::Response,
+            MSG_TYPE_BUSY => MessageType::Busy,
+            MSG_TYPE_PING => MessageType::Ping,
+            MSG_TYPE_PONG => MessageType::Pong,
+            MSG_TYPE_LEAVE => MessageType::Leave,
+            MSG_TYPE_RELAY => MessageType::Relay,
+            MSG_TYPE_POLL => MessageType::Poll,
             _ => return Err("invalid message type")?,
         };
 
+        // timestamp and nonce
+        let timestamp = decoder.read_u64()?;
+        let nonce = decoder.read_u64()?;
+
         // sender
-        let sender_size = bytes[1] as usize;
-        // message type(1) + sender size(1) + sender(sender_size) + view size(>=1)
-        if bytes.len() < 3 + sender_size {
+        let sender_size = decoder.read_u8()? as usize;
+        if sender_size == 0 {
             Err("invalid message")?
         }
-        let sender = String::from_utf8(bytes[2..2+sender_size].to_vec())?;
+        let sender = String::from_utf8(decoder.read_slice(sender_size)?.to_vec())?;
 
-        // view size
-        let view_size = bytes[2+sender_size];
-        // message type(1) + sender size(1) + sender(sender_size) + view size(2 * view_size)
-        if bytes.len() < (2 + sender_size + 2 * view_size as usize) {
-            Err("invalid message")?
-        }
-        if view_size > 0 {
-            let mut index = 3+sender_size;
-            let mut peers = vec![];
+        // view
+        let view_size = decoder.read_u8()?;
+        let view = if view_size > 0 {
+            let mut peers = Vec::with_capacity(view_size as usize);
             for _ in 0..view_size {
-                let peer_length = bytes[index] as usize;
-                // index + 1 + peer length
-                if bytes.len() < index + 1 + peer_length{
-                    return Err("invalid message")?;
-                }
-                let parsed_peer = Peer::from_bytes(&bytes[index+1..index+1+peer_length])?;
-                peers.push(parsed_peer);
-                index += peer_length + 1;
+                let peer_length = decoder.read_u8()? as usize;
+                peers.push(Peer::from_bytes(decoder.read_slice(peer_length)?)?);
             }
-            Ok(Message {
-                sender,
-                message_type,
-                view: Some(peers)
-            })
+            Some(peers)
+        }
+        else {
+            None
+        };
+
+        // size estimate
+        let size_estimate = if first_byte & FLAG_SIZE_ESTIMATE != 0 {
+            let epoch = decoder.read_u64()?;
+            let count = decoder.read_u8()?;
+            let minima = (0..count).map(|_| decoder.read_u64().map(f64::from_bits)).collect::<Result<Vec<f64>, _>>()?;
+            Some((epoch, minima))
+        }
+        else {
+            None
+        };
+
+        // payload
+        let payload = if first_byte & FLAG_PAYLOAD != 0 {
+            let size = u16::from_be_bytes([decoder.read_u8()?, decoder.read_u8()?]) as usize;
+            Some(decoder.read_slice(size)?.to_vec())
         }
         else {
-            Ok(Message {
-                sender,
-                message_type,
-                view: None
-            })
+            None
+        };
+
+        // parameters, if they are all that remains
+        let rest = decoder.read_rest(PARAMETERS_SIZE)?;
+        let parameters = match rest.len() {
+            0 => None,
+            PARAMETERS_SIZE => {
+                let read_u16 = |i: usize| u16::from_be_bytes([rest[i], rest[i + 1]]) as usize;
+                Some(ProtocolParameters::new(read_u16(0), read_u16(2), read_u16(4)))
+            }
+            _ => Err("invalid message: unexpected trailing bytes")?,
+        };
+
+        Ok(Some(Message {
+            sender,
+            message_type,
+            timestamp,
+            nonce,
+            view,
+            observer,
+            size_estimate,
+            payload,
+            parameters,
+        }))
+    }
+}
+
+/// Reads the fields of a serialized message from a stream, failing instead of reading past its end
+struct Decoder<R: Read> {
+    reader: R,
+    /// The bytes of the last field read
+    field: Vec<u8>,
+}
+
+impl<R: Read> Decoder<R> {
+    fn new(reader: R) -> Decoder<R> {
+        Decoder { reader, field: vec![] }
+    }
+
+    /// Reads the first byte, returns None if the stream is empty
+    fn read_first(&mut self) -> Result<Option<u8>, Box<dyn Error>> {
+        let mut byte = [0u8; 1];
+        loop {
+            match self.reader.read(&mut byte) {
+                Ok(0) => return Ok(None),
+                Ok(_) => return Ok(Some(byte[0])),
+                Err(e) if e.kind() == ErrorKind::Interrupted => {},
+                Err(e) => return Err(e.into()),
+            }
         }
     }
-}
\ No newline at end of file
+
+    /// Reads the given number of bytes
+    fn read_slice(&mut self, length: usize) -> Result<&[u8], Box<dyn Error>> {
+        self.field.resize(length, 0);
+        self.reader.read_exact(&mut self.field).map_err(truncated)?;
+        Ok(&self.field)
+    }
+
+    fn read_u8(&mut self) -> Result<u8, Box<dyn Error>> {
+        let mut value = [0u8; 1];
+        self.reader.read_exact(&mut value).map_err(truncated)?;
+        Ok(value[0])
+    }
+
+    fn read_u64(&mut self) -> Result<u64, Box<dyn Error>> {
+        let mut value = [0u8; 8];
+        self.reader.read_exact(&mut value).map_err(truncated)?;
+        Ok(u64::from_be_bytes(value))
+    }
+
+    /// Reads until the end of the stream, stopping after one byte more than the maximum
+    ///
+    /// # Arguments
+    ///
+    /// * `max` - The number of bytes expected at most
+    fn read_rest(&mut self, max: usize) -> Result<Vec<u8>, Box<dyn Error>> {
+        let mut rest = vec![];
+        (&mut self.reader).take(max as u64 + 1).read_to_end(&mut rest)?;
+        Ok(rest)
+    }
+}
+
+/// Reports a stream ending before a field as an invalid message rather than as a failure of the stream
+fn truncated(error: std::io::Error) -> Box<dyn Error> {
+    if error.kind() == ErrorKind::UnexpectedEof {
+        "invalid message: truncated".into()
+    }
+    else {
+        error.into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_view() -> Vec<Peer> {
+        (0..20).map(|i| Peer::new(format!("node-{}.gossip.example.com:9000", i))).collect()
+    }
+
+    // the serializations of a message, the compressed one being last when compression is enabled
+    fn encodings(message: &Message) -> Vec<Vec<u8>> {
+        vec![
+            message.as_bytes(),
+            #[cfg(feature = "compression")]
+            message.as_compressed_bytes(),
+        ]
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn compressed_round_trip() {
+        let message = Message::new_request("127.0.0.1:9000".to_owned(), Some(sample_view()));
+
+        let plain = message.as_bytes();
+        let compressed = message.as_compressed_bytes();
+        assert!(compressed.len() < plain.len());
+        assert_eq!(compressed[0] & FLAG_COMPRESSED, FLAG_COMPRESSED);
+
+        let decoded = Message::from_bytes(&compressed).unwrap();
+        assert_eq!(decoded.sender(), "127.0.0.1:9000");
+        assert!(matches!(decoded.message_type(), MessageType::Request));
+        assert_eq!(decoded.view().as_ref().unwrap(), &sample_view());
+        assert_eq!(decoded.timestamp(), message.timestamp());
+        assert_eq!(decoded.nonce(), message.nonce());
+    }
+
+    #[test]
+    fn reading_stops_at_the_first_invalid_field() {
+        let bytes = Message::new_request("127.0.0.1:9000".to_owned(), Some(sample_view()))
+            .with_payload(vec![7; 1000])
+            .as_bytes();
+        let mut reader = &bytes[..];
+        assert_eq!(Message::read_from(&mut reader).unwrap().unwrap().as_bytes(), bytes);
+        assert!(reader.is_empty());
+
+        let mut invalid = bytes.clone();
+        invalid[0] = 0xF0;
+        let mut reader = &invalid[..];
+        assert!(Message::read_from(&mut reader).is_err());
+        assert_eq!(reader.len(), invalid.len() - 1);
+
+        assert!(Message::read_from(&bytes[..bytes.len() - 1]).is_err());
+        assert!(Message::read_from(&[][..]).unwrap().is_none());
+    }
+
+    #[test]
+    fn busy_round_trip() {
+        let message = Message::new_busy("127.0.0.1:9000".to_owned());
+        let decoded = Message::from_bytes(&message.as_bytes()).unwrap();
+        assert!(matches!(decoded.message_type(), MessageType::Busy));
+        assert!(decoded.view().is_none());
+    }
+
+    #[test]
+    fn pong_echoes_ping_nonce() {
+        let ping = Message::new_ping("127.0.0.1:9000".to_owned());
+        let pong = Message::new_pong("127.0.0.1:9001".to_owned(), ping.nonce());
+        let decoded = Message::from_bytes(&pong.as_bytes()).unwrap();
+        assert!(matches!(decoded.message_type(), MessageType::Pong));
+        assert_eq!(decoded.nonce(), ping.nonce());
+    }
+
+    #[test]
+    fn relay_forwards_message() {
+        let request = Message::new_request("127.0.0.1:9000".to_owned(), Some(sample_view()));
+        let relay = Message::new_relay("127.0.0.1:9000".to_owned(), "192.168.1.2:9000", &request).unwrap();
+        let decoded = Message::from_bytes(encodings(&relay).last().unwrap()).unwrap();
+        let (destination, forwarded) = decoded.relayed().unwrap();
+        assert_eq!(destination, "192.168.1.2:9000");
+        let forwarded = Message::from_bytes(forwarded).unwrap();
+        assert_eq!((forwarded.nonce(), forwarded.view().as_ref().unwrap()), (request.nonce(), &sample_view()));
+
+        assert!(Message::new_relay("127.0.0.1:9000".to_owned(), "", &request).is_err());
+        let large = Message::new_request("127.0.0.1:9000".to_owned(), None).with_payload(vec![0; u16::MAX as usize]);
+        assert!(Message::new_relay("127.0.0.1:9000".to_owned(), "192.168.1.2:9000", &large).is_err());
+        assert!(Message::from_bytes(&Message::new_poll("127.0.0.1:9000".to_owned()).as_bytes()).unwrap().relayed().is_none());
+    }
+
+    #[test]
+    fn observer_flag_round_trip() {
+        let message = Message::new_request("127.0.0.1:9000".to_owned(), None).with_observer_flag();
+        assert!(Message::from_bytes(&message.as_bytes()).unwrap().is_observer());
+        let decoded = Message::from_bytes(encodings(&message).last().unwrap()).unwrap();
+        assert!(decoded.is_observer());
+        assert!(matches!(decoded.message_type(), MessageType::Request));
+        assert!(!Message::from_bytes(&Message::new_ping("127.0.0.1:9000".to_owned()).as_bytes()).unwrap().is_observer());
+    }
+
+    #[test]
+    fn size_estimate_round_trip() {
+        let message = Message::new_response("127.0.0.1:9000".to_owned(), Some(sample_view())).with_size_estimate(7, vec![0.5, 0.25]);
+        for bytes in encodings(&message) {
+            let decoded = Message::from_bytes(&bytes).unwrap();
+            assert_eq!(decoded.size_estimate(), Some((7, &[0.5, 0.25][..])));
+            assert_eq!(decoded.view().as_ref().unwrap(), &sample_view());
+        }
+        let bytes = message.as_bytes();
+        assert!(Message::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        assert!(Message::from_bytes(&Message::new_busy("127.0.0.1:9000".to_owned()).as_bytes()).unwrap().size_estimate().is_none());
+    }
+
+    #[test]
+    fn payload_round_trip() {
+        let message = Message::new_request("127.0.0.1:9000".to_owned(), Some(sample_view()))
+            .with_size_estimate(7, vec![0.5])
+            .with_payload(b"version 3".to_vec());
+        for bytes in encodings(&message) {
+            let decoded = Message::from_bytes(&bytes).unwrap();
+            assert_eq!(decoded.payload(), Some(&b"version 3"[..]));
+            assert_eq!(decoded.size_estimate(), Some((7, &[0.5][..])));
+        }
+        let bytes = message.as_bytes();
+        assert!(Message::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        let empty = Message::new_response("127.0.0.1:9000".to_owned(), None).with_payload(vec![]);
+        assert_eq!(Message::from_bytes(&empty.as_bytes()).unwrap().payload(), Some(&[][..]));
+    }
+
+    #[test]
+    fn parameters_round_trip() {
+        let parameters = ProtocolParameters::new(30, 1, 14);
+        let message = Message::new_request("127.0.0.1:9000".to_owned(), Some(sample_view()))
+            .with_payload(vec![1, 2])
+            .with_parameters(parameters);
+        for bytes in encodings(&message) {
+            let decoded = Message::from_bytes(&bytes).unwrap();
+            assert_eq!(decoded.parameters(), Some(parameters));
+            assert_eq!(decoded.payload(), Some(&[1, 2][..]));
+        }
+        let bytes = message.as_bytes();
+        assert!(Message::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        let bare = Message::new_response("127.0.0.1:9000".to_owned(), None).with_parameters(parameters);
+        assert_eq!(Message::from_bytes(&bare.as_bytes()).unwrap().parameters(), Some(parameters));
+        assert!(Message::from_bytes(&Message::new_busy("127.0.0.1:9000".to_owned()).as_bytes()).unwrap().parameters().is_none());
+    }
+
+    #[test]
+    fn proof_of_work_covers_content() {
+        let mut message = Message::new_request("127.0.0.1:9000".to_owned(), Some(sample_view()));
+        (message.timestamp, message.nonce) = (0, 0);
+        let message = message.with_proof_of_work(10);
+        let decoded = Message::from_bytes(encodings(&message).last().unwrap()).unwrap();
+        assert!(decoded.has_proof_of_work(10));
+
+        // another view with the same nonce does not carry the work
+        let mut forged = Message::new_request("127.0.0.1:9000".to_owned(), Some(vec![Peer::new("127.0.0.1:9666".to_owned())]));
+        (forged.timestamp, forged.nonce) = (0, message.nonce());
+        assert!(!forged.has_proof_of_work(10));
+    }
+
+    #[test]
+    fn rejects_unknown_message_type() {
+        let mut bytes = Message::new_busy("127.0.0.1:9000".to_owned()).as_bytes();
+        bytes[0] = 0x70;
+        assert!(Message::from_bytes(&bytes).is_err());
+    }
+
+    #[test]
+    fn rejects_truncated_and_padded_messages() {
+        let bytes = Message::new_request("127.0.0.1:9000".to_owned(), Some(sample_view())).as_bytes();
+        for length in 0..bytes.len() {
+            assert!(Message::from_bytes(&bytes[..length]).is_err());
+        }
+        let mut padded = bytes.clone();
+        padded.push(0);
+        assert!(Message::from_bytes(&padded).is_err());
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn rejects_oversized_compressed_message() {
+        let mut bytes = vec![MSG_TYPE_RESP | FLAG_COMPRESSED];
+        bytes.extend_from_slice(&(u32::MAX).to_le_bytes());
+        bytes.extend_from_slice(&[0, 0, 0]);
+        assert!(Message::from_bytes(&bytes).is_err());
+    }
+
+    #[cfg(not(feature = "compression"))]
+    #[test]
+    fn rejects_compressed_message_without_compression() {
+        let mut bytes = Message::new_request("127.0.0.1:9000".to_owned(), Some(sample_view())).as_bytes();
+        bytes[0] |= FLAG_COMPRESSED;
+        assert!(Message::from_bytes(&bytes).is_err());
+    }
+}
@@ -1,25 +1,35 @@
+#[cfg(feature = "monitoring")]
 use std::io::Read;
+#[cfg(feature = "monitoring")]
 use std::io::Write;
 
-use slog::debug;
-use slog::o;
-use slog::warn;
-use slog::Logger;
+#[cfg(feature = "monitoring")]
+use slog::{debug, o, warn, Logger};
 
-/// Configuration for sending protocol monitoring data
+#[cfg(feature = "monitoring")]
+use crate::audit::Rejection;
+#[cfg(feature = "monitoring")]
+use crate::report::MonitoringReport;
+
+/// Configuration for sending protocol monitoring data, posted as a [crate::MonitoringReport].
+/// Without the `monitoring` feature, only the disabled configuration is available.
 #[derive(Clone)]
 pub struct MonitoringConfig {
     /// Enable sending data
     enabled: bool,
     /// Monitoring host
+    #[cfg(feature = "monitoring")]
     host: String,
     /// URL context
+    #[cfg(feature = "monitoring")]
     context: String,
     /// Logger
+    #[cfg(feature = "monitoring")]
     logger: Logger,
 }
 
 impl MonitoringConfig {
+    #[cfg(feature = "monitoring")]
     /// Creates a new monitoring configuration
     ///
     /// # Arguments
@@ -28,11 +38,7 @@ impl MonitoringConfig {
     /// * `url` - URL of monitoring host
     pub fn new(enabled: bool, url: &str, logger: Logger) -> MonitoringConfig {
         // remove leading protocol
-        let protocol_removed = if url.starts_with("http://") {
-            &url[7..] }
-        else {
-            url
-        };
+        let protocol_removed = url.strip_prefix("http://").unwrap_or(url);
         // separate host and context
         let (host, context) = match protocol_removed.find("/") {
             Some(index) => (&protocol_removed[..index], &protocol_removed[index..]),
@@ -50,35 +56,42 @@ impl MonitoringConfig {
         self.enabled
     }
 
+    #[cfg(feature = "monitoring")]
     /// Send monitoring data
     ///
     /// # Arguments
     ///
     /// * `pid` - Identifier of sending process
     /// * `peers` - List of peers in the view of the process
-    pub fn send_data(&self, pid: &str, peers: Vec<String>) {
+    /// * `indegree` - Estimated in-degree of the process
+    pub fn send_data(&self, pid: &str, peers: Vec<String>, indegree: f64) {
+        self.send_report(pid, peers, indegree, &[]);
+    }
+
+    #[cfg(feature = "monitoring")]
+    /// Send monitoring data along with the peer entries rejected since the last report
+    ///
+    /// # Arguments
+    ///
+    /// * `pid` - Identifier of sending process
+    /// * `peers` - List of peers in the view of the process
+    /// * `indegree` - Estimated in-degree of the process
+    /// * `rejections` - The rejected peer entries, see [crate::Config::with_audit]
+    pub fn send_report(&self, pid: &str, peers: Vec<String>, indegree: f64, rejections: &[Rejection]) {
+        let report = MonitoringReport::new(pid.to_owned(), peers, indegree, rejections);
         let pid = pid.to_owned();
         let host = self.host.clone();
         let context = self.context.clone();
         let logger = self.logger.clone();
         std::thread::spawn(move || {
-            let peers_str = peers.iter()
-                .map(|peer| format!("\"{}\"", peer))
-                .collect::<Vec<String>>().join(",");
-            let json = format!(
-                "{{\
-                \"id\":\"{}\",\
-                \"peers\":[{}],\
-                \"messages\":[{}]\
-            }}", pid, peers_str, "");
-            //println!("send_data:\n{}", json);
-            match MonitoringConfig::post(&host, &context, json, logger.clone()) {
+            match MonitoringConfig::post(&host, &context, report.to_json(), logger.clone()) {
                 Ok(()) => debug!(logger, "Peer {}: monitoring data sent", pid),
                 Err(e) => warn!(logger, "Peer {} could not send monitoring data to {}: {}", pid, host, e),
             }
         });
     }
 
+    #[cfg(feature = "monitoring")]
     fn post(host: &str, context: &str, json: String, logger: Logger) -> std::io::Result<()> {
 
         let bytes = json.as_bytes();
@@ -103,7 +116,7 @@ impl MonitoringConfig {
 
         //println!("request_data = {:?}", request_data);
 
-        let _request = stream.write_all(request_data.as_bytes())?;
+        stream.write_all(request_data.as_bytes())?;
         //println!("request = {:?}", request);
 
         let mut buf = String::new();
@@ -119,8 +132,11 @@ impl Default for MonitoringConfig {
     fn default() -> Self {
         MonitoringConfig {
             enabled: false,
+            #[cfg(feature = "monitoring")]
             host: "".to_string(),
+            #[cfg(feature = "monitoring")]
             context: "".to_string(),
+            #[cfg(feature = "monitoring")]
             logger: Logger::root(slog::Discard, o!()),
         }
     }
@@ -1,33 +1,416 @@
-use std::net::{TcpStream, TcpListener, SocketAddr};
+use std::cell::RefCell;
+use std::net::{Shutdown, TcpListener, TcpStream, SocketAddr};
 use std::error::Error;
-use std::io::{Read, Write};
-use std::thread::JoinHandle;
+use std::fmt;
+use std::io::{ErrorKind, IoSlice, Read, Write};
+use std::time::Duration;
 
 use slog::{debug, error, info, warn, Logger};
 
+use crate::audit::RejectionReason;
+use crate::config::Config;
+use crate::event::Events;
+use crate::lanes::{Lane, LaneSender};
+use crate::limit::ConnectionPermit;
 use crate::message::Message;
-use std::sync::mpsc::Sender;
-use std::sync::Arc;
+use crate::quarantine::Quarantine;
+use crate::supervisor::{InitError, Tasks};
+use crate::thread::TaskHandle;
+use std::sync::{Arc, Mutex};
 use std::sync::atomic::AtomicBool;
 
-/// Create a thread for listening to TCP connections
+// Number of bytes read at once from a stream
+const READ_SIZE: usize = 8 * 1024;
+
+// Capacity above which the buffer of a thread is released after a message, such as after a large payload
+const MAX_KEPT_CAPACITY: usize = 128 * 1024;
+
+/// A message read from a connection, or the error of a malformed message
+type ReadMessage = Result<Option<Message>, Box<dyn Error>>;
+
+thread_local! {
+    /// Buffer of the messages read and written by the thread, reused so that each message is not allocated
+    static BUFFER: RefCell<Vec<u8>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Runs a function with the emptied buffer of the thread
+///
+/// # Arguments
+///
+/// * `f` - The function
+fn with_buffer<T, F: FnOnce(&mut Vec<u8>) -> T>(f: F) -> T {
+    BUFFER.with(|buffer| {
+        let mut buffer = buffer.borrow_mut();
+        buffer.clear();
+        let result = f(&mut buffer);
+        if buffer.capacity() > MAX_KEPT_CAPACITY {
+            *buffer = Vec::new();
+        }
+        result
+    })
+}
+
+/// Writes all the slices with as few system calls as possible
+///
+/// # Arguments
+///
+/// * `stream` - The stream
+/// * `slices` - The slices, written in order
+fn write_all_vectored(stream: &mut TcpStream, slices: &mut [IoSlice]) -> std::io::Result<()> {
+    let mut slices = slices;
+    IoSlice::advance_slices(&mut slices, 0);
+    while !slices.is_empty() {
+        match stream.write_vectored(slices) {
+            Ok(0) => return Err(std::io::Error::new(ErrorKind::WriteZero, "failed to write the whole message")),
+            Ok(written) => IoSlice::advance_slices(&mut slices, written),
+            Err(e) if e.kind() == ErrorKind::Interrupted => {},
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+/// A connection with another peer, carrying one message in each direction
+pub struct Connection {
+    /// The underlying stream
+    stream: TcpStream,
+    /// Compress the message written on the connection
+    #[cfg(feature = "compression")]
+    compression: bool,
+    /// Maximum size in bytes of the message read on the connection, 0 for no limit
+    max_frame_size: usize,
+    /// Encryption state of the connection, plaintext if none
+    #[cfg(feature = "noise")]
+    transport: Option<snow::TransportState>,
+    /// Synthetic network conditions applied to the written message, if any
+    #[cfg(feature = "impairment")]
+    impairment: Option<crate::impairment::Impairment>,
+    /// Whether the connection was opened by the node, the messages duplicated by the impairment being sent again
+    #[cfg(feature = "impairment")]
+    initiator: bool,
+    /// Counts the outbound connection until it is closed, if limited
+    _permit: Option<ConnectionPermit>,
+}
+
+impl Connection {
+    /// Opens a connection to another peer
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - Address of the peer
+    /// * `config` - The configuration of the node
+    pub fn connect(address: &SocketAddr, config: &Config) -> Result<Connection, Box<dyn Error>> {
+        let timeout = if config.exchange_timeout() > 0 { Some(Duration::from_millis(config.exchange_timeout())) } else { None };
+        #[cfg(feature = "impairment")]
+        if config.impairment().is_some_and(|impairment| impairment.is_blocked(address)) {
+            Err(std::io::Error::new(ErrorKind::ConnectionRefused, "link is blocked"))?
+        }
+        let permit = config.outbound_limit().map(|limit| limit.acquire(timeout)).transpose()?;
+        let stream = crate::socket::connect(address, timeout, config.socket())?;
+        stream.set_read_timeout(timeout)?;
+        stream.set_write_timeout(timeout)?;
+        let mut connection = Self::establish(stream, config, true)?;
+        connection._permit = permit;
+        Ok(connection)
+    }
+
+    /// Sets up a connection accepted from another peer, reading and writing within the exchange timeout,
+    /// or within the sampling period without timeout, so that a peer connecting without writing does not block the listener
+    ///
+    /// # Arguments
+    ///
+    /// * `stream` - The accepted stream
+    /// * `config` - The configuration of the node
+    pub fn accept(stream: TcpStream, config: &Config) -> Result<Connection, Box<dyn Error>> {
+        let timeout = Some(accept_timeout(config));
+        stream.set_read_timeout(timeout)?;
+        stream.set_write_timeout(timeout)?;
+        Self::establish(stream, config, false)
+    }
+
+    #[cfg_attr(not(any(feature = "noise", feature = "impairment")), allow(unused_variables))]
+    #[cfg_attr(not(feature = "noise"), allow(unused_mut))]
+    fn establish(mut stream: TcpStream, config: &Config, initiator: bool) -> Result<Connection, Box<dyn Error>> {
+        #[cfg(feature = "noise")]
+        let transport = match config.noise() {
+            Some(noise) if initiator => Some(crate::noise::initiate(&mut stream, noise)?),
+            Some(noise) => Some(crate::noise::respond(&mut stream, noise)?),
+            None => None,
+        };
+        Ok(Connection {
+            stream,
+            #[cfg(feature = "compression")]
+            compression: config.is_compression(),
+            max_frame_size: config.max_frame_size(),
+            #[cfg(feature = "noise")]
+            transport,
+            #[cfg(feature = "impairment")]
+            impairment: config.impairment().cloned(),
+            #[cfg(feature = "impairment")]
+            initiator,
+            _permit: None,
+        })
+    }
+
+    /// Returns the address of the other peer, as seen on the connection, if still connected
+    pub(crate) fn remote_address(&self) -> Option<SocketAddr> {
+        self.stream.peer_addr().ok()
+    }
+
+    /// Writes a message and closes the writing half of the connection
+    ///
+    /// # Arguments
+    ///
+    /// * `message` - The message to be written
+    pub fn write_message(&mut self, message: &Message) -> Result<(), Box<dyn Error>> {
+        #[cfg(feature = "impairment")]
+        if let Some(impairment) = self.impairment.clone() {
+            match impairment.delay() {
+                Some(delay) => std::thread::sleep(delay),
+                // the receiver finds the connection closed without message
+                None => {
+                    self.stream.shutdown(Shutdown::Write)?;
+                    return Ok(());
+                }
+            }
+            if let Some(bytes) = self.impaired_bytes(&impairment, message)? {
+                return self.write_bytes(&bytes);
+            }
+        }
+        #[cfg(feature = "noise")]
+        if self.transport.is_some() {
+            return self.write_bytes(&self.serialize(message));
+        }
+        #[cfg(feature = "compression")]
+        if self.compression {
+            return self.write_bytes(&message.as_compressed_bytes());
+        }
+        // the data of the application is written from the message, between the serialized header and trailer
+        with_buffer(|buffer| {
+            let payload = message.write_head(buffer);
+            let head_size = buffer.len();
+            message.write_tail(buffer);
+            let (head, tail) = buffer.split_at(head_size);
+            write_all_vectored(&mut self.stream, &mut [IoSlice::new(head), IoSlice::new(payload), IoSlice::new(tail)])
+        })?;
+        self.stream.shutdown(Shutdown::Write)?;
+        Ok(())
+    }
+
+    /// Serializes a message as it is written on the connection, before its encryption
+    ///
+    /// # Arguments
+    ///
+    /// * `message` - The message to be written
+    #[cfg_attr(not(any(feature = "noise", feature = "impairment")), allow(dead_code))]
+    fn serialize(&self, message: &Message) -> Vec<u8> {
+        #[cfg(feature = "compression")]
+        if self.compression {
+            return message.as_compressed_bytes();
+        }
+        message.as_bytes()
+    }
+
+    /// Writes serialized bytes, encrypted if the connection is, and closes the writing half of the connection
+    ///
+    /// # Arguments
+    ///
+    /// * `bytes` - The serialized message
+    #[cfg_attr(not(any(feature = "compression", feature = "noise", feature = "impairment")), allow(dead_code))]
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), Box<dyn Error>> {
+        #[cfg(feature = "noise")]
+        if let Some(transport) = self.transport.as_mut() {
+            return crate::noise::write(&mut self.stream, transport, bytes);
+        }
+        self.stream.write_all(bytes)?;
+        self.stream.shutdown(Shutdown::Write)?;
+        Ok(())
+    }
+
+    /// Writes a duplicate of a message on a second connection, and returns the bytes of the message
+    /// to be written if it is corrupted or duplicated, or None if it is written unchanged
+    ///
+    /// # Arguments
+    ///
+    /// * `impairment` - The synthetic network conditions
+    /// * `message` - The message to be written
+    #[cfg(feature = "impairment")]
+    fn impaired_bytes(&self, impairment: &crate::impairment::Impairment, message: &Message) -> Result<Option<Vec<u8>>, Box<dyn Error>> {
+        #[cfg(feature = "noise")]
+        let encrypted = self.transport.is_some();
+        #[cfg(not(feature = "noise"))]
+        let encrypted = false;
+        let duplicated = self.initiator && !encrypted && impairment.duplicates();
+        let mut bytes = self.serialize(message);
+        if duplicated {
+            // the answer to the duplicate is not read
+            let mut duplicate = TcpStream::connect(self.stream.peer_addr()?)?;
+            duplicate.write_all(&bytes)?;
+            duplicate.shutdown(Shutdown::Write)?;
+        }
+        let corrupted = impairment.corrupt(&mut bytes);
+        Ok(if duplicated || corrupted { Some(bytes) } else { None })
+    }
+
+    /// Reads a message until the other peer closes its writing half of the connection.
+    /// Returns None if the peer closed the connection without writing.
+    pub fn read_message(&mut self) -> Result<Option<Message>, Box<dyn Error>> {
+        self.try_read_message()?
+    }
+
+    /// Reads a message, failing as soon as more bytes than the maximum frame size were read.
+    /// The outer error is a failure of the connection, the inner one a malformed message.
+    fn try_read_message(&mut self) -> Result<ReadMessage, Box<dyn Error>> {
+        #[cfg(feature = "noise")]
+        if let Some(transport) = self.transport.as_mut() {
+            let bytes = crate::noise::read(&mut self.stream, transport, self.max_frame_size)?;
+            if self.max_frame_size > 0 && bytes.len() > self.max_frame_size {
+                return Ok(Err("message exceeds the maximum frame size".into()));
+            }
+            return Ok(Message::read_from(&bytes[..]));
+        }
+        // one more byte for detecting larger messages
+        let limit = if self.max_frame_size > 0 { self.max_frame_size as u64 + 1 } else { u64::MAX };
+        with_buffer(|buffer| {
+            let mut reader = BufferedStream::new((&mut self.stream).take(limit), buffer);
+            let message = Message::read_from(&mut reader);
+            if reader.stream.limit() == 0 {
+                return Ok(Err("message exceeds the maximum frame size".into()));
+            }
+            match message {
+                Err(e) if e.is::<std::io::Error>() => Err(e),
+                message => Ok(message),
+            }
+        })
+    }
+}
+
+/// Returns the deadline of reading and answering the message of an accepted connection: the exchange timeout,
+/// or the sampling period if there is none, as an answer arriving later is not awaited by the other peer anyway
+///
+/// # Arguments
+///
+/// * `config` - The configuration of the node
+fn accept_timeout(config: &Config) -> Duration {
+    match config.exchange_timeout() {
+        0 => Duration::from_secs(config.sampling_period().max(1)),
+        timeout => Duration::from_millis(timeout),
+    }
+}
+
+/// Reads a stream through the buffer of the thread, so that each field of a message is not read with a system call
+struct BufferedStream<'a, R: Read> {
+    stream: R,
+    /// The bytes read from the stream
+    buffer: &'a mut Vec<u8>,
+    /// The position of the next byte to be read in the buffer
+    position: usize,
+}
+
+impl<'a, R: Read> BufferedStream<'a, R> {
+    fn new(stream: R, buffer: &'a mut Vec<u8>) -> BufferedStream<'a, R> {
+        BufferedStream { stream, buffer, position: 0 }
+    }
+}
+
+impl<R: Read> Read for BufferedStream<'_, R> {
+    fn read(&mut self, out: &mut [u8]) -> std::io::Result<usize> {
+        if self.position == self.buffer.len() {
+            self.position = 0;
+            self.buffer.resize(READ_SIZE, 0);
+            let read = self.stream.read(self.buffer);
+            self.buffer.truncate(*read.as_ref().unwrap_or(&0));
+            read?;
+        }
+        let available = &self.buffer[self.position..];
+        let size = available.len().min(out.len());
+        out[..size].copy_from_slice(&available[..size]);
+        self.position += size;
+        Ok(size)
+    }
+}
+
+/// A failure to listen to an address of the node
+#[derive(Debug)]
+pub enum BindError {
+    /// The port of the address, and the following ports of the range, are in use by other sockets,
+    /// see [Config::with_port_range] and [Config::with_bind_retries]
+    AddressInUse { address: SocketAddr },
+    /// The address could not be bound for another reason, such as missing permissions or an unknown interface
+    Failed { address: SocketAddr, error: std::io::Error },
+}
+
+impl fmt::Display for BindError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BindError::AddressInUse { address } => write!(f, "could not listen to {}: address in use", address),
+            BindError::Failed { address, error } => write!(f, "could not listen to {}: {}", address, error),
+        }
+    }
+}
+
+impl Error for BindError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            BindError::AddressInUse { .. } => None,
+            BindError::Failed { error, .. } => Some(error),
+        }
+    }
+}
+
+/// Binds a listener of the node, and returns it with its local address, whose port is assigned by
+/// the system when the configured port is 0, or is the first free port of the range of the configuration
 ///
 /// # Arguments
 ///
 /// * `bind_address` - The socket bind address
-/// * `sender` - A sender for notifying of received messages
-pub fn start_listener(bind_address: &SocketAddr, sender: Sender<Message>, shutdown_handle: &Arc<AtomicBool>, logger: Logger) -> JoinHandle<()> {
+/// * `config` - The configuration of the node
+pub fn bind_listener(bind_address: &SocketAddr, config: &Config) -> Result<(TcpListener, SocketAddr), BindError> {
+    let ports = if bind_address.port() == 0 { 1 } else { config.port_range() };
+    for attempt in 0..=config.bind_retries() {
+        if attempt > 0 {
+            std::thread::sleep(Duration::from_millis(config.bind_retry_interval()));
+        }
+        for offset in 0..ports {
+            let mut address = *bind_address;
+            match bind_address.port().checked_add(offset) {
+                Some(port) => address.set_port(port),
+                None => break,
+            }
+            match crate::socket::bind(&address, config.socket()).and_then(|listener| Ok((listener.local_addr()?, listener))) {
+                Ok((local_address, listener)) => return Ok((listener, local_address)),
+                Err(e) if e.kind() == ErrorKind::AddrInUse => (),
+                Err(error) => return Err(BindError::Failed { address, error }),
+            }
+        }
+    }
+    Err(BindError::AddressInUse { address: *bind_address })
+}
+
+/// Create a thread for listening to TCP connections
+///
+/// # Arguments
+///
+/// * `config` - The configuration of the node
+/// * `listener` - The listener, bound with [bind_listener]
+/// * `sender` - A sender for notifying of received messages, along with the connection for answering them,
+///   on the lane of their type
+/// * `quarantine` - The quarantine, whose connections are dropped and where malformed messages are reported
+/// * `events` - The events of the node
+/// * `tasks` - The tasks of the service, recording how the listener ends
+#[allow(clippy::too_many_arguments)]
+pub fn start_listener(config: &Config, listener: TcpListener, sender: LaneSender<(Message, Connection)>, shutdown_handle: &Arc<AtomicBool>, quarantine: Arc<Mutex<Quarantine>>, events: Arc<Mutex<Events>>, tasks: &Arc<Tasks>, logger: Logger) -> Result<TaskHandle, InitError> {
 
-    let listener = TcpListener::bind(bind_address)
-        .expect(&format!("Could not listen to bind_address {}", bind_address));
+    let config = config.clone();
+    let bind_address = listener.local_addr().map(|address| address.to_string()).unwrap_or_default();
     info!(logger, "Listening on {}", bind_address);
 
     // shutdown flag
     let shutdown_requested = Arc::clone(shutdown_handle);
 
-    std::thread::Builder::new().name(format!("{} - gbps listener", bind_address)).spawn(move || {
+    let threads = config.threads().clone();
+    threads.spawn(&bind_address, "listener", tasks, logger.clone(), move || {
         info!(logger, "Started listener thread");
-        // TOD: handle hanging connections wher peer connect but does not write
         for incoming_stream in listener.incoming() {
 
             // check for shutdown request
@@ -38,25 +421,53 @@ pub fn start_listener(bind_address: &SocketAddr, sender: Sender<Message>, shutdo
 
             // handle request
             match incoming_stream {
-                Ok(mut stream) => {
-                    if let Err(e) = handle_message(&mut stream, &sender, logger.clone()) {
-                        error!(logger, "Error processing request: {}", e);
-                    }
-                }
+                Ok(stream) => match receive_message(stream, &config, &quarantine, &events, &logger) {
+                    Ok(Some(received)) => if sender.send(Lane::of(&received.0), received).is_err() {
+                        error!(logger, "Error processing request: message handling stopped");
+                    },
+                    Ok(None) => (),
+                    Err(e) => error!(logger, "Error processing request: {}", e),
+                },
                 Err(e) => warn!(logger, "Connection failed: {}", e),
             }
         }
         info!(logger, "Listener thread exiting");
-    }).unwrap()
+    })
 }
 
-fn handle_message(stream: &mut TcpStream, sender: &Sender<Message>, logger: Logger) -> Result<(), Box<dyn Error>>{
-    debug!(logger, "handle_message");
-    let mut buf = Vec::new();
-    stream.read_to_end(&mut buf)?;
-    let message = Message::from_bytes(&buf)?;
-    sender.send(message)?;
-    Ok(())
+/// Reads the message of an accepted connection, and returns it with the connection for answering it.
+/// Returns None if the connection was dropped, such as that of a quarantined peer.
+///
+/// # Arguments
+///
+/// * `stream` - The accepted connection
+/// * `config` - The configuration of the node
+/// * `quarantine` - The quarantine, whose connections are dropped and where malformed messages are reported
+/// * `events` - The events of the node
+pub(crate) fn receive_message(stream: TcpStream, config: &Config, quarantine: &Mutex<Quarantine>, events: &Mutex<Events>, logger: &Logger) -> Result<Option<(Message, Connection)>, Box<dyn Error>> {
+    let remote_address = match stream.peer_addr() {
+        Ok(remote_address) => remote_address,
+        Err(e) => {
+            warn!(logger, "Connection failed: {}", e);
+            return Ok(None);
+        }
+    };
+    if quarantine.lock().unwrap().is_quarantined(&remote_address.to_string()) {
+        debug!(logger, "Dropping connection from quarantined {}", remote_address);
+        events.lock().unwrap().audit().reject(&remote_address.ip().to_string(), RejectionReason::Quarantined);
+        return Ok(None);
+    }
+    if let Err(e) = crate::socket::configure_accepted(&stream, config.socket()) {
+        warn!(logger, "Could not set socket options: {}", e);
+    }
+    debug!(logger, "receive_message");
+    let mut connection = Connection::accept(stream, config)?;
+    let message = connection.try_read_message()?.inspect_err(|_| {
+        // the sender of a malformed message is unknown, report its IP address
+        events.lock().unwrap().audit().reject(&remote_address.ip().to_string(), RejectionReason::Malformed);
+        crate::quarantine::report_violation(quarantine, events, &remote_address.ip().to_string(), logger);
+    })?.ok_or("empty message")?;
+    Ok(Some((message, connection)))
 }
 
 /// Sends a message to another peer
@@ -65,9 +476,174 @@ fn handle_message(stream: &mut TcpStream, sender: &Sender<Message>, logger: Logg
 ///
 /// * `address` - Address of the peer
 /// * `message` - The message to be sent
-pub fn send(address: &SocketAddr, message: Message, logger: Logger) -> Result<(), Box<dyn Error>> {
+/// * `config` - The configuration of the sending node
+pub fn send(address: &SocketAddr, message: Message, config: &Config, logger: Logger) -> Result<(), Box<dyn Error>> {
     debug!(logger, "Sending -> {:?} to {:?}", message, address);
-    let mut stream = TcpStream::connect(address)?;
-    stream.write(&message.as_bytes())?;
+    Connection::connect(address, config)?.write_message(&message)
+}
+
+/// Sends a message to another peer and waits for its answer on the same connection.
+/// Returns None if the peer closed the connection without answering.
+///
+/// # Arguments
+///
+/// * `address` - Address of the peer
+/// * `message` - The message to be sent
+/// * `config` - The configuration of the sending node
+pub fn exchange(address: &SocketAddr, message: Message, config: &Config, logger: Logger) -> Result<Option<Message>, Box<dyn Error>> {
+    debug!(logger, "Exchanging -> {:?} with {:?}", message, address);
+    let mut connection = Connection::connect(address, config)?;
+    connection.write_message(&message)?;
+    connection.read_message()
+}
+
+/// Parses the address of a peer. Besides the usual socket addresses, accepts the scoped
+/// IPv6 addresses whose zone is an interface name, such as `[fe80::1%eth0]:9000`,
+/// the name being resolved to the index of the interface.
+///
+/// # Arguments
+///
+/// * `address` - The address of the peer
+pub fn parse_address(address: &str) -> Result<SocketAddr, Box<dyn Error>> {
+    if let Ok(address) = address.parse::<SocketAddr>() {
+        return Ok(address);
+    }
+    let (start, end) = match (address.find('%'), address.find(']')) {
+        (Some(start), Some(end)) if start < end => (start, end),
+        _ => Err(format!("invalid address {}", address))?,
+    };
+    let index = interface_index(&address[start + 1..end])?;
+    Ok(format!("{}%{}{}", &address[..start], index, &address[end..]).parse()?)
+}
+
+#[cfg(unix)]
+fn interface_index(name: &str) -> Result<u32, Box<dyn Error>> {
+    let name = std::ffi::CString::new(name)?;
+    // SAFETY: the name is a valid C string that outlives the call
+    match unsafe { libc::if_nametoindex(name.as_ptr()) } {
+        0 => Err(format!("unknown interface {}", name.to_string_lossy()))?,
+        index => Ok(index),
+    }
+}
+
+#[cfg(not(unix))]
+fn interface_index(name: &str) -> Result<u32, Box<dyn Error>> {
+    Err(format!("interface names are not supported on this platform, use the index of {}", name))?
+}
+
+/// Returns true if an error of a connection is a timeout
+///
+/// # Arguments
+///
+/// * `error` - The error returned by an exchange
+pub fn is_timeout(error: &(dyn Error + 'static)) -> bool {
+    match error.downcast_ref::<std::io::Error>() {
+        Some(e) => matches!(e.kind(), ErrorKind::TimedOut | ErrorKind::WouldBlock),
+        None => false,
+    }
+}
+
+/// Opens and closes a connection to the local listener, so that it
+/// wakes up and notices a shutdown request
+///
+/// # Arguments
+///
+/// * `address` - The listener bind address
+pub fn wake_listener(address: &SocketAddr) -> Result<(), Box<dyn Error>> {
+    TcpStream::connect(address)?;
     Ok(())
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn limits_outbound_connections() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let config = Config::new("127.0.0.1:0".parse().unwrap(), true, true, 1, 0, 20, 2, 8, None)
+            .with_exchange_timeout(50)
+            .with_max_outbound_connections(1);
+        let first = Connection::connect(&address, &config).unwrap();
+        // clones of the configuration share the limit
+        assert!(Connection::connect(&address, &config.clone()).is_err());
+        drop(first);
+        assert!(Connection::connect(&address, &config).is_ok());
+    }
+
+    #[test]
+    fn binds_the_next_free_port() {
+        let taken = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = taken.local_addr().unwrap();
+        let config = Config::new(address, true, true, 1, 0, 20, 2, 8, None)
+            .with_bind_retries(1, 10);
+        match bind_listener(&address, &config) {
+            Err(BindError::AddressInUse { address: in_use }) => assert_eq!(in_use, address),
+            other => panic!("unexpected result {:?}", other.map(|(_, local_address)| local_address)),
+        }
+        let (_, local_address) = bind_listener(&address, &config.with_port_range(16)).unwrap();
+        assert!(local_address.port() > address.port());
+    }
+
+    #[test]
+    fn messages_are_written_from_reused_buffers() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let config = Config::new("127.0.0.1:0".parse().unwrap(), true, true, 1, 0, 20, 2, 8, None);
+        let message = Message::new_request("127.0.0.1:9000".to_owned(), Some(vec![crate::Peer::new("127.0.0.1:9001".to_owned())]))
+            .with_payload(vec![7; 1000])
+            .with_parameters(crate::ProtocolParameters::new(20, 2, 8));
+        for _ in 0..2 {
+            Connection::connect(&address, &config).unwrap().write_message(&message).unwrap();
+            let mut connection = Connection::accept(listener.accept().unwrap().0, &config).unwrap();
+            let received = connection.read_message().unwrap().unwrap();
+            assert_eq!(received.as_bytes(), message.as_bytes());
+        }
+        assert!(BUFFER.with(|buffer| buffer.borrow().capacity()) >= message.as_bytes().len());
+    }
+
+    #[test]
+    fn silent_peers_time_out() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let config = Config::new("127.0.0.1:0".parse().unwrap(), true, true, 1, 0, 20, 2, 8, None)
+            .with_exchange_timeout(100);
+        // connected without writing
+        let _silent = TcpStream::connect(address).unwrap();
+        let mut connection = Connection::accept(listener.accept().unwrap().0, &config).unwrap();
+        let start = std::time::Instant::now();
+        let error = connection.read_message().unwrap_err();
+        assert!(is_timeout(error.as_ref()), "{}", error);
+        assert!(start.elapsed() < Duration::from_secs(2));
+    }
+
+    #[test]
+    fn silent_peers_time_out_without_exchange_timeout() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        // sampling period of 1 second, and no exchange timeout
+        let config = Config::new("127.0.0.1:0".parse().unwrap(), true, true, 1, 0, 20, 2, 8, None);
+        let _silent = TcpStream::connect(address).unwrap();
+        let mut connection = Connection::accept(listener.accept().unwrap().0, &config).unwrap();
+        let start = std::time::Instant::now();
+        let error = connection.read_message().unwrap_err();
+        assert!(is_timeout(error.as_ref()), "{}", error);
+        assert!(start.elapsed() < Duration::from_secs(3));
+    }
+
+    #[test]
+    fn larger_messages_than_the_frame_size_are_refused() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let config = Config::new("127.0.0.1:0".parse().unwrap(), true, true, 1, 0, 20, 2, 8, None)
+            .with_max_frame_size(500);
+        for (size, accepted) in [(400, true), (600, false)] {
+            let message = Message::new_request("127.0.0.1:9000".to_owned(), None).with_payload(vec![7; size]);
+            Connection::connect(&address, &config).unwrap().write_message(&message).unwrap();
+            let mut connection = Connection::accept(listener.accept().unwrap().0, &config).unwrap();
+            let received = connection.try_read_message().unwrap();
+            assert_eq!(received.is_ok(), accepted, "{:?}", received);
+        }
+    }
+}
@@ -0,0 +1,344 @@
+use std::error::Error;
+use std::io::{Read, Write};
+use std::net::{Shutdown, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use snow::{Builder, HandshakeState, TransportState};
+
+// Handshake pattern and primitives used for the encrypted channel
+const NOISE_PARAMS: &str = "Noise_XX_25519_ChaChaPoly_BLAKE2s";
+// Maximum size of a Noise message
+const MAX_NOISE_MESSAGE_SIZE: usize = 65535;
+// Size of the authentication tag appended to each encrypted message
+const TAG_SIZE: usize = 16;
+
+/// Static keys used for encrypting and authenticating the connections between peers
+/// with a Noise XX handshake.
+///
+/// The keys can be rotated while the node is running: the clones of a configuration share
+/// its keys, so that the changes made through the configuration given to
+/// [crate::Config::with_noise] apply to the next connections of the node. To rotate the key pair
+/// of a node without a coordinated restart, its new public key is first trusted by the other
+/// nodes along with the previous one, and the previous key is revoked once every node rotated.
+#[derive(Clone)]
+pub struct NoiseConfig {
+    /// Static private and public keys of the node
+    key_pair: Arc<Mutex<(Vec<u8>, Vec<u8>)>>,
+    /// Public keys of the nodes allowed to connect, no key being accepted if empty
+    trusted_keys: Arc<Mutex<Vec<Vec<u8>>>>,
+    /// Whether any key is accepted, regardless of the trusted keys
+    any_key_trusted: Arc<AtomicBool>,
+}
+
+impl NoiseConfig {
+    /// Creates a configuration from an existing static key pair, accepting any key until trusted keys are set
+    ///
+    /// # Arguments
+    ///
+    /// * `private_key` - Static private key of the node
+    /// * `public_key` - Static public key of the node
+    pub fn new(private_key: Vec<u8>, public_key: Vec<u8>) -> NoiseConfig {
+        NoiseConfig {
+            key_pair: Arc::new(Mutex::new((private_key, public_key))),
+            trusted_keys: Arc::new(Mutex::new(vec![])),
+            any_key_trusted: Arc::new(AtomicBool::new(true)),
+        }
+    }
+
+    /// Creates a configuration with a newly generated static key pair
+    pub fn generate() -> Result<NoiseConfig, Box<dyn Error>> {
+        let keypair = Builder::new(NOISE_PARAMS.parse()?).generate_keypair()?;
+        Ok(NoiseConfig::new(keypair.private, keypair.public))
+    }
+
+    /// Restricts the connections to the nodes whose static public key is in the list, no node being accepted if it is empty
+    ///
+    /// # Arguments
+    ///
+    /// * `trusted_keys` - The public keys of the trusted nodes, including the previous keys of the nodes being rotated
+    pub fn with_trusted_keys(self, trusted_keys: Vec<Vec<u8>>) -> NoiseConfig {
+        *self.trusted_keys.lock().unwrap() = trusted_keys;
+        self.any_key_trusted.store(false, Ordering::SeqCst);
+        self
+    }
+
+    /// Sets whether the connections of any node are accepted, or only those of the trusted keys
+    ///
+    /// # Arguments
+    ///
+    /// * `any_key_trusted` - Whether any key is accepted
+    pub fn with_any_key_trusted(self, any_key_trusted: bool) -> NoiseConfig {
+        self.any_key_trusted.store(any_key_trusted, Ordering::SeqCst);
+        self
+    }
+
+    /// Returns the static public key of the node
+    pub fn public_key(&self) -> Vec<u8> {
+        self.key_pair.lock().unwrap().1.clone()
+    }
+
+    /// Returns the public keys of the trusted nodes
+    pub fn trusted_keys(&self) -> Vec<Vec<u8>> {
+        self.trusted_keys.lock().unwrap().clone()
+    }
+
+    pub fn is_any_key_trusted(&self) -> bool {
+        self.any_key_trusted.load(Ordering::SeqCst)
+    }
+
+    /// Replaces the static key pair of the node for the next connections
+    ///
+    /// # Arguments
+    ///
+    /// * `private_key` - New static private key of the node
+    /// * `public_key` - New static public key of the node
+    pub fn rotate_key_pair(&self, private_key: Vec<u8>, public_key: Vec<u8>) {
+        *self.key_pair.lock().unwrap() = (private_key, public_key);
+    }
+
+    /// Replaces the static key pair of the node with a newly generated one, and returns the new public key
+    pub fn rotate(&self) -> Result<Vec<u8>, Box<dyn Error>> {
+        let keypair = Builder::new(NOISE_PARAMS.parse()?).generate_keypair()?;
+        self.rotate_key_pair(keypair.private, keypair.public.clone());
+        Ok(keypair.public)
+    }
+
+    /// Accepts the connections of a node with a public key, such as the new key of a node being rotated
+    ///
+    /// # Arguments
+    ///
+    /// * `public_key` - Static public key of the node
+    pub fn trust_key(&self, public_key: Vec<u8>) {
+        let mut trusted_keys = self.trusted_keys.lock().unwrap();
+        if !trusted_keys.contains(&public_key) {
+            trusted_keys.push(public_key);
+        }
+    }
+
+    /// Refuses the next connections with a public key, such as the previous key of a node that was rotated.
+    /// Only the remaining trusted keys are accepted afterwards, even if any key was, and no key once the last one is revoked.
+    ///
+    /// # Arguments
+    ///
+    /// * `public_key` - Static public key no longer trusted
+    pub fn revoke_key(&self, public_key: &[u8]) {
+        let mut trusted_keys = self.trusted_keys.lock().unwrap();
+        trusted_keys.retain(|trusted| trusted != public_key);
+        self.any_key_trusted.store(false, Ordering::SeqCst);
+    }
+
+    fn private_key(&self) -> Vec<u8> {
+        self.key_pair.lock().unwrap().0.clone()
+    }
+
+    /// Checks the static key authenticated by the remote node during the handshake
+    fn check_remote(&self, handshake: &HandshakeState) -> Result<(), Box<dyn Error>> {
+        let trusted_keys = self.trusted_keys.lock().unwrap();
+        match handshake.get_remote_static() {
+            Some(key) if self.is_any_key_trusted() || trusted_keys.iter().any(|trusted| trusted == key) => Ok(()),
+            Some(_) => Err("untrusted remote static key")?,
+            None => Err("missing remote static key")?,
+        }
+    }
+}
+
+/// Performs the handshake as initiator and returns the state for encrypting the transport
+///
+/// # Arguments
+///
+/// * `stream` - A stream connected to the remote node
+/// * `config` - The static keys of the node
+pub fn initiate(stream: &mut TcpStream, config: &NoiseConfig) -> Result<TransportState, Box<dyn Error>> {
+    let mut handshake = Builder::new(NOISE_PARAMS.parse()?)
+        .local_private_key(&config.private_key())
+        .build_initiator()?;
+    let mut buffer = vec![0u8; MAX_NOISE_MESSAGE_SIZE];
+
+    // -> e
+    let length = handshake.write_message(&[], &mut buffer)?;
+    write_frame(stream, &buffer[..length])?;
+    // <- e, ee, s, es
+    let frame = read_frame(stream)?.ok_or("connection closed during handshake")?;
+    handshake.read_message(&frame, &mut buffer)?;
+    // -> s, se
+    let length = handshake.write_message(&[], &mut buffer)?;
+    write_frame(stream, &buffer[..length])?;
+    config.check_remote(&handshake)?;
+
+    Ok(handshake.into_transport_mode()?)
+}
+
+/// Performs the handshake as responder and returns the state for encrypting the transport
+///
+/// # Arguments
+///
+/// * `stream` - A stream accepted from the remote node
+/// * `config` - The static keys of the node
+pub fn respond(stream: &mut TcpStream, config: &NoiseConfig) -> Result<TransportState, Box<dyn Error>> {
+    let mut handshake = Builder::new(NOISE_PARAMS.parse()?)
+        .local_private_key(&config.private_key())
+        .build_responder()?;
+    let mut buffer = vec![0u8; MAX_NOISE_MESSAGE_SIZE];
+
+    // <- e
+    let frame = read_frame(stream)?.ok_or("connection closed during handshake")?;
+    handshake.read_message(&frame, &mut buffer)?;
+    // -> e, ee, s, es
+    let length = handshake.write_message(&[], &mut buffer)?;
+    write_frame(stream, &buffer[..length])?;
+    // <- s, se
+    let frame = read_frame(stream)?.ok_or("connection closed during handshake")?;
+    handshake.read_message(&frame, &mut buffer)?;
+    config.check_remote(&handshake)?;
+
+    Ok(handshake.into_transport_mode()?)
+}
+
+/// Encrypts and writes bytes, then closes the writing half of the stream
+///
+/// # Arguments
+///
+/// * `stream` - A stream with a completed handshake
+/// * `transport` - The transport state resulting from the handshake
+/// * `bytes` - The bytes to be sent
+pub fn write(stream: &mut TcpStream, transport: &mut TransportState, bytes: &[u8]) -> Result<(), Box<dyn Error>> {
+    let mut buffer = vec![0u8; MAX_NOISE_MESSAGE_SIZE];
+    for chunk in bytes.chunks(MAX_NOISE_MESSAGE_SIZE - TAG_SIZE) {
+        let length = transport.write_message(chunk, &mut buffer)?;
+        write_frame(stream, &buffer[..length])?;
+    }
+    stream.shutdown(Shutdown::Write)?;
+    Ok(())
+}
+
+/// Reads and decrypts bytes until the remote node closes its writing half of the stream
+///
+/// # Arguments
+///
+/// * `stream` - A stream with a completed handshake
+/// * `transport` - The transport state resulting from the handshake
+/// * `limit` - The number of bytes after which reading stops, 0 for no limit
+pub fn read(stream: &mut TcpStream, transport: &mut TransportState, limit: usize) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut buffer = vec![0u8; MAX_NOISE_MESSAGE_SIZE];
+    let mut bytes = vec![];
+    while let Some(frame) = read_frame(stream)? {
+        let length = transport.read_message(&frame, &mut buffer)?;
+        bytes.extend_from_slice(&buffer[..length]);
+        if limit > 0 && bytes.len() > limit {
+            break;
+        }
+    }
+    Ok(bytes)
+}
+
+/// Writes a Noise message prefixed with its length on two bytes
+fn write_frame(stream: &mut TcpStream, message: &[u8]) -> std::io::Result<()> {
+    stream.write_all(&(message.len() as u16).to_be_bytes())?;
+    stream.write_all(message)
+}
+
+/// Reads a Noise message prefixed with its length, returns None if the stream was closed
+fn read_frame(stream: &mut TcpStream) -> std::io::Result<Option<Vec<u8>>> {
+    let mut length = [0u8; 2];
+    match stream.read_exact(&mut length) {
+        Ok(()) => (),
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+    let mut message = vec![0u8; u16::from_be_bytes(length) as usize];
+    stream.read_exact(&mut message)?;
+    Ok(Some(message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    fn exchange(sender: NoiseConfig, receiver: NoiseConfig, bytes: Vec<u8>) -> Result<Vec<u8>, String> {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let handle = std::thread::spawn(move || {
+            let mut stream = TcpStream::connect(address).unwrap();
+            let mut transport = initiate(&mut stream, &sender).map_err(|e| e.to_string())?;
+            write(&mut stream, &mut transport, &bytes).map_err(|e| e.to_string())?;
+            // the answer is the request reversed
+            read(&mut stream, &mut transport, 0).map_err(|e| e.to_string())
+        });
+        let (mut stream, _) = listener.accept().unwrap();
+        if let Ok(mut transport) = respond(&mut stream, &receiver) {
+            let mut received = read(&mut stream, &mut transport, 0).unwrap();
+            received.reverse();
+            write(&mut stream, &mut transport, &received).unwrap();
+        }
+        drop(stream);
+        handle.join().unwrap()
+    }
+
+    #[test]
+    fn encrypted_round_trip() {
+        let sender = NoiseConfig::generate().unwrap();
+        let receiver = NoiseConfig::generate().unwrap()
+            .with_trusted_keys(vec![sender.public_key()]);
+        let bytes = (0..100_000).map(|i| i as u8).collect::<Vec<u8>>();
+        let mut reversed = bytes.clone();
+        reversed.reverse();
+        assert_eq!(exchange(sender, receiver, bytes).unwrap(), reversed);
+    }
+
+    #[test]
+    fn rejects_untrusted_key() {
+        let sender = NoiseConfig::generate().unwrap();
+        let other = NoiseConfig::generate().unwrap();
+        let receiver = NoiseConfig::generate().unwrap()
+            .with_trusted_keys(vec![other.public_key()]);
+        assert!(exchange(sender, receiver, vec![1, 2, 3]).is_err());
+    }
+
+    #[test]
+    fn rotates_keys_without_restart() {
+        let keypair = Builder::new(NOISE_PARAMS.parse().unwrap()).generate_keypair().unwrap();
+        let sender = NoiseConfig::new(keypair.private.clone(), keypair.public.clone());
+        // a node that kept the previous key pair
+        let stale = NoiseConfig::new(keypair.private, keypair.public.clone());
+        let receiver = NoiseConfig::generate().unwrap()
+            .with_trusted_keys(vec![keypair.public.clone()]);
+
+        // the clones given to the service share the keys
+        let new_key = sender.clone().rotate().unwrap();
+        assert_eq!(sender.public_key(), new_key);
+        assert!(exchange(sender.clone(), receiver.clone(), vec![1]).is_err());
+
+        // the new key is trusted along with the previous one, then the previous one is revoked
+        receiver.trust_key(new_key.clone());
+        assert!(exchange(sender.clone(), receiver.clone(), vec![1]).is_ok());
+        assert!(exchange(stale.clone(), receiver.clone(), vec![1]).is_ok());
+        receiver.revoke_key(&keypair.public);
+        assert_eq!(receiver.trusted_keys(), vec![new_key]);
+        assert!(exchange(stale, receiver.clone(), vec![1]).is_err());
+        assert!(exchange(sender, receiver, vec![1]).is_ok());
+    }
+
+    #[test]
+    fn revoking_the_last_key_refuses_every_key() {
+        let sender = NoiseConfig::generate().unwrap();
+        let other = NoiseConfig::generate().unwrap();
+        let receiver = NoiseConfig::generate().unwrap()
+            .with_trusted_keys(vec![sender.public_key()]);
+        assert!(exchange(sender.clone(), receiver.clone(), vec![1]).is_ok());
+        receiver.revoke_key(&sender.public_key());
+        assert!(receiver.trusted_keys().is_empty());
+        assert!(exchange(sender.clone(), receiver.clone(), vec![1]).is_err());
+        assert!(exchange(other, receiver.clone(), vec![1]).is_err());
+
+        // a revocation also applies to a node accepting any key
+        let receiver = NoiseConfig::generate().unwrap();
+        assert!(receiver.is_any_key_trusted());
+        assert!(exchange(sender.clone(), receiver.clone(), vec![1]).is_ok());
+        receiver.revoke_key(&sender.public_key());
+        assert!(!receiver.is_any_key_trusted());
+        assert!(exchange(sender.clone(), receiver.clone(), vec![1]).is_err());
+        assert!(exchange(sender, receiver.with_any_key_trusted(true), vec![1]).is_ok());
+    }
+}
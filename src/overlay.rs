@@ -0,0 +1,153 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::peer::Peer;
+
+/// Knowledge of the overlay made of the views of some of its nodes,
+/// exportable as a graph where each node points to the peers of its view
+#[derive(Default)]
+pub struct Overlay {
+    /// The view of each known node, by address
+    views: BTreeMap<String, Vec<Peer>>,
+}
+
+impl Overlay {
+    /// Creates an empty overlay
+    pub fn new() -> Overlay {
+        Overlay::default()
+    }
+
+    /// Adds or replaces the view of a node, for instance a view reported through monitoring
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - Address of the node
+    /// * `peers` - The view of the node
+    pub fn add_view(&mut self, address: String, peers: Vec<Peer>) {
+        self.views.insert(address, peers);
+    }
+
+    /// Returns the addresses of all the nodes of the graph, whether their view is known or not
+    fn nodes(&self) -> BTreeSet<&str> {
+        self.views.iter()
+            .flat_map(|(address, peers)| std::iter::once(address.as_str()).chain(peers.iter().map(|peer| peer.address())))
+            .collect()
+    }
+
+    /// Returns the number of nodes of the graph, whether their view is known or not
+    pub fn node_count(&self) -> usize {
+        self.nodes().len()
+    }
+
+    /// Returns the number of edges of the graph, one for each peer of each known view
+    pub fn edge_count(&self) -> usize {
+        self.views.values().map(|peers| peers.len()).sum()
+    }
+
+    /// Returns true if every node can be reached from every other, ignoring the direction of the edges
+    pub fn is_connected(&self) -> bool {
+        let nodes = self.nodes();
+        let mut neighbours: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+        for (address, peers) in &self.views {
+            for peer in peers {
+                neighbours.entry(address.as_str()).or_default().push(peer.address());
+                neighbours.entry(peer.address()).or_default().push(address.as_str());
+            }
+        }
+        let mut reached = BTreeSet::new();
+        let mut pending: Vec<&str> = nodes.iter().take(1).copied().collect();
+        while let Some(node) = pending.pop() {
+            if reached.insert(node) {
+                pending.extend(neighbours.get(node).into_iter().flatten().copied());
+            }
+        }
+        reached.len() == nodes.len()
+    }
+
+    /// Exports the overlay in the DOT format of Graphviz, edges being labelled with the age of the peers
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph overlay {\n");
+        for node in self.nodes() {
+            dot.push_str(&format!("    \"{}\";\n", escape_dot(node)));
+        }
+        for (address, peers) in &self.views {
+            for peer in peers {
+                dot.push_str(&format!("    \"{}\" -> \"{}\" [label=\"{}\"];\n", escape_dot(address), escape_dot(peer.address()), peer.age()));
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// Exports the overlay in the GraphML format, edges having the age of the peers as attribute
+    pub fn to_graphml(&self) -> String {
+        let mut graphml = String::from(concat!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+            "<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">\n",
+            "  <key id=\"age\" for=\"edge\" attr.name=\"age\" attr.type=\"int\"/>\n",
+            "  <graph id=\"overlay\" edgedefault=\"directed\">\n"));
+        for node in self.nodes() {
+            graphml.push_str(&format!("    <node id=\"{}\"/>\n", escape_xml(node)));
+        }
+        for (address, peers) in &self.views {
+            for peer in peers {
+                graphml.push_str(&format!("    <edge source=\"{}\" target=\"{}\"><data key=\"age\">{}</data></edge>\n",
+                    escape_xml(address), escape_xml(peer.address()), peer.age()));
+            }
+        }
+        graphml.push_str("  </graph>\n</graphml>\n");
+        graphml
+    }
+}
+
+fn escape_dot(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn escape_xml(value: &str) -> String {
+    value.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;").replace('\'', "&apos;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn overlay() -> Overlay {
+        let mut overlay = Overlay::new();
+        let peer = Peer::with_age("127.0.0.1:9001".to_owned(), 1);
+        overlay.add_view("127.0.0.1:9000".to_owned(), vec![peer, Peer::new("[::1]:9002".to_owned())]);
+        overlay
+    }
+
+    #[test]
+    fn exports_dot() {
+        assert_eq!(overlay().to_dot(), concat!(
+            "digraph overlay {\n",
+            "    \"127.0.0.1:9000\";\n",
+            "    \"127.0.0.1:9001\";\n",
+            "    \"[::1]:9002\";\n",
+            "    \"127.0.0.1:9000\" -> \"127.0.0.1:9001\" [label=\"1\"];\n",
+            "    \"127.0.0.1:9000\" -> \"[::1]:9002\" [label=\"0\"];\n",
+            "}\n"));
+    }
+
+    #[test]
+    fn exports_graphml() {
+        let graphml = overlay().to_graphml();
+        assert_eq!(graphml.matches("<node ").count(), 3);
+        assert_eq!((overlay().node_count(), overlay().edge_count()), (3, 2));
+        assert!(graphml.contains("<edge source=\"127.0.0.1:9000\" target=\"127.0.0.1:9001\"><data key=\"age\">1</data></edge>"));
+        assert_eq!(escape_xml("<a&'\">"), "&lt;a&amp;&apos;&quot;&gt;");
+    }
+
+    #[test]
+    fn checks_connectivity() {
+        let mut overlay = overlay();
+        assert!(overlay.is_connected());
+        overlay.add_view("127.0.0.1:9003".to_owned(), vec![Peer::new("127.0.0.1:9004".to_owned())]);
+        assert!(!overlay.is_connected());
+        // connected through an edge in either direction
+        overlay.add_view("127.0.0.1:9004".to_owned(), vec![Peer::new("127.0.0.1:9001".to_owned())]);
+        assert!(overlay.is_connected());
+        assert!(Overlay::new().is_connected());
+    }
+}
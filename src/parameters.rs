@@ -0,0 +1,124 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::config::Config;
+
+// Maximum number of senders whose mismatching parameters are remembered
+const MAX_MISMATCHES: usize = 1024;
+
+/// The parameters of the view selection of a node, advertised in its exchanges
+/// so that nodes configured differently can interoperate predictably,
+/// see [crate::Config::with_advertised_parameters]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ProtocolParameters {
+    /// Size of the view
+    view_size: usize,
+    /// Healing parameter
+    healing_factor: usize,
+    /// Swapping parameter
+    swapping_factor: usize,
+}
+
+impl ProtocolParameters {
+    /// Creates the parameters of a node
+    ///
+    /// # Arguments
+    ///
+    /// * `view_size` - Size of the view, at most 65535
+    /// * `healing_factor` - Healing parameter, at most 65535
+    /// * `swapping_factor` - Swapping parameter, at most 65535
+    pub fn new(view_size: usize, healing_factor: usize, swapping_factor: usize) -> ProtocolParameters {
+        ProtocolParameters { view_size, healing_factor, swapping_factor }
+    }
+
+    /// Returns the parameters of a configuration
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The configuration
+    pub(crate) fn of(config: &Config) -> ProtocolParameters {
+        ProtocolParameters::new(config.view_size(), config.healing_factor(), config.swapping_factor())
+    }
+
+    pub fn view_size(&self) -> usize {
+        self.view_size
+    }
+
+    pub fn healing_factor(&self) -> usize {
+        self.healing_factor
+    }
+
+    pub fn swapping_factor(&self) -> usize {
+        self.swapping_factor
+    }
+}
+
+impl fmt::Display for ProtocolParameters {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "c={} h={} s={}", self.view_size, self.healing_factor, self.swapping_factor)
+    }
+}
+
+/// The peers whose advertised parameters differ from those of the node
+pub(crate) struct ParameterMismatches {
+    /// Parameters of the node
+    own: ProtocolParameters,
+    /// Last parameters advertised by each mismatching peer
+    peers: HashMap<String, ProtocolParameters>,
+}
+
+impl ParameterMismatches {
+    pub(crate) fn new(own: ProtocolParameters) -> ParameterMismatches {
+        ParameterMismatches {
+            own,
+            peers: HashMap::new(),
+        }
+    }
+
+    /// Records the parameters advertised by a peer, and returns whether they are a new mismatch,
+    /// either from a peer not known to mismatch or from a peer that changed its parameters
+    ///
+    /// # Arguments
+    ///
+    /// * `peer` - Address of the peer
+    /// * `parameters` - The parameters advertised by the peer
+    pub(crate) fn record(&mut self, peer: &str, parameters: ProtocolParameters) -> bool {
+        if parameters == self.own {
+            self.peers.remove(peer);
+            return false;
+        }
+        if self.peers.get(peer) == Some(&parameters) {
+            return false;
+        }
+        // senders are not authenticated, so that their number is bounded
+        if self.peers.len() >= MAX_MISMATCHES && !self.peers.contains_key(peer) {
+            return false;
+        }
+        self.peers.insert(peer.to_owned(), parameters);
+        true
+    }
+
+    /// Returns the peers known to advertise parameters different from those of the node
+    pub(crate) fn peers(&self) -> HashMap<String, ProtocolParameters> {
+        self.peers.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_each_mismatch_once() {
+        let mut mismatches = ParameterMismatches::new(ProtocolParameters::new(20, 2, 8));
+        assert!(!mismatches.record("127.0.0.1:9001", ProtocolParameters::new(20, 2, 8)));
+        assert!(mismatches.record("127.0.0.1:9002", ProtocolParameters::new(30, 2, 8)));
+        assert!(!mismatches.record("127.0.0.1:9002", ProtocolParameters::new(30, 2, 8)));
+        assert!(mismatches.record("127.0.0.1:9002", ProtocolParameters::new(30, 1, 14)));
+        assert_eq!(mismatches.peers().len(), 1);
+
+        // a reconfigured peer is forgotten
+        assert!(!mismatches.record("127.0.0.1:9002", ProtocolParameters::new(20, 2, 8)));
+        assert!(mismatches.peers().is_empty());
+    }
+}
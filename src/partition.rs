@@ -0,0 +1,101 @@
+use std::collections::{HashSet, VecDeque};
+
+/// Suspects a network partition from the outcome of the last exchanges and the size of the view
+pub struct PartitionDetector {
+    /// Number of cycles observed
+    window: usize,
+    /// Partner and success of the last exchanges
+    exchanges: VecDeque<(String, bool)>,
+    /// Size of the view at the last cycles
+    view_sizes: VecDeque<usize>,
+}
+
+impl PartitionDetector {
+    /// Creates a detector observing the given number of cycles
+    ///
+    /// # Arguments
+    ///
+    /// * `window` - Number of cycles observed
+    pub fn new(window: usize) -> PartitionDetector {
+        PartitionDetector {
+            window,
+            exchanges: VecDeque::with_capacity(window),
+            view_sizes: VecDeque::with_capacity(window),
+        }
+    }
+
+    /// Records the outcome of a cycle
+    ///
+    /// # Arguments
+    ///
+    /// * `partner` - Address of the peer contacted
+    /// * `success` - Whether the peer answered
+    /// * `view_size` - Size of the view at the end of the cycle
+    pub fn record(&mut self, partner: &str, success: bool, view_size: usize) {
+        if self.exchanges.len() == self.window {
+            self.exchanges.pop_front();
+            self.view_sizes.pop_front();
+        }
+        self.exchanges.push_back((partner.to_owned(), success));
+        self.view_sizes.push_back(view_size);
+    }
+
+    /// Returns the peers that could not be reached if a partition is suspected:
+    /// at least half of the exchanges of a full window failed, with at least two
+    /// different peers, or the view shrank to less than half of its size.
+    /// The observations are cleared when a partition is suspected.
+    pub fn check(&mut self) -> Option<Vec<String>> {
+        if self.window == 0 || self.exchanges.len() < self.window {
+            return None;
+        }
+
+        let unreachable = self.exchanges.iter()
+            .filter(|(_, success)| !success)
+            .map(|(partner, _)| partner.clone())
+            .collect::<HashSet<String>>();
+        let failures = self.exchanges.iter().filter(|(_, success)| !success).count();
+        let max_view_size = self.view_sizes.iter().max().copied().unwrap_or(0);
+        let view_size = self.view_sizes.back().copied().unwrap_or(0);
+
+        if (failures * 2 >= self.window && unreachable.len() >= 2) || view_size * 2 < max_view_size {
+            self.exchanges.clear();
+            self.view_sizes.clear();
+            let mut unreachable = unreachable.into_iter().collect::<Vec<String>>();
+            unreachable.sort();
+            Some(unreachable)
+        }
+        else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn suspects_partition_when_a_subset_fails() {
+        let mut detector = PartitionDetector::new(4);
+        detector.record("a", true, 10);
+        detector.record("b", false, 10);
+        detector.record("c", true, 10);
+        assert!(detector.check().is_none());
+        // a single unreachable peer is not a partition
+        detector.record("b", false, 10);
+        assert!(detector.check().is_none());
+        detector.record("d", false, 10);
+        assert_eq!(detector.check().unwrap(), vec!["b", "d"]);
+        // cleared after detection
+        assert!(detector.check().is_none());
+    }
+
+    #[test]
+    fn suspects_partition_when_view_shrinks() {
+        let mut detector = PartitionDetector::new(3);
+        detector.record("a", true, 10);
+        detector.record("a", true, 7);
+        detector.record("a", true, 4);
+        assert_eq!(detector.check().unwrap(), Vec::<String>::new());
+    }
+}
@@ -1,302 +1,196 @@
-use std::thread::JoinHandle;
-use std::time::Duration;
-use std::collections::{VecDeque, HashSet};
+use std::time::{Duration, Instant, SystemTime};
+use std::collections::{HashMap, VecDeque};
 use std::sync::{Arc, Mutex};
 use std::error::Error;
-use std::sync::mpsc::Receiver;
-use std::iter::FromIterator;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::mpsc::{Receiver, RecvTimeoutError, Sender};
 
-use rand::Rng;
-use rand::seq::SliceRandom;
 use slog::{debug, error, info, warn, Logger};
 
 use crate::message::{Message, MessageType};
 use std::hash::{Hash, Hasher};
-use crate::monitor::MonitoringConfig;
-use crate::config::Config;
-use std::net::SocketAddr;
+use crate::audit::{AuditLog, Rejection, RejectionReason};
+use crate::bootstrap::Bootstrap;
+use crate::backoff::SendBackoff;
+use crate::breaker::CircuitBreaker;
+use crate::config::{Config, ConfigError};
+use crate::event::{Event, Events};
+use crate::handle::SamplingHandle;
+use crate::replay::ReplayGuard;
+use crate::lanes::LaneReceiver;
+use crate::load::RequestLoad;
+#[cfg(feature = "port-mapping")]
+use crate::mapping::PortMapping;
+use crate::network::{BindError, Connection};
+use crate::overlay::Overlay;
+use crate::parameters::{ParameterMismatches, ProtocolParameters};
+use crate::partition::PartitionDetector;
+use crate::pending::PendingRequests;
+use crate::piggyback::Piggyback;
+use crate::policy::{SampleSource, SelfAgePolicy};
+use crate::quarantine::Quarantine;
+use crate::recent::RecentSenders;
+use crate::heartbeat::{ByteBudget, RecentPartners};
+use crate::recorder::{Recorder, Sample};
+use crate::relay::RelayQueues;
+use crate::score::{PeerScore, PeerScores};
+use crate::seen::LastSeen;
+use crate::size::SizeEstimator;
+use crate::stats::Stats;
+use crate::sampler::PeerSampler;
+use crate::selection::Descriptor;
+use crate::snapshot::ViewSnapshot;
+use crate::state::ServiceState;
+use crate::stream::PeerIter;
+use crate::supervisor::{CycleTrigger, InitError, ServiceHandle, Tasks};
+use crate::thread::TaskHandle;
+use crate::view::View;
+use std::net::{IpAddr, SocketAddr, TcpListener};
 use std::sync::atomic::AtomicBool;
 
-/// The view at each node
-struct View {
-    /// The address of the node
-    host_address: String,
-    /// The list of peers in the node view
-    peers: Vec<Peer>,
-    /// The queue from which peer are retrieved for the application layer
-    queue: VecDeque<Peer>,
-    /// Logger
-    logger: Logger,
-}
-impl View {
-    /// Creates a new view with the node's address
-    ///
-    /// # Arguments
-    ///
-    /// * `address` - Addres of peer
-    /// * `logger` - Logger
-    fn new(host_address: String, logger: Logger) -> View {
-        View {
-            host_address,
-            peers: vec![],
-            queue: VecDeque::new(),
-            logger,
-        }
-    }
-
-    /// Randomly select a peer for exchanging views at each cycle
-    fn select_peer(&self) -> Option<Peer> {
-        if self.peers.is_empty() {
-            None
-        }
-        else {
-            let selected_peer = rand::thread_rng().gen_range(0..self.peers.len());
-            Some(self.peers[selected_peer].clone())
-        }
-    }
-
-    /// Randomly reorder the current view
-    fn permute(&mut self) {
-        self.peers.shuffle(&mut rand::thread_rng());
-    }
+// Byte separator between the peer address and the peer age
+const SEPARATOR: u8 = 0x2C; // b','
+/// Maximum size in bytes of the locality label of a peer
+pub const MAX_LOCALITY_SIZE: usize = 64;
 
-    /// Move the oldest peers to the end of the view if the size
-    /// of the view is larger than the healing factor
-    ///
-    /// # Arguments
-    ///
-    /// * `h` - The number of peer that should be moved
-    fn move_oldest_to_end(&mut self, h: usize) {
-        if self.peers.len() > h {
-            let mut h_oldest_peers = self.peers.clone();
-            h_oldest_peers.sort_by_key(|peer| peer.age);
-            h_oldest_peers.reverse();
-            h_oldest_peers.truncate(h); //
-            // (peers.len - h) at the beginning, h at the end
-            let mut new_view_start = vec![];
-            let mut new_view_end = vec![];
-            for peer in &self.peers {
-                if h_oldest_peers.contains(&peer) {
-                    new_view_end.push(peer.clone());
-                }
-                else {
-                    new_view_start.push(peer.clone());
-                }
-            }
-            new_view_start.append(&mut new_view_end);
-            let _ = std::mem::replace(&mut self.peers, new_view_start);
-        }
-    }
+/// Information about a peer.
+///
+/// The age counts the cycles since the descriptor was created by the peer itself. It is set when
+/// the descriptor is created, only increases afterwards, saturating at its maximum, and is ignored
+/// when comparing peers, which are identified by their address.
+///
+/// The incarnation is that of the node when it created the descriptor, increasing when it restarts,
+/// so that the descriptors of its previous life are replaced, see [Config::with_incarnation].
+/// The locality is a label of the region or zone of the peer, see [Config::with_locality].
+#[derive(Clone, Debug)]
+pub struct Peer {
+    /// Socket address of the peer
+    address: String,
+    /// Age of the peer
+    age: u16,
+    /// Incarnation of the peer, 0 if not advertised
+    incarnation: u32,
+    /// Locality label of the peer, if advertised
+    locality: Option<String>,
+}
 
-    /// Returns the peers at the beginning of the view
+impl Peer {
+    /// Creates a new peer with the specified address and age 0
     ///
     /// # Arguments
     ///
-    /// * `c` - The size of the view
-    fn head(&self, c: usize) -> Vec<Peer> {
-        let count = std::cmp::min(c / 2 - 1, self.peers.len());
-        let mut head = Vec::new();
-        for i in 0..count {
-            head.push(self.peers[i].clone());
-        }
-        head
-    }
-
-    /// Increases by one the age of each peer in the view
-    fn increase_age(&mut self) {
-        for peer in self.peers.iter_mut() {
-            peer.age += 1;
-        }
+    /// * `address` - Network address of peer
+    pub fn new(address: String) -> Peer {
+        Peer {address, age: 0, incarnation: 0, locality: None}
     }
 
-    /// Merge a view received received from a peer with the current view
+    /// Creates a new peer with the specified address and age, such as a descriptor received
+    /// from another node or built by a test
     ///
     /// # Arguments
     ///
-    /// * `c` - The size of the view
-    /// * `h` - The healing parameter
-    /// * `s` - The swap parameter
-    /// * `buffer` - The view received
-    fn select(&mut self, c:usize, h: usize, s: usize, buffer: &Vec<Peer>, monitoring_config: MonitoringConfig) {
-        let my_address = self.host_address.clone();
-        // Add received peers to current view, omitting the node's own address
-        buffer.iter()
-            .filter(|peer| peer.address != my_address)
-            .for_each(|peer| self.peers.push(peer.clone()));
-        // Perform peer selection algorithm
-        self.remove_duplicates();
-        self.remove_old_items(c, h);
-        self.remove_head(c, s);
-        self.remove_at_random(c);
-        // Update peer queue for application layer
-        self.update_queue();
-
-        // Debug and monitoring
-        let new_view = self.peers.iter()
-            .map(|peer| peer.address.to_owned())
-            .collect::<Vec<String>>();
-        debug!(self.logger, "{}", new_view.join(", "));
-        if monitoring_config.enabled() {
-            monitoring_config.send_data(&self.host_address, new_view);
-        }
-    }
-
-    /// Removes duplicates peers from the view and keep the most recent one
-    fn remove_duplicates(&mut self) {
-        let mut unique_peers: HashSet<Peer> = HashSet::new();
-        self.peers.iter().for_each(|peer| {
-            if let Some(entry) = unique_peers.get(peer) {
-                // duplicate peer, check age
-                if peer.age < entry.age {
-                    unique_peers.replace(peer.clone());
-                }
-            }
-            else {
-                // unique peer
-                unique_peers.insert(peer.clone());
-            }
-        });
-        let new_view = Vec::from_iter(unique_peers);
-        let _ = std::mem::replace(&mut self.peers, new_view);
+    /// * `address` - Network address of peer
+    /// * `age` - Age of the descriptor in cycles
+    pub fn with_age(address: String, age: u16) -> Peer {
+        Peer {address, age, incarnation: 0, locality: None}
     }
 
-    /// Removes the oldest items from the view based on the healing parameter
+    /// Sets the incarnation of the node when it created the descriptor
     ///
     /// # Arguments
     ///
-    /// * `c` - The size of the view
-    /// * `h` - The healing parameter
-    fn remove_old_items(&mut self, c: usize, h: usize) {
-        let min = if self.peers.len() > c { self.peers.len() - c } else { 0 };
-        let removal_count = std::cmp::min(h, min);
-        if removal_count > 0 {
-            let mut kept_peers = self.peers.clone();
-            kept_peers.sort_by_key(|peer| peer.age);
-            kept_peers.truncate(kept_peers.len() - removal_count);
-            let mut new_view = vec![];
-            for peer in &self.peers {
-                if kept_peers.contains(&peer) {
-                    new_view.push(peer.clone());
-                }
-            }
-            let _ = std::mem::replace(&mut self.peers, new_view);
-        }
+    /// * `incarnation` - Incarnation of the peer
+    pub fn with_incarnation(mut self, incarnation: u32) -> Peer {
+        self.incarnation = incarnation;
+        self
     }
 
-    /// Removes peers at the beginning of the current view based on the swap parameter
-    ///
-    /// # Arguments
-    ///
-    /// * `c` - The size of the view
-    /// * `s` - The swap parameter
-    fn remove_head(&mut self, c: usize, s: usize) {
-        let min = if self.peers.len() > c { self.peers.len() - c } else { 0 };
-        let removal_count = std::cmp::min(s, min);
-        self.peers.drain(0..removal_count);
+    /// Increments the age of peer by one
+    pub fn increment_age(&mut self) {
+        self.age = self.age.saturating_add(1);
     }
 
-    /// Removes peers at random to match the view size parameter
+    /// Increases the age of peer, such as by the time elapsed when ages measure time
     ///
     /// # Arguments
     ///
-    /// * `c` - The size of the view
-    fn remove_at_random(&mut self, c: usize) {
-        if self.peers.len() > c {
-            for _ in 0..(self.peers.len() - c) {
-                let remove_index = rand::thread_rng().gen_range(0..self.peers.len());
-                self.peers.remove(remove_index);
-            }
-        }
+    /// * `amount` - The increase
+    pub(crate) fn add_age(&mut self, amount: u16) {
+        self.age = self.age.saturating_add(amount);
     }
 
-    /// Update peer queue by adding peers that appeared in the view
-    /// and removing those that were removed.
-    fn update_queue(&mut self) {
-
-        // compute index of removed peers
-        let removed_peers = self.queue.iter().enumerate()
-            .filter(|(_, peer)| !self.peers.contains(peer))
-            .map(|(index, _)| index)
-            .collect::<Vec<usize>>();
-
-        // compute new peers
-        let added_peers = self.peers.iter()
-            .filter(|peer| !self.queue.contains(peer))
-            .map(|peer| peer.to_owned())
-            .collect::<Vec<Peer>>();
-
-        // removed old peers by descending index
-        removed_peers.iter().rev().for_each(|index| { self.queue.remove(*index); });
-
-        // add new peers
-        for peer in added_peers {
-            self.queue.push_back(peer);
-        }
-    }
-
-    /// Returns a random peer for use in the application layer.
-    /// The peer is selected from the queue of newly added peers if available,
-    /// otherwise at random from the view.
-    pub fn get_peer(&mut self) -> Option<Peer> {
-        if let Some(peer) = self.queue.pop_front() {
-            Some(peer)
-        }
-        else {
-            self.select_peer()
-        }
+    /// Returns the age of peer
+    pub fn age(&self) -> u16 {
+        self.age
     }
-}
-
-// Byte separator between the peer address and the peer age
-const SEPARATOR: u8 = 0x2C; // b','
-
-/// Information about a peer
-#[derive(Clone, Debug)]
-pub struct Peer {
-    /// Socket address of the peer
-    address: String,
-    /// Age of the peer
-    age: u16,
-}
 
-impl Peer {
-    /// Creates a new peer with the specified address and age 0
+    /// Sets the locality label of the peer, such as its region or zone
     ///
     /// # Arguments
     ///
-    /// * `address` - Network address of peer
-    pub fn new(address: String) -> Peer {
-        Peer {address, age: 0}
+    /// * `locality` - Label of at most [MAX_LOCALITY_SIZE] bytes
+    pub fn with_locality(mut self, locality: String) -> Peer {
+        self.locality = Some(locality);
+        self
     }
 
-    /// Increments the age of peer by one
-    pub fn increment_age(&mut self) {
-        if self.age < u16::max_value() {
-            self.age += 1;
-        }
+    /// Returns the incarnation of peer, 0 if not advertised
+    pub fn incarnation(&self) -> u32 {
+        self.incarnation
     }
 
-    /// Returns the age of peer
-    pub fn age(&self) -> u16 {
-        self.age
+    /// Returns the locality label of peer, if advertised
+    pub fn locality(&self) -> Option<&str> {
+        self.locality.as_deref()
     }
 
     /// Returns the address of peer
     pub fn address(&self) -> &str { &self.address }
 
+    /// Returns the socket address of the peer, resolving the interface name
+    /// of a scoped IPv6 address such as `[fe80::1%eth0]:9000`
+    pub fn socket_address(&self) -> Result<SocketAddr, Box<dyn Error>> {
+        crate::network::parse_address(&self.address)
+    }
+
     /// Serializes peer into an array of bytes.
     /// Starts with the address of the peer first followed by the age of the peer
     /// address and age are separated by a [SEPARATOR] byte.
+    /// The incarnation follows on four bytes if not 0 or if there is a locality, so that the descriptors
+    /// without incarnation are read by the nodes ignoring incarnations, then the size of the locality
+    /// on one byte followed by the locality, if any.
+    /// Localities are truncated to [MAX_LOCALITY_SIZE] bytes.
     pub fn as_bytes(&self) -> Vec<u8> {
+        let mut v = vec![];
+        self.write_bytes(&mut v);
+        v
+    }
+
+    /// Serializes peer at the end of a buffer, as by [Peer::as_bytes]
+    ///
+    /// # Arguments
+    ///
+    /// * `v` - The buffer
+    pub(crate) fn write_bytes(&self, v: &mut Vec<u8>) {
         // peer address
-        let mut v = self.address.as_bytes().to_vec();
+        v.extend_from_slice(self.address.as_bytes());
         // separator
         v.push(SEPARATOR);
         // peer age: first byte
         v.push((self.age >> 8) as u8);
         // peer age: second byte
         v.push((self.age & 0x00FF) as u8);
-        v
+        // peer incarnation, if any
+        if self.incarnation > 0 || self.locality.is_some() {
+            v.extend_from_slice(&self.incarnation.to_be_bytes());
+        }
+        // peer locality, if any
+        if let Some(locality) = &self.locality {
+            let locality = &locality.as_bytes()[..locality.len().min(MAX_LOCALITY_SIZE)];
+            v.push(locality.len() as u8);
+            v.extend_from_slice(locality);
+        }
     }
 
     /// Deserializes a peer from an array of bytes
@@ -310,10 +204,19 @@ impl Peer {
             .find(|(_, b)| { **b == SEPARATOR})
             .map(|(i, _)| {i});
         if let Some(index) = separator_index {
-            // check that there are exactly two bytes for the age after separator
-            if bytes.len() != index + 3 {
-                Err("invalid age")?
-            }
+            // check that there are two bytes for the age after separator, and four for the incarnation if any
+            let extension = bytes.get(index+3..).ok_or("invalid age")?;
+            let incarnation = match extension.len() {
+                0 => 0,
+                1..=3 => Err("invalid age")?,
+                _ => u32::from_be_bytes([extension[0], extension[1], extension[2], extension[3]]),
+            };
+            // followed by the locality if any
+            let locality = match extension.get(4) {
+                Some(size) if extension.len() == 5 + *size as usize && (*size as usize) <= MAX_LOCALITY_SIZE => Some(String::from_utf8(extension[5..].to_vec())?),
+                Some(_) => Err("invalid locality")?,
+                None => None,
+            };
             // retrieve address
             let address = String::from_utf8(bytes[..index].to_vec())?;
             // build age
@@ -321,6 +224,8 @@ impl Peer {
             Ok(Peer{
                 address,
                 age,
+                incarnation,
+                locality,
             })
         }
         else {
@@ -339,6 +244,68 @@ impl Hash for Peer {
         self.address.hash(state)
     }
 }
+impl Descriptor for Peer {
+    fn age(&self) -> u16 {
+        self.age
+    }
+
+    fn increment_age(&mut self) {
+        Peer::increment_age(self)
+    }
+
+    /// A descriptor is fresher than another descriptor of the same peer
+    /// if of a later incarnation, or of the same incarnation and younger
+    fn is_fresher_than(&self, other: &Peer) -> bool {
+        (self.incarnation, std::cmp::Reverse(self.age)) > (other.incarnation, std::cmp::Reverse(other.age))
+    }
+}
+
+/// Formats the peer as its address followed by its age, such as `127.0.0.1:9000(3)`,
+/// by its incarnation if any, such as `127.0.0.1:9000(3,2)`, and by its locality if any, such as `127.0.0.1:9000(3,2,eu-west)`
+impl fmt::Display for Peer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(locality) = &self.locality {
+            write!(f, "{}({},{},{})", self.address, self.age, self.incarnation, locality)
+        }
+        else if self.incarnation > 0 {
+            write!(f, "{}({},{})", self.address, self.age, self.incarnation)
+        }
+        else {
+            write!(f, "{}({})", self.address, self.age)
+        }
+    }
+}
+
+/// Parses a peer formatted as its address followed by its age, such as `127.0.0.1:9000(3)`,
+/// and optionally its incarnation and its locality, such as `127.0.0.1:9000(3,2,eu-west)`, or as its address alone for a peer of age 0
+impl FromStr for Peer {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Peer, Box<dyn Error>> {
+        let s = s.trim();
+        let (address, age, incarnation, locality) = match s.strip_suffix(')') {
+            Some(rest) => {
+                let (address, fields) = rest.split_once('(').ok_or("missing opening parenthesis")?;
+                let mut fields = fields.splitn(3, ',');
+                let age = fields.next().unwrap_or_default().parse::<u16>().map_err(|_| "invalid age")?;
+                let incarnation = match fields.next() {
+                    Some(incarnation) => incarnation.parse::<u32>().map_err(|_| "invalid incarnation")?,
+                    None => 0,
+                };
+                (address, age, incarnation, fields.next())
+            }
+            None => (s, 0, 0, None),
+        };
+        if address.is_empty() {
+            Err("empty address")?
+        }
+        let peer = Peer::with_age(address.to_owned(), age).with_incarnation(incarnation);
+        Ok(match locality {
+            Some(locality) => peer.with_locality(locality.to_owned()),
+            None => peer,
+        })
+    }
+}
 
 /// Peer sampling service to by used by application
 pub struct PeerSamplingService {
@@ -347,11 +314,57 @@ pub struct PeerSamplingService {
     /// View containing a list of other peers
     view: Arc<Mutex<View>>,
     // Handles for activity threads
-    thread_handles: Vec<JoinHandle<()>>,
+    thread_handles: Vec<TaskHandle>,
     /// Handle for shutting down the TCP listener thread
     shutdown_tcp_listener: Arc<AtomicBool>,
     /// Handle for shutting down the peer sampling thread
     shutdown_peer_sampling: Arc<AtomicBool>,
+    /// Whether the node is leaving, answering pulls without advertising its descriptor
+    draining: Arc<AtomicBool>,
+    /// Pings waiting for an answer, by nonce
+    pending_pings: Arc<Mutex<HashMap<u64, Sender<()>>>>,
+    /// Receivers of the view updates
+    subscribers: Arc<Mutex<Vec<Sender<Vec<Peer>>>>>,
+    /// Receivers of the protocol events, and the last events
+    events: Arc<Mutex<Events>>,
+    /// Statistics of the protocol
+    stats: Arc<Mutex<Stats>>,
+    /// Behavior observed for each peer
+    scores: Arc<Mutex<PeerScores>>,
+    /// Senders of the recent messages, for recovering when the view is empty or unreachable
+    recent_senders: Arc<Mutex<RecentSenders>>,
+    /// Peers ignored after repeated protocol violations
+    quarantine: Arc<Mutex<Quarantine>>,
+    /// Estimator of the number of nodes of the overlay
+    size_estimator: Arc<Mutex<SizeEstimator>>,
+    /// Provider and consumer of the application payloads attached to the exchanges
+    piggyback: Arc<Mutex<Option<Box<dyn Piggyback>>>>,
+    /// Peers advertising parameters of the view selection different from those of the node
+    parameter_mismatches: Arc<Mutex<ParameterMismatches>>,
+    /// Requests sent to partners that were not answered yet
+    pending_requests: Arc<Mutex<PendingRequests>>,
+    /// Messages held for the nodes polling the node as their relay
+    relay_queues: Arc<Mutex<RelayQueues>>,
+    /// The last partners of the exchanges, sent heartbeats
+    recent_partners: Arc<Mutex<RecentPartners>>,
+    /// The last time each peer was seen
+    last_seen: Arc<Mutex<LastSeen>>,
+    /// The peers not attempted after repeated timeouts
+    breaker: Arc<Mutex<CircuitBreaker>>,
+    /// The peers not attempted after failed exchanges
+    backoff: Arc<Mutex<SendBackoff>>,
+    /// Triggers a cycle of the peer sampling thread, which acknowledges its completion with the partner contacted
+    cycle_trigger: CycleTrigger,
+    /// Handle on the tasks of the last start, stopping them on shutdown
+    service_handle: Option<ServiceHandle>,
+    /// Wakes the event loop after a cycle is triggered, if the service runs on a single thread
+    #[cfg(feature = "event-loop")]
+    cycle_waker: Option<Arc<mio::Waker>>,
+    /// Port mapping obtained from the gateway when binding, renewed by a task once the service starts
+    #[cfg(feature = "port-mapping")]
+    port_mapping: Option<PortMapping>,
+    /// The source of contact peers, given back by the peer sampling thread when it stops for restarting the service
+    bootstrap: Arc<Mutex<Option<Box<dyn Bootstrap>>>>,
     /// Logger
     logger: Logger,
 }
@@ -363,192 +376,2141 @@ impl PeerSamplingService {
     /// # Arguments
     ///
     /// * `config` - The parameters for the peer sampling protocol
+    ///
+    /// # Panics
+    ///
+    /// If the parameters are invalid, see [Config::validate]
     pub fn new(config: Config, logger: Logger) -> PeerSamplingService {
-        PeerSamplingService {
-            view: Arc::new(Mutex::new(View::new(config.address().to_string(), logger.clone()))),
-            config,
+        Self::try_new(config, logger).unwrap_or_else(|e| panic!("Invalid configuration: {}", e))
+    }
+
+    /// Create a new peer sampling service, or returns why the parameters are invalid
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The parameters for the peer sampling protocol
+    pub fn try_new(config: Config, logger: Logger) -> Result<PeerSamplingService, ConfigError> {
+        config.validate()?;
+        for warning in config.warnings() {
+            warn!(logger, "Configuration: {}", warning);
+        }
+        let mut view = View::new(config.address().to_string());
+        view.set_strict_selection(config.is_strict_selection());
+        view.set_sampling_policy(config.sampling_policy());
+        view.set_uniformity_window(config.uniformity_window());
+        view.set_prefix_quota(config.prefix_quota().cloned());
+        view.set_locality_quota(config.locality_quota());
+        view.set_age_unit(if config.age_unit() > 0 { Some(Duration::from_millis(config.age_unit())) } else { None });
+        let quarantine = Quarantine::new(config.quarantine_violations(), Duration::from_secs(config.quarantine_duration()));
+        let recent_senders = RecentSenders::new(config.recent_senders());
+        let mut events = Events::new(config.event_history());
+        events.set_audit(AuditLog::new(config.audit_capacity(), config.monitoring().enabled()));
+        let size_estimator = SizeEstimator::new(config.size_estimation_samples(), config.size_estimation_epoch());
+        // a request can be answered until the next cycle, or until the end of the exchange if it lasts longer
+        let answer_window = Duration::from_secs(config.sampling_period().max(1)).max(Duration::from_millis(config.exchange_timeout()));
+        Ok(PeerSamplingService {
+            view: Arc::new(Mutex::new(view)),
             thread_handles: Vec::new(),
             shutdown_tcp_listener: Arc::new(AtomicBool::new(false)),
             shutdown_peer_sampling: Arc::new(AtomicBool::new(false)),
+            draining: Arc::new(AtomicBool::new(false)),
+            pending_pings: Arc::new(Mutex::new(HashMap::new())),
+            subscribers: Arc::new(Mutex::new(vec![])),
+            events: Arc::new(Mutex::new(events)),
+            stats: Arc::new(Mutex::new(Stats::default())),
+            scores: Arc::new(Mutex::new(PeerScores::default())),
+            recent_senders: Arc::new(Mutex::new(recent_senders)),
+            quarantine: Arc::new(Mutex::new(quarantine)),
+            size_estimator: Arc::new(Mutex::new(size_estimator)),
+            piggyback: Arc::new(Mutex::new(None)),
+            parameter_mismatches: Arc::new(Mutex::new(ParameterMismatches::new(ProtocolParameters::of(&config)))),
+            pending_requests: Arc::new(Mutex::new(PendingRequests::new(answer_window))),
+            relay_queues: Arc::new(Mutex::new(RelayQueues::new(config.max_relayed_size(), config.max_relayed_per_second()))),
+            recent_partners: Arc::new(Mutex::new(RecentPartners::new(if config.heartbeat_interval() > 0 { config.heartbeat_partners() } else { 0 }))),
+            last_seen: Arc::new(Mutex::new(LastSeen::new(config.view_size() * crate::seen::TRACKED_PER_VIEW_ENTRY))),
+            breaker: Arc::new(Mutex::new(CircuitBreaker::new(config.breaker_timeouts(), Duration::from_secs(config.breaker_cooldown())))),
+            backoff: Arc::new(Mutex::new(SendBackoff::new(Duration::from_millis(config.send_backoff_base()), Duration::from_millis(config.send_backoff_max())))),
+            cycle_trigger: Arc::new(Mutex::new(None)),
+            service_handle: None,
+            #[cfg(feature = "event-loop")]
+            cycle_waker: None,
+            #[cfg(feature = "port-mapping")]
+            port_mapping: None,
+            bootstrap: Arc::new(Mutex::new(None)),
+            config,
             logger,
-        }
+        })
     }
 
-    /// Initializes service
+    /// Initializes service, and returns a handle for supervising its tasks. Fails without starting
+    /// if an address of the node cannot be bound, such as when its port is in use, so that the
+    /// application can retry with another address, or if a task could not be started.
     ///
     /// # Arguments
     ///
-    /// * `initial_peer` - A closure returning the initial peer for starting the protocol
-    pub fn init(&mut self, initial_peer: Box<dyn FnOnce() -> Option<Vec<Peer>>>) {
-        // get address of initial peer
-        if let Some(mut initial_peers) = initial_peer() {
-            self.view.lock().unwrap().peers.append(&mut initial_peers);
+    /// * `bootstrap` - The source of the initial peers for starting the protocol, queried again while the view is empty
+    pub fn init(&mut self, mut bootstrap: Box<dyn Bootstrap>) -> Result<ServiceHandle, InitError> {
+        // bind first, advertising the port assigned by the system if the configured port is 0
+        let listeners = self.bind()?;
+
+        // get address of initial peer, or of the peers known before a restart
+        let snapshot = self.snapshot();
+        if let Some(initial_peers) = bootstrap.peers().or_else(|| snapshot.as_ref().and_then(|snapshot| snapshot.load())) {
+            self.view.lock().unwrap().add_peers(initial_peers);
         }
 
-        // listen to incoming message
-        let (tx, rx) = std::sync::mpsc::channel();
-        let listener_handle = crate::network::start_listener(&self.config.address(), tx, &self.shutdown_tcp_listener, self.logger.clone());
-        self.thread_handles.push(listener_handle);
+        self.start(listeners, bootstrap, snapshot)
+    }
 
-        // handle received messages
-        let receiver_handle = self.start_receiver(rx);
-        self.thread_handles.push(receiver_handle);
+    /// Initializes the service with the source of contact peers selected in the configuration,
+    /// see [Config::with_bootstrap], the node waiting to be contacted by other peers if none was selected.
+    /// Returns a handle for supervising its tasks, failing as [PeerSamplingService::init] does.
+    pub fn init_configured(&mut self) -> Result<ServiceHandle, InitError> {
+        let bootstrap = match self.config.bootstrap() {
+            Some(provider) => provider.source(),
+            None => Box::new(|| None),
+        };
+        self.init(bootstrap)
+    }
 
-        // start peer sampling
-        let sampling_handle = self.start_sampling_activity();
-        self.thread_handles.push(sampling_handle);
+    /// Starts the service again after it was shut down, on the same addresses and with the same
+    /// bootstrap source. The last view is kept, or restored from the saved view if empty, and the
+    /// subscriptions, statistics and scores of the peers are preserved. Returns a handle for
+    /// supervising the new tasks.
+    pub fn restart(&mut self) -> Result<ServiceHandle, Box<dyn Error>> {
+        if !self.thread_handles.is_empty() {
+            Err("service is running")?
+        }
+        let bootstrap = self.bootstrap.lock().unwrap().take().ok_or("service was not initialized")?;
+        let listeners = match self.bind() {
+            Ok(listeners) => listeners,
+            Err(e) => {
+                // kept for another attempt
+                *self.bootstrap.lock().unwrap() = Some(bootstrap);
+                return Err(e.into());
+            }
+        };
+        // not shared with the handles of the previous start
+        self.shutdown_tcp_listener = Arc::new(AtomicBool::new(false));
+        self.shutdown_peer_sampling = Arc::new(AtomicBool::new(false));
+        self.draining.store(false, std::sync::atomic::Ordering::SeqCst);
+        self.config.next_incarnation();
+        // the descriptor of the new incarnation is new
+        self.view.lock().unwrap().reset_own_age();
 
-        info!(self.logger, "All activity threads were started");
+        let snapshot = self.snapshot();
+        {
+            let mut view = self.view.lock().unwrap();
+            if view.peers().is_empty() {
+                if let Some(peers) = snapshot.as_ref().and_then(|snapshot| snapshot.load()) {
+                    view.add_peers(peers);
+                }
+            }
+        }
+
+        info!(self.logger, "Restarting with {} peers", self.view.lock().unwrap().peers().len());
+        Ok(self.start(listeners, bootstrap, snapshot)?)
     }
 
-    /// Returns a random peer for the client application.
-    /// The peer is pseudo-random peer from the set of all peers.
-    /// The local view is built using [Gossip-Based Peer Sampling].
-    pub fn get_peer(&mut self) -> Option<Peer> {
-        self.view.lock().unwrap().get_peer()
+    /// Binds the listeners to the addresses of the node, and advertises the ports assigned by the system
+    /// and the external address mapped by the gateway or learned from the STUN servers, if requested
+    fn bind(&mut self) -> Result<Vec<TcpListener>, BindError> {
+        let (listeners, local_addresses): (Vec<TcpListener>, Vec<SocketAddr>) = self.config.addresses().iter()
+            .map(|address| crate::network::bind_listener(address, &self.config))
+            .collect::<Result<Vec<(TcpListener, SocketAddr)>, BindError>>()?
+            .into_iter()
+            .unzip();
+        self.config.set_address(local_addresses[0]);
+        self.config.set_additional_addresses(local_addresses[1..].to_vec());
+        #[cfg(feature = "port-mapping")]
+        self.map_port();
+        self.discover_external_address();
+        let mut view = self.view.lock().unwrap();
+        view.set_host_address(local_addresses[0].to_string());
+        view.set_aliases(Self::aliases(&self.config));
+        Ok(listeners)
     }
 
-    /// Stops the threads related to peer sampling activity
-    pub fn shutdown(&mut self) -> Result<(), Box<dyn Error>> {
-        // request shutdown
-        self.shutdown_peer_sampling.store(true, std::sync::atomic::Ordering::SeqCst);
-        self.shutdown_tcp_listener.store(true, std::sync::atomic::Ordering::SeqCst);
+    /// Returns the addresses of the node other than its bind address, which are omitted from the view
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The configuration, with the bound addresses
+    fn aliases(config: &Config) -> Vec<String> {
+        config.additional_addresses().iter().copied().chain(config.external_address()).map(|address| address.to_string()).collect()
+    }
+
+    /// Returns true if the port of the node was mapped by the gateway, whose external address is then advertised
+    fn is_port_mapped(&self) -> bool {
+        #[cfg(feature = "port-mapping")]
         {
-            let guard = self.view.lock().unwrap();
-            crate::network::send(&guard.host_address.parse()?, Message::new_response(guard.host_address.to_owned(), None), self.logger.clone())?;
+            self.port_mapping.is_some()
         }
-        // wait for termination
-        let handles = self.thread_handles.drain(..);
-        let mut join_error = false;
-        for handle in handles {
-            if let Err(e) = handle.join() {
-                error!(self.logger, "Error joining thread: {:?}", e);
-                join_error = true;
-            }
+        #[cfg(not(feature = "port-mapping"))]
+        {
+            false
         }
-        info!(self.logger, "All activity threads were stopped");
-        if join_error {
-            Err("An error occurred during thread joining")?
+    }
+
+    /// Learns the external address of the node from the STUN servers, if configured and the port
+    /// was not mapped, and advertises it instead of the bind address
+    fn discover_external_address(&mut self) {
+        if self.config.stun_servers().is_empty() || self.is_port_mapped() {
+            return;
         }
-        else {
-            Ok(())
+        match crate::stun::discover(self.config.stun_servers(), self.config.address(), crate::stun::SERVER_TIMEOUT) {
+            Ok(mapped) => {
+                let external = SocketAddr::new(mapped.ip(), self.config.address().port());
+                if mapped.port() != external.port() {
+                    debug!(self.logger, "The NAT does not preserve ports: the STUN servers saw port {}", mapped.port());
+                }
+                info!(self.logger, "Advertising {} learned from the STUN servers", external);
+                self.config.set_external_address(Some(external));
+            }
+            Err(e) => {
+                warn!(self.logger, "Advertising the bind address: {}", e);
+                self.config.set_external_address(None);
+            }
         }
     }
 
-    /// Builds the view to be exchanged with another peer
+    /// Starts the task querying the STUN servers periodically, which advertises the new external
+    /// address of the node when it changes, such as after the gateway was assigned another address
     ///
     /// # Arguments
     ///
-    /// * `config` - The configuration parameters
-    /// * `view` - The current view
-    fn build_buffer(config: &Config, view: &mut View) -> Vec<Peer> {
-        let mut buffer = vec![ Peer::new(config.address().to_string()) ];
-        view.permute();
-        view.move_oldest_to_end(config.healing_factor());
-        buffer.append(&mut view.head(config.view_size()));
-        buffer
+    /// * `tasks` - The tasks of the service, recording how the task ends
+    fn start_external_address_discovery(&mut self, tasks: &Arc<Tasks>) -> Result<(), InitError> {
+        if self.config.stun_servers().is_empty() || self.config.stun_interval() == 0 || self.is_port_mapped() {
+            return Ok(());
+        }
+        let config = self.config.clone();
+        let view = Arc::clone(&self.view);
+        let shutdown_requested = Arc::clone(&self.shutdown_peer_sampling);
+        let logger = self.logger.clone();
+        let threads = self.config.threads().clone();
+        let thread_handle = threads.spawn(&self.config.address().to_string(), "stun", tasks, logger.clone(), move || {
+            let period = Duration::from_secs(config.stun_interval());
+            let mut query = Instant::now() + period;
+            while !shutdown_requested.load(std::sync::atomic::Ordering::SeqCst) {
+                if Instant::now() >= query {
+                    match crate::stun::discover(config.stun_servers(), config.address(), crate::stun::SERVER_TIMEOUT) {
+                        Ok(mapped) => {
+                            let external = SocketAddr::new(mapped.ip(), config.address().port());
+                            if config.external_address() != Some(external) {
+                                info!(logger, "Advertising {} learned from the STUN servers", external);
+                                config.update_external_address(external);
+                                view.lock().unwrap().set_aliases(PeerSamplingService::aliases(&config));
+                            }
+                        }
+                        // the last address learned is still advertised
+                        Err(e) => warn!(logger, "Could not learn the external address: {}", e),
+                    }
+                    query = Instant::now() + period;
+                }
+                std::thread::sleep(Duration::from_millis(100));
+            }
+        })?;
+        self.thread_handles.push(thread_handle);
+        Ok(())
     }
 
-    /// Creates a thread for handling messages
+    /// Starts the task polling the relay of the node for the messages of the peers that could not
+    /// connect to it, if a relay is configured
     ///
     /// # Arguments
     ///
-    /// * `receiver` - The channel used for receiving incoming messages
-    fn start_receiver(&self, receiver: Receiver<Message>) -> JoinHandle<()>{
+    /// * `tasks` - The tasks of the service, recording how the task ends
+    fn start_relay_polling(&mut self, tasks: &Arc<Tasks>) -> Result<(), InitError> {
+        let relay = match self.config.relay() {
+            Some(relay) => *relay,
+            None => return Ok(()),
+        };
+        let mut handler = self.message_handler();
         let config = self.config.clone();
-        let view_arc = self.view.clone();
+        let shutdown_requested = Arc::clone(&self.shutdown_peer_sampling);
         let logger = self.logger.clone();
-        std::thread::Builder::new().name(format!("{} - gbps receiver", config.address())).spawn(move|| {
-            info!(logger, "Started message handling thread");
-            while let Ok(message) = receiver.recv() {
-                debug!(logger, "Received: {:?}", message);
-                let mut view = view_arc.lock().unwrap();
-                if let MessageType::Request = message.message_type() {
-                    if config.is_pull() {
-                        let buffer = Self::build_buffer(&config, &mut view);
-                        debug!(logger, "Built response buffer: {:?}", buffer);
-                        if let Ok(remote_address) = message.sender().parse::<SocketAddr>() {
-                            match crate::network::send(&remote_address, Message::new_response(config.address().to_string(), Some(buffer)), logger.clone()) {
-                                Ok(()) => debug!(logger, "Buffer sent successfully"),
-                                Err(e) => error!(logger, "Error sending buffer: {}", e),
+        let threads = self.config.threads().clone();
+        let thread_handle = threads.spawn(&self.config.address().to_string(), "relay", tasks, logger.clone(), move || {
+            let period = Duration::from_millis(config.relay_poll_interval());
+            let mut poll = Instant::now() + period;
+            while !shutdown_requested.load(std::sync::atomic::Ordering::SeqCst) {
+                if Instant::now() >= poll {
+                    // one held message per poll, until the relay has no more
+                    for _ in 0..crate::relay::MAX_HELD_MESSAGES {
+                        let request = Message::new_poll(config.address_for(&relay.to_string()).to_string());
+                        match crate::network::exchange(&relay, request, &config, logger.clone()) {
+                            Ok(Some(relayed)) if *relayed.message_type() == MessageType::Relay => handler.handle_relayed(relayed, &relay),
+                            Ok(Some(unexpected)) => {
+                                warn!(logger, "Unexpected answer to a poll from {}: {:?}", relay, unexpected.message_type());
+                                break;
+                            }
+                            Ok(None) => break,
+                            Err(e) => {
+                                warn!(logger, "Could not poll the relay {}: {}", relay, e);
+                                break;
                             }
-                        }
-                        else {
-                            error!(logger, "Could not parse sender address {}", &message.sender());
                         }
                     }
+                    poll = Instant::now() + period;
                 }
+                std::thread::sleep(Duration::from_millis(period.as_millis().min(100) as u64));
+            }
+        })?;
+        self.thread_handles.push(thread_handle);
+        Ok(())
+    }
 
-                if let Some(buffer) = message.view() {
-                    view.select(config.view_size(), config.healing_factor(), config.swapping_factor(), &buffer, config.monitoring().clone());
-                }
-                else {
-                    warn!(logger, "received a response with an empty buffer");
+    /// Starts the task sending heartbeats to the recent partners without exchange for the heartbeat
+    /// interval, if enabled, within the bandwidth allowed to the heartbeats
+    ///
+    /// # Arguments
+    ///
+    /// * `tasks` - The tasks of the service, recording how the task ends
+    fn start_heartbeats(&mut self, tasks: &Arc<Tasks>) -> Result<(), InitError> {
+        if self.config.heartbeat_interval() == 0 || self.config.heartbeat_partners() == 0 {
+            return Ok(());
+        }
+        let config = self.config.clone();
+        let recent_partners = Arc::clone(&self.recent_partners);
+        let shutdown_requested = Arc::clone(&self.shutdown_peer_sampling);
+        let logger = self.logger.clone();
+        let threads = self.config.threads().clone();
+        let thread_handle = threads.spawn(&self.config.address().to_string(), "heartbeat", tasks, logger.clone(), move || {
+            let interval = Duration::from_millis(config.heartbeat_interval());
+            let mut budget = ByteBudget::new(config.max_heartbeat_bytes_per_second());
+            while !shutdown_requested.load(std::sync::atomic::Ordering::SeqCst) {
+                let due = recent_partners.lock().unwrap().due(interval);
+                for partner in due {
+                    let address = match crate::network::parse_address(&partner) {
+                        Ok(address) => address,
+                        Err(_) => continue,
+                    };
+                    let mut ping = Message::new_ping(config.address_for(&partner).to_string());
+                    if config.is_observer() {
+                        ping = ping.with_observer_flag();
+                    }
+                    // the other partners are tried again at the next iteration
+                    if !budget.spend(ping.as_bytes().len()) {
+                        debug!(logger, "Heartbeat bandwidth exhausted, postponing the heartbeat to {}", partner);
+                        break;
+                    }
+                    recent_partners.lock().unwrap().refresh(&partner);
+                    if let Err(e) = crate::network::send(&address, ping, &config, logger.clone()) {
+                        debug!(logger, "Could not send a heartbeat to {}: {}", partner, e);
+                    }
                 }
+                std::thread::sleep(Duration::from_millis(100));
+            }
+        })?;
+        self.thread_handles.push(thread_handle);
+        Ok(())
+    }
 
-                view.increase_age();
+    /// Requests a mapping of the bound port from the gateway, if configured, and advertises the mapped
+    /// address instead of the bind address, or the bind address if no mapping was obtained
+    #[cfg(feature = "port-mapping")]
+    fn map_port(&mut self) {
+        let port_mapping = match self.config.port_mapping() {
+            Some(port_mapping) => port_mapping.clone(),
+            None => return,
+        };
+        match PortMapping::request(&port_mapping, self.config.address()) {
+            Ok(mapping) => {
+                info!(self.logger, "Advertising {} mapped by the gateway for {} seconds", mapping.external(), mapping.lifetime().as_secs());
+                self.config.set_external_address(Some(mapping.external()));
+                self.port_mapping = Some(mapping);
             }
-            info!(logger, "Message handling thread exiting");
-        }).unwrap()
+            Err(e) => {
+                warn!(self.logger, "Advertising the bind address: {}", e);
+                self.config.set_external_address(None);
+            }
+        }
     }
 
-    /// Creates a thread that periodically executes the peer sampling
-    fn start_sampling_activity(&self) -> JoinHandle<()> {
+    /// Starts the task renewing the port mapping before it expires, which removes it when the service stops
+    ///
+    /// # Arguments
+    ///
+    /// * `tasks` - The tasks of the service, recording how the task ends
+    #[cfg(feature = "port-mapping")]
+    fn start_port_mapping(&mut self, tasks: &Arc<Tasks>) -> Result<(), InitError> {
+        let mut mapping = match self.port_mapping.take() {
+            Some(mapping) => mapping,
+            None => return Ok(()),
+        };
         let config = self.config.clone();
-        let view_arc = self.view.clone();
+        let view = Arc::clone(&self.view);
         let shutdown_requested = Arc::clone(&self.shutdown_peer_sampling);
         let logger = self.logger.clone();
-        std::thread::Builder::new().name(format!("{} - gbps sampling", config.address())).spawn(move || {
-            info!(logger, "Started peer sampling thread");
-            loop {
-                // Compute time for sleep cycle
-                let deviation =
-                    if config.sampling_deviation() == 0 { 0 }
-                    else { rand::thread_rng().gen_range(0..(config.sampling_deviation() * 1000)) };
-                let sleep_time = config.sampling_period() * 1000 + deviation;
-                std::thread::sleep(Duration::from_millis(sleep_time));
-
-                debug!(logger, "Sampling peers");
-                let mut view = view_arc.lock().unwrap();
-                if let Some(peer) = view.select_peer() {
-                    if config.is_push() {
-                        let buffer = Self::build_buffer(&config, &mut view);
-                        // send local view
-                        if let Ok(remote_address) = &peer.address.parse::<SocketAddr>() {
-                            match crate::network::send(remote_address, Message::new_request(config.address().to_string(), Some(buffer)), logger.clone()) {
-                                Ok(()) => debug!(logger, "Buffer sent successfully"),
-                                Err(e) => error!(logger, "Error sending buffer: {}", e),
-                            }
-                        }
-                        else {
-                            error!(logger, "Could not parse sender address {}", &peer.address);
-                        }
-                    }
-                    else {
-                        // send empty view to trigger response
-                        if let Ok(remote_address) = &peer.address.parse::<SocketAddr>() {
-                            match crate::network::send(remote_address, Message::new_request(config.address().to_string(), None), logger.clone()) {
-                                Ok(()) => debug!(logger, "Empty view sent successfully"),
-                                Err(e) => error!(logger, "Error sending empty view: {}", e),
-                            }
-                        }
-                        else {
-                            error!(logger, "Could not parse sender address {}", &peer.address);
+        let threads = self.config.threads().clone();
+        let thread_handle = threads.spawn(&self.config.address().to_string(), "port mapping", tasks, logger.clone(), move || {
+            let renewal_period = |mapping: &PortMapping| (mapping.lifetime() / 2).max(Duration::from_secs(1));
+            let mut renewal = Instant::now() + renewal_period(&mapping);
+            while !shutdown_requested.load(std::sync::atomic::Ordering::SeqCst) {
+                if Instant::now() >= renewal {
+                    let previous = mapping.external();
+                    match mapping.renew() {
+                        Ok(external) if external != previous => {
+                            info!(logger, "Advertising {} mapped by the gateway instead of {}", external, previous);
+                            config.update_external_address(external);
+                            view.lock().unwrap().set_aliases(PeerSamplingService::aliases(&config));
                         }
+                        Ok(_) => debug!(logger, "Renewed the port mapping of {}", previous),
+                        // tried again after the same period, the mapping possibly lasting until then
+                        Err(e) => warn!(logger, "Could not renew the port mapping: {}", e),
                     }
-                    view.increase_age();
-                }
-                else {
-                    warn!(logger, "No peer found for sampling")
+                    renewal = Instant::now() + renewal_period(&mapping);
                 }
+                std::thread::sleep(Duration::from_millis(100));
+            }
+            match mapping.release() {
+                Ok(()) => info!(logger, "Removed the port mapping of {}", mapping.external()),
+                Err(e) => warn!(logger, "Could not remove the port mapping: {}", e),
+            }
+        })?;
+        self.thread_handles.push(thread_handle);
+        Ok(())
+    }
 
-                // check for shutdown request
-                if shutdown_requested.load(std::sync::atomic::Ordering::SeqCst) {
-                    break;
+    /// Returns the file where the view is saved, if configured
+    fn snapshot(&self) -> Option<ViewSnapshot> {
+        let snapshot = self.config.view_snapshot().map(ViewSnapshot::new);
+        #[cfg(feature = "encrypted-snapshot")]
+        let snapshot = match self.config.snapshot_key() {
+            Some(key) => snapshot.map(|snapshot| snapshot.with_key(*key).with_previous_keys(self.config.previous_snapshot_keys().to_vec())),
+            None => snapshot,
+        };
+        snapshot
+    }
+
+    /// Starts the activity threads, and returns the handle for supervising them.
+    /// If a thread cannot be started, those already started are stopped.
+    ///
+    /// # Arguments
+    ///
+    /// * `listeners` - The listeners, bound to the addresses of the node
+    /// * `bootstrap` - The source of contact peers when the view is empty
+    /// * `snapshot` - The file where the view is saved, if any
+    fn start(&mut self, listeners: Vec<TcpListener>, bootstrap: Box<dyn Bootstrap>, snapshot: Option<ViewSnapshot>) -> Result<ServiceHandle, InitError> {
+        let tasks = Arc::new(Tasks::default());
+        self.cycle_trigger = Arc::new(Mutex::new(None));
+        let mut handle = ServiceHandle::new(tasks.clone(), self.shutdown_tcp_listener.clone(), self.shutdown_peer_sampling.clone(), self.cycle_trigger.clone(), self.config.addresses(), self.logger.clone());
+        match self.start_tasks(listeners, bootstrap, snapshot, &tasks, &mut handle) {
+            Ok(()) => {
+                self.service_handle = Some(handle.clone());
+                Ok(handle)
+            }
+            Err(e) => {
+                error!(self.logger, "Stopping the started threads: {}", e);
+                handle.abort();
+                for started in self.thread_handles.drain(..) {
+                    let _ = started.join();
                 }
+                Err(e)
             }
+        }
+    }
 
+    /// Starts the listeners, the handling of the received messages and the peer sampling
+    ///
+    /// # Arguments
+    ///
+    /// * `listeners` - The listeners, bound to the addresses of the node
+    /// * `bootstrap` - The source of contact peers when the view is empty
+    /// * `snapshot` - The file where the view is saved, if any
+    /// * `tasks` - The tasks of the service, recording how they end
+    /// * `handle` - The handle on the tasks, given the waker of the event loop if any
+    #[cfg_attr(not(feature = "event-loop"), allow(unused_variables))]
+    fn start_tasks(&mut self, listeners: Vec<TcpListener>, bootstrap: Box<dyn Bootstrap>, snapshot: Option<ViewSnapshot>, tasks: &Arc<Tasks>, handle: &mut ServiceHandle) -> Result<(), InitError> {
+        self.start_external_address_discovery(tasks)?;
+        self.start_relay_polling(tasks)?;
+        self.start_heartbeats(tasks)?;
+        #[cfg(feature = "port-mapping")]
+        self.start_port_mapping(tasks)?;
+
+        #[cfg(feature = "event-loop")]
+        if self.config.is_event_loop() {
+            return self.start_event_loop(listeners, bootstrap, snapshot, tasks, handle);
+        }
+
+        // listen to incoming message on all the addresses, the control messages being handled first
+        let (tx, rx) = crate::lanes::channel();
+        for listener in listeners {
+            let listener_handle = crate::network::start_listener(&self.config, listener, tx.clone(), &self.shutdown_tcp_listener, self.quarantine.clone(), self.events.clone(), tasks, self.logger.clone())?;
+            self.thread_handles.push(listener_handle);
+        }
+
+        // handle received messages
+        let receiver_handle = self.start_receiver(rx, tasks)?;
+        self.thread_handles.push(receiver_handle);
+
+        // start peer sampling
+        let (cycle_trigger, cycle_receiver) = std::sync::mpsc::channel();
+        *self.cycle_trigger.lock().unwrap() = Some(cycle_trigger);
+        let sampling_handle = self.start_sampling_activity(bootstrap, snapshot, cycle_receiver, tasks)?;
+        self.thread_handles.push(sampling_handle);
+
+        info!(self.logger, "All activity threads were started");
+        Ok(())
+    }
+
+    /// Starts the single thread running the listeners, the handling of the received messages and the peer sampling
+    ///
+    /// # Arguments
+    ///
+    /// * `listeners` - The listeners, bound to the addresses of the node
+    /// * `bootstrap` - The source of contact peers when the view is empty
+    /// * `snapshot` - The file where the view is saved, if any
+    /// * `tasks` - The tasks of the service, recording how the thread ends
+    /// * `handle` - The handle on the tasks, given the waker of the event loop
+    #[cfg(feature = "event-loop")]
+    fn start_event_loop(&mut self, listeners: Vec<TcpListener>, bootstrap: Box<dyn Bootstrap>, snapshot: Option<ViewSnapshot>, tasks: &Arc<Tasks>, handle: &mut ServiceHandle) -> Result<(), InitError> {
+        let (cycle_trigger, cycle_receiver) = std::sync::mpsc::channel();
+        let activity = self.sampling_activity(bootstrap, snapshot);
+        let threads = self.config.threads().clone();
+        let address = self.config.address().to_string();
+        let (event_loop, waker) = crate::event_loop::EventLoop::new(listeners, self.message_handler(), activity, cycle_receiver, self.shutdown_tcp_listener.clone(), &self.config, self.logger.clone())
+            .map_err(|error| InitError::Spawn { task: threads.name(&address, "event loop"), error })?;
+        let stopped_bootstrap = self.bootstrap.clone();
+        let thread_handle = threads.spawn(&address, "event loop", tasks, self.logger.clone(), move || {
+            *stopped_bootstrap.lock().unwrap() = Some(event_loop.run().into_bootstrap());
+        })?;
+        self.thread_handles.push(thread_handle);
+        *self.cycle_trigger.lock().unwrap() = Some(cycle_trigger);
+        handle.set_waker(waker.clone());
+        self.cycle_waker = Some(waker);
+        Ok(())
+    }
+
+    /// Returns the address of the node, with the port assigned by the system
+    /// once the service is initialized if the configured port was 0
+    pub fn local_address(&self) -> SocketAddr {
+        *self.config.address()
+    }
+
+    /// Returns all the addresses of the node, starting with the address returned by [Self::local_address]
+    pub fn local_addresses(&self) -> Vec<SocketAddr> {
+        self.config.addresses()
+    }
+
+    /// Returns the address advertised to the peers outside the network of the node, if any, such as the
+    /// address learned from the STUN servers, see [Config::with_stun_servers]
+    pub fn external_address(&self) -> Option<SocketAddr> {
+        self.config.external_address()
+    }
+
+    /// Returns a random peer for the client application.
+    /// The peer is pseudo-random peer from the set of all peers.
+    /// The local view is built using [Gossip-Based Peer Sampling].
+    pub fn get_peer(&mut self) -> Option<Peer> {
+        self.view.lock().unwrap().get_peer()
+    }
+
+    /// Returns a peer for the client application, sampled as by [PeerSamplingService::get_peer],
+    /// along with how it was sampled according to the sampling policy, see [Config::with_sampling_policy]
+    pub fn sample_peer(&mut self) -> Option<(Peer, SampleSource)> {
+        self.view.lock().unwrap().sample_peer(&[])
+    }
+
+    /// Returns a random peer for the client application other than the excluded peers,
+    /// such as the partners already contacted during a round of the application
+    ///
+    /// # Arguments
+    ///
+    /// * `excluded` - The peers not to be returned
+    pub fn get_peer_excluding(&mut self, excluded: &[Peer]) -> Option<Peer> {
+        self.view.lock().unwrap().get_peer_excluding(excluded)
+    }
+
+    /// Returns a peer for the client application that is probably alive: a random peer of the view
+    /// seen recently, directly or in the buffers of other peers, otherwise the most recently seen peer of the view
+    ///
+    /// # Arguments
+    ///
+    /// * `max_age` - Time since the peers were last seen for being considered recent
+    pub fn get_recent_peer(&mut self, max_age: Duration) -> Option<Peer> {
+        let view = self.view.lock().unwrap();
+        self.last_seen.lock().unwrap().recent_peer(&view, max_age)
+    }
+
+    /// Returns a peer for the client application that is not suspected dead: not quarantined, and neither
+    /// timing out repeatedly nor failing recently in the exchanges of the node, see [Config::with_circuit_breaker]
+    /// and [Config::with_send_backoff]. As by [PeerSamplingService::get_recent_peer], a peer seen in the last
+    /// few cycles is preferred, so that the peer can be used immediately with few connection errors.
+    pub fn get_alive_peer(&mut self) -> Option<Peer> {
+        let view = self.view.lock().unwrap();
+        let mut quarantine = self.quarantine.lock().unwrap();
+        let (breaker, backoff) = (self.breaker.lock().unwrap(), self.backoff.lock().unwrap());
+        let alive = |peer: &Peer| !quarantine.is_quarantined(peer.address()) && breaker.allows(peer.address()) && backoff.allows(peer.address());
+        self.last_seen.lock().unwrap().recent_peer_filtered(&view, self.config.age_duration(crate::seen::ALIVE_AGE), alive)
+    }
+
+    /// Returns a random peer for the client application in a locality, such as a partner in the same zone
+    /// for bulk transfers, if the peers advertise their locality, see [Config::with_locality]
+    ///
+    /// # Arguments
+    ///
+    /// * `locality` - The locality of the peer
+    pub fn get_peer_in(&mut self, locality: &str) -> Option<Peer> {
+        self.view.lock().unwrap().get_peer_in(locality)
+    }
+
+    /// Returns a random peer for the client application in another locality, such as a partner in another
+    /// zone for resilience. The peers that do not advertise their locality are not returned.
+    ///
+    /// # Arguments
+    ///
+    /// * `locality` - The locality the peer is not in
+    pub fn get_peer_outside(&mut self, locality: &str) -> Option<Peer> {
+        self.view.lock().unwrap().get_peer_outside(locality)
+    }
+
+    /// Returns an iterator over peers sampled as by [PeerSamplingService::get_peer],
+    /// which ends only when the view is empty
+    pub fn peers_iter(&self) -> PeerIter {
+        PeerIter::new(self.view.clone())
+    }
+
+    /// Returns an endless asynchronous stream of peers sampled as by [PeerSamplingService::get_peer],
+    /// pending while the view is empty
+    #[cfg(feature = "stream")]
+    pub fn peer_stream(&self) -> crate::stream::PeerStream {
+        crate::stream::PeerStream::new(self.view.clone(), Duration::from_millis(100))
+    }
+
+    /// Merges a peer discovered by the application, such as from a tracker or user input,
+    /// into the view, with the same rules as the peers received from other nodes:
+    /// duplicates are removed and the view is trimmed to its size
+    ///
+    /// # Arguments
+    ///
+    /// * `peer` - The discovered peer
+    pub fn add_peer_hint(&self, peer: Peer) {
+        info!(self.logger, "Merging peer hint {}", peer.address());
+        Self::select(&self.config, &mut self.view.lock().unwrap(), &[peer], &self.subscribers, &self.stats, &self.events, &self.logger);
+    }
+
+    /// Returns a handle sharing the view, the statistics and the events of the service,
+    /// which can be cloned and sent to the threads of the application
+    pub fn handle(&self) -> SamplingHandle {
+        SamplingHandle::new(self.view.clone(), self.subscribers.clone(), self.events.clone(), self.stats.clone(), self.scores.clone(), self.size_estimator.clone(), self.last_seen.clone())
+    }
+
+    /// Probes the liveness of a peer without exchanging views,
+    /// and returns the round-trip time of the probe. The answer is read on the connection
+    /// of the probe with [Config::with_direct_answers].
+    ///
+    /// # Arguments
+    ///
+    /// * `peer` - The peer to be probed
+    /// * `timeout` - Maximum time to wait for the answer
+    pub fn ping(&self, peer: &Peer, timeout: Duration) -> Result<Duration, Box<dyn Error>> {
+        let mut ping = Message::new_ping(self.config.address_for(peer.address()).to_string());
+        if self.config.is_observer() {
+            ping = ping.with_observer_flag();
+        }
+        let nonce = ping.nonce();
+        let (tx, rx) = std::sync::mpsc::channel();
+        self.pending_pings.lock().unwrap().insert(nonce, tx);
+
+        let start = Instant::now();
+        let result = if self.config.is_direct_answers() {
+            // the answer is awaited as long as the timeout of the probe
+            let config = self.config.clone().with_exchange_timeout(timeout.as_millis().max(1) as u64);
+            match crate::network::exchange(&peer.socket_address()?, ping, &config, self.logger.clone()) {
+                Ok(Some(pong)) if *pong.message_type() == MessageType::Pong && pong.nonce() == nonce => Ok(()),
+                Ok(_) => Err("no answer to ping".into()),
+                Err(e) => Err(e),
+            }
+        }
+        else {
+            crate::network::send(&peer.socket_address()?, ping, &self.config, self.logger.clone())
+                .and_then(|()| rx.recv_timeout(timeout).map_err(|_| "no answer to ping".into()))
+        };
+        self.pending_pings.lock().unwrap().remove(&nonce);
+        result.map(|()| start.elapsed())
+    }
+
+    /// Returns the statistics of the protocol at the node
+    pub fn stats(&self) -> Stats {
+        let uniformity = self.view.lock().unwrap().uniformity();
+        let mut stats = self.stats.lock().unwrap().clone();
+        stats.set_uniformity(uniformity);
+        stats
+    }
+
+    /// Sets the provider and consumer of the application payloads attached to the exchanges,
+    /// when enabled with [Config::with_piggyback]
+    ///
+    /// # Arguments
+    ///
+    /// * `piggyback` - Provider and consumer of the payloads
+    pub fn set_piggyback(&self, piggyback: Box<dyn Piggyback>) {
+        *self.piggyback.lock().unwrap() = Some(piggyback);
+    }
+
+    /// Returns the estimated number of nodes of the overlay, for instance for tuning the fanout
+    /// of a gossip protocol, or None if the estimation is disabled, see [Config::with_size_estimation]
+    pub fn estimated_network_size(&self) -> Option<f64> {
+        self.size_estimator.lock().unwrap().estimate()
+    }
+
+    /// Returns the peers that advertised parameters of the view selection different from those of the node,
+    /// with their parameters, see [Config::with_advertised_parameters]
+    pub fn parameter_mismatches(&self) -> HashMap<String, ProtocolParameters> {
+        self.parameter_mismatches.lock().unwrap().peers()
+    }
+
+    /// Exports the view, the queue of peers not yet returned and the statistics of the node,
+    /// such as for a bug report
+    pub fn export_state(&self) -> ServiceState {
+        let view = self.view.lock().unwrap();
+        ServiceState::new(view.peers().to_vec(), view.queue().iter().cloned().collect(), self.stats.lock().unwrap().clone())
+    }
+
+    /// Replaces the view, the queue and the statistics of the node by those of an exported state,
+    /// such as for reproducing a problematic view in a test, and notifies the view subscribers
+    ///
+    /// # Arguments
+    ///
+    /// * `state` - The exported state
+    pub fn import_state(&self, state: ServiceState) {
+        let (peers, queue, stats) = state.into_parts();
+        let mut view = self.view.lock().unwrap();
+        view.restore(peers, queue);
+        *self.stats.lock().unwrap() = stats;
+        crate::sampler::notify(&mut self.subscribers.lock().unwrap(), &view.peers().to_vec());
+    }
+
+    /// Returns the last time each peer was seen, as the sender of a message, the partner of an exchange
+    /// or in a received buffer, the time being then estimated from the age of its descriptor.
+    /// The least recently seen peers are forgotten first.
+    pub fn last_seen(&self) -> HashMap<String, SystemTime> {
+        self.last_seen.lock().unwrap().all()
+    }
+
+    /// Returns the score of each peer, computed from the exchanges initiated
+    /// with the peer and from its protocol violations
+    pub fn scores(&self) -> HashMap<String, PeerScore> {
+        self.scores.lock().unwrap().snapshot()
+    }
+
+    /// Returns the knowledge of the overlay of the node, made of its own view,
+    /// for exporting the topology as a graph
+    pub fn overlay(&self) -> Overlay {
+        let mut overlay = Overlay::new();
+        overlay.add_view(self.config.address().to_string(), self.view.lock().unwrap().peers().to_vec());
+        overlay
+    }
+
+    /// Returns a receiver notified with the events of the protocol
+    pub fn subscribe_events(&self) -> Receiver<Event> {
+        self.events.lock().unwrap().subscribe()
+    }
+
+    /// Returns the last peer entries that were not merged into the view, oldest first,
+    /// as many as configured with [Config::with_audit]
+    pub fn rejections(&self) -> Vec<Rejection> {
+        self.events.lock().unwrap().audit().rejections()
+    }
+
+    /// Returns a receiver notified with the peer entries that are not merged into the view,
+    /// when enabled with [Config::with_audit]
+    pub fn subscribe_rejections(&self) -> Receiver<Rejection> {
+        self.events.lock().unwrap().audit().subscribe()
+    }
+
+    /// Returns the last events of the protocol with the time they occurred, oldest first,
+    /// as many as configured with [Config::with_event_history]
+    pub fn recent_events(&self) -> Vec<(SystemTime, Event)> {
+        self.events.lock().unwrap().recent()
+    }
+
+    /// Starts one cycle of the protocol without waiting for its completion, such as from the
+    /// scheduler of the application. With [crate::CycleSchedule::Triggered], this is
+    /// the only way cycles are performed besides [PeerSamplingService::run_round].
+    pub fn trigger_cycle(&self) -> Result<(), Box<dyn Error>> {
+        self.start_round()?;
+        Ok(())
+    }
+
+    /// Immediately performs one exchange outside of the schedule, and returns the address
+    /// of the peer contacted, or None if no peer was found. Useful right after bootstrap,
+    /// or when the application suspects its samples to be stale. The next scheduled cycle
+    /// is not delayed.
+    pub fn trigger_exchange(&self) -> Result<Option<String>, Box<dyn Error>> {
+        Ok(self.start_round()?.recv()?)
+    }
+
+    /// Performs one cycle of the protocol and waits for its completion.
+    /// In round-synchronized mode, this is the only way cycles are performed.
+    pub fn run_round(&self) -> Result<(), Box<dyn Error>> {
+        self.start_round()?.recv()?;
+        Ok(())
+    }
+
+    /// Performs one round of the protocol on all the services: the cycle of each service
+    /// is started, and all of them are completed before returning, so that round k
+    /// of all the nodes completes before round k+1 starts.
+    ///
+    /// # Arguments
+    ///
+    /// * `services` - The services of the experiment, with round-synchronized mode enabled
+    pub fn run_synchronized_round(services: &[PeerSamplingService]) -> Result<(), Box<dyn Error>> {
+        let completions = services.iter()
+            .map(|service| service.start_round())
+            .collect::<Result<Vec<Receiver<Option<String>>>, Box<dyn Error>>>()?;
+        for completion in completions {
+            completion.recv()?;
+        }
+        Ok(())
+    }
+
+    /// Starts a cycle and returns a receiver notified of its completion
+    fn start_round(&self) -> Result<Receiver<Option<String>>, Box<dyn Error>> {
+        let (tx, rx) = std::sync::mpsc::channel();
+        self.cycle_trigger.lock().unwrap().as_ref().ok_or("service is not running")?.send(tx)?;
+        #[cfg(feature = "event-loop")]
+        if let Some(waker) = self.cycle_waker.as_ref() {
+            waker.wake()?;
+        }
+        Ok(rx)
+    }
+
+    /// Leaves the overlay gracefully, then shuts the service down. The node stops advertising its
+    /// descriptor and starting exchanges, and announces its departure to the peers of its view,
+    /// but keeps answering pulls during a grace period, giving the other nodes time to heal their views.
+    ///
+    /// # Arguments
+    ///
+    /// * `grace_period` - Time during which pulls are answered before the service stops
+    pub fn drain(&mut self, grace_period: Duration) -> Result<(), Box<dyn Error>> {
+        info!(self.logger, "Draining for {} ms before shutting down", grace_period.as_millis());
+        self.draining.store(true, std::sync::atomic::Ordering::SeqCst);
+        self.announce_leave();
+        std::thread::sleep(grace_period);
+        self.shutdown()
+    }
+
+    /// Returns whether the node is leaving the overlay, see [PeerSamplingService::drain]
+    pub fn is_draining(&self) -> bool {
+        self.draining.load(std::sync::atomic::Ordering::SeqCst)
+    }
+
+    /// Sends a leave message to the peers of the view
+    fn announce_leave(&self) {
+        let peers = self.view.lock().unwrap().peers().to_vec();
+        for peer in peers {
+            match peer.socket_address() {
+                Ok(remote_address) => if let Err(e) = crate::network::send(&remote_address, Message::new_leave(self.config.address_for(&peer.address).to_string()), &self.config, self.logger.clone()) {
+                    debug!(self.logger, "Could not send leave message to {}: {}", peer.address, e);
+                },
+                Err(_) => error!(self.logger, "Could not parse peer address {}", &peer.address),
+            }
+        }
+    }
+
+    /// Stops the threads related to peer sampling activity
+    pub fn shutdown(&mut self) -> Result<(), Box<dyn Error>> {
+        // request shutdown
+        if let Some(handle) = self.service_handle.take() {
+            handle.abort();
+        }
+        #[cfg(feature = "event-loop")]
+        {
+            self.cycle_waker = None;
+        }
+        // wait for termination
+        let handles = self.thread_handles.drain(..);
+        let mut join_error = false;
+        for handle in handles {
+            if let Err(e) = handle.join() {
+                error!(self.logger, "Error joining thread: {}", e);
+                join_error = true;
+            }
+        }
+        info!(self.logger, "All activity threads were stopped");
+
+        // announce departure to the peers in the view, unless announced when draining
+        if !self.draining.load(std::sync::atomic::Ordering::SeqCst) {
+            self.announce_leave();
+        }
+
+        if join_error {
+            Err("An error occurred during thread joining")?
+        }
+        else {
+            Ok(())
+        }
+    }
+
+    /// Builds the view to be exchanged with another peer
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The configuration parameters
+    /// * `view` - The current view
+    /// * `own_address` - The node's own descriptor added at the beginning of the buffer, if any
+    fn build_buffer(config: &Config, view: &mut View, own_address: Option<SocketAddr>) -> Vec<Peer> {
+        // the ages sent are current when they measure time
+        view.update_age(Instant::now());
+        let mut buffer = match own_address { Some(address) => vec![ Self::own_descriptor(config, view, address) ], None => vec![] };
+        view.permute();
+        view.move_oldest_to_end(config.healing_factor());
+        let count = config.buffer_size().saturating_sub(buffer.len());
+        buffer.append(&mut view.head(count));
+        buffer
+    }
+
+    /// Returns the descriptor of the node, with its incarnation, its locality, and the age of the self age policy
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The configuration parameters
+    /// * `view` - The current view, which keeps the age of the descriptor
+    /// * `address` - The address of the node advertised to the partner
+    fn own_descriptor(config: &Config, view: &View, address: SocketAddr) -> Peer {
+        let age = match config.self_age_policy() {
+            SelfAgePolicy::Reset => 0,
+            SelfAgePolicy::Preserve => view.own_age(),
+        };
+        let peer = Peer::with_age(address.to_string(), age).with_incarnation(config.incarnation());
+        match config.locality() {
+            Some(locality) => peer.with_locality(locality.to_owned()),
+            None => peer,
+        }
+    }
+
+    /// Sends a request to the selected peer. With pull, waits for its answer on the same
+    /// connection, and otherwise only pushes the buffer, the partner not answering.
+    /// If the peer cannot be reached and the node has a relay, the request is forwarded through
+    /// the relay instead, the answer arriving later when the peer polls it; the returned flag is
+    /// then true.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The configuration parameters
+    /// * `address` - Address of the selected peer
+    /// * `buffer` - The buffer pushed, none for only pulling
+    /// * `size_estimator` - Estimator of the size of the network, whose values are attached to the request
+    /// * `piggyback` - Provider of the application payload attached to the request
+    /// * `pending_requests` - Requests waiting for an answer, where the request is recorded with pull
+    /// * `logger` - Logger
+    fn exchange(config: &Config, address: &SocketAddr, buffer: Option<Vec<Peer>>, size_estimator: &Mutex<SizeEstimator>, piggyback: &Mutex<Option<Box<dyn Piggyback>>>, pending_requests: &Mutex<PendingRequests>, logger: &Logger) -> Result<(Option<Message>, bool), Box<dyn Error>> {
+        let mut request = Message::new_request(config.address_for(&address.to_string()).to_string(), buffer);
+        if config.is_observer() {
+            // an observer is not counted among the nodes either
+            request = request.with_observer_flag();
+        }
+        else if let Some((epoch, minima)) = size_estimator.lock().unwrap().values() {
+            request = request.with_size_estimate(epoch, minima);
+        }
+        let mut request = Self::attach_parameters(config, Self::attach_payload(config, piggyback, &address.to_string(), request, logger));
+        if config.proof_of_work() > 0 && request.view().is_some() {
+            request = request.with_proof_of_work(config.proof_of_work());
+        }
+        if config.is_pull() {
+            pending_requests.lock().unwrap().record(&address.to_string(), request.nonce());
+        }
+        debug!(logger, "Sending -> {:?} to {:?}", request, address);
+        let mut connection = match Connection::connect(address, config) {
+            Ok(connection) => connection,
+            Err(e) => return match config.relay().filter(|relay| *relay != address) {
+                Some(relay) => {
+                    debug!(logger, "Could not reach {} ({}), forwarding the request through {}", address, e, relay);
+                    let relayed = Message::new_relay(request.sender().to_owned(), &address.to_string(), &request)?;
+                    crate::network::send(relay, relayed, config, logger.clone()).map(|()| (None, true))
+                }
+                None => Err(e),
+            },
+        };
+        connection.write_message(&request)?;
+        if config.is_pull() {
+            connection.read_message().map(|reply| (reply, false))
+        }
+        else {
+            Ok((None, false))
+        }
+    }
+
+    /// Attaches the payload of the application to a message, if piggybacking is enabled
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The configuration parameters
+    /// * `piggyback` - Provider of the payload
+    /// * `partner` - Address of the receiver of the message
+    /// * `message` - The message
+    /// * `logger` - Logger
+    fn attach_payload(config: &Config, piggyback: &Mutex<Option<Box<dyn Piggyback>>>, partner: &str, message: Message, logger: &Logger) -> Message {
+        if config.max_payload_size() == 0 {
+            return message;
+        }
+        match piggyback.lock().unwrap().as_mut().map(|piggyback| piggyback.payload(partner)) {
+            Some(payload) if payload.len() > config.max_payload_size() => {
+                warn!(logger, "Not sending a payload of {} bytes, larger than {}", payload.len(), config.max_payload_size());
+                message
+            }
+            Some(payload) => message.with_payload(payload),
+            None => message,
+        }
+    }
+
+    /// Hands the payload attached to a received message to the application, if piggybacking is enabled
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The configuration parameters
+    /// * `piggyback` - Consumer of the payload
+    /// * `message` - The message received
+    /// * `logger` - Logger
+    fn deliver_payload(config: &Config, piggyback: &Mutex<Option<Box<dyn Piggyback>>>, message: &Message, logger: &Logger) {
+        match message.payload() {
+            Some(payload) if config.max_payload_size() == 0 || payload.len() > config.max_payload_size() =>
+                debug!(logger, "Ignoring a payload of {} bytes from {}", payload.len(), message.sender()),
+            Some(payload) => if let Some(piggyback) = piggyback.lock().unwrap().as_mut() {
+                piggyback.receive(message.sender(), payload);
+            },
+            None => (),
+        }
+    }
+
+    /// Attaches the parameters of the view selection of the node to a message, if advertised
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The configuration parameters
+    /// * `message` - The message
+    fn attach_parameters(config: &Config, message: Message) -> Message {
+        if config.is_advertising_parameters() {
+            message.with_parameters(ProtocolParameters::of(config))
+        }
+        else {
+            message
+        }
+    }
+
+    /// Records the parameters advertised by the sender of a message, and reports them if they
+    /// differ from those of the node and were not reported yet
+    ///
+    /// # Arguments
+    ///
+    /// * `message` - The message received
+    /// * `mismatches` - The peers known to advertise other parameters
+    /// * `events` - Events of the node
+    /// * `logger` - Logger
+    fn check_parameters(message: &Message, mismatches: &Mutex<ParameterMismatches>, events: &Mutex<Events>, logger: &Logger) {
+        if let Some(parameters) = message.parameters() {
+            if mismatches.lock().unwrap().record(message.sender(), parameters) {
+                warn!(logger, "Peer {} uses other parameters: {}", message.sender(), parameters);
+                events.lock().unwrap().notify(Event::ParameterMismatch { peer: message.sender().to_owned(), parameters });
+            }
+        }
+    }
+
+    /// Returns the number of peers exchanged with a node: the buffer size, reduced for a node with a smaller view
+    /// to the same share of its view, so that the view selection behaves as between nodes with the same parameters
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The configuration parameters
+    /// * `parameters` - The parameters advertised by the other node, if any
+    fn exchanged_buffer_size(config: &Config, parameters: Option<ProtocolParameters>) -> usize {
+        match parameters {
+            Some(parameters) if parameters.view_size() < config.view_size() =>
+                (config.buffer_size() * parameters.view_size() / config.view_size()).max(1),
+            _ => config.buffer_size(),
+        }
+    }
+
+    /// Truncates a received buffer to the number of peers exchanged with its sender, see [Self::exchanged_buffer_size]
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The configuration parameters
+    /// * `buffer` - The received buffer
+    /// * `parameters` - The parameters advertised by the sender, if any
+    fn clamped_buffer<'a>(config: &Config, buffer: &'a [Peer], parameters: Option<ProtocolParameters>) -> &'a [Peer] {
+        match parameters {
+            Some(_) => &buffer[..buffer.len().min(Self::exchanged_buffer_size(config, parameters))],
+            None => buffer,
+        }
+    }
+
+    /// Merges a received buffer into the view, and reports the new view
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The configuration parameters
+    /// * `view` - The current view
+    /// * `buffer` - The view received
+    /// * `subscribers` - Receivers of the view updates
+    /// * `stats` - Statistics of the node
+    /// * `events` - Events of the node, auditing the rejected peers
+    /// * `logger` - Logger
+    fn select(config: &Config, view: &mut View, buffer: &[Peer], subscribers: &Mutex<Vec<Sender<Vec<Peer>>>>, stats: &Mutex<Stats>, events: &Mutex<Events>, logger: &Logger) {
+        // count the occurrences of the node's own address before it is filtered out
+        let own_entries = buffer.iter().filter(|peer| view.is_host(peer.address())).collect::<Vec<&Peer>>();
+        let indegree = {
+            let mut stats = stats.lock().unwrap();
+            stats.record_occurrences(own_entries.len() as u64);
+            stats.indegree()
+        };
+
+        let merge_start = Instant::now();
+        view.select(config.view_size(), config.healing_factor(), config.swapping_factor(), buffer);
+        stats.lock().unwrap().record_merge_duration(merge_start.elapsed());
+
+        let rejections = {
+            let mut events = events.lock().unwrap();
+            let audit = events.audit();
+            own_entries.iter().for_each(|peer| audit.reject(peer.address(), RejectionReason::OwnAddress));
+            view.over_quota().iter().for_each(|peer| audit.reject(peer.address(), RejectionReason::PrefixQuota));
+            view.over_locality_quota().iter().for_each(|peer| audit.reject(peer.address(), RejectionReason::LocalityQuota));
+            audit.take_unreported()
+        };
+
+        // Debug and monitoring
+        let new_view = view.peers().iter()
+            .map(|peer| peer.address().to_owned())
+            .collect::<Vec<String>>();
+        debug!(logger, "{}", new_view.join(", "));
+        #[cfg(feature = "monitoring")]
+        if config.monitoring().enabled() {
+            config.monitoring().send_report(view.host_address(), new_view, indegree, &rejections);
+        }
+        // only reported through monitoring
+        #[cfg(not(feature = "monitoring"))]
+        let _ = (indegree, rejections);
+        crate::sampler::notify(&mut subscribers.lock().unwrap(), &view.peers().to_vec());
+    }
+
+    /// Truncates a received buffer to the maximum accepted size,
+    /// larger buffers counting as a protocol violation of their sender
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The configuration of the node
+    /// * `buffer` - The received buffer
+    /// * `sender` - Address of the peer that sent the buffer
+    fn accepted_buffer<'a>(config: &Config, buffer: &'a [Peer], sender: &str, scores: &Mutex<PeerScores>, quarantine: &Mutex<Quarantine>, events: &Mutex<Events>, logger: &Logger) -> &'a [Peer] {
+        let limit = config.max_received_buffer_size();
+        if limit == 0 || buffer.len() <= limit {
+            return buffer;
+        }
+        warn!(logger, "Truncating buffer of {} peers from {} to {} peers", buffer.len(), sender, limit);
+        buffer[limit..].iter().for_each(|peer| events.lock().unwrap().audit().reject(peer.address(), RejectionReason::OversizedBuffer));
+        scores.lock().unwrap().record_violation(sender);
+        crate::quarantine::report_violation(quarantine, events, sender, logger);
+        &buffer[..limit]
+    }
+
+    /// Records the peers of a received buffer as seen as long ago as the age of their descriptor
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The configuration of the node
+    /// * `view` - The view of the node, whose own addresses are not recorded
+    /// * `last_seen` - The last time each peer was seen
+    /// * `buffer` - The received buffer
+    fn record_seen(config: &Config, view: &View, last_seen: &Mutex<LastSeen>, buffer: &[Peer]) {
+        let mut last_seen = last_seen.lock().unwrap();
+        for peer in buffer.iter().filter(|peer| !view.is_host(peer.address())) {
+            last_seen.record(peer.address(), config.age_duration(peer.age()));
+        }
+    }
+
+    /// Creates a handler of the received messages, sharing the state of the service
+    fn message_handler(&self) -> MessageHandler {
+        let config = self.config.clone();
+        MessageHandler {
+            replay_guard: if config.message_ttl() > 0 { Some(ReplayGuard::new(config.message_ttl())) } else { None },
+            request_load: RequestLoad::new(Duration::from_secs(config.sampling_period())),
+            request_rate: RequestLoad::new(Duration::from_secs(1)),
+            view: self.view.clone(),
+            pending_pings: self.pending_pings.clone(),
+            subscribers: self.subscribers.clone(),
+            stats: self.stats.clone(),
+            scores: self.scores.clone(),
+            quarantine: self.quarantine.clone(),
+            recent_senders: self.recent_senders.clone(),
+            events: self.events.clone(),
+            size_estimator: self.size_estimator.clone(),
+            piggyback: self.piggyback.clone(),
+            parameter_mismatches: self.parameter_mismatches.clone(),
+            pending_requests: self.pending_requests.clone(),
+            relay_queues: self.relay_queues.clone(),
+            last_seen: self.last_seen.clone(),
+            draining: self.draining.clone(),
+            config,
+            logger: self.logger.clone(),
+        }
+    }
+
+    /// Creates a thread for handling messages
+    ///
+    /// # Arguments
+    ///
+    /// * `receiver` - The channel used for receiving incoming messages, along with the connection for answering them, control messages first
+    /// * `tasks` - The tasks of the service, recording how the thread ends
+    fn start_receiver(&self, receiver: LaneReceiver<(Message, Connection)>, tasks: &Arc<Tasks>) -> Result<TaskHandle, InitError> {
+        let mut handler = self.message_handler();
+        let stats = self.stats.clone();
+        let logger = self.logger.clone();
+        let threads = self.config.threads().clone();
+        threads.spawn(&self.config.address().to_string(), "receiver", tasks, logger.clone(), move|| {
+            info!(logger, "Started message handling thread");
+            while let Some(((message, connection), waited)) = receiver.recv() {
+                stats.lock().unwrap().record_queue_latency(waited);
+                handler.handle(message, connection);
+            }
+            info!(logger, "Message handling thread exiting");
+        })
+    }
+
+    /// Creates the periodic peer sampling, sharing the state of the service
+    ///
+    /// # Arguments
+    ///
+    /// * `bootstrap` - The source of contact peers when the view is empty
+    /// * `snapshot` - The file where the view is saved, for recovering when the view is empty
+    fn sampling_activity(&self, bootstrap: Box<dyn Bootstrap>, snapshot: Option<ViewSnapshot>) -> SamplingActivity {
+        let config = self.config.clone();
+        let recorder = config.recording().and_then(|recording| match Recorder::open(recording) {
+            Ok(recorder) => Some(recorder),
+            Err(e) => {
+                error!(self.logger, "Could not open data file {}: {}", recording.path().display(), e);
+                None
+            }
+        });
+        SamplingActivity {
+            view: self.view.clone(),
+            subscribers: self.subscribers.clone(),
+            events: self.events.clone(),
+            stats: self.stats.clone(),
+            scores: self.scores.clone(),
+            quarantine: self.quarantine.clone(),
+            recent_senders: self.recent_senders.clone(),
+            size_estimator: self.size_estimator.clone(),
+            piggyback: self.piggyback.clone(),
+            parameter_mismatches: self.parameter_mismatches.clone(),
+            pending_requests: self.pending_requests.clone(),
+            recent_partners: self.recent_partners.clone(),
+            last_seen: self.last_seen.clone(),
+            draining: self.draining.clone(),
+            bootstrap,
+            snapshot,
+            partition_detector: PartitionDetector::new(config.partition_window()),
+            breaker: self.breaker.clone(),
+            backoff: self.backoff.clone(),
+            failed_exchanges: 0,
+            last_partners: VecDeque::new(),
+            address: config.address().to_string(),
+            round: 0,
+            recorder,
+            config,
+            logger: self.logger.clone(),
+        }
+    }
+
+    /// Creates a thread that periodically executes the peer sampling
+    ///
+    /// # Arguments
+    ///
+    /// * `bootstrap` - The source of contact peers when the view is empty
+    /// * `snapshot` - The file where the view is saved, for recovering when the view is empty
+    /// * `cycle_trigger` - The channel used for triggering cycles, closed on shutdown
+    /// * `tasks` - The tasks of the service, recording how the thread ends
+    fn start_sampling_activity(&self, bootstrap: Box<dyn Bootstrap>, snapshot: Option<ViewSnapshot>, cycle_trigger: Receiver<Sender<Option<String>>>, tasks: &Arc<Tasks>) -> Result<TaskHandle, InitError> {
+        let mut activity = self.sampling_activity(bootstrap, snapshot);
+        let shutdown_requested = Arc::clone(&self.shutdown_peer_sampling);
+        let stopped_bootstrap = self.bootstrap.clone();
+        let logger = self.logger.clone();
+        let threads = self.config.threads().clone();
+        threads.spawn(&self.config.address().to_string(), "sampling", tasks, logger.clone(), move || {
+            info!(logger, "Started peer sampling thread");
+            let mut scheduled = activity.first_cycle();
+            loop {
+                // wait for the next scheduled cycle, or for a triggered cycle that does not delay it
+                let completion = match scheduled {
+                    Some(deadline) => match cycle_trigger.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                        Ok(completion) => Some(completion),
+                        Err(RecvTimeoutError::Timeout) => {
+                            scheduled = activity.following_cycle();
+                            None
+                        },
+                        Err(RecvTimeoutError::Disconnected) => break,
+                    },
+                    None => match cycle_trigger.recv() {
+                        Ok(completion) => Some(completion),
+                        Err(_) => break,
+                    },
+                };
+
+                let partner = activity.run_cycle();
+                if let Some(completion) = completion {
+                    let _ = completion.send(partner);
+                }
+
+                // check for shutdown request
+                if shutdown_requested.load(std::sync::atomic::Ordering::SeqCst) {
+                    break;
+                }
+            }
+
+            *stopped_bootstrap.lock().unwrap() = Some(activity.into_bootstrap());
             info!(logger, "Peer sampling thread exiting");
-        }).unwrap()
+        })
+    }
+}
+
+/// Handles the messages received by the node, answering the requests on their connection
+pub(crate) struct MessageHandler {
+    config: Config,
+    view: Arc<Mutex<View>>,
+    pending_pings: Arc<Mutex<HashMap<u64, Sender<()>>>>,
+    subscribers: Arc<Mutex<Vec<Sender<Vec<Peer>>>>>,
+    stats: Arc<Mutex<Stats>>,
+    scores: Arc<Mutex<PeerScores>>,
+    quarantine: Arc<Mutex<Quarantine>>,
+    recent_senders: Arc<Mutex<RecentSenders>>,
+    events: Arc<Mutex<Events>>,
+    size_estimator: Arc<Mutex<SizeEstimator>>,
+    piggyback: Arc<Mutex<Option<Box<dyn Piggyback>>>>,
+    parameter_mismatches: Arc<Mutex<ParameterMismatches>>,
+    pending_requests: Arc<Mutex<PendingRequests>>,
+    relay_queues: Arc<Mutex<RelayQueues>>,
+    last_seen: Arc<Mutex<LastSeen>>,
+    draining: Arc<AtomicBool>,
+    /// Rejects the messages received twice, if enabled
+    replay_guard: Option<ReplayGuard>,
+    /// Requests received during the last sampling period, for the in-degree cap
+    request_load: RequestLoad,
+    /// Requests received during the last second, for the request rate limit
+    request_rate: RequestLoad,
+    logger: Logger,
+}
+
+impl MessageHandler {
+    /// Reads the message of an accepted connection and handles it
+    ///
+    /// # Arguments
+    ///
+    /// * `stream` - The accepted connection
+    #[cfg(feature = "event-loop")]
+    pub(crate) fn receive(&mut self, stream: std::net::TcpStream) {
+        match crate::network::receive_message(stream, &self.config, &self.quarantine, &self.events, &self.logger) {
+            Ok(Some((message, connection))) => self.handle(message, connection),
+            Ok(None) => (),
+            Err(e) => error!(self.logger, "Error processing request: {}", e),
+        }
+    }
+
+    /// Handles a received message
+    ///
+    /// # Arguments
+    ///
+    /// * `message` - The message
+    /// * `connection` - The connection of the message, for answering it
+    pub(crate) fn handle(&mut self, message: Message, mut connection: Connection) {
+        let remote = connection.remote_address().map(|address| address.ip());
+        self.process(message, remote, move |answer| connection.write_message(answer))
+    }
+
+    /// Handles a message forwarded by the relay of the node, answering it through the relay
+    ///
+    /// # Arguments
+    ///
+    /// * `relayed` - The message of type [MessageType::Relay] polled from the relay
+    /// * `relay` - Address of the relay
+    pub(crate) fn handle_relayed(&mut self, relayed: Message, relay: &SocketAddr) {
+        let message = match relayed.relayed().map(|(_, bytes)| Message::from_bytes(bytes)) {
+            Some(Ok(message)) => message,
+            Some(Err(e)) => return warn!(self.logger, "Dropping a malformed message relayed from {}: {}", relayed.sender(), e),
+            None => return debug!(self.logger, "Nothing relayed from {}", relayed.sender()),
+        };
+        if message.sender() != relayed.sender() {
+            return warn!(self.logger, "Dropping a message of {} relayed by {}", message.sender(), relayed.sender());
+        }
+        let (config, logger, relay, partner) = (self.config.clone(), self.logger.clone(), *relay, message.sender().to_owned());
+        // the message did not come from its sender
+        self.process(message, None, move |answer| {
+            let relayed = Message::new_relay(answer.sender().to_owned(), &partner, answer)?;
+            crate::network::send(&relay, relayed, &config, logger.clone())
+        })
+    }
+
+    /// Handles a message, answering it if needed
+    ///
+    /// # Arguments
+    ///
+    /// * `message` - The message
+    /// * `remote` - The IP address the message was received from, None if it was forwarded by another node
+    /// * `answer` - Sends the answer to the sender of the message, such as on the connection of the message
+    fn process<F: FnMut(&Message) -> Result<(), Box<dyn Error>>>(&mut self, message: Message, remote: Option<IpAddr>, mut answer: F) {
+        let MessageHandler { config, view: view_arc, pending_pings, subscribers, stats, scores, quarantine, recent_senders, events, size_estimator, piggyback, parameter_mismatches, pending_requests, relay_queues, last_seen, draining, replay_guard, request_load, request_rate, logger } = self;
+        debug!(logger, "Received: {:?}", message);
+        if quarantine.lock().unwrap().is_quarantined(message.sender()) {
+            debug!(logger, "Dropping message from quarantined {}", message.sender());
+            events.lock().unwrap().audit().reject(message.sender(), RejectionReason::Quarantined);
+            return;
+        }
+        if let Some(guard) = replay_guard.as_mut() {
+            if let Err(e) = guard.check(&message) {
+                warn!(logger, "Dropping message from {}: {}", message.sender(), e);
+                if !e.is_violation() {
+                    return;
+                }
+                events.lock().unwrap().audit().reject(message.sender(), RejectionReason::Replayed);
+                scores.lock().unwrap().record_violation(message.sender());
+                crate::quarantine::report_violation(quarantine, events, message.sender(), logger);
+                return;
+            }
+        }
+        // the senders of relayed messages do not accept connections
+        let own_message = config.addresses().iter().any(|address| address.to_string() == message.sender());
+        if !matches!(message.message_type(), MessageType::Leave | MessageType::Relay | MessageType::Poll) && !message.is_observer() && !own_message {
+            recent_senders.lock().unwrap().record(message.sender());
+        }
+        if *message.message_type() != MessageType::Leave && !message.is_observer() && !own_message {
+            last_seen.lock().unwrap().record(message.sender(), Duration::ZERO);
+        }
+        match message.message_type() {
+            MessageType::Request if config.max_requests_per_second() > 0 && request_rate.record() > config.max_requests_per_second() => {
+                // refuse requests above the admitted rate
+                debug!(logger, "Request rate exceeded, refusing request from {}", message.sender());
+                if let Err(e) = answer(&Message::new_busy(config.address_for(message.sender()).to_string()).in_reply_to(message.nonce())) {
+                    error!(logger, "Error sending busy message: {}", e);
+                }
+                return;
+            }
+            MessageType::Busy => {
+                debug!(logger, "Peer {} is busy", message.sender());
+                return;
+            }
+            MessageType::Response if !Self::answers_request(&message, pending_requests, config) => {
+                // a response is only merged by the node that sent the request
+                debug!(logger, "Ignoring a response from {} not answering a request", message.sender());
+                events.lock().unwrap().audit().reject(message.sender(), RejectionReason::UnsolicitedResponse);
+                return;
+            }
+            MessageType::Ping if config.is_direct_answers() => {
+                // answer on the connection of the ping, the sender may not accept connections
+                if let Err(e) = answer(&Message::new_pong(config.address_for(message.sender()).to_string(), message.nonce())) {
+                    error!(logger, "Error sending pong: {}", e);
+                }
+                return;
+            }
+            MessageType::Ping => {
+                match crate::network::parse_address(message.sender()) {
+                    Ok(remote_address) => if let Err(e) = crate::network::send(&remote_address, Message::new_pong(config.address_for(message.sender()).to_string(), message.nonce()), config, logger.clone()) {
+                        error!(logger, "Error sending pong: {}", e);
+                    },
+                    Err(_) => error!(logger, "Could not parse sender address {}", &message.sender()),
+                }
+                return;
+            }
+            MessageType::Leave if !Self::is_sent_by_sender(&message, remote) => {
+                // the sender is not authenticated, a forged departure would evict another peer
+                warn!(logger, "Ignoring a departure of {} received from {:?}", message.sender(), remote);
+                return;
+            }
+            MessageType::Leave => {
+                info!(logger, "Peer {} is leaving", message.sender());
+                view_arc.lock().unwrap().remove_peer(message.sender());
+                last_seen.lock().unwrap().remove(message.sender());
+                return;
+            }
+            MessageType::Pong => {
+                match pending_pings.lock().unwrap().remove(&message.nonce()) {
+                    Some(waiting) => { let _ = waiting.send(()); },
+                    None => debug!(logger, "Unexpected pong from {}", message.sender()),
+                }
+                return;
+            }
+            MessageType::Relay => {
+                // held until the destination polls the node
+                let sender = message.sender().to_owned();
+                if let Err(e) = relay_queues.lock().unwrap().hold(message) {
+                    debug!(logger, "Not relaying the message of {}: {}", sender, e);
+                }
+                return;
+            }
+            MessageType::Poll => {
+                let held = relay_queues.lock().unwrap().take(message.sender());
+                if let Some(held) = held {
+                    if let Err(e) = answer(&held) {
+                        error!(logger, "Error sending relayed message: {}", e);
+                    }
+                }
+                return;
+            }
+            _ => (),
+        }
+        if let MessageType::Request = message.message_type() {
+            PeerSamplingService::deliver_payload(config, piggyback, &message, logger);
+            PeerSamplingService::check_parameters(&message, parameter_mismatches, events, logger);
+        }
+        let mut view = view_arc.lock().unwrap();
+        if let MessageType::Request = message.message_type() {
+            if let Some((epoch, minima)) = message.size_estimate() {
+                size_estimator.lock().unwrap().merge(epoch, minima);
+            }
+            if config.is_pull() {
+                // answer with an indirection buffer when overloaded
+                let overloaded = config.indegree_cap() > 0 && request_load.record() > config.indegree_cap();
+                if overloaded {
+                    debug!(logger, "In-degree cap reached, answering {} with an indirection buffer", message.sender());
+                }
+                // an observer never advertises itself, nor does a leaving node
+                let leaving = draining.load(std::sync::atomic::Ordering::SeqCst);
+                let own_address = if overloaded || config.is_observer() || leaving { None } else { Some(config.address_for(message.sender())) };
+                let mut buffer = PeerSamplingService::build_buffer(config, &mut view, own_address);
+                // a node with a smaller view would only keep part of the buffer
+                buffer.truncate(PeerSamplingService::exchanged_buffer_size(config, message.parameters()));
+                debug!(logger, "Built response buffer: {:?}", buffer);
+                let mut response = Message::new_response(config.address_for(message.sender()).to_string(), Some(buffer)).in_reply_to(message.nonce());
+                if leaving {
+                    // so that the partner does not keep the address among its recent senders
+                    response = response.with_observer_flag();
+                }
+                if let Some((epoch, minima)) = size_estimator.lock().unwrap().values().filter(|_| !config.is_observer()) {
+                    response = response.with_size_estimate(epoch, minima);
+                }
+                let response = PeerSamplingService::attach_parameters(config, PeerSamplingService::attach_payload(config, piggyback, message.sender(), response, logger));
+                // answer on the connection of the request
+                match answer(&response) {
+                    Ok(()) => debug!(logger, "Buffer sent successfully"),
+                    Err(e) => error!(logger, "Error sending buffer: {}", e),
+                }
+            }
+        }
+        drop(answer);
+
+        // the peers pushed by unknown senders are only merged with a proof of work, the senders being known
+        // by the address they claim only when the message comes from its IP address
+        let known = view.peers().iter().any(|peer| peer.address() == message.sender()) && Self::is_sent_by_sender(&message, remote);
+        let unproven = config.proof_of_work() > 0 && message.view().is_some() && *message.message_type() == MessageType::Request
+            && !known && !message.has_proof_of_work(config.proof_of_work());
+        if unproven {
+            debug!(logger, "Ignoring the peers pushed by {} without proof of work", message.sender());
+            events.lock().unwrap().audit().reject(message.sender(), RejectionReason::NoProofOfWork);
+        }
+        else if let Some(buffer) = message.view() {
+            let buffer = PeerSamplingService::accepted_buffer(config, buffer, message.sender(), scores, quarantine, events, logger);
+            let buffer = PeerSamplingService::clamped_buffer(config, buffer, message.parameters());
+            PeerSamplingService::record_seen(config, &view, last_seen, buffer);
+            PeerSamplingService::select(config, &mut view, buffer, subscribers, stats, events, logger);
+            events.lock().unwrap().record(Event::Merged { peer: message.sender().to_owned(), peers: buffer.len() });
+        }
+        else {
+            debug!(logger, "received a message with an empty buffer");
+        }
+
+        view.increase_age();
+    }
+
+    /// Returns true if a message was received from the IP address of its sender, the port of
+    /// the connections opened by the sender being assigned by its system
+    ///
+    /// # Arguments
+    ///
+    /// * `message` - The message
+    /// * `remote` - The IP address the message was received from, None if it was forwarded by another node
+    fn is_sent_by_sender(message: &Message, remote: Option<IpAddr>) -> bool {
+        match (remote, crate::network::parse_address(message.sender())) {
+            (Some(remote), Ok(sender)) => remote.to_canonical() == sender.ip().to_canonical(),
+            _ => false,
+        }
+    }
+
+    /// Returns true if a response received by the listener answers a recent request sent to its sender
+    ///
+    /// # Arguments
+    ///
+    /// * `message` - The response
+    /// * `pending_requests` - Requests waiting for an answer
+    /// * `config` - The configuration of the node
+    fn answers_request(message: &Message, pending_requests: &Mutex<PendingRequests>, config: &Config) -> bool {
+        match crate::network::parse_address(message.sender()) {
+            Ok(sender) => pending_requests.lock().unwrap().answer(&sender.to_string(), message.nonce(), config.is_strict_responses()),
+            Err(_) => false,
+        }
+    }
+}
+
+/// The outcome of a cycle, recorded in the events and the experiment data
+#[derive(Clone, Copy, Debug)]
+enum ExchangeOutcome {
+    /// The partner answered with its view
+    Response,
+    /// The partner answered that it is busy
+    Busy,
+    /// The partner answered with a message that is not an answer
+    Unexpected,
+    /// The view was pushed without waiting for an answer
+    Pushed,
+    /// The request was relayed to the partner, its answer arriving later
+    Relayed,
+    /// The answer of the partner does not match the request
+    Uncorrelated,
+    /// The partner closed the connection without answering
+    NoAnswer,
+    /// The exchange timed out
+    Timeout,
+    /// The exchange failed, such as when the partner could not be reached
+    Failed,
+    /// No peer could be selected
+    NoPeer,
+}
+
+impl ExchangeOutcome {
+    /// Returns the code of the outcome, such as `no answer`
+    fn as_str(&self) -> &'static str {
+        match self {
+            ExchangeOutcome::Response => "response",
+            ExchangeOutcome::Busy => "busy",
+            ExchangeOutcome::Unexpected => "unexpected",
+            ExchangeOutcome::Pushed => "pushed",
+            ExchangeOutcome::Relayed => "relayed",
+            ExchangeOutcome::Uncorrelated => "uncorrelated",
+            ExchangeOutcome::NoAnswer => "no answer",
+            ExchangeOutcome::Timeout => "timeout",
+            ExchangeOutcome::Failed => "failed",
+            ExchangeOutcome::NoPeer => "no peer",
+        }
+    }
+}
+
+/// Performs the cycles of the peer sampling, exchanging the view with a partner at each cycle
+pub(crate) struct SamplingActivity {
+    config: Config,
+    view: Arc<Mutex<View>>,
+    subscribers: Arc<Mutex<Vec<Sender<Vec<Peer>>>>>,
+    events: Arc<Mutex<Events>>,
+    stats: Arc<Mutex<Stats>>,
+    scores: Arc<Mutex<PeerScores>>,
+    quarantine: Arc<Mutex<Quarantine>>,
+    recent_senders: Arc<Mutex<RecentSenders>>,
+    size_estimator: Arc<Mutex<SizeEstimator>>,
+    piggyback: Arc<Mutex<Option<Box<dyn Piggyback>>>>,
+    parameter_mismatches: Arc<Mutex<ParameterMismatches>>,
+    pending_requests: Arc<Mutex<PendingRequests>>,
+    recent_partners: Arc<Mutex<RecentPartners>>,
+    last_seen: Arc<Mutex<LastSeen>>,
+    draining: Arc<AtomicBool>,
+    /// The source of contact peers when the view is empty
+    bootstrap: Box<dyn Bootstrap>,
+    /// The file where the view is saved, if any
+    snapshot: Option<ViewSnapshot>,
+    partition_detector: PartitionDetector,
+    breaker: Arc<Mutex<CircuitBreaker>>,
+    backoff: Arc<Mutex<SendBackoff>>,
+    /// Number of consecutive exchanges that failed
+    failed_exchanges: usize,
+    /// The last partners, not selected again unless there is no other peer
+    last_partners: VecDeque<String>,
+    /// Address of the node, in the recorded samples
+    address: String,
+    /// Number of cycles performed
+    round: u64,
+    /// Recorder of the experiment data, if enabled
+    recorder: Option<Recorder>,
+    logger: Logger,
+}
+
+impl SamplingActivity {
+    /// Returns when the first cycle is scheduled, none if the cycles are only triggered
+    pub(crate) fn first_cycle(&self) -> Option<Instant> {
+        self.next_cycle(0)
+    }
+
+    /// Returns when the cycle following a scheduled cycle is scheduled, none if the cycles are only triggered
+    pub(crate) fn following_cycle(&self) -> Option<Instant> {
+        self.next_cycle(self.round + 1)
+    }
+
+    // the first cycles of a node that joins are scheduled at the shorter warm-up period
+    fn next_cycle(&self, round: u64) -> Option<Instant> {
+        let config = &self.config;
+        let delay = config.schedule().next_delay(config.sampling_period(), config.sampling_deviation(), &mut rand::thread_rng());
+        let warm_up = round < config.warm_up_cycles() as u64;
+        delay.map(|delay| Instant::now() + if warm_up { Duration::from_millis(config.warm_up_period()) } else { delay })
+    }
+
+    /// Performs a cycle, and returns the address of the partner contacted, none if no peer was found
+    pub(crate) fn run_cycle(&mut self) -> Option<String> {
+        let SamplingActivity { config, view: view_arc, subscribers, events, stats, scores, quarantine, recent_senders, size_estimator, piggyback, parameter_mismatches, pending_requests, recent_partners, last_seen, draining, bootstrap, snapshot, partition_detector, breaker, backoff, failed_exchanges, last_partners, address, round, recorder, logger } = self;
+
+        // a leaving node only answers the exchanges of the other nodes
+        if draining.load(std::sync::atomic::Ordering::SeqCst) {
+            return None;
+        }
+
+        // merge the peers added to the bootstrap source
+        if let Some(peers) = bootstrap.updates() {
+            info!(logger, "Merging {} new bootstrap peers", peers.len());
+            PeerSamplingService::select(config, &mut view_arc.lock().unwrap(), &peers, subscribers, stats, events, logger);
+        }
+
+        debug!(logger, "Sampling peers");
+        let selected = {
+            let mut view = view_arc.lock().unwrap();
+            // quarantined peers and peers that repeatedly timed out are not selected for exchanges
+            let mut quarantine = quarantine.lock().unwrap();
+            let (breaker, backoff) = (breaker.lock().unwrap(), backoff.lock().unwrap());
+            let mut selectable = |peer: &Peer| !quarantine.is_quarantined(peer.address()) && breaker.allows(peer.address()) && backoff.allows(peer.address());
+            // nor are the last partners, unless there is no other peer
+            let partner = view.select_peer_filtered(|peer| selectable(peer) && !last_partners.contains(&peer.address))
+                .or_else(|| view.select_peer_filtered(&mut selectable));
+            partner.map(|peer| {
+                // send local view, or empty view to trigger response
+                let buffer = if config.is_push() && !config.is_observer() { Some(PeerSamplingService::build_buffer(config, &mut view, Some(config.address_for(peer.address())))) } else { None };
+                (peer, buffer)
+            })
+        };
+        let (partner, outcome) = if let Some((peer, buffer)) = selected {
+            if config.partner_history() > 0 {
+                last_partners.push_back(peer.address.clone());
+                if last_partners.len() > config.partner_history() {
+                    last_partners.pop_front();
+                }
+            }
+            // exchange over a single connection, without holding the view
+            let exchange_start = Instant::now();
+            let (reply, mut outcome) = match peer.socket_address() {
+                Ok(remote_address) => match PeerSamplingService::exchange(config, &remote_address, buffer, size_estimator, piggyback, pending_requests, logger) {
+                    Ok((_, true)) => (None, ExchangeOutcome::Relayed),
+                    Ok((reply, _)) if !config.is_pull() => (reply, ExchangeOutcome::Pushed),
+                    Ok((Some(reply), _)) if matches!(reply.message_type(), MessageType::Response | MessageType::Busy)
+                        && !pending_requests.lock().unwrap().answer(&remote_address.to_string(), reply.nonce(), config.is_strict_responses()) => {
+                        warn!(logger, "Ignoring an answer from {} not matching the request", &peer.address);
+                        (None, ExchangeOutcome::Uncorrelated)
+                    }
+                    Ok((reply, _)) => (reply, ExchangeOutcome::NoAnswer),
+                    Err(e) if crate::network::is_timeout(e.as_ref()) => {
+                        warn!(logger, "Exchange with {} timed out", &peer.address);
+                        (None, ExchangeOutcome::Timeout)
+                    }
+                    Err(e) => {
+                        error!(logger, "Error exchanging views with {}: {}", &peer.address, e);
+                        (None, ExchangeOutcome::Failed)
+                    }
+                },
+                Err(_) => {
+                    error!(logger, "Could not parse sender address {}", &peer.address);
+                    (None, ExchangeOutcome::Failed)
+                }
+            };
+
+            let mut view = view_arc.lock().unwrap();
+            // in push mode only, the partner does not answer
+            let answered = reply.is_some() || matches!(outcome, ExchangeOutcome::Pushed);
+            let timed_out = matches!(outcome, ExchangeOutcome::Timeout);
+            if (answered || timed_out) && breaker.lock().unwrap().record(&peer.address, !answered) {
+                info!(logger, "Not attempting {} for {} seconds after repeated timeouts", &peer.address, config.breaker_cooldown());
+            }
+            if answered || matches!(outcome, ExchangeOutcome::Failed | ExchangeOutcome::Timeout) {
+                if let Some(delay) = backoff.lock().unwrap().record(&peer.address, !answered) {
+                    debug!(logger, "Not attempting {} for {} ms after failing to reach it", &peer.address, delay.as_millis());
+                }
+            }
+            scores.lock().unwrap().record_exchange(&peer.address, if answered { Some(exchange_start.elapsed()) } else { None });
+            if answered {
+                recent_partners.lock().unwrap().record(&peer.address);
+                last_seen.lock().unwrap().record(&peer.address, Duration::ZERO);
+            }
+            match reply {
+                Some(message) => match message.message_type() {
+                    MessageType::Response => {
+                        outcome = ExchangeOutcome::Response;
+                        if let Some((epoch, minima)) = message.size_estimate() {
+                            size_estimator.lock().unwrap().merge(epoch, minima);
+                        }
+                        PeerSamplingService::deliver_payload(config, piggyback, &message, logger);
+                        PeerSamplingService::check_parameters(&message, parameter_mismatches, events, logger);
+                        if let Some(buffer) = message.view() {
+                            let buffer = PeerSamplingService::accepted_buffer(config, buffer, &peer.address, scores, quarantine, events, logger);
+                            let buffer = PeerSamplingService::clamped_buffer(config, buffer, message.parameters());
+                            PeerSamplingService::record_seen(config, &view, last_seen, buffer);
+                            PeerSamplingService::select(config, &mut view, buffer, subscribers, stats, events, logger);
+                        }
+                    },
+                    MessageType::Busy => {
+                        outcome = ExchangeOutcome::Busy;
+                        debug!(logger, "Peer {} is busy", message.sender());
+                    },
+                    _ => {
+                        outcome = ExchangeOutcome::Unexpected;
+                        scores.lock().unwrap().record_violation(&peer.address);
+                        crate::quarantine::report_violation(quarantine, events, &peer.address, logger);
+                        warn!(logger, "Unexpected answer from {}: {:?}", message.sender(), message.message_type());
+                    },
+                },
+                None => debug!(logger, "No answer from {}", &peer.address),
+            }
+            view.increase_age();
+
+            // forget the peers that consistently misbehave
+            if config.score_threshold() > 0.0 {
+                for address in scores.lock().unwrap().below(config.score_threshold()) {
+                    if view.peers().iter().any(|peer| peer.address() == address) {
+                        info!(logger, "Removing peer {} with a low score", address);
+                        view.remove_peer(&address);
+                    }
+                }
+            }
+
+            // as many consecutive failures as peers in the view: try the recent senders
+            *failed_exchanges = if answered { 0 } else { *failed_exchanges + 1 };
+            if *failed_exchanges >= view.peers().len() {
+                if let Some(peers) = recent_senders.lock().unwrap().peers() {
+                    info!(logger, "All exchanges failing, adding {} recent senders", peers.len());
+                    view.add_peers(peers);
+                    *failed_exchanges = 0;
+                }
+            }
+
+            // bootstrap again when partitioned
+            partition_detector.record(&peer.address, answered, view.peers().len());
+            if let Some(unreachable) = partition_detector.check() {
+                warn!(logger, "Partition suspected, unreachable peers: {:?}", unreachable);
+                events.lock().unwrap().notify(Event::PartitionSuspected { unreachable });
+                if let Some(peers) = bootstrap.peers() {
+                    PeerSamplingService::select(config, &mut view, &peers, subscribers, stats, events, logger);
+                }
+            }
+            events.lock().unwrap().record(Event::Exchanged { peer: peer.address.clone(), outcome: outcome.as_str().to_owned() });
+            (Some(peer.address), outcome)
+        }
+        else {
+            warn!(logger, "No peer found for sampling");
+            // retry the bootstrap source for the next cycle, then the recent senders and the last saved view
+            if let Some(peers) = bootstrap.peers() {
+                info!(logger, "Bootstrapping with {} peers", peers.len());
+                view_arc.lock().unwrap().add_peers(peers);
+            }
+            else if let Some(peers) = recent_senders.lock().unwrap().peers() {
+                info!(logger, "Recovering with {} recent senders", peers.len());
+                view_arc.lock().unwrap().add_peers(peers);
+            }
+            else if let Some(peers) = snapshot.as_ref().and_then(|snapshot| snapshot.load()) {
+                info!(logger, "Recovering with {} peers of the saved view", peers.len());
+                view_arc.lock().unwrap().add_peers(peers);
+            }
+            (None, ExchangeOutcome::NoPeer)
+        };
+
+        stats.lock().unwrap().end_cycle();
+
+        if let Some(snapshot) = snapshot.as_ref() {
+            if let Err(e) = snapshot.save(view_arc.lock().unwrap().peers()) {
+                error!(logger, "Error saving view: {}", e);
+            }
+        }
+
+        // record experiment data
+        *round += 1;
+        if let Some(recorder) = recorder.as_mut() {
+            let view = view_arc.lock().unwrap().peers().to_vec();
+            let sample = Sample { round: *round, node: address, partner: partner.as_deref(), outcome: outcome.as_str(), view: &view };
+            if let Err(e) = recorder.record(&sample) {
+                error!(logger, "Error recording sample: {}", e);
+            }
+        }
+
+        partner
+    }
+
+    /// Returns the source of contact peers, given back to the service when the activity stops
+    pub(crate) fn into_bootstrap(self) -> Box<dyn Bootstrap> {
+        self.bootstrap
+    }
+}
+
+impl PeerSampler for PeerSamplingService {
+    fn get_peer(&mut self) -> Option<Peer> {
+        PeerSamplingService::get_peer(self)
+    }
+
+    fn view_snapshot(&self) -> Vec<Peer> {
+        self.view.lock().unwrap().peers().to_vec()
+    }
+
+    fn subscribe(&mut self) -> Receiver<Vec<Peer>> {
+        let (tx, rx) = std::sync::mpsc::channel();
+        self.subscribers.lock().unwrap().push(tx);
+        rx
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ConfigWarning;
+    use crate::policy::SamplingPolicy;
+
+    fn view_with_peers(count: usize) -> View {
+        View::with_peers("127.0.0.1:9000".to_owned(), (1..=count).map(|i| Peer::new(format!("127.0.0.1:{}", 9000 + i))).collect())
+    }
+
+    #[test]
+    fn buffer_size_is_configurable() {
+        let config = Config::new("127.0.0.1:9000".parse().unwrap(), true, true, 1, 0, 20, 2, 8, None);
+        let mut view = view_with_peers(20);
+        let buffer = PeerSamplingService::build_buffer(&config, &mut view, Some(*config.address()));
+        assert_eq!(buffer.len(), 10);
+        assert_eq!(buffer[0].address(), "127.0.0.1:9000");
+
+        let config = config.with_buffer_size(4);
+        assert_eq!(PeerSamplingService::build_buffer(&config, &mut view, Some(*config.address())).len(), 4);
+        assert_eq!(PeerSamplingService::build_buffer(&config, &mut view, None).len(), 4);
+
+        let mut view = view_with_peers(2);
+        assert_eq!(PeerSamplingService::build_buffer(&config, &mut view, Some(*config.address())).len(), 3);
+    }
+
+    #[test]
+    fn own_descriptor_age_follows_policy() {
+        let config = Config::new("127.0.0.1:9000".parse().unwrap(), true, true, 1, 0, 20, 2, 8, None);
+        let mut view = view_with_peers(2);
+        view.increase_age();
+        view.increase_age();
+        assert_eq!(PeerSamplingService::build_buffer(&config, &mut view, Some(*config.address()))[0].age(), 0);
+
+        let config = config.with_self_age_policy(SelfAgePolicy::Preserve);
+        let buffer = PeerSamplingService::build_buffer(&config, &mut view, Some(*config.address()));
+        assert_eq!((buffer[0].address(), buffer[0].age()), ("127.0.0.1:9000", 2));
+        view.reset_own_age();
+        assert_eq!(PeerSamplingService::build_buffer(&config, &mut view, Some(*config.address()))[0].age(), 0);
+    }
+
+    #[test]
+    fn invalid_parameters_are_rejected() {
+        let logger = crate::log::terminal_logger();
+        let address = "127.0.0.1:9000".parse().unwrap();
+        let invalid = [
+            (Config::new(address, true, true, 1, 0, 0, 0, 0, None), ConfigError::EmptyView),
+            (Config::new(address, false, false, 1, 0, 20, 2, 8, None), ConfigError::NoExchange),
+            (Config::new(address, true, true, 1, 0, 20, 3, 8, None), ConfigError::SelectionFactors { healing_factor: 3, swapping_factor: 8, view_size: 20 }),
+            (Config::new(address, true, true, 0, 0, 20, 2, 8, None), ConfigError::ZeroSamplingPeriod),
+            (Config::new(address, true, false, 1, 0, 20, 2, 8, None).with_observer(true), ConfigError::ObserverWithoutPull),
+            (Config::new(address, true, true, 1, 0, 20, 2, 8, None).with_size_estimation(256, 60), ConfigError::SizeEstimationSamples { samples: 256 }),
+            (Config::new(address, true, true, 1, 0, 20, 2, 8, None).with_locality("x".repeat(65)), ConfigError::LocalityTooLong { size: 65 }),
+            (Config::new(address, true, true, 1, 0, 20, 2, 8, None).with_buffer_size(0), ConfigError::BufferSizeOutOfRange { buffer_size: 0, view_size: 20 }),
+            (Config::new(address, true, true, 1, 0, 20, 2, 8, None).with_buffer_size(21), ConfigError::BufferSizeOutOfRange { buffer_size: 21, view_size: 20 }),
+            (Config::new(address, true, true, 1, 0, 600, 2, 8, None), ConfigError::BufferSizeOutOfRange { buffer_size: 300, view_size: 600 }),
+            (Config::new(address, true, true, 1, 0, 1, 0, 0, None), ConfigError::BufferSizeOutOfRange { buffer_size: 0, view_size: 1 }),
+            #[cfg(feature = "event-loop")]
+            (Config::new(address, true, true, 1, 0, 20, 2, 8, None).with_event_loop(), ConfigError::EventLoopWithoutTimeout),
+        ];
+        for (config, error) in invalid {
+            assert_eq!(PeerSamplingService::try_new(config, logger.clone()).err(), Some(error));
+        }
+        let triggered = Config::new(address, true, true, 0, 0, 20, 2, 8, None).with_synchronized_rounds(true);
+        assert!(PeerSamplingService::try_new(triggered, logger).is_ok());
+    }
+
+    #[test]
+    fn deviations_from_the_paper_are_reported() {
+        let address = "127.0.0.1:9000".parse().unwrap();
+        assert!(Config::new(address, true, true, 1, 0, 20, 2, 8, None).warnings().is_empty());
+        assert_eq!(Config::new(address, false, true, 1, 0, 20, 0, 8, None).with_buffer_size(4).warnings(), vec![
+            ConfigWarning::NotPushPull,
+            ConfigWarning::NoHealing,
+            ConfigWarning::BufferSize { buffer_size: 4, view_size: 20 },
+        ]);
+    }
+
+    #[test]
+    fn state_is_exported_and_imported() {
+        let config = Config::new("127.0.0.1:9000".parse().unwrap(), true, true, 1, 0, 20, 2, 8, None);
+        let mut service = PeerSamplingService::new(config, crate::log::terminal_logger());
+        let updates = service.subscribe();
+        let state = "view 127.0.0.1:9001(4) 127.0.0.1:9002(1)\nqueue 127.0.0.1:9002(1)\ncycles 7\nself_occurrences 3\nindegree 0.5\n";
+        service.import_state(state.parse().unwrap());
+
+        assert_eq!(service.export_state().to_string(), state);
+        assert_eq!(updates.try_recv().unwrap().len(), 2);
+        assert_eq!(service.get_peer().unwrap().address(), "127.0.0.1:9002");
+        assert_eq!(service.stats().cycles(), 7);
+    }
+
+    #[test]
+    fn peer_hints_are_merged_into_view() {
+        let config = Config::new("127.0.0.1:9000".parse().unwrap(), true, true, 1, 0, 4, 1, 1, None);
+        let mut service = PeerSamplingService::new(config, crate::log::terminal_logger());
+        let updates = service.subscribe();
+        service.import_state("view 127.0.0.1:9001(2) 127.0.0.1:9002(0) 127.0.0.1:9003(1) 127.0.0.1:9004(0)".parse().unwrap());
+
+        // the fresher hint replaces the older descriptor, and the oldest peer is removed
+        service.add_peer_hint(Peer::new("127.0.0.1:9001".to_owned()));
+        service.add_peer_hint(Peer::new("127.0.0.1:9000".to_owned()));
+        service.add_peer_hint(Peer::new("127.0.0.1:9005".to_owned()));
+        let view = service.view_snapshot();
+        assert_eq!(view.len(), 4);
+        assert!(view.contains(&Peer::new("127.0.0.1:9005".to_owned())));
+        assert!(!view.contains(&Peer::new("127.0.0.1:9000".to_owned())));
+        assert_eq!(view.iter().find(|peer| peer.address() == "127.0.0.1:9001").map(|peer| peer.age()), Some(0));
+        assert_eq!(updates.try_iter().count(), 4);
+    }
+
+    #[test]
+    fn rejected_peers_are_audited() {
+        let config = Config::new("127.0.0.1:9000".parse().unwrap(), true, true, 1, 0, 20, 2, 8, None)
+            .with_prefix_quota(crate::quota::PrefixQuota::new(1).with_ipv4_prefix(32))
+            .with_audit(8);
+        let service = PeerSamplingService::new(config, crate::log::terminal_logger());
+        let rejections = service.subscribe_rejections();
+        service.add_peer_hint(Peer::new("127.0.0.1:9000".to_owned()));
+        service.add_peer_hint(Peer::new("127.0.0.1:9001".to_owned()));
+        service.add_peer_hint(Peer::with_age("127.0.0.1:9002".to_owned(), 1));
+
+        let audited = service.rejections().iter().map(|rejection| (rejection.peer().to_owned(), rejection.reason())).collect::<Vec<(String, RejectionReason)>>();
+        assert_eq!(audited, vec![
+            ("127.0.0.1:9000".to_owned(), RejectionReason::OwnAddress),
+            ("127.0.0.1:9002".to_owned(), RejectionReason::PrefixQuota),
+        ]);
+        assert_eq!(rejections.try_iter().count(), 2);
+        assert_eq!(service.view_snapshot(), vec![Peer::new("127.0.0.1:9001".to_owned())]);
+    }
+
+    #[test]
+    fn uniformity_of_samples_is_reported() {
+        let config = Config::new("127.0.0.1:9000".parse().unwrap(), true, true, 1, 0, 20, 2, 8, None)
+            .with_sampling_policy(SamplingPolicy::RoundRobin)
+            .with_uniformity_test(100);
+        let mut service = PeerSamplingService::new(config, crate::log::terminal_logger());
+        service.import_state("view 127.0.0.1:9001(0) 127.0.0.1:9002(0) 127.0.0.1:9003(0) 127.0.0.1:9004(0)".parse().unwrap());
+        assert_eq!(service.stats().uniformity(), None);
+
+        (0..100).for_each(|_| { service.get_peer(); });
+        assert_eq!(service.stats().uniformity(), Some(0.0));
+        // a peer returned much more often than the others
+        service.import_state("view 127.0.0.1:9001(0) 127.0.0.1:9005(0)".parse().unwrap());
+        assert!(service.stats().uniformity().unwrap() > 10.0);
+    }
+
+    #[test]
+    fn advertised_address_matches_peer_family() {
+        let config = Config::new("127.0.0.1:9000".parse().unwrap(), true, true, 1, 0, 20, 2, 8, None)
+            .with_additional_address("[::1]:9000".parse().unwrap())
+            .with_additional_address("192.168.1.2:9000".parse().unwrap());
+        assert_eq!(config.address_for("10.0.0.1:9000"), "127.0.0.1:9000".parse().unwrap());
+        assert_eq!(config.address_for("[fe80::1]:9000"), "[::1]:9000".parse().unwrap());
+        assert_eq!(config.address_for("invalid"), "127.0.0.1:9000".parse().unwrap());
+
+        // the external address is not advertised to local peers
+        let config = config.with_external_address("203.0.113.7:9100".parse().unwrap());
+        assert_eq!(config.address_for("10.0.0.1:9000"), "203.0.113.7:9100".parse().unwrap());
+        assert_eq!(config.address_for("127.0.0.1:9001"), "127.0.0.1:9000".parse().unwrap());
+        assert_eq!(config.address_for("[fe80::1]:9000"), "[::1]:9000".parse().unwrap());
+    }
+
+    #[test]
+    fn received_buffer_is_truncated() {
+        let logger = Logger::root(slog::Discard, slog::o!());
+        let scores = Mutex::new(PeerScores::default());
+        let quarantine = Mutex::new(Quarantine::new(0, Duration::from_secs(60)));
+        let events = Mutex::new(Events::new(0));
+        let buffer = view_with_peers(8).peers().to_vec();
+        let config = Config::new("127.0.0.1:9000".parse().unwrap(), true, true, 1, 0, 20, 2, 8, None);
+        let accepted = PeerSamplingService::accepted_buffer(&config, &buffer, "127.0.0.1:9001", &scores, &quarantine, &events, &logger);
+        assert_eq!(accepted.len(), 8);
+
+        let config = config.with_max_received_buffer_size(5);
+        let accepted = PeerSamplingService::accepted_buffer(&config, &buffer, "127.0.0.1:9001", &scores, &quarantine, &events, &logger);
+        assert_eq!(accepted, &buffer[..5]);
+        assert_eq!(scores.lock().unwrap().snapshot()["127.0.0.1:9001"].violations(), 1);
+    }
+
+    #[test]
+    fn buffer_of_larger_view_is_clamped() {
+        let buffer = view_with_peers(15).peers().to_vec();
+        let config = Config::new("127.0.0.1:9000".parse().unwrap(), true, true, 1, 0, 10, 1, 4, None);
+        assert_eq!(PeerSamplingService::clamped_buffer(&config, &buffer, Some(ProtocolParameters::new(30, 2, 8))), &buffer[..5]);
+        assert_eq!(PeerSamplingService::clamped_buffer(&config, &buffer, Some(ProtocolParameters::new(10, 1, 4))), &buffer[..5]);
+        assert_eq!(PeerSamplingService::clamped_buffer(&config, &buffer, Some(ProtocolParameters::new(4, 1, 1))), &buffer[..2]);
+        assert_eq!(PeerSamplingService::clamped_buffer(&config, &buffer, None).len(), 15);
+        assert_eq!(PeerSamplingService::clamped_buffer(&config, &buffer[..3], Some(ProtocolParameters::new(30, 2, 8))).len(), 3);
+    }
+
+    #[test]
+    fn exchanged_buffer_follows_the_configured_buffer_size() {
+        let config = Config::new("127.0.0.1:9000".parse().unwrap(), true, true, 1, 0, 20, 2, 8, None).with_buffer_size(6);
+        assert_eq!(PeerSamplingService::exchanged_buffer_size(&config, None), 6);
+        assert_eq!(PeerSamplingService::exchanged_buffer_size(&config, Some(ProtocolParameters::new(40, 2, 8))), 6);
+        assert_eq!(PeerSamplingService::exchanged_buffer_size(&config, Some(ProtocolParameters::new(10, 1, 4))), 3);
+        assert_eq!(PeerSamplingService::exchanged_buffer_size(&config, Some(ProtocolParameters::new(1, 0, 0))), 1);
+    }
+
+    #[test]
+    fn peers_round_trip_as_strings() {
+        for peer in [Peer::with_age("127.0.0.1:9001".to_owned(), 3), Peer::with_age("[fe80::1%eth0]:9000".to_owned(), 0)] {
+            let parsed = peer.to_string().parse::<Peer>().unwrap();
+            assert_eq!((parsed.address(), parsed.age()), (peer.address(), peer.age()));
+        }
+        assert_eq!(Peer::with_age("127.0.0.1:9001".to_owned(), 3).to_string(), "127.0.0.1:9001(3)");
+        assert_eq!("127.0.0.1:9001".parse::<Peer>().unwrap().age(), 0);
+        assert!("127.0.0.1:9001(-1)".parse::<Peer>().is_err());
+        assert!("127.0.0.1:9001)".parse::<Peer>().is_err());
+        assert!("(2)".parse::<Peer>().is_err());
+    }
+
+    #[test]
+    fn age_only_increases() {
+        let mut peer = Peer::with_age("127.0.0.1:9001".to_owned(), 7);
+        assert_eq!(Peer::from_bytes(&peer.as_bytes()).unwrap().age(), 7);
+        peer.increment_age();
+        assert_eq!(peer.age(), 8);
+        let mut peer = Peer::with_age("127.0.0.1:9001".to_owned(), u16::MAX);
+        peer.increment_age();
+        assert_eq!(peer.age(), u16::MAX);
+    }
+
+    #[test]
+    fn incarnations_round_trip() {
+        let peer = Peer::with_age("127.0.0.1:9001".to_owned(), 3).with_incarnation(70000);
+        let decoded = Peer::from_bytes(&peer.as_bytes()).unwrap();
+        assert_eq!((decoded.age(), decoded.incarnation()), (3, 70000));
+        assert_eq!(peer.to_string(), "127.0.0.1:9001(3,70000)");
+        assert_eq!("127.0.0.1:9001(3,70000)".parse::<Peer>().unwrap().incarnation(), 70000);
+        // without incarnation, the descriptor is unchanged
+        assert_eq!(Peer::with_age("127.0.0.1:9001".to_owned(), 3).as_bytes().len(), "127.0.0.1:9001".len() + 3);
+        let bytes = peer.as_bytes();
+        assert!(Peer::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        assert!("127.0.0.1:9001(3,x)".parse::<Peer>().is_err());
+    }
+
+    #[test]
+    fn localities_round_trip() {
+        let peer = Peer::with_age("127.0.0.1:9001".to_owned(), 3).with_locality("eu-west".to_owned());
+        let decoded = Peer::from_bytes(&peer.as_bytes()).unwrap();
+        assert_eq!((decoded.age(), decoded.incarnation(), decoded.locality()), (3, 0, Some("eu-west")));
+        assert_eq!(peer.to_string(), "127.0.0.1:9001(3,0,eu-west)");
+        let parsed = "127.0.0.1:9001(3,2,eu-west)".parse::<Peer>().unwrap();
+        assert_eq!((parsed.incarnation(), parsed.locality()), (2, Some("eu-west")));
+        let bytes = peer.as_bytes();
+        assert!(Peer::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        // truncated to the maximum size
+        let long = Peer::new("127.0.0.1:9001".to_owned()).with_locality("x".repeat(MAX_LOCALITY_SIZE + 1));
+        assert_eq!(Peer::from_bytes(&long.as_bytes()).unwrap().locality().map(str::len), Some(MAX_LOCALITY_SIZE));
+    }
+
+    #[test]
+    fn scoped_ipv6_addresses() {
+        let peer = Peer::from_bytes(&Peer::new("[fe80::1%2]:9000".to_owned()).as_bytes()).unwrap();
+        match peer.socket_address().unwrap() {
+            SocketAddr::V6(address) => assert_eq!(address.scope_id(), 2),
+            address => panic!("unexpected address {}", address),
+        }
+        #[cfg(target_os = "linux")]
+        match Peer::new("[fe80::1%lo]:9000".to_owned()).socket_address().unwrap() {
+            SocketAddr::V6(address) => assert_ne!(address.scope_id(), 0),
+            address => panic!("unexpected address {}", address),
+        }
+        assert!(Peer::new("[fe80::1%gbps-missing]:9000".to_owned()).socket_address().is_err());
+        assert!(Peer::new("127.0.0.1%lo:9000".to_owned()).socket_address().is_err());
     }
 }
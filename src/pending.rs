@@ -0,0 +1,84 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// The last request sent to each partner, for rejecting the responses that do not
+/// correspond to a recent request, such as a response pushed by another node
+pub struct PendingRequests {
+    /// Time during which a request can be answered
+    window: Duration,
+    /// Nonce of the last request sent to each partner, with the time it can no longer be answered
+    requests: HashMap<String, (u64, Instant)>,
+}
+
+impl PendingRequests {
+    /// Creates an empty set of requests
+    ///
+    /// # Arguments
+    ///
+    /// * `window` - Time during which a request can be answered
+    pub fn new(window: Duration) -> PendingRequests {
+        PendingRequests {
+            window,
+            requests: HashMap::new(),
+        }
+    }
+
+    /// Records a request sent to a partner, replacing its previous request
+    ///
+    /// # Arguments
+    ///
+    /// * `partner` - Address of the partner
+    /// * `nonce` - Nonce of the request
+    pub fn record(&mut self, partner: &str, nonce: u64) {
+        self.record_at(partner, nonce, Instant::now())
+    }
+
+    fn record_at(&mut self, partner: &str, nonce: u64, now: Instant) {
+        // forget the requests that can no longer be answered
+        self.requests.retain(|_, (_, expiry)| *expiry > now);
+        self.requests.insert(partner.to_owned(), (nonce, now + self.window));
+    }
+
+    /// Returns true if an answer of a partner corresponds to a recent request sent to that partner,
+    /// which is then considered answered
+    ///
+    /// # Arguments
+    ///
+    /// * `partner` - Address of the partner
+    /// * `nonce` - Nonce of the answer, which echoes the nonce of the request
+    /// * `strict` - Whether the nonce must be that of the request, and not only the partner
+    pub fn answer(&mut self, partner: &str, nonce: u64, strict: bool) -> bool {
+        self.answer_at(partner, nonce, strict, Instant::now())
+    }
+
+    fn answer_at(&mut self, partner: &str, nonce: u64, strict: bool, now: Instant) -> bool {
+        match self.requests.get(partner) {
+            Some((expected, expiry)) if *expiry > now && (!strict || *expected == nonce) => {
+                self.requests.remove(partner);
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn answers_recent_requests_once() {
+        let now = Instant::now();
+        let mut pending = PendingRequests::new(Duration::from_secs(5));
+        assert!(!pending.answer_at("127.0.0.1:9001", 7, false, now));
+
+        pending.record_at("127.0.0.1:9001", 7, now);
+        pending.record_at("127.0.0.1:9002", 8, now);
+        assert!(!pending.answer_at("127.0.0.1:9001", 9, true, now));
+        assert!(pending.answer_at("127.0.0.1:9001", 7, true, now));
+        assert!(!pending.answer_at("127.0.0.1:9001", 7, true, now));
+        // only the partner is checked when not strict, until the request expires
+        assert!(!pending.answer_at("127.0.0.1:9002", 9, false, now + Duration::from_secs(5)));
+        assert!(pending.answer_at("127.0.0.1:9002", 9, false, now));
+    }
+}
@@ -0,0 +1,23 @@
+/// Application data attached to the exchanges of the protocol, for disseminating small pieces
+/// of information, such as versions or load figures, without opening other connections.
+///
+/// The payload is attached to every request and response, and each payload received from
+/// a partner is handed back to the application. Both methods are called from the threads of
+/// the service and must not wait on the service itself.
+pub trait Piggyback: Send {
+    /// Returns the payload attached to a message sent to a partner, at most
+    /// [crate::Config::with_piggyback] bytes, larger payloads not being sent
+    ///
+    /// # Arguments
+    ///
+    /// * `partner` - Address of the partner
+    fn payload(&mut self, partner: &str) -> Vec<u8>;
+
+    /// Receives the payload attached to a message of a partner
+    ///
+    /// # Arguments
+    ///
+    /// * `sender` - Address of the partner
+    /// * `payload` - The payload
+    fn receive(&mut self, sender: &str, payload: &[u8]);
+}
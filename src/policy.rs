@@ -0,0 +1,35 @@
+/// How the peers returned to the application are sampled from the view
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SamplingPolicy {
+    /// Each time the view is updated, the peers of the view that are not queued are appended
+    /// to a queue, from which peers are returned first, and peers are returned at random
+    /// from the view while the queue is empty. This is the default. The peers that entered
+    /// the view are returned quickly, at the cost of a bias towards them.
+    Queue,
+    /// Peers are returned uniformly at random from the view
+    Uniform,
+    /// The peers of the view are returned in turn, in the order of the view
+    RoundRobin,
+}
+
+/// Age given to the node's own descriptor in the buffers it sends, see [crate::Config::with_self_age_policy]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SelfAgePolicy {
+    /// The descriptor has age 0 in every buffer, as in the paper. This is the default.
+    /// Healing favours the nodes that sent buffers recently.
+    Reset,
+    /// The descriptor ages like the peers of the view, from 0 when the node starts a new incarnation,
+    /// so that the age advertised measures the lifetime of the node rather than its last exchange
+    Preserve,
+}
+
+/// How a returned peer was sampled, see [crate::PeerSamplingService::sample_peer]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SampleSource {
+    /// From the queue of the peers that entered the view
+    Queue,
+    /// At random from the view
+    Random,
+    /// In turn from the view
+    RoundRobin,
+}
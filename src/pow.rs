@@ -0,0 +1,61 @@
+use sha2::{Digest, Sha256};
+
+/// Returns whether a nonce solves the proof of work over the digest of a message,
+/// the hash of the digest followed by the nonce starting with `difficulty` zero bits
+///
+/// # Arguments
+///
+/// * `digest` - Hash of the message with a zero nonce
+/// * `nonce` - The nonce of the message
+/// * `difficulty` - Number of leading zero bits required
+pub(crate) fn is_solved(digest: &[u8], nonce: u64, difficulty: u8) -> bool {
+    let hash = Sha256::new().chain_update(digest).chain_update(nonce.to_be_bytes()).finalize();
+    leading_zeros(&hash) >= difficulty as u32
+}
+
+/// Returns the first nonce from a starting value that solves the proof of work, after
+/// 2^difficulty attempts on average
+///
+/// # Arguments
+///
+/// * `digest` - Hash of the message with a zero nonce
+/// * `start` - The first nonce attempted
+/// * `difficulty` - Number of leading zero bits required
+pub(crate) fn solve(digest: &[u8], start: u64, difficulty: u8) -> u64 {
+    let mut nonce = start;
+    while !is_solved(digest, nonce, difficulty) {
+        nonce = nonce.wrapping_add(1);
+    }
+    nonce
+}
+
+/// Returns the hash of bytes
+pub(crate) fn digest(bytes: &[u8]) -> Vec<u8> {
+    Sha256::digest(bytes).to_vec()
+}
+
+fn leading_zeros(hash: &[u8]) -> u32 {
+    let mut zeros = 0;
+    for byte in hash {
+        zeros += byte.leading_zeros();
+        if *byte != 0 {
+            break;
+        }
+    }
+    zeros
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn solution_is_verified() {
+        let digest = digest(b"message");
+        let nonce = solve(&digest, 0, 8);
+        assert!(is_solved(&digest, nonce, 8));
+        assert!(!is_solved(&super::digest(b"other message"), solve(&super::digest(b"other message"), 0, 8), 24));
+        assert_eq!(leading_zeros(&[0, 0x1f, 0xff]), 11);
+        assert_eq!(leading_zeros(&[0, 0]), 16);
+    }
+}
@@ -0,0 +1,138 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use slog::{warn, Logger};
+
+use crate::event::{Event, Events};
+
+/// Excludes for some time the peers that repeatedly violate the protocol
+pub struct Quarantine {
+    /// Number of violations within the duration leading to quarantine, 0 to disable quarantine
+    threshold: usize,
+    /// Duration of the quarantine, also the period over which violations are counted
+    duration: Duration,
+    /// Time of the recent violations, by peer address or IP address
+    violations: HashMap<String, Vec<Instant>>,
+    /// End of the quarantine, by peer address or IP address
+    quarantined: HashMap<String, Instant>,
+}
+
+impl Quarantine {
+    /// Creates a new quarantine
+    ///
+    /// # Arguments
+    ///
+    /// * `threshold` - Number of violations leading to quarantine, 0 to disable quarantine
+    /// * `duration` - Duration of the quarantine and period over which violations are counted
+    pub fn new(threshold: usize, duration: Duration) -> Quarantine {
+        Quarantine {
+            threshold,
+            duration,
+            violations: HashMap::new(),
+            quarantined: HashMap::new(),
+        }
+    }
+
+    /// Records a violation and returns the number of recent violations
+    /// if the peer has just been quarantined
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - Address of the peer, or its IP address if the peer address is unknown
+    pub fn record_violation(&mut self, key: &str) -> Option<usize> {
+        self.record_violation_at(key, Instant::now())
+    }
+
+    fn record_violation_at(&mut self, key: &str, now: Instant) -> Option<usize> {
+        if self.threshold == 0 || self.is_quarantined_at(key, now) {
+            return None;
+        }
+        let duration = self.duration;
+        let violations = self.violations.entry(key.to_owned()).or_default();
+        violations.retain(|time| now.duration_since(*time) < duration);
+        violations.push(now);
+        let count = violations.len();
+        if count >= self.threshold {
+            self.violations.remove(key);
+            self.quarantined.insert(key.to_owned(), now + duration);
+            Some(count)
+        }
+        else {
+            None
+        }
+    }
+
+    /// Returns true if a peer is quarantined, either by its address or by its IP address
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - Address of the peer
+    pub fn is_quarantined(&mut self, address: &str) -> bool {
+        let now = Instant::now();
+        self.is_quarantined_at(address, now) || match crate::network::parse_address(address) {
+            Ok(address) => self.is_quarantined_at(&address.ip().to_string(), now),
+            Err(_) => false,
+        }
+    }
+
+    fn is_quarantined_at(&mut self, key: &str, now: Instant) -> bool {
+        match self.quarantined.get(key) {
+            Some(end) if *end > now => true,
+            Some(_) => {
+                self.quarantined.remove(key);
+                false
+            }
+            None => false,
+        }
+    }
+}
+
+/// Records a violation, and notifies the subscribers when the peer is quarantined
+///
+/// # Arguments
+///
+/// * `quarantine` - The quarantine of the node
+/// * `events` - The events of the node
+/// * `key` - Address of the peer, or its IP address if the peer address is unknown
+/// * `logger` - Logger
+pub(crate) fn report_violation(quarantine: &Mutex<Quarantine>, events: &Mutex<Events>, key: &str, logger: &Logger) {
+    if let Some(violations) = quarantine.lock().unwrap().record_violation(key) {
+        warn!(logger, "Quarantining {} after {} protocol violations", key, violations);
+        events.lock().unwrap().notify(Event::PeerQuarantined { peer: key.to_owned(), violations });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quarantines_repeated_violations() {
+        let mut quarantine = Quarantine::new(3, Duration::from_secs(10));
+        let start = Instant::now();
+        assert_eq!(quarantine.record_violation_at("127.0.0.1:9001", start), None);
+        // violations older than the duration are forgotten
+        assert_eq!(quarantine.record_violation_at("127.0.0.1:9001", start + Duration::from_secs(11)), None);
+        assert_eq!(quarantine.record_violation_at("127.0.0.1:9001", start + Duration::from_secs(12)), None);
+        assert_eq!(quarantine.record_violation_at("127.0.0.1:9001", start + Duration::from_secs(13)), Some(3));
+        assert!(quarantine.is_quarantined_at("127.0.0.1:9001", start + Duration::from_secs(22)));
+        assert!(!quarantine.is_quarantined_at("127.0.0.1:9001", start + Duration::from_secs(23)));
+        assert!(!quarantine.is_quarantined_at("127.0.0.1:9002", start));
+    }
+
+    #[test]
+    fn quarantines_by_ip_address() {
+        let mut quarantine = Quarantine::new(1, Duration::from_secs(10));
+        assert_eq!(quarantine.record_violation("10.0.0.1"), Some(1));
+        assert!(quarantine.is_quarantined("10.0.0.1:9000"));
+        assert!(!quarantine.is_quarantined("10.0.0.2:9000"));
+    }
+
+    #[test]
+    fn zero_threshold_disables_quarantine() {
+        let mut quarantine = Quarantine::new(0, Duration::from_secs(10));
+        assert_eq!(quarantine.record_violation("10.0.0.1"), None);
+        assert!(!quarantine.is_quarantined("10.0.0.1:9000"));
+    }
+}
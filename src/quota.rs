@@ -0,0 +1,144 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use crate::peer::Peer;
+
+/// Limits the number of peers of the view in the same network, so that an attacker controlling
+/// many ports or addresses of a subnet cannot take over the view, see [crate::Config::with_prefix_quota].
+///
+/// The peers whose address is not an IP address, such as host names, are not limited.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PrefixQuota {
+    /// Maximum number of peers of the view per network
+    max_peers: usize,
+    /// Length in bits of the prefix of the IPv4 networks
+    ipv4_prefix: u8,
+    /// Length in bits of the prefix of the IPv6 networks
+    ipv6_prefix: u8,
+}
+
+impl PrefixQuota {
+    /// Creates a quota for /24 IPv4 networks and /64 IPv6 networks
+    ///
+    /// # Arguments
+    ///
+    /// * `max_peers` - Maximum number of peers of the view per network
+    pub fn new(max_peers: usize) -> PrefixQuota {
+        PrefixQuota { max_peers, ipv4_prefix: 24, ipv6_prefix: 64 }
+    }
+
+    /// Sets the length of the prefix of the IPv4 networks
+    ///
+    /// # Arguments
+    ///
+    /// * `ipv4_prefix` - Length of the prefix in bits, at most 32
+    pub fn with_ipv4_prefix(mut self, ipv4_prefix: u8) -> PrefixQuota {
+        self.ipv4_prefix = ipv4_prefix.min(32);
+        self
+    }
+
+    /// Sets the length of the prefix of the IPv6 networks
+    ///
+    /// # Arguments
+    ///
+    /// * `ipv6_prefix` - Length of the prefix in bits, at most 128
+    pub fn with_ipv6_prefix(mut self, ipv6_prefix: u8) -> PrefixQuota {
+        self.ipv6_prefix = ipv6_prefix.min(128);
+        self
+    }
+
+    pub fn max_peers(&self) -> usize {
+        self.max_peers
+    }
+
+    pub fn ipv4_prefix(&self) -> u8 {
+        self.ipv4_prefix
+    }
+
+    pub fn ipv6_prefix(&self) -> u8 {
+        self.ipv6_prefix
+    }
+
+    /// Returns the network of an address, none if it is not an IP address
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - Address of a peer
+    pub fn network(&self, address: &str) -> Option<IpAddr> {
+        match address.parse::<SocketAddr>().ok()?.ip() {
+            IpAddr::V4(ip) => {
+                let mask = u32::MAX.checked_shl(32 - self.ipv4_prefix as u32).unwrap_or(0);
+                Some(IpAddr::V4(Ipv4Addr::from(u32::from(ip) & mask)))
+            }
+            IpAddr::V6(ip) => {
+                let mask = u128::MAX.checked_shl(128 - self.ipv6_prefix as u32).unwrap_or(0);
+                Some(IpAddr::V6(Ipv6Addr::from(u128::from(ip) & mask)))
+            }
+        }
+    }
+
+    /// Returns the positions of the peers exceeding the quota of their network,
+    /// the freshest peers of each network being kept
+    ///
+    /// # Arguments
+    ///
+    /// * `peers` - The peers of a view
+    pub(crate) fn excess(&self, peers: &[Peer]) -> Vec<usize> {
+        excess_by(peers, self.max_peers, |peer| self.network(peer.address()))
+    }
+}
+
+/// Returns the positions of the peers exceeding the maximum number of peers of their group,
+/// the freshest peers of each group being kept
+///
+/// # Arguments
+///
+/// * `peers` - The peers of a view
+/// * `max_peers` - Maximum number of peers per group
+/// * `group` - The group of a peer, none for the peers that are not limited
+pub(crate) fn excess_by<K: Hash + Eq, F: Fn(&Peer) -> Option<K>>(peers: &[Peer], max_peers: usize, group: F) -> Vec<usize> {
+    let mut by_age = (0..peers.len()).collect::<Vec<usize>>();
+    by_age.sort_by_key(|index| peers[*index].age());
+    let mut counts: HashMap<K, usize> = HashMap::new();
+    let mut excess = by_age.into_iter()
+        .filter(|index| match group(&peers[*index]) {
+            Some(group) => {
+                let count = counts.entry(group).or_insert(0);
+                *count += 1;
+                *count > max_peers
+            }
+            None => false,
+        })
+        .collect::<Vec<usize>>();
+    excess.sort_unstable();
+    excess
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn networks_are_masked() {
+        let quota = PrefixQuota::new(2);
+        assert_eq!(quota.network("10.1.2.3:9000"), Some("10.1.2.0".parse().unwrap()));
+        assert_eq!(quota.network("[2001:db8::1:2]:9000"), Some("2001:db8::".parse().unwrap()));
+        assert_eq!(quota.clone().with_ipv4_prefix(0).network("10.1.2.3:9000"), Some("0.0.0.0".parse().unwrap()));
+        assert_eq!(quota.clone().with_ipv6_prefix(128).network("[::1]:9000"), Some("::1".parse().unwrap()));
+        assert_eq!(quota.network("node.example.com:9000"), None);
+    }
+
+    #[test]
+    fn freshest_peers_are_kept() {
+        let peers = vec![
+            Peer::with_age("10.0.0.1:9000".to_owned(), 3),
+            Peer::with_age("10.0.0.2:9000".to_owned(), 1),
+            Peer::with_age("10.0.1.1:9000".to_owned(), 5),
+            Peer::with_age("10.0.0.3:9000".to_owned(), 2),
+            Peer::with_age("node:9000".to_owned(), 0),
+        ];
+        assert_eq!(PrefixQuota::new(2).excess(&peers), vec![0]);
+        assert_eq!(PrefixQuota::new(1).with_ipv4_prefix(16).excess(&peers), vec![0, 2, 3]);
+    }
+}
@@ -0,0 +1,76 @@
+use std::collections::VecDeque;
+
+use crate::peer::Peer;
+
+/// The most recent senders of messages, kept as a last resort pool of peers
+/// when the view is empty or when all exchanges are failing
+pub struct RecentSenders {
+    /// Maximum number of senders kept, 0 to keep none
+    capacity: usize,
+    /// Addresses of the senders, the most recent first
+    senders: VecDeque<String>,
+}
+
+impl RecentSenders {
+    /// Creates an empty pool
+    ///
+    /// # Arguments
+    ///
+    /// * `capacity` - Maximum number of senders kept, 0 to keep none
+    pub fn new(capacity: usize) -> RecentSenders {
+        RecentSenders {
+            capacity,
+            senders: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    /// Records the sender of a message, forgetting the least recent sender when full
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - Address of the sender
+    pub fn record(&mut self, address: &str) {
+        if self.capacity == 0 {
+            return;
+        }
+        if let Some(index) = self.senders.iter().position(|sender| sender == address) {
+            self.senders.remove(index);
+        }
+        self.senders.push_front(address.to_owned());
+        self.senders.truncate(self.capacity);
+    }
+
+    /// Returns the senders, the most recent first, or None if there are none
+    pub fn peers(&self) -> Option<Vec<Peer>> {
+        if self.senders.is_empty() {
+            None
+        }
+        else {
+            Some(self.senders.iter().map(|address| Peer::new(address.clone())).collect())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addresses(senders: &RecentSenders) -> Vec<String> {
+        senders.peers().unwrap_or_default().iter().map(|peer| peer.address().to_owned()).collect()
+    }
+
+    #[test]
+    fn keeps_most_recent_senders() {
+        let mut senders = RecentSenders::new(2);
+        assert!(senders.peers().is_none());
+        senders.record("127.0.0.1:9001");
+        senders.record("127.0.0.1:9002");
+        senders.record("127.0.0.1:9001");
+        senders.record("127.0.0.1:9003");
+        assert_eq!(addresses(&senders), vec!["127.0.0.1:9003", "127.0.0.1:9001"]);
+
+        let mut senders = RecentSenders::new(0);
+        senders.record("127.0.0.1:9001");
+        assert!(senders.peers().is_none());
+    }
+}
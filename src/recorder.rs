@@ -0,0 +1,192 @@
+use std::error::Error;
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::peer::Peer;
+
+/// Format of the experiment data files
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RecordFormat {
+    /// Comma-separated values with a header line, the view being a `;`-separated list of `address/age`
+    Csv,
+    /// One JSON object per line
+    JsonLines,
+}
+
+/// Where and how the samples of each cycle are recorded
+#[derive(Clone, Debug)]
+pub struct RecordingConfig {
+    /// Path of the data file
+    path: PathBuf,
+    /// Format of the data file
+    format: RecordFormat,
+}
+
+impl RecordingConfig {
+    /// Records the samples in a file, which is created if needed and appended to otherwise
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path of the data file, one per node
+    /// * `format` - Format of the data file
+    pub fn new<P: Into<PathBuf>>(path: P, format: RecordFormat) -> RecordingConfig {
+        RecordingConfig {
+            path: path.into(),
+            format,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn format(&self) -> RecordFormat {
+        self.format
+    }
+}
+
+/// A sample of the state of a node at the end of a cycle
+pub struct Sample<'a> {
+    /// Number of the cycle, starting at 1
+    pub round: u64,
+    /// Address of the node
+    pub node: &'a str,
+    /// Address of the peer contacted during the cycle
+    pub partner: Option<&'a str>,
+    /// Outcome of the exchange, such as `response`, `busy` or `failed`
+    pub outcome: &'a str,
+    /// The view of the node at the end of the cycle
+    pub view: &'a [Peer],
+}
+
+/// Appends the samples of a node to a data file
+pub struct Recorder {
+    /// The data file
+    writer: BufWriter<File>,
+    /// Format of the data file
+    format: RecordFormat,
+}
+
+impl Recorder {
+    /// Opens the data file, writing the CSV header if the file is empty
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - Location and format of the data file
+    pub fn open(config: &RecordingConfig) -> Result<Recorder, Box<dyn Error>> {
+        let file = OpenOptions::new().create(true).append(true).open(config.path())?;
+        let empty = file.metadata()?.len() == 0;
+        let mut recorder = Recorder {
+            writer: BufWriter::new(file),
+            format: config.format(),
+        };
+        if empty && recorder.format == RecordFormat::Csv {
+            writeln!(recorder.writer, "round,timestamp,node,partner,outcome,view_size,view")?;
+        }
+        Ok(recorder)
+    }
+
+    /// Appends a sample and flushes it to the file
+    ///
+    /// # Arguments
+    ///
+    /// * `sample` - The sample to be recorded
+    pub fn record(&mut self, sample: &Sample) -> Result<(), Box<dyn Error>> {
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis();
+        match self.format {
+            RecordFormat::Csv => {
+                let view = sample.view.iter()
+                    .map(|peer| format!("{}/{}", peer.address(), peer.age()))
+                    .collect::<Vec<String>>()
+                    .join(";");
+                writeln!(self.writer, "{},{},{},{},{},{},{}",
+                    sample.round, timestamp, escape_csv(sample.node), escape_csv(sample.partner.unwrap_or("")),
+                    sample.outcome, sample.view.len(), escape_csv(&view))?;
+            }
+            RecordFormat::JsonLines => {
+                let view = sample.view.iter()
+                    .map(|peer| format!("{{\"address\":{},\"age\":{}}}", escape_json(peer.address()), peer.age()))
+                    .collect::<Vec<String>>()
+                    .join(",");
+                writeln!(self.writer, "{{\"round\":{},\"timestamp\":{},\"node\":{},\"partner\":{},\"outcome\":{},\"view_size\":{},\"view\":[{}]}}",
+                    sample.round, timestamp, escape_json(sample.node),
+                    sample.partner.map(escape_json).unwrap_or_else(|| "null".to_owned()),
+                    escape_json(sample.outcome), sample.view.len(), view)?;
+            }
+        }
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Quotes a CSV field if needed
+fn escape_csv(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    }
+    else {
+        value.to_owned()
+    }
+}
+
+/// Returns a JSON string
+fn escape_json(value: &str) -> String {
+    let mut escaped = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(format: RecordFormat, extension: &str) -> Vec<String> {
+        let path = std::env::temp_dir().join(format!("gbps-recorder-{}.{}", std::process::id(), extension));
+        let _ = std::fs::remove_file(&path);
+        let config = RecordingConfig::new(&path, format);
+        let peer = Peer::with_age("127.0.0.1:9001".to_owned(), 1);
+        let view = vec![peer, Peer::new("[::1]:9002".to_owned())];
+        for round in 1..=2 {
+            // reopened for each sample, as after a restart
+            Recorder::open(&config).unwrap()
+                .record(&Sample { round, node: "127.0.0.1:9000", partner: Some("127.0.0.1:9001"), outcome: "response", view: &view })
+                .unwrap();
+        }
+        Recorder::open(&config).unwrap()
+            .record(&Sample { round: 3, node: "127.0.0.1:9000", partner: None, outcome: "no peer", view: &[] })
+            .unwrap();
+        let lines = std::fs::read_to_string(&path).unwrap().lines().map(|line| line.to_owned()).collect();
+        std::fs::remove_file(&path).unwrap();
+        lines
+    }
+
+    #[test]
+    fn records_csv() {
+        let lines = record(RecordFormat::Csv, "csv");
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[0], "round,timestamp,node,partner,outcome,view_size,view");
+        assert!(lines[1].starts_with("1,"));
+        assert!(lines[1].ends_with(",127.0.0.1:9000,127.0.0.1:9001,response,2,127.0.0.1:9001/1;[::1]:9002/0"));
+        assert!(lines[3].ends_with(",127.0.0.1:9000,,no peer,0,"));
+    }
+
+    #[test]
+    fn records_json_lines() {
+        let lines = record(RecordFormat::JsonLines, "jsonl");
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("{\"round\":1,\"timestamp\":"));
+        assert!(lines[0].ends_with(",\"node\":\"127.0.0.1:9000\",\"partner\":\"127.0.0.1:9001\",\"outcome\":\"response\",\"view_size\":2,\"view\":[{\"address\":\"127.0.0.1:9001\",\"age\":1},{\"address\":\"[::1]:9002\",\"age\":0}]}"));
+        assert!(lines[2].contains("\"partner\":null,\"outcome\":\"no peer\",\"view_size\":0,\"view\":[]}"));
+        assert_eq!(escape_json("a\"\\\n"), "\"a\\\"\\\\\\u000a\"");
+    }
+}
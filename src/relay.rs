@@ -0,0 +1,126 @@
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use crate::load::RequestLoad;
+use crate::message::Message;
+
+/// Number of messages held for each destination, the oldest being dropped first
+pub(crate) const MAX_HELD_MESSAGES: usize = 16;
+
+/// Time during which a message is held for its destination
+const HOLD_TIME: Duration = Duration::from_secs(30);
+
+/// The messages held by a relay for the nodes that do not accept connections, until they poll them,
+/// see [crate::Config::with_relaying]
+pub(crate) struct RelayQueues {
+    /// Maximum size in bytes of a relayed message, 0 if the node does not relay
+    max_message_size: usize,
+    /// Maximum number of messages relayed per second
+    max_messages_per_second: usize,
+    /// Messages relayed during the last second
+    rate: RequestLoad,
+    /// Messages held for each destination, oldest first, with the time they arrived
+    queues: HashMap<String, VecDeque<(Message, Instant)>>,
+}
+
+impl RelayQueues {
+    /// Creates empty queues
+    ///
+    /// # Arguments
+    ///
+    /// * `max_message_size` - Maximum size in bytes of a relayed message, 0 if the node does not relay
+    /// * `max_messages_per_second` - Maximum number of messages relayed per second
+    pub(crate) fn new(max_message_size: usize, max_messages_per_second: usize) -> RelayQueues {
+        RelayQueues {
+            max_message_size,
+            max_messages_per_second,
+            rate: RequestLoad::new(Duration::from_secs(1)),
+            queues: HashMap::new(),
+        }
+    }
+
+    /// Holds a message of type [crate::MessageType::Relay] for its destination, or returns why it is dropped
+    ///
+    /// # Arguments
+    ///
+    /// * `message` - The message
+    pub(crate) fn hold(&mut self, message: Message) -> Result<(), String> {
+        self.hold_at(message, Instant::now())
+    }
+
+    fn hold_at(&mut self, message: Message, now: Instant) -> Result<(), String> {
+        if self.max_message_size == 0 {
+            return Err("not relaying".to_owned());
+        }
+        let (destination, forwarded) = message.relayed().ok_or("nothing to relay")?;
+        if forwarded.len() > self.max_message_size {
+            return Err(format!("message of {} bytes larger than {}", forwarded.len(), self.max_message_size));
+        }
+        if self.rate.record_at(now) > self.max_messages_per_second {
+            return Err("relay rate exceeded".to_owned());
+        }
+        self.purge(now);
+        let queue = self.queues.entry(destination.to_owned()).or_default();
+        if queue.len() == MAX_HELD_MESSAGES {
+            queue.pop_front();
+        }
+        queue.push_back((message, now));
+        Ok(())
+    }
+
+    /// Returns the oldest message held for a destination, if any
+    ///
+    /// # Arguments
+    ///
+    /// * `destination` - Address of the node polling the relay
+    pub(crate) fn take(&mut self, destination: &str) -> Option<Message> {
+        self.take_at(destination, Instant::now())
+    }
+
+    fn take_at(&mut self, destination: &str, now: Instant) -> Option<Message> {
+        self.purge(now);
+        let queue = self.queues.get_mut(destination)?;
+        let message = queue.pop_front().map(|(message, _)| message);
+        if queue.is_empty() {
+            self.queues.remove(destination);
+        }
+        message
+    }
+
+    /// Drops the messages held for too long, whose destination did not poll the relay
+    fn purge(&mut self, now: Instant) {
+        self.queues.retain(|_, queue| {
+            queue.retain(|(_, arrival)| now.duration_since(*arrival) < HOLD_TIME);
+            !queue.is_empty()
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn relayed(destination: &str, payload: usize) -> Message {
+        let request = Message::new_request("127.0.0.1:9000".to_owned(), None).with_payload(vec![0; payload]);
+        Message::new_relay("127.0.0.1:9000".to_owned(), destination, &request).unwrap()
+    }
+
+    #[test]
+    fn holds_messages_until_polled() {
+        let now = Instant::now();
+        let mut queues = RelayQueues::new(1024, 3);
+        queues.hold_at(relayed("10.0.0.1:9000", 10), now).unwrap();
+        queues.hold_at(relayed("10.0.0.1:9000", 20), now).unwrap();
+        assert!(queues.hold_at(relayed("10.0.0.2:9000", 2048), now).unwrap_err().contains("larger"));
+        queues.hold_at(relayed("10.0.0.2:9000", 10), now).unwrap();
+        assert_eq!(queues.hold_at(relayed("10.0.0.2:9000", 10), now), Err("relay rate exceeded".to_owned()));
+
+        let first = queues.take_at("10.0.0.1:9000", now).unwrap();
+        assert_eq!(Message::from_bytes(first.relayed().unwrap().1).unwrap().payload().unwrap().len(), 10);
+        assert!(queues.take_at("10.0.0.1:9000", now).is_some());
+        assert!(queues.take_at("10.0.0.1:9000", now).is_none());
+        // dropped when not polled in time
+        assert!(queues.take_at("10.0.0.2:9000", now + HOLD_TIME).is_none());
+        assert!(RelayQueues::new(0, 3).hold_at(relayed("10.0.0.1:9000", 10), now).is_err());
+    }
+}
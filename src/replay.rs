@@ -0,0 +1,147 @@
+use std::collections::{HashSet, VecDeque};
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::message::Message;
+
+// Maximum number of nonces remembered, the messages received beyond it within the window being rejected
+const MAX_SEEN_NONCES: usize = 65536;
+
+/// The reason a message was rejected by the guard
+#[derive(Debug, PartialEq)]
+pub enum ReplayError {
+    /// The message is older than the window, or as far in the future
+    OutsideWindow,
+    /// The message was already received within the window
+    AlreadyReceived,
+    /// The guard remembers as many messages as it can, until the earliest ones are too old
+    Full,
+}
+
+impl ReplayError {
+    /// Returns true if the message was rejected for a violation of its sender, rather than because the guard is full
+    pub fn is_violation(&self) -> bool {
+        !matches!(self, ReplayError::Full)
+    }
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReplayError::OutsideWindow => write!(f, "message outside of accepted time window"),
+            ReplayError::AlreadyReceived => write!(f, "message already received"),
+            ReplayError::Full => write!(f, "too many messages received within the time window"),
+        }
+    }
+}
+
+/// Rejects messages that are too old or that were already received
+pub struct ReplayGuard {
+    /// Maximum age of an accepted message in milliseconds
+    window: u64,
+    /// Nonces of the messages received within the window
+    seen: HashSet<u64>,
+    /// Timestamps and nonces of the received messages, in the order they were received
+    received: VecDeque<(u64, u64)>,
+}
+
+impl ReplayGuard {
+    /// Creates a new guard accepting messages created within the given window
+    ///
+    /// # Arguments
+    ///
+    /// * `ttl` - Maximum age of a message in seconds
+    pub fn new(ttl: u64) -> ReplayGuard {
+        ReplayGuard {
+            // a time to live too large to be counted in milliseconds accepts messages of any age
+            window: ttl.saturating_mul(1000),
+            seen: HashSet::new(),
+            received: VecDeque::new(),
+        }
+    }
+
+    /// Checks that a received message is recent and was not seen before
+    ///
+    /// # Arguments
+    ///
+    /// * `message` - The received message
+    pub fn check(&mut self, message: &Message) -> Result<(), ReplayError> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_millis() as u64)
+            .unwrap_or(0);
+        self.check_at(message.timestamp(), message.nonce(), now)
+    }
+
+    fn check_at(&mut self, timestamp: u64, nonce: u64, now: u64) -> Result<(), ReplayError> {
+        // forget the earliest received messages that would be rejected for their age anyway,
+        // a message received later being forgotten once those received before it are
+        while let Some(&(seen_timestamp, seen_nonce)) = self.received.front() {
+            if now.saturating_sub(seen_timestamp) <= self.window {
+                break;
+            }
+            self.received.pop_front();
+            self.seen.remove(&seen_nonce);
+        }
+
+        // tolerate the same clock skew in both directions
+        if now.saturating_sub(timestamp) > self.window || timestamp.saturating_sub(now) > self.window {
+            Err(ReplayError::OutsideWindow)
+        }
+        else if self.seen.contains(&nonce) {
+            Err(ReplayError::AlreadyReceived)
+        }
+        else if self.seen.len() >= MAX_SEEN_NONCES {
+            Err(ReplayError::Full)
+        }
+        else {
+            self.seen.insert(nonce);
+            self.received.push_back((timestamp, nonce));
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_old_and_future_messages() {
+        let mut guard = ReplayGuard::new(10);
+        assert!(guard.check_at(100_000, 1, 100_000).is_ok());
+        assert!(guard.check_at(89_999, 2, 100_000).is_err());
+        assert!(guard.check_at(110_001, 3, 100_000).is_err());
+        assert!(guard.check_at(90_000, 4, 100_000).is_ok());
+    }
+
+    #[test]
+    fn rejects_replayed_messages() {
+        let mut guard = ReplayGuard::new(10);
+        assert!(guard.check_at(100_000, 1, 100_000).is_ok());
+        assert!(guard.check_at(100_000, 1, 105_000).is_err());
+        // expired entries are forgotten, but then rejected for their age
+        assert!(guard.check_at(100_000, 1, 120_000).is_err());
+        assert!(guard.seen.is_empty() && guard.received.is_empty());
+    }
+
+    #[test]
+    fn rejects_messages_beyond_the_capacity() {
+        let mut guard = ReplayGuard::new(10);
+        for nonce in 0..MAX_SEEN_NONCES as u64 {
+            assert!(guard.check_at(100_000, nonce, 100_000).is_ok());
+        }
+        assert_eq!(guard.check_at(100_000, MAX_SEEN_NONCES as u64, 100_000), Err(ReplayError::Full));
+        assert_eq!(guard.seen.len(), MAX_SEEN_NONCES);
+        // room is made as the received messages expire
+        assert!(guard.check_at(111_000, MAX_SEEN_NONCES as u64, 111_000).is_ok());
+        assert_eq!(guard.seen.len(), 1);
+    }
+
+    #[test]
+    fn accepts_any_age_with_a_huge_time_to_live() {
+        let mut guard = ReplayGuard::new(u64::MAX);
+        assert_eq!(guard.window, u64::MAX);
+        assert!(guard.check_at(0, 1, 100_000).is_ok());
+        assert!(guard.check_at(0, 1, 100_000).is_err());
+    }
+}
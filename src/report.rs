@@ -0,0 +1,215 @@
+use std::error::Error;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, ToSocketAddrs};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::audit::Rejection;
+
+/// Version of the format of the monitoring reports, incremented when fields are changed or removed
+pub const REPORT_VERSION: u32 = 1;
+
+// Maximum size of the headers of a monitoring request
+const MAX_HEADER_SIZE: usize = 8 * 1024;
+// Maximum size of the body of a monitoring request
+const MAX_BODY_SIZE: usize = 1024 * 1024;
+
+/// The monitoring data sent by a node after each view update, see [crate::MonitoringConfig],
+/// posted as a JSON object such as:
+///
+/// ```text
+/// {"version":1,"id":"127.0.0.1:9000","peers":["127.0.0.1:9001"],"indegree":1.5,"messages":[],
+///  "rejections":[{"peer":"10.0.0.1:9000","reason":"prefix_quota"}]}
+/// ```
+///
+/// The reports sent before versioning have no `version` field, and are decoded as version 0.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MonitoringReport {
+    /// Version of the format
+    #[serde(default)]
+    version: u32,
+    /// Address of the node
+    id: String,
+    /// Addresses of the peers of the view, in order
+    peers: Vec<String>,
+    /// Estimated in-degree of the node
+    indegree: f64,
+    /// Unused, kept for the collectors of the first reports
+    #[serde(default)]
+    messages: Vec<String>,
+    /// The peer entries rejected since the last report, see [crate::Config::with_audit]
+    #[serde(default)]
+    rejections: Vec<ReportedRejection>,
+}
+
+/// A rejected peer entry in a monitoring report
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ReportedRejection {
+    /// Address of the rejected peer
+    peer: String,
+    /// Reason code of the rejection, such as `own_address`, see [crate::RejectionReason]
+    reason: String,
+}
+
+impl MonitoringReport {
+    /// Creates a report of the current version
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - Address of the node
+    /// * `peers` - Addresses of the peers of the view
+    /// * `indegree` - Estimated in-degree of the node
+    /// * `rejections` - The peer entries rejected since the last report
+    pub fn new(id: String, peers: Vec<String>, indegree: f64, rejections: &[Rejection]) -> MonitoringReport {
+        MonitoringReport {
+            version: REPORT_VERSION,
+            id,
+            peers,
+            indegree,
+            messages: vec![],
+            rejections: rejections.iter()
+                .map(|rejection| ReportedRejection { peer: rejection.peer().to_owned(), reason: rejection.reason().to_string() })
+                .collect(),
+        }
+    }
+
+    /// Decodes a report, failing if it is invalid or of a later version
+    ///
+    /// # Arguments
+    ///
+    /// * `json` - The body of a monitoring request
+    pub fn from_json(json: &[u8]) -> Result<MonitoringReport, Box<dyn Error>> {
+        let report: MonitoringReport = serde_json::from_slice(json)?;
+        if report.version > REPORT_VERSION {
+            Err(format!("unsupported monitoring report version {}", report.version))?
+        }
+        Ok(report)
+    }
+
+    /// Encodes the report as JSON
+    pub fn to_json(&self) -> String {
+        // only strings and numbers, which always serialize
+        serde_json::to_string(self).unwrap_or_default()
+    }
+
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn peers(&self) -> &[String] {
+        &self.peers
+    }
+
+    pub fn indegree(&self) -> f64 {
+        self.indegree
+    }
+
+    pub fn rejections(&self) -> &[ReportedRejection] {
+        &self.rejections
+    }
+}
+
+impl ReportedRejection {
+    pub fn peer(&self) -> &str {
+        &self.peer
+    }
+
+    pub fn reason(&self) -> &str {
+        &self.reason
+    }
+}
+
+/// Receives the reports posted by the nodes to a monitoring URL, for collectors written in Rust
+/// and for testing the monitoring of a deployment
+pub struct MonitoringCollector {
+    /// Listener of the monitoring requests
+    listener: TcpListener,
+}
+
+impl MonitoringCollector {
+    /// Listens for monitoring requests
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - Address of the collector, the nodes posting to `http://<address>/<any path>`
+    pub fn bind<A: ToSocketAddrs>(address: A) -> std::io::Result<MonitoringCollector> {
+        Ok(MonitoringCollector { listener: TcpListener::bind(address)? })
+    }
+
+    /// Returns the address the collector listens to, such as when bound to port 0
+    pub fn local_address(&self) -> std::io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Waits for the next monitoring request, answers it and returns its report
+    pub fn receive(&self) -> Result<MonitoringReport, Box<dyn Error>> {
+        let (mut stream, _) = self.listener.accept()?;
+        stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+
+        // headers, up to the empty line
+        let mut request = vec![];
+        let mut buffer = [0u8; 1024];
+        let separator = loop {
+            if let Some(position) = request.windows(4).position(|bytes| bytes == b"\r\n\r\n") {
+                break position;
+            }
+            if request.len() > MAX_HEADER_SIZE {
+                Err("monitoring request headers too large")?
+            }
+            let count = stream.read(&mut buffer)?;
+            if count == 0 {
+                Err("incomplete monitoring request")?
+            }
+            request.extend_from_slice(&buffer[..count]);
+        };
+        let headers = String::from_utf8_lossy(&request[..separator]).into_owned();
+        let length = headers.lines()
+            .filter_map(|line| line.split_once(':'))
+            .find(|(name, _)| name.trim().eq_ignore_ascii_case("content-length"))
+            .ok_or("missing content length")?
+            .1.trim().parse::<usize>()?;
+        if length > MAX_BODY_SIZE {
+            Err("monitoring report too large")?
+        }
+
+        // body
+        let mut body = request.split_off(separator + 4);
+        while body.len() < length {
+            let count = stream.read(&mut buffer)?;
+            if count == 0 {
+                Err("incomplete monitoring report")?
+            }
+            body.extend_from_slice(&buffer[..count]);
+        }
+        body.truncate(length);
+        stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")?;
+
+        MonitoringReport::from_json(&body)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_reports_of_each_version() {
+        let report = MonitoringReport::new("127.0.0.1:9000".to_owned(), vec!["127.0.0.1:9001".to_owned()], 1.5, &[]);
+        assert_eq!(MonitoringReport::from_json(report.to_json().as_bytes()).unwrap(), report);
+
+        // the hand-built reports of the first version of the format
+        let legacy = br#"{"id":"127.0.0.1:9000","peers":["127.0.0.1:9001"],"indegree":1.500,"messages":[]}"#;
+        let decoded = MonitoringReport::from_json(legacy).unwrap();
+        assert_eq!(decoded.version(), 0);
+        assert_eq!(decoded.peers(), &["127.0.0.1:9001".to_owned()]);
+        assert!(decoded.rejections().is_empty());
+
+        let later = br#"{"version":2,"id":"127.0.0.1:9000","peers":[],"indegree":0}"#;
+        assert!(MonitoringReport::from_json(later).is_err());
+    }
+}
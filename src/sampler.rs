@@ -1 +1,114 @@
-pub struct PeerSampler{}
\ No newline at end of file
+use std::collections::VecDeque;
+use std::sync::mpsc::{Receiver, Sender};
+
+use crate::peer::Peer;
+
+/// The interface of a peer sampling service, as used by the application layer.
+///
+/// Implemented by [crate::PeerSamplingService] and by [MockPeerSampler], so that
+/// applications building on the service can be tested without networking.
+pub trait PeerSampler {
+    /// Returns a random peer for the client application
+    fn get_peer(&mut self) -> Option<Peer>;
+
+    /// Returns a copy of the current view
+    fn view_snapshot(&self) -> Vec<Peer>;
+
+    /// Returns a receiver notified with the new view every time the view is updated
+    fn subscribe(&mut self) -> Receiver<Vec<Peer>>;
+}
+
+/// Sends an update to the subscribers and forgets those that have gone away
+///
+/// # Arguments
+///
+/// * `subscribers` - The senders of the subscriptions
+/// * `update` - The update, such as the new view
+pub(crate) fn notify<T: Clone>(subscribers: &mut Vec<Sender<T>>, update: &T) {
+    subscribers.retain(|subscriber| subscriber.send(update.clone()).is_ok());
+}
+
+/// A peer sampler returning peers from a view controlled by the test
+pub struct MockPeerSampler {
+    /// The view set by the test
+    peers: Vec<Peer>,
+    /// The peers not yet returned since the view was last set
+    queue: VecDeque<Peer>,
+    /// Receivers of the view updates
+    subscribers: Vec<Sender<Vec<Peer>>>,
+}
+
+impl MockPeerSampler {
+    /// Creates a new mock with an initial view
+    ///
+    /// # Arguments
+    ///
+    /// * `peers` - The initial view
+    pub fn new(peers: Vec<Peer>) -> MockPeerSampler {
+        MockPeerSampler {
+            queue: peers.iter().cloned().collect(),
+            peers,
+            subscribers: vec![],
+        }
+    }
+
+    /// Replaces the view and notifies the subscribers
+    ///
+    /// # Arguments
+    ///
+    /// * `peers` - The new view
+    pub fn set_view(&mut self, peers: Vec<Peer>) {
+        self.queue = peers.iter().cloned().collect();
+        self.peers = peers;
+        notify(&mut self.subscribers, &self.peers);
+    }
+}
+
+impl PeerSampler for MockPeerSampler {
+    /// Returns the peers of the view in order, starting over when all were returned
+    fn get_peer(&mut self) -> Option<Peer> {
+        if self.queue.is_empty() {
+            self.queue = self.peers.iter().cloned().collect();
+        }
+        self.queue.pop_front()
+    }
+
+    fn view_snapshot(&self) -> Vec<Peer> {
+        self.peers.clone()
+    }
+
+    fn subscribe(&mut self) -> Receiver<Vec<Peer>> {
+        let (tx, rx) = std::sync::mpsc::channel();
+        self.subscribers.push(tx);
+        rx
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn peers(ports: &[u16]) -> Vec<Peer> {
+        ports.iter().map(|port| Peer::new(format!("127.0.0.1:{}", port))).collect()
+    }
+
+    #[test]
+    fn mock_cycles_through_view() {
+        let mut sampler = MockPeerSampler::new(peers(&[9001, 9002]));
+        let returned = (0..4).map(|_| sampler.get_peer().unwrap().address().to_owned()).collect::<Vec<String>>();
+        assert_eq!(returned, vec!["127.0.0.1:9001", "127.0.0.1:9002", "127.0.0.1:9001", "127.0.0.1:9002"]);
+        assert!(MockPeerSampler::new(vec![]).get_peer().is_none());
+    }
+
+    #[test]
+    fn mock_notifies_subscribers() {
+        let mut sampler = MockPeerSampler::new(peers(&[9001]));
+        let updates = sampler.subscribe();
+        drop(sampler.subscribe());
+
+        sampler.set_view(peers(&[9002, 9003]));
+        assert_eq!(updates.try_recv().unwrap(), peers(&[9002, 9003]));
+        assert_eq!(sampler.view_snapshot(), peers(&[9002, 9003]));
+        assert_eq!(sampler.subscribers.len(), 1);
+    }
+}
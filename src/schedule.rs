@@ -0,0 +1,62 @@
+use std::time::Duration;
+
+use rand::Rng;
+
+/// The distribution of the time between two cycles of the protocol
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CycleSchedule {
+    /// Cycles are exactly one sampling period apart
+    Fixed,
+    /// Cycles are one sampling period apart, plus a uniform random deviation
+    /// up to the sampling deviation. This is the default.
+    Uniform,
+    /// The time between cycles is exponentially distributed with the sampling period as mean,
+    /// so that the cycles of a node form a Poisson process
+    Exponential,
+    /// Cycles are only performed when requested, see [crate::PeerSamplingService::trigger_cycle]
+    Triggered,
+}
+
+impl CycleSchedule {
+    /// Returns the time until the next cycle, or None if cycles are only triggered
+    ///
+    /// # Arguments
+    ///
+    /// * `period` - The sampling period in seconds
+    /// * `deviation` - The maximum deviation in seconds
+    /// * `rng` - The random generator
+    pub fn next_delay<R: Rng>(&self, period: u64, deviation: u64, rng: &mut R) -> Option<Duration> {
+        let period = Duration::from_secs(period);
+        match self {
+            CycleSchedule::Fixed => Some(period),
+            CycleSchedule::Uniform if deviation == 0 => Some(period),
+            CycleSchedule::Uniform => Some(period + Duration::from_millis(rng.gen_range(0..deviation * 1000))),
+            // inverse transform sampling, with a uniform sample in (0, 1]
+            CycleSchedule::Exponential => Some(period.mul_f64(-(1.0 - rng.gen::<f64>()).ln())),
+            CycleSchedule::Triggered => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    #[test]
+    fn delays_follow_schedule() {
+        let mut rng = StdRng::seed_from_u64(3);
+        assert_eq!(CycleSchedule::Fixed.next_delay(5, 2, &mut rng), Some(Duration::from_secs(5)));
+        assert_eq!(CycleSchedule::Triggered.next_delay(5, 2, &mut rng), None);
+        for _ in 0..100 {
+            let delay = CycleSchedule::Uniform.next_delay(5, 2, &mut rng).unwrap();
+            assert!(delay >= Duration::from_secs(5) && delay < Duration::from_secs(7));
+        }
+
+        // the mean of the exponential delays is the period
+        let total = (0..10000).map(|_| CycleSchedule::Exponential.next_delay(5, 2, &mut rng).unwrap()).sum::<Duration>();
+        let mean = total.as_secs_f64() / 10000.0;
+        assert!((mean - 5.0).abs() < 0.3, "mean {}", mean);
+    }
+}
@@ -0,0 +1,133 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+// Weight of the last exchange in the smoothed latency
+const LATENCY_SMOOTHING: f64 = 0.2;
+// Number of observations before a score is considered significant
+const MIN_OBSERVATIONS: u64 = 3;
+
+/// The behavior observed for a peer
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PeerScore {
+    /// Number of exchanges initiated with the peer
+    exchanges: u64,
+    /// Number of exchanges that the peer did not answer
+    failures: u64,
+    /// Smoothed latency of the answered exchanges
+    latency: Option<Duration>,
+    /// Number of protocol violations, such as replayed or unexpected messages
+    violations: u64,
+}
+
+impl PeerScore {
+    /// Returns the number of exchanges initiated with the peer
+    pub fn exchanges(&self) -> u64 {
+        self.exchanges
+    }
+
+    /// Returns the number of exchanges that the peer did not answer
+    pub fn failures(&self) -> u64 {
+        self.failures
+    }
+
+    /// Returns the smoothed latency of the answered exchanges
+    pub fn latency(&self) -> Option<Duration> {
+        self.latency
+    }
+
+    /// Returns the number of protocol violations of the peer
+    pub fn violations(&self) -> u64 {
+        self.violations
+    }
+
+    /// Returns the score of the peer between 0 and 1, higher being better:
+    /// the ratio of answered exchanges, reduced by the latency in seconds and by the violations
+    pub fn score(&self) -> f64 {
+        let success_ratio = if self.exchanges == 0 { 1.0 } else { (self.exchanges - self.failures) as f64 / self.exchanges as f64 };
+        let latency = self.latency.map(|latency| latency.as_secs_f64()).unwrap_or(0.0);
+        success_ratio / (1.0 + latency) / (1.0 + self.violations as f64)
+    }
+
+    /// Returns true if enough was observed for the score to be significant
+    fn is_significant(&self) -> bool {
+        self.exchanges + self.violations >= MIN_OBSERVATIONS
+    }
+}
+
+/// The scores of the peers, by address
+#[derive(Default)]
+pub struct PeerScores {
+    scores: HashMap<String, PeerScore>,
+}
+
+impl PeerScores {
+    /// Records the outcome of an exchange initiated with a peer
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - Address of the peer
+    /// * `latency` - Duration of the exchange, or None if the peer did not answer
+    pub fn record_exchange(&mut self, address: &str, latency: Option<Duration>) {
+        let score = self.scores.entry(address.to_owned()).or_default();
+        score.exchanges += 1;
+        match latency {
+            Some(latency) => score.latency = Some(match score.latency {
+                Some(average) => average.mul_f64(1.0 - LATENCY_SMOOTHING) + latency.mul_f64(LATENCY_SMOOTHING),
+                None => latency,
+            }),
+            None => score.failures += 1,
+        }
+    }
+
+    /// Records a protocol violation of a peer
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - Address of the peer
+    pub fn record_violation(&mut self, address: &str) {
+        self.scores.entry(address.to_owned()).or_default().violations += 1;
+    }
+
+    /// Returns the addresses of the peers observed enough whose score is below a threshold
+    ///
+    /// # Arguments
+    ///
+    /// * `threshold` - The minimum score
+    pub fn below(&self, threshold: f64) -> Vec<String> {
+        self.scores.iter()
+            .filter(|(_, score)| score.is_significant() && score.score() < threshold)
+            .map(|(address, _)| address.clone())
+            .collect()
+    }
+
+    /// Returns a copy of the scores
+    pub fn snapshot(&self) -> HashMap<String, PeerScore> {
+        self.scores.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scores_success_latency_and_violations() {
+        let mut scores = PeerScores::default();
+        scores.record_exchange("a", Some(Duration::from_millis(0)));
+        scores.record_exchange("a", Some(Duration::from_millis(0)));
+        scores.record_exchange("b", None);
+        scores.record_exchange("b", Some(Duration::from_secs(1)));
+        scores.record_violation("c");
+
+        let snapshot = scores.snapshot();
+        assert_eq!(snapshot["a"].score(), 1.0);
+        assert_eq!(snapshot["b"].failures(), 1);
+        assert_eq!(snapshot["b"].score(), 0.25);
+        assert_eq!(snapshot["c"].score(), 0.5);
+
+        // only significant scores are considered
+        assert!(scores.below(0.9).is_empty());
+        scores.record_exchange("b", None);
+        assert_eq!(scores.below(0.9), vec!["b"]);
+    }
+}
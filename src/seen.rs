@@ -0,0 +1,149 @@
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime};
+
+use crate::peer::Peer;
+use crate::view::View;
+
+/// Number of peers tracked for each entry of the view
+pub(crate) const TRACKED_PER_VIEW_ENTRY: usize = 8;
+
+/// Age, in cycles or units of time, of the peers seen recently enough to be probably alive
+pub(crate) const ALIVE_AGE: u16 = 3;
+
+/// The last time each peer was seen, directly as the sender of a message or the partner of an exchange,
+/// or indirectly in a received buffer, estimated from the age of its descriptor
+pub(crate) struct LastSeen {
+    /// Maximum number of peers tracked, the least recently seen being forgotten first
+    capacity: usize,
+    /// Time each peer was last seen, by address
+    peers: HashMap<String, SystemTime>,
+}
+
+impl LastSeen {
+    /// Creates an empty map
+    ///
+    /// # Arguments
+    ///
+    /// * `capacity` - Maximum number of peers tracked
+    pub(crate) fn new(capacity: usize) -> LastSeen {
+        LastSeen {
+            capacity: capacity.max(1),
+            peers: HashMap::new(),
+        }
+    }
+
+    /// Records that a peer was seen, unless it was seen more recently
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - Address of the peer
+    /// * `ago` - Time elapsed since the peer was seen, zero when seen directly
+    pub(crate) fn record(&mut self, address: &str, ago: Duration) {
+        self.record_at(address, SystemTime::now() - ago)
+    }
+
+    fn record_at(&mut self, address: &str, seen: SystemTime) {
+        match self.peers.get_mut(address) {
+            Some(last) => *last = (*last).max(seen),
+            None => {
+                if self.peers.len() >= self.capacity {
+                    let least_recent = self.peers.iter().min_by_key(|(_, seen)| **seen).map(|(address, _)| address.clone());
+                    if let Some(least_recent) = least_recent {
+                        self.peers.remove(&least_recent);
+                    }
+                }
+                self.peers.insert(address.to_owned(), seen);
+            }
+        }
+    }
+
+    /// Forgets a peer, such as one that left
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - Address of the peer
+    pub(crate) fn remove(&mut self, address: &str) {
+        self.peers.remove(address);
+    }
+
+    /// Returns the time a peer was last seen, if tracked
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - Address of the peer
+    pub(crate) fn get(&self, address: &str) -> Option<SystemTime> {
+        self.peers.get(address).copied()
+    }
+
+    /// Returns the time each tracked peer was last seen
+    pub(crate) fn all(&self) -> HashMap<String, SystemTime> {
+        self.peers.clone()
+    }
+
+    /// Returns a random peer of the view seen recently, or the most recently seen peer
+    /// of the view if none was, or any peer of the view if none was seen at all
+    ///
+    /// # Arguments
+    ///
+    /// * `view` - The view
+    /// * `max_age` - Time since the peers were last seen for being considered recent
+    pub(crate) fn recent_peer(&self, view: &View, max_age: Duration) -> Option<Peer> {
+        self.recent_peer_filtered(view, max_age, |_| true)
+    }
+
+    /// Returns a peer as by [LastSeen::recent_peer] among the peers of the view accepted by a filter
+    ///
+    /// # Arguments
+    ///
+    /// * `view` - The view
+    /// * `max_age` - Time since the peers were last seen for being considered recent
+    /// * `filter` - Returns true for the peers that can be returned
+    pub(crate) fn recent_peer_filtered<F: FnMut(&Peer) -> bool>(&self, view: &View, max_age: Duration, mut filter: F) -> Option<Peer> {
+        let now = SystemTime::now();
+        let seen_since = |peer: &Peer| self.get(peer.address()).map(|seen| now.duration_since(seen).unwrap_or_default());
+        view.select_peer_filtered(|peer| seen_since(peer).is_some_and(|since| since <= max_age) && filter(peer))
+            .or_else(|| view.peers().iter().filter(|peer| seen_since(peer).is_some() && filter(peer)).min_by_key(|peer| seen_since(peer)).cloned())
+            .or_else(|| view.select_peer_filtered(filter))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_most_recent_sightings() {
+        let now = SystemTime::now();
+        let mut seen = LastSeen::new(2);
+        seen.record_at("10.0.0.1:9000", now - Duration::from_secs(10));
+        // an older indirect sighting does not replace a recent one
+        seen.record_at("10.0.0.1:9000", now - Duration::from_secs(20));
+        assert_eq!(seen.get("10.0.0.1:9000"), Some(now - Duration::from_secs(10)));
+        seen.record_at("10.0.0.2:9000", now - Duration::from_secs(5));
+        seen.record_at("10.0.0.3:9000", now);
+        // the least recently seen peer is forgotten
+        assert_eq!(seen.get("10.0.0.1:9000"), None);
+        assert_eq!(seen.all().len(), 2);
+        seen.remove("10.0.0.3:9000");
+        assert_eq!(seen.get("10.0.0.3:9000"), None);
+    }
+
+    #[test]
+    fn prefers_recently_seen_peers() {
+        let now = SystemTime::now();
+        let peers = ["10.0.0.1:9000", "10.0.0.2:9000", "10.0.0.3:9000"].iter().map(|address| Peer::new(address.to_string())).collect();
+        let view = View::with_peers("127.0.0.1:9000".to_owned(), peers);
+        let mut seen = LastSeen::new(8);
+        assert!(seen.recent_peer(&view, Duration::from_secs(10)).is_some());
+        seen.record_at("10.0.0.1:9000", now - Duration::from_secs(60));
+        seen.record_at("10.0.0.2:9000", now - Duration::from_secs(30));
+        assert_eq!(seen.recent_peer(&view, Duration::from_secs(10)).unwrap().address(), "10.0.0.2:9000");
+        seen.record_at("10.0.0.3:9000", now);
+        for _ in 0..10 {
+            assert_eq!(seen.recent_peer(&view, Duration::from_secs(10)).unwrap().address(), "10.0.0.3:9000");
+        }
+        // the filtered peers are never returned
+        assert_eq!(seen.recent_peer_filtered(&view, Duration::from_secs(10), |peer| peer.address() != "10.0.0.3:9000").unwrap().address(), "10.0.0.2:9000");
+        assert_eq!(seen.recent_peer_filtered(&view, Duration::from_secs(10), |_| false), None);
+    }
+}
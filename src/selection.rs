@@ -0,0 +1,210 @@
+//! The view selection of the paper as functions over a list of node descriptors, without
+//! networking, clock nor hashing, for applications running the protocol over their own transport.
+//!
+//! Only `core`, `alloc` and `rand` are used, so that this module compiles without the standard
+//! library when the default `std` feature is disabled, which leaves it as the only module of the crate,
+//! with `rand` built without its default features. [crate::View] relies on these functions and adds
+//! the quotas, the time-based ages and the queue of the samples returned to the application.
+
+use alloc::vec::Vec;
+
+use rand::Rng;
+use rand::seq::SliceRandom;
+
+/// The descriptor of a node, exchanged and selected by the protocol.
+/// Descriptors are equal when they describe the same node, whatever their age.
+pub trait Descriptor: Clone + PartialEq {
+    /// Returns the age of the descriptor
+    fn age(&self) -> u16;
+
+    /// Increases the age by one, saturating at its maximum
+    fn increment_age(&mut self);
+
+    /// Returns true if the descriptor is more recent than another descriptor of the same node,
+    /// by default if it is younger
+    ///
+    /// # Arguments
+    ///
+    /// * `other` - Another descriptor of the same node
+    fn is_fresher_than(&self, other: &Self) -> bool {
+        self.age() < other.age()
+    }
+}
+
+/// Randomly reorders the view
+///
+/// # Arguments
+///
+/// * `view` - The view
+/// * `rng` - The random number generator
+pub fn permute<D, R: Rng>(view: &mut [D], rng: &mut R) {
+    view.shuffle(rng);
+}
+
+/// Moves the oldest descriptors to the end of the view if the size
+/// of the view is larger than the healing factor
+///
+/// # Arguments
+///
+/// * `view` - The view
+/// * `h` - The number of descriptors that should be moved
+pub fn move_oldest_to_end<D: Descriptor>(view: &mut Vec<D>, h: usize) {
+    if view.len() > h {
+        let mut h_oldest = view.clone();
+        h_oldest.sort_by_key(|descriptor| descriptor.age());
+        h_oldest.reverse();
+        h_oldest.truncate(h);
+        // (view.len - h) at the beginning, h at the end
+        let (mut start, mut end): (Vec<D>, Vec<D>) = view.drain(..).partition(|descriptor| !h_oldest.contains(descriptor));
+        start.append(&mut end);
+        *view = start;
+    }
+}
+
+/// Increases by one the age of each descriptor in the view
+///
+/// # Arguments
+///
+/// * `view` - The view
+pub fn increase_age<D: Descriptor>(view: &mut [D]) {
+    for descriptor in view.iter_mut() {
+        descriptor.increment_age();
+    }
+}
+
+/// Merges a buffer received from a partner into the view, and reduces the view to its size:
+/// removes duplicates, then old items, then the head of the view, then descriptors at random
+///
+/// # Arguments
+///
+/// * `view` - The view
+/// * `c` - The size of the view
+/// * `h` - The healing parameter
+/// * `s` - The swap parameter
+/// * `buffer` - The buffer received, without the descriptor of the node itself
+/// * `rng` - The random number generator
+pub fn select<D: Descriptor, R: Rng>(view: &mut Vec<D>, c: usize, h: usize, s: usize, buffer: &[D], rng: &mut R) {
+    view.extend_from_slice(buffer);
+    remove_duplicates(view);
+    remove_old_items(view, c, h);
+    remove_head(view, c, s);
+    remove_at_random(view, c, rng);
+}
+
+/// Removes the duplicate descriptors of the view and keeps the most recent one, see [Descriptor::is_fresher_than].
+/// The order of the view is preserved, the most recent descriptor taking the position of the first occurrence of the node.
+///
+/// # Arguments
+///
+/// * `view` - The view
+pub fn remove_duplicates<D: Descriptor>(view: &mut Vec<D>) {
+    let mut unique: Vec<D> = Vec::with_capacity(view.len());
+    for descriptor in view.drain(..) {
+        match unique.iter().position(|entry| *entry == descriptor) {
+            // duplicate descriptor, keep the most recent
+            Some(index) => if descriptor.is_fresher_than(&unique[index]) {
+                unique[index] = descriptor;
+            },
+            // unique descriptor
+            None => unique.push(descriptor),
+        }
+    }
+    *view = unique;
+}
+
+/// Removes the oldest descriptors from the view based on the healing parameter
+///
+/// # Arguments
+///
+/// * `view` - The view
+/// * `c` - The size of the view
+/// * `h` - The healing parameter
+pub fn remove_old_items<D: Descriptor>(view: &mut Vec<D>, c: usize, h: usize) {
+    let removal_count = h.min(view.len().saturating_sub(c));
+    if removal_count > 0 {
+        let mut kept = view.clone();
+        kept.sort_by_key(|descriptor| descriptor.age());
+        kept.truncate(kept.len() - removal_count);
+        view.retain(|descriptor| kept.contains(descriptor));
+    }
+}
+
+/// Removes descriptors at the beginning of the view based on the swap parameter
+///
+/// # Arguments
+///
+/// * `view` - The view
+/// * `c` - The size of the view
+/// * `s` - The swap parameter
+pub fn remove_head<D>(view: &mut Vec<D>, c: usize, s: usize) {
+    let removal_count = s.min(view.len().saturating_sub(c));
+    view.drain(0..removal_count);
+}
+
+/// Removes descriptors at random to match the view size parameter
+///
+/// # Arguments
+///
+/// * `view` - The view
+/// * `c` - The size of the view
+/// * `rng` - The random number generator
+pub fn remove_at_random<D, R: Rng>(view: &mut Vec<D>, c: usize, rng: &mut R) {
+    while view.len() > c {
+        let remove_index = rng.gen_range(0..view.len());
+        view.remove(remove_index);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    /// A descriptor of an embedded application, identified by a number
+    #[derive(Clone, Debug)]
+    struct Node {
+        id: u32,
+        age: u16,
+    }
+
+    impl PartialEq for Node {
+        fn eq(&self, other: &Self) -> bool {
+            self.id == other.id
+        }
+    }
+
+    impl Descriptor for Node {
+        fn age(&self) -> u16 {
+            self.age
+        }
+
+        fn increment_age(&mut self) {
+            self.age = self.age.saturating_add(1);
+        }
+    }
+
+    fn node(id: u32, age: u16) -> Node {
+        Node { id, age }
+    }
+
+    #[test]
+    fn selects_descriptors_of_any_type() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let mut view = (0..6).map(|id| node(id, id as u16)).collect::<Vec<Node>>();
+        let buffer = vec![node(1, 0), node(10, 0), node(11, 0), node(12, 0)];
+
+        // c = 6, h = 2, s = 1
+        select(&mut view, 6, 2, 1, &buffer, &mut rng);
+        let ids = view.iter().map(|node| node.id).collect::<Vec<u32>>();
+        // the oldest (4 and 5) are removed, then the head (0)
+        assert_eq!(ids, vec![1, 2, 3, 10, 11, 12]);
+        // the younger duplicate replaced the older one in place
+        assert_eq!(view[0].age, 0);
+
+        increase_age(&mut view);
+        assert!(view.iter().all(|node| node.age >= 1));
+        move_oldest_to_end(&mut view, 2);
+        assert_eq!(view.iter().map(|node| node.id).skip(4).collect::<Vec<u32>>(), vec![2, 3]);
+    }
+}
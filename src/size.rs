@@ -0,0 +1,144 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Estimates the number of nodes of the overlay by gossiping the minima of random values.
+///
+/// At each epoch, every node draws `k` values from an exponential distribution of rate 1,
+/// and the exchanges keep the minimum of each value. Once the minima have spread, each minimum
+/// follows an exponential distribution of rate n, the number of nodes, which is estimated
+/// without bias by (k - 1) divided by the sum of the minima, with a relative error of about 1/sqrt(k).
+///
+/// Epochs are derived from the clock so that the nodes restart together, forgetting the nodes
+/// that left. The estimate reported is the one of the last complete epoch.
+pub(crate) struct SizeEstimator {
+    /// Number of values drawn at each epoch, 0 when the estimation is disabled
+    samples: usize,
+    /// Length of an epoch in seconds
+    epoch_length: u64,
+    /// The current epoch
+    epoch: u64,
+    /// Minimum of each value known at the current epoch
+    minima: Vec<f64>,
+    /// Estimate of the last complete epoch
+    previous: Option<f64>,
+}
+
+impl SizeEstimator {
+    pub(crate) fn new(samples: usize, epoch_length: u64) -> SizeEstimator {
+        let epoch_length = epoch_length.max(1);
+        SizeEstimator {
+            samples,
+            epoch_length,
+            epoch: current_epoch(epoch_length),
+            minima: draw(samples),
+            previous: None,
+        }
+    }
+
+    /// Starts a new epoch with new values if the clock reached it
+    fn refresh(&mut self) {
+        let epoch = current_epoch(self.epoch_length);
+        if epoch > self.epoch {
+            self.start_epoch(epoch);
+        }
+    }
+
+    fn start_epoch(&mut self, epoch: u64) {
+        self.previous = estimate(&self.minima);
+        self.epoch = epoch;
+        self.minima = draw(self.samples);
+    }
+
+    /// Returns the epoch and the minima to be attached to an outgoing message, if the estimation is enabled
+    pub(crate) fn values(&mut self) -> Option<(u64, Vec<f64>)> {
+        if self.samples == 0 {
+            return None;
+        }
+        self.refresh();
+        Some((self.epoch, self.minima.clone()))
+    }
+
+    /// Merges the minima received from a peer. Minima of a past epoch are ignored, and those of a later
+    /// epoch, from a peer whose clock is ahead, make the node move to that epoch.
+    ///
+    /// # Arguments
+    ///
+    /// * `epoch` - Epoch of the minima
+    /// * `minima` - The minima received
+    pub(crate) fn merge(&mut self, epoch: u64, minima: &[f64]) {
+        if self.samples == 0 || minima.len() != self.samples {
+            return;
+        }
+        self.refresh();
+        if epoch > self.epoch {
+            self.start_epoch(epoch);
+        }
+        if epoch == self.epoch {
+            for (known, received) in self.minima.iter_mut().zip(minima) {
+                if *received < *known {
+                    *known = *received;
+                }
+            }
+        }
+    }
+
+    /// Returns the estimated number of nodes, that of the current epoch until an epoch completes
+    pub(crate) fn estimate(&mut self) -> Option<f64> {
+        if self.samples == 0 {
+            return None;
+        }
+        self.refresh();
+        self.previous.or_else(|| estimate(&self.minima))
+    }
+}
+
+fn current_epoch(epoch_length: u64) -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|elapsed| elapsed.as_secs()).unwrap_or(0) / epoch_length
+}
+
+fn draw(samples: usize) -> Vec<f64> {
+    // 1 - x lies in (0, 1], whose logarithm is finite
+    (0..samples).map(|_| -(1.0 - rand::random::<f64>()).ln()).collect()
+}
+
+fn estimate(minima: &[f64]) -> Option<f64> {
+    let sum: f64 = minima.iter().sum();
+    if minima.len() < 2 || sum <= 0.0 {
+        None
+    }
+    else {
+        Some((minima.len() - 1) as f64 / sum)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn estimates_number_of_nodes() {
+        let mut nodes = (0..100).map(|_| SizeEstimator::new(256, 3600)).collect::<Vec<SizeEstimator>>();
+        assert!(nodes[0].estimate().unwrap() < 10.0);
+        // gossip the minima until they have spread to every node
+        for _ in 0..2 {
+            for i in 0..nodes.len() {
+                let (epoch, minima) = nodes[i].values().unwrap();
+                for node in nodes.iter_mut() {
+                    node.merge(epoch, &minima);
+                }
+            }
+        }
+        let estimate = nodes[42].estimate().unwrap();
+        assert!((70.0..130.0).contains(&estimate), "estimate {}", estimate);
+
+        // the minima of a past epoch are ignored, and those of a later epoch replace the current ones
+        let (epoch, _) = nodes[0].values().unwrap();
+        nodes[0].merge(epoch - 1, &vec![0.0; 256]);
+        assert_eq!(nodes[0].estimate(), Some(estimate));
+        nodes[0].merge(epoch + 1, &vec![1.0; 256]);
+        assert_eq!(nodes[0].estimate(), Some(estimate));
+        let (later, minima) = nodes[0].values().unwrap();
+        assert_eq!(later, epoch + 1);
+        assert!(minima.iter().all(|minimum| *minimum <= 1.0));
+        assert!(SizeEstimator::new(0, 60).values().is_none());
+    }
+}
@@ -0,0 +1,185 @@
+use std::path::{Path, PathBuf};
+
+#[cfg(feature = "encrypted-snapshot")]
+use chacha20poly1305::{aead::Aead, ChaCha20Poly1305, KeyInit, Nonce};
+
+use crate::peer::Peer;
+
+// Beginning of an encrypted file, followed by the nonce and the encrypted addresses
+#[cfg(feature = "encrypted-snapshot")]
+const ENCRYPTED_MAGIC: &[u8] = b"GBPS-ENC1";
+// Size of the nonce of the cipher
+#[cfg(feature = "encrypted-snapshot")]
+const NONCE_SIZE: usize = 12;
+
+/// A copy of the view saved to a file, one address per line, for recovering
+/// from an empty view or for rejoining the overlay after a restart
+pub struct ViewSnapshot {
+    /// Path of the file
+    path: PathBuf,
+    /// Key with which the file is encrypted, none if saved in clear
+    #[cfg(feature = "encrypted-snapshot")]
+    key: Option<[u8; 32]>,
+    /// Previous keys with which the file may still be encrypted
+    #[cfg(feature = "encrypted-snapshot")]
+    previous_keys: Vec<[u8; 32]>,
+}
+
+impl ViewSnapshot {
+    /// Creates a snapshot saved to a file
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path of the file
+    pub fn new<P: AsRef<Path>>(path: P) -> ViewSnapshot {
+        ViewSnapshot {
+            path: path.as_ref().to_path_buf(),
+            #[cfg(feature = "encrypted-snapshot")]
+            key: None,
+            #[cfg(feature = "encrypted-snapshot")]
+            previous_keys: vec![],
+        }
+    }
+
+    /// Encrypts the file with ChaCha20-Poly1305 and a random nonce at each save.
+    /// A file saved in clear is still loaded.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - Key of the cipher
+    #[cfg(feature = "encrypted-snapshot")]
+    pub fn with_key(mut self, key: [u8; 32]) -> ViewSnapshot {
+        self.key = Some(key);
+        self
+    }
+
+    /// Also decrypts a file encrypted with a previous key, which is encrypted with the current key at the next save
+    ///
+    /// # Arguments
+    ///
+    /// * `previous_keys` - The previous keys
+    #[cfg(feature = "encrypted-snapshot")]
+    pub fn with_previous_keys(mut self, previous_keys: Vec<[u8; 32]>) -> ViewSnapshot {
+        self.previous_keys = previous_keys;
+        self
+    }
+
+    /// Saves the addresses of the view, unless the view is empty so that
+    /// the last known peers are kept for recovery
+    ///
+    /// # Arguments
+    ///
+    /// * `peers` - The view
+    pub fn save(&self, peers: &[Peer]) -> std::io::Result<()> {
+        if peers.is_empty() {
+            return Ok(());
+        }
+        // replace the file at once, so that a crash does not leave it truncated
+        let temporary = self.path.with_extension("tmp");
+        let mut content = String::new();
+        for peer in peers {
+            content.push_str(peer.address());
+            content.push('\n');
+        }
+        let content = self.encrypt(content.into_bytes())?;
+        let mut file = std::fs::File::create(&temporary)?;
+        std::io::Write::write_all(&mut file, &content)?;
+        file.sync_all()?;
+        std::fs::rename(&temporary, &self.path)
+    }
+
+    /// Returns the saved peers, with a fresh age, or None if there are none
+    pub fn load(&self) -> Option<Vec<Peer>> {
+        let content = std::fs::read(&self.path).ok()?;
+        let content = String::from_utf8(self.decrypt(content)?).ok()?;
+        let peers = content.lines()
+            .map(|line| line.trim())
+            .filter(|line| !line.is_empty())
+            .map(|line| Peer::new(line.to_owned()))
+            .collect::<Vec<Peer>>();
+        if peers.is_empty() { None } else { Some(peers) }
+    }
+
+    #[cfg(feature = "encrypted-snapshot")]
+    fn encrypt(&self, content: Vec<u8>) -> std::io::Result<Vec<u8>> {
+        let key = match self.key.as_ref() {
+            Some(key) => key,
+            None => return Ok(content),
+        };
+        let nonce: [u8; NONCE_SIZE] = rand::random();
+        let ciphertext = ChaCha20Poly1305::new(key.into()).encrypt(Nonce::from_slice(&nonce), content.as_slice())
+            .map_err(|_| std::io::Error::other("could not encrypt the view"))?;
+        Ok([ENCRYPTED_MAGIC, &nonce, &ciphertext].concat())
+    }
+
+    #[cfg(not(feature = "encrypted-snapshot"))]
+    fn encrypt(&self, content: Vec<u8>) -> std::io::Result<Vec<u8>> {
+        Ok(content)
+    }
+
+    /// Returns the content in clear, or None if it is encrypted and cannot be decrypted with any of the keys
+    #[cfg(feature = "encrypted-snapshot")]
+    fn decrypt(&self, content: Vec<u8>) -> Option<Vec<u8>> {
+        let encrypted = match content.strip_prefix(ENCRYPTED_MAGIC) {
+            Some(encrypted) if encrypted.len() >= NONCE_SIZE => encrypted,
+            // saved in clear, before encryption was enabled
+            _ => return Some(content),
+        };
+        let (nonce, ciphertext) = encrypted.split_at(NONCE_SIZE);
+        self.key.iter().chain(self.previous_keys.iter())
+            .find_map(|key| ChaCha20Poly1305::new(key.into()).decrypt(Nonce::from_slice(nonce), ciphertext).ok())
+    }
+
+    #[cfg(not(feature = "encrypted-snapshot"))]
+    fn decrypt(&self, content: Vec<u8>) -> Option<Vec<u8>> {
+        Some(content)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_last_non_empty_view() {
+        let path = std::env::temp_dir().join(format!("gbps-snapshot-{}.peers", std::process::id()));
+        let snapshot = ViewSnapshot::new(&path);
+        assert!(snapshot.load().is_none());
+
+        let peer = Peer::with_age("127.0.0.1:9001".to_owned(), 1);
+        snapshot.save(&[peer, Peer::new("[::1]:9002".to_owned())]).unwrap();
+        snapshot.save(&[]).unwrap();
+        let loaded = snapshot.load().unwrap();
+        assert_eq!(loaded, vec![Peer::new("127.0.0.1:9001".to_owned()), Peer::new("[::1]:9002".to_owned())]);
+        assert_eq!(loaded[0].age(), 0);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(feature = "encrypted-snapshot")]
+    #[test]
+    fn encrypts_saved_view() {
+        let path = std::env::temp_dir().join(format!("gbps-encrypted-snapshot-{}.peers", std::process::id()));
+        let peers = vec![Peer::new("127.0.0.1:9001".to_owned())];
+        // a view saved in clear is read, then encrypted
+        ViewSnapshot::new(&path).save(&peers).unwrap();
+        let snapshot = ViewSnapshot::new(&path).with_key([7; 32]);
+        assert_eq!(snapshot.load(), Some(peers.clone()));
+        snapshot.save(&peers).unwrap();
+
+        let content = std::fs::read(&path).unwrap();
+        assert!(content.starts_with(ENCRYPTED_MAGIC));
+        assert!(!content.windows(9).any(|window| window == b"127.0.0.1"));
+        assert_eq!(snapshot.load(), Some(peers.clone()));
+        assert!(ViewSnapshot::new(&path).with_key([8; 32]).load().is_none());
+        assert!(ViewSnapshot::new(&path).load().is_none());
+
+        // after a rotation, the view is read with the previous key and saved with the new one
+        let rotated = ViewSnapshot::new(&path).with_key([8; 32]).with_previous_keys(vec![[7; 32]]);
+        assert_eq!(rotated.load(), Some(peers.clone()));
+        rotated.save(&peers).unwrap();
+        assert_eq!(ViewSnapshot::new(&path).with_key([8; 32]).load(), Some(peers));
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,223 @@
+use std::io;
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::time::Duration;
+
+/// Options of the sockets of the node, applied to the listener, to the connections
+/// it accepts and to the outgoing connections
+#[derive(Clone, Debug, Default)]
+pub struct SocketConfig {
+    /// Type of service byte, or traffic class for IPv6, such as a DSCP marking shifted by two bits
+    tos: Option<u32>,
+    /// Idle time before TCP keepalive probes are sent, none if disabled
+    keepalive: Option<Duration>,
+    /// Allow binding the listener while connections of a previous run are in TIME_WAIT,
+    /// none for the default of the standard library, set on Unix only
+    reuse_address: Option<bool>,
+    /// Network interface the sockets are bound to, such as `eth0`
+    bind_device: Option<String>,
+}
+
+impl SocketConfig {
+    /// Creates a configuration leaving the options of the operating system
+    pub fn new() -> SocketConfig {
+        SocketConfig::default()
+    }
+
+    /// Marks the gossip traffic with a type of service byte, or traffic class for IPv6
+    ///
+    /// # Arguments
+    ///
+    /// * `tos` - The type of service byte, such as `0x20` for DSCP CS1
+    pub fn with_tos(mut self, tos: u8) -> SocketConfig {
+        self.tos = Some(tos as u32);
+        self
+    }
+
+    /// Enables TCP keepalive
+    ///
+    /// # Arguments
+    ///
+    /// * `idle` - Idle time in seconds before the first probe is sent
+    pub fn with_keepalive(mut self, idle: u64) -> SocketConfig {
+        self.keepalive = Some(Duration::from_secs(idle));
+        self
+    }
+
+    /// Sets SO_REUSEADDR on the listener, so that a node restarting quickly
+    /// can bind its address again. Like the standard library, set by default on Unix only.
+    ///
+    /// # Arguments
+    ///
+    /// * `reuse_address` - Set SO_REUSEADDR
+    pub fn with_reuse_address(mut self, reuse_address: bool) -> SocketConfig {
+        self.reuse_address = Some(reuse_address);
+        self
+    }
+
+    /// Binds the sockets to a network interface with SO_BINDTODEVICE, only supported on Linux
+    ///
+    /// # Arguments
+    ///
+    /// * `device` - Name of the interface
+    pub fn with_bind_device(mut self, device: String) -> SocketConfig {
+        self.bind_device = Some(device);
+        self
+    }
+
+    pub fn tos(&self) -> Option<u32> {
+        self.tos
+    }
+
+    pub fn keepalive(&self) -> Option<Duration> {
+        self.keepalive
+    }
+
+    pub fn is_reuse_address(&self) -> bool {
+        self.reuse_address.unwrap_or(cfg!(unix))
+    }
+
+    pub fn bind_device(&self) -> Option<&str> {
+        self.bind_device.as_deref()
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+mod sys {
+    use socket2::{Domain, Protocol, SockRef, Socket, TcpKeepalive, Type};
+
+    use super::*;
+
+    /// Creates a socket with the options set before binding or connecting
+    fn new_socket(address: &SocketAddr, options: &SocketConfig) -> io::Result<Socket> {
+        let socket = Socket::new(Domain::for_address(*address), Type::STREAM, Some(Protocol::TCP))?;
+        if let Some(device) = options.bind_device() {
+            bind_device(&socket, device)?;
+        }
+        configure(&SockRef::from(&socket), address, options)?;
+        Ok(socket)
+    }
+
+    /// Sets the options inherited by each connection
+    fn configure(socket: &SockRef, address: &SocketAddr, options: &SocketConfig) -> io::Result<()> {
+        if let Some(tos) = options.tos() {
+            set_tos(socket, address, tos)?;
+        }
+        if let Some(idle) = options.keepalive() {
+            socket.set_tcp_keepalive(&TcpKeepalive::new().with_time(idle))?;
+        }
+        Ok(())
+    }
+
+    #[cfg(any(target_os = "android", target_os = "fuchsia", target_os = "linux"))]
+    fn bind_device(socket: &Socket, device: &str) -> io::Result<()> {
+        socket.bind_device(Some(device.as_bytes()))
+    }
+
+    #[cfg(not(any(target_os = "android", target_os = "fuchsia", target_os = "linux")))]
+    fn bind_device(_socket: &Socket, _device: &str) -> io::Result<()> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "binding to a device is not supported on this platform"))
+    }
+
+    #[cfg(any(target_os = "android", target_os = "linux", target_os = "macos", target_os = "freebsd", target_os = "netbsd", target_os = "openbsd"))]
+    fn set_tos(socket: &SockRef, address: &SocketAddr, tos: u32) -> io::Result<()> {
+        if address.is_ipv6() { socket.set_tclass_v6(tos) } else { socket.set_tos(tos) }
+    }
+
+    #[cfg(not(any(target_os = "android", target_os = "linux", target_os = "macos", target_os = "freebsd", target_os = "netbsd", target_os = "openbsd")))]
+    fn set_tos(_socket: &SockRef, _address: &SocketAddr, _tos: u32) -> io::Result<()> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "type of service is not supported on this platform"))
+    }
+
+    pub fn bind(address: &SocketAddr, options: &SocketConfig) -> io::Result<TcpListener> {
+        let socket = new_socket(address, options)?;
+        socket.set_reuse_address(options.is_reuse_address())?;
+        socket.bind(&(*address).into())?;
+        socket.listen(128)?;
+        Ok(socket.into())
+    }
+
+    pub fn connect(address: &SocketAddr, timeout: Option<Duration>, options: &SocketConfig) -> io::Result<TcpStream> {
+        let socket = new_socket(address, options)?;
+        match timeout {
+            Some(timeout) => socket.connect_timeout(&(*address).into(), timeout)?,
+            None => socket.connect(&(*address).into())?,
+        }
+        Ok(socket.into())
+    }
+
+    pub fn configure_accepted(stream: &TcpStream, options: &SocketConfig) -> io::Result<()> {
+        configure(&SockRef::from(stream), &stream.local_addr()?, options)
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+mod sys {
+    use super::*;
+
+    pub fn bind(address: &SocketAddr, _options: &SocketConfig) -> io::Result<TcpListener> {
+        TcpListener::bind(address)
+    }
+
+    pub fn connect(address: &SocketAddr, timeout: Option<Duration>, _options: &SocketConfig) -> io::Result<TcpStream> {
+        match timeout {
+            Some(timeout) => TcpStream::connect_timeout(address, timeout),
+            None => TcpStream::connect(address),
+        }
+    }
+
+    pub fn configure_accepted(_stream: &TcpStream, _options: &SocketConfig) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Binds the listener of the node
+///
+/// # Arguments
+///
+/// * `address` - The bind address
+/// * `options` - The socket options
+pub fn bind(address: &SocketAddr, options: &SocketConfig) -> io::Result<TcpListener> {
+    sys::bind(address, options)
+}
+
+/// Opens a connection to another peer
+///
+/// # Arguments
+///
+/// * `address` - Address of the peer
+/// * `timeout` - Maximum duration of the connection, none for the default of the system
+/// * `options` - The socket options
+pub fn connect(address: &SocketAddr, timeout: Option<Duration>, options: &SocketConfig) -> io::Result<TcpStream> {
+    sys::connect(address, timeout, options)
+}
+
+/// Sets the options of a connection accepted by the listener
+///
+/// # Arguments
+///
+/// * `stream` - The accepted connection
+/// * `options` - The socket options
+pub fn configure_accepted(stream: &TcpStream, options: &SocketConfig) -> io::Result<()> {
+    sys::configure_accepted(stream, options)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn options_are_applied() {
+        let options = SocketConfig::new().with_tos(0x20).with_keepalive(30).with_reuse_address(true);
+        let listener = bind(&"127.0.0.1:0".parse().unwrap(), &options).unwrap();
+        let address = listener.local_addr().unwrap();
+        let stream = connect(&address, Some(Duration::from_secs(1)), &options).unwrap();
+        let (accepted, _) = listener.accept().unwrap();
+        configure_accepted(&accepted, &options).unwrap();
+
+        let socket = socket2::SockRef::from(&stream);
+        assert!(socket.keepalive().unwrap());
+        #[cfg(target_os = "linux")]
+        assert_eq!(socket.tos().unwrap(), 0x20);
+        assert!(socket2::SockRef::from(&accepted).keepalive().unwrap());
+    }
+}
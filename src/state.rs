@@ -0,0 +1,116 @@
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::peer::Peer;
+use crate::stats::Stats;
+
+/// The state of a service, exported for bug reports and imported for reproducing
+/// a view in tests, see [crate::PeerSamplingService::export_state].
+///
+/// Formatted as one line per field, the peers being written with their age, such as:
+///
+/// ```text
+/// view 127.0.0.1:9001(3) 127.0.0.1:9002(0)
+/// queue 127.0.0.1:9002(0)
+/// cycles 12
+/// self_occurrences 30
+/// indegree 2.5
+/// ```
+#[derive(Clone, Debug)]
+pub struct ServiceState {
+    /// The view, in order
+    view: Vec<Peer>,
+    /// The peers not yet returned to the application
+    queue: Vec<Peer>,
+    /// The statistics of the node
+    stats: Stats,
+}
+
+impl ServiceState {
+    /// Creates a state
+    ///
+    /// # Arguments
+    ///
+    /// * `view` - The view, in order
+    /// * `queue` - The peers not yet returned to the application
+    /// * `stats` - The statistics of the node
+    pub fn new(view: Vec<Peer>, queue: Vec<Peer>, stats: Stats) -> ServiceState {
+        ServiceState { view, queue, stats }
+    }
+
+    pub fn view(&self) -> &[Peer] {
+        &self.view
+    }
+
+    pub fn queue(&self) -> &[Peer] {
+        &self.queue
+    }
+
+    pub fn stats(&self) -> &Stats {
+        &self.stats
+    }
+
+    pub(crate) fn into_parts(self) -> (Vec<Peer>, Vec<Peer>, Stats) {
+        (self.view, self.queue, self.stats)
+    }
+}
+
+fn write_peers(f: &mut fmt::Formatter, name: &str, peers: &[Peer]) -> fmt::Result {
+    write!(f, "{}", name)?;
+    for peer in peers {
+        write!(f, " {}", peer)?;
+    }
+    writeln!(f)
+}
+
+impl fmt::Display for ServiceState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_peers(f, "view", &self.view)?;
+        write_peers(f, "queue", &self.queue)?;
+        writeln!(f, "cycles {}", self.stats.cycles())?;
+        writeln!(f, "self_occurrences {}", self.stats.self_occurrences())?;
+        writeln!(f, "indegree {}", self.stats.indegree())
+    }
+}
+
+impl FromStr for ServiceState {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<ServiceState, Box<dyn Error>> {
+        let (mut view, mut queue) = (vec![], vec![]);
+        let (mut cycles, mut self_occurrences, mut indegree) = (0, 0, 0.0);
+        for line in s.lines().map(|line| line.trim()).filter(|line| !line.is_empty()) {
+            let (name, values) = line.split_once(' ').unwrap_or((line, ""));
+            match name {
+                "view" => view = values.split_whitespace().map(Peer::from_str).collect::<Result<Vec<Peer>, _>>()?,
+                "queue" => queue = values.split_whitespace().map(Peer::from_str).collect::<Result<Vec<Peer>, _>>()?,
+                "cycles" => cycles = values.trim().parse()?,
+                "self_occurrences" => self_occurrences = values.trim().parse()?,
+                "indegree" => indegree = values.trim().parse()?,
+                _ => Err(format!("unknown field {}", name))?,
+            }
+        }
+        Ok(ServiceState::new(view, queue, Stats::restore(cycles, self_occurrences, indegree)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn state_round_trips_as_text() {
+        let view = vec![Peer::with_age("127.0.0.1:9001".to_owned(), 3), Peer::with_age("[::1]:9002".to_owned(), 0)];
+        let state = ServiceState::new(view.clone(), view[1..].to_vec(), Stats::restore(12, 30, 2.5));
+        let text = state.to_string();
+        assert_eq!(text, "view 127.0.0.1:9001(3) [::1]:9002(0)\nqueue [::1]:9002(0)\ncycles 12\nself_occurrences 30\nindegree 2.5\n");
+
+        let parsed = text.parse::<ServiceState>().unwrap();
+        assert_eq!(parsed.view().iter().map(|peer| peer.age()).collect::<Vec<u16>>(), vec![3, 0]);
+        assert_eq!(parsed.queue(), &view[1..]);
+        assert_eq!((parsed.stats().cycles(), parsed.stats().self_occurrences(), parsed.stats().indegree()), (12, 30, 2.5));
+        assert!("view 127.0.0.1:9001(x)".parse::<ServiceState>().is_err());
+        assert!("views".parse::<ServiceState>().is_err());
+    }
+}
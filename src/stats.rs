@@ -0,0 +1,205 @@
+use std::time::Duration;
+
+// Weight of the last cycle in the smoothed in-degree estimate
+const INDEGREE_SMOOTHING: f64 = 0.2;
+
+// Upper bounds in microseconds of the buckets of the histograms, the last bucket counting the longer durations
+const BUCKET_BOUNDS: [u64; 12] = [10, 50, 100, 500, 1_000, 5_000, 10_000, 50_000, 100_000, 500_000, 1_000_000, 5_000_000];
+
+/// Distribution of durations, counted in buckets of increasing bounds
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Histogram {
+    /// Number of durations in each bucket, the last one counting the durations above the last bound
+    counts: [u64; BUCKET_BOUNDS.len() + 1],
+    /// Sum of the durations
+    sum: Duration,
+    /// Longest duration
+    max: Duration,
+}
+
+impl Histogram {
+    /// Returns the upper bounds of the buckets, the durations above the last bound being counted in an additional bucket
+    pub fn bounds() -> Vec<Duration> {
+        BUCKET_BOUNDS.iter().map(|bound| Duration::from_micros(*bound)).collect()
+    }
+
+    /// Returns the number of durations in each bucket, one more than the bounds
+    pub fn counts(&self) -> &[u64] {
+        &self.counts
+    }
+
+    /// Returns the number of durations recorded
+    pub fn count(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    pub fn sum(&self) -> Duration {
+        self.sum
+    }
+
+    pub fn max(&self) -> Duration {
+        self.max
+    }
+
+    /// Returns the mean duration, None if nothing was recorded
+    pub fn mean(&self) -> Option<Duration> {
+        match self.count() {
+            0 => None,
+            count => Some(self.sum / count as u32),
+        }
+    }
+
+    /// Returns the upper bound of the bucket containing a quantile, or the longest duration
+    /// for the last bucket, None if nothing was recorded
+    ///
+    /// # Arguments
+    ///
+    /// * `quantile` - The quantile, such as 0.99
+    pub fn quantile(&self, quantile: f64) -> Option<Duration> {
+        let rank = (quantile.clamp(0.0, 1.0) * self.count() as f64).ceil().max(1.0) as u64;
+        let mut cumulated = 0;
+        for (index, count) in self.counts.iter().enumerate() {
+            cumulated += count;
+            if cumulated >= rank {
+                return Some(BUCKET_BOUNDS.get(index).map_or(self.max, |bound| Duration::from_micros(*bound).min(self.max)));
+            }
+        }
+        None
+    }
+
+    /// Records a duration
+    pub(crate) fn record(&mut self, duration: Duration) {
+        let micros = duration.as_micros();
+        let bucket = BUCKET_BOUNDS.iter().position(|bound| micros <= *bound as u128).unwrap_or(BUCKET_BOUNDS.len());
+        self.counts[bucket] += 1;
+        self.sum += duration;
+        self.max = self.max.max(duration);
+    }
+}
+
+/// Statistics of the peer sampling protocol at a node
+#[derive(Clone, Debug, Default)]
+pub struct Stats {
+    /// Number of cycles performed
+    cycles: u64,
+    /// Number of times the node's own address appeared in the received buffers
+    self_occurrences: u64,
+    /// Occurrences of the node's own address during the current cycle
+    cycle_occurrences: u64,
+    /// Smoothed number of occurrences of the node's own address per cycle
+    indegree: f64,
+    /// Uniformity score of the last peers returned to the application, if tested
+    uniformity: Option<f64>,
+    /// Time the received messages waited before being handled
+    queue_latency: Histogram,
+    /// Duration of the merges of the received buffers into the view
+    merge_duration: Histogram,
+}
+
+impl Stats {
+    /// Returns the number of cycles performed
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    /// Returns the number of times the node's own address appeared in the buffers received from other peers
+    pub fn self_occurrences(&self) -> u64 {
+        self.self_occurrences
+    }
+
+    /// Returns the estimated in-degree of the node: the exponentially smoothed number
+    /// of times per cycle its address appears in the buffers received from other peers,
+    /// which is proportional to the number of views containing the node.
+    /// A value much higher than for the other nodes reveals a hub.
+    pub fn indegree(&self) -> f64 {
+        self.indegree
+    }
+
+    /// Returns how far the last peers returned to the application are from being uniformly
+    /// distributed over the view, if tested with [crate::Config::with_uniformity_test]:
+    /// the chi-square statistic divided by its degrees of freedom, close to 1 for uniform
+    /// samples and much higher for biased ones. None until there are enough samples.
+    pub fn uniformity(&self) -> Option<f64> {
+        self.uniformity
+    }
+
+    /// Returns the distribution of the time the received messages waited for the handling thread,
+    /// which grows when the node falls behind. Not recorded with [crate::Config::with_event_loop],
+    /// where the messages are handled as they arrive.
+    pub fn queue_latency(&self) -> &Histogram {
+        &self.queue_latency
+    }
+
+    /// Returns the distribution of the duration of the merges of the received buffers into the view
+    pub fn merge_duration(&self) -> &Histogram {
+        &self.merge_duration
+    }
+
+    pub(crate) fn record_queue_latency(&mut self, latency: Duration) {
+        self.queue_latency.record(latency);
+    }
+
+    pub(crate) fn record_merge_duration(&mut self, duration: Duration) {
+        self.merge_duration.record(duration);
+    }
+
+    pub(crate) fn set_uniformity(&mut self, uniformity: Option<f64>) {
+        self.uniformity = uniformity;
+    }
+
+    /// Creates statistics with the counters of a previous state
+    pub(crate) fn restore(cycles: u64, self_occurrences: u64, indegree: f64) -> Stats {
+        Stats { cycles, self_occurrences, indegree, ..Stats::default() }
+    }
+
+    /// Records the occurrences of the node's own address in a received buffer
+    pub(crate) fn record_occurrences(&mut self, occurrences: u64) {
+        self.self_occurrences += occurrences;
+        self.cycle_occurrences += occurrences;
+    }
+
+    /// Updates the in-degree estimate at the end of a cycle
+    pub(crate) fn end_cycle(&mut self) {
+        self.cycles += 1;
+        self.indegree = if self.cycles == 1 {
+            self.cycle_occurrences as f64
+        }
+        else {
+            INDEGREE_SMOOTHING * self.cycle_occurrences as f64 + (1.0 - INDEGREE_SMOOTHING) * self.indegree
+        };
+        self.cycle_occurrences = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn indegree_is_smoothed() {
+        let mut stats = Stats::default();
+        stats.record_occurrences(2);
+        stats.record_occurrences(3);
+        stats.end_cycle();
+        assert_eq!(stats.indegree(), 5.0);
+        stats.end_cycle();
+        assert!((stats.indegree() - 4.0).abs() < 1e-9);
+        assert_eq!(stats.cycles(), 2);
+        assert_eq!(stats.self_occurrences(), 5);
+    }
+
+    #[test]
+    fn histogram_counts_durations() {
+        let mut histogram = Histogram::default();
+        assert_eq!((histogram.mean(), histogram.quantile(0.5)), (None, None));
+        for micros in [5, 20, 30, 700, 8_000_000] {
+            histogram.record(Duration::from_micros(micros));
+        }
+        assert_eq!(histogram.count(), 5);
+        assert_eq!((histogram.counts()[0], histogram.counts()[1], histogram.counts()[4], histogram.counts()[12]), (1, 2, 1, 1));
+        assert_eq!(histogram.quantile(0.5), Some(Duration::from_micros(50)));
+        assert_eq!(histogram.quantile(1.0), Some(Duration::from_secs(8)));
+        assert_eq!(histogram.mean(), Some(Duration::from_micros(1_600_151)));
+        assert_eq!(Histogram::bounds().len() + 1, histogram.counts().len());
+    }
+}
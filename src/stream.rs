@@ -0,0 +1,102 @@
+use std::sync::{Arc, Mutex};
+
+use crate::peer::Peer;
+use crate::view::View;
+
+/// An iterator over peers sampled from the view of a service, as returned by
+/// [crate::PeerSamplingService::get_peer], see [crate::PeerSamplingService::peers_iter].
+///
+/// The iterator never ends while the view has peers, and ends when the view is empty.
+pub struct PeerIter {
+    /// View of the service
+    view: Arc<Mutex<View>>,
+}
+
+impl PeerIter {
+    pub(crate) fn new(view: Arc<Mutex<View>>) -> PeerIter {
+        PeerIter { view }
+    }
+}
+
+impl Iterator for PeerIter {
+    type Item = Peer;
+
+    fn next(&mut self) -> Option<Peer> {
+        self.view.lock().unwrap().get_peer()
+    }
+}
+
+/// An endless asynchronous stream of peers sampled from the view of a service,
+/// see [crate::PeerSamplingService::peer_stream].
+///
+/// While the view is empty, the stream is pending and checks the view again periodically,
+/// so that it does not depend on an asynchronous runtime.
+#[cfg(feature = "stream")]
+pub struct PeerStream {
+    /// View of the service
+    view: Arc<Mutex<View>>,
+    /// Time between two checks of an empty view
+    retry: std::time::Duration,
+}
+
+#[cfg(feature = "stream")]
+impl PeerStream {
+    pub(crate) fn new(view: Arc<Mutex<View>>, retry: std::time::Duration) -> PeerStream {
+        PeerStream { view, retry }
+    }
+}
+
+#[cfg(feature = "stream")]
+impl futures_core::Stream for PeerStream {
+    type Item = Peer;
+
+    fn poll_next(self: std::pin::Pin<&mut Self>, cx: &mut std::task::Context<'_>) -> std::task::Poll<Option<Peer>> {
+        match self.view.lock().unwrap().get_peer() {
+            Some(peer) => std::task::Poll::Ready(Some(peer)),
+            None => {
+                let (waker, retry) = (cx.waker().clone(), self.retry);
+                std::thread::spawn(move || {
+                    std::thread::sleep(retry);
+                    waker.wake();
+                });
+                std::task::Poll::Pending
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn iterator_samples_until_view_is_empty() {
+        let mut view = View::new("127.0.0.1:9000".to_owned());
+        view.add_peers(vec![Peer::new("127.0.0.1:9001".to_owned()), Peer::new("127.0.0.1:9002".to_owned())]);
+        let view = Arc::new(Mutex::new(view));
+        let sampled = PeerIter::new(view.clone()).take(10).collect::<Vec<Peer>>();
+        assert_eq!(sampled.len(), 10);
+
+        view.lock().unwrap().remove_peer("127.0.0.1:9001");
+        view.lock().unwrap().remove_peer("127.0.0.1:9002");
+        assert!(PeerIter::new(view).next().is_none());
+    }
+
+    #[cfg(feature = "stream")]
+    #[test]
+    fn stream_waits_for_peers() {
+        use futures_core::Stream;
+        use std::task::{Context, Poll, Waker};
+
+        let view = Arc::new(Mutex::new(View::new("127.0.0.1:9000".to_owned())));
+        let mut stream = PeerStream::new(view.clone(), std::time::Duration::from_millis(10));
+        let mut context = Context::from_waker(Waker::noop());
+        assert!(std::pin::Pin::new(&mut stream).poll_next(&mut context).is_pending());
+
+        view.lock().unwrap().add_peers(vec![Peer::new("127.0.0.1:9001".to_owned())]);
+        match std::pin::Pin::new(&mut stream).poll_next(&mut context) {
+            Poll::Ready(Some(peer)) => assert_eq!(peer.address(), "127.0.0.1:9001"),
+            _ => panic!("expected a peer"),
+        }
+    }
+}
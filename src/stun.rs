@@ -0,0 +1,208 @@
+use std::error::Error;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs, UdpSocket};
+use std::time::{Duration, Instant};
+
+/// Magic cookie of STUN messages (RFC 5389), also used for masking the addresses
+const MAGIC_COOKIE: u32 = 0x2112_A442;
+/// Type of a binding request
+const BINDING_REQUEST: u16 = 0x0001;
+/// Type of a successful binding response
+const BINDING_RESPONSE: u16 = 0x0101;
+/// Attribute of the mapped address in RFC 3489 servers
+const MAPPED_ADDRESS: u16 = 0x0001;
+/// Attribute of the mapped address masked with the magic cookie
+const XOR_MAPPED_ADDRESS: u16 = 0x0020;
+/// Time waited for the answer of each server
+pub(crate) const SERVER_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Learns the address of the node as seen from outside its network by sending a STUN binding request
+/// to each server in turn, from a UDP socket bound to the address of the node, and returns the first
+/// address obtained or the failures of all the servers
+///
+/// # Arguments
+///
+/// * `servers` - The STUN servers, as `host:port`
+/// * `local` - The address of the node, the port of the socket being assigned by the system if in use
+/// * `timeout` - Time waited for the answer of each server
+pub(crate) fn discover(servers: &[String], local: &SocketAddr, timeout: Duration) -> Result<SocketAddr, Box<dyn Error>> {
+    // the NATs preserving ports map the same port as that of the listener
+    let socket = UdpSocket::bind(local).or_else(|_| UdpSocket::bind(SocketAddr::new(local.ip(), 0)))?;
+    let mut failures = vec![];
+    for server in servers {
+        let address = server.to_socket_addrs().ok()
+            .and_then(|mut addresses| addresses.find(|address| address.is_ipv6() == local.is_ipv6()));
+        let result = match address {
+            Some(address) => binding(&socket, address, timeout),
+            None => Err("no address of the family of the node".into()),
+        };
+        match result {
+            Ok(mapped) => return Ok(mapped),
+            Err(e) => failures.push(format!("{}: {}", server, e)),
+        }
+    }
+    Err(format!("no address learned from the STUN servers ({})", failures.join(", ")))?
+}
+
+/// Sends a binding request to a server, retransmitted with a doubling interval as in RFC 5389,
+/// and returns the mapped address of the response
+fn binding(socket: &UdpSocket, server: SocketAddr, timeout: Duration) -> Result<SocketAddr, Box<dyn Error>> {
+    let transaction: [u8; 12] = rand::random();
+    let request = binding_request(&transaction);
+    let deadline = Instant::now() + timeout;
+    let mut interval = Duration::from_millis(500);
+    let mut buffer = [0u8; 512];
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            Err("no answer")?
+        }
+        socket.send_to(&request, server)?;
+        socket.set_read_timeout(Some(interval.min(remaining)))?;
+        match socket.recv_from(&mut buffer) {
+            // answers of other servers or of previous requests are ignored
+            Ok((length, sender)) if sender == server => match parse_binding_response(&buffer[..length], &transaction) {
+                Ok(mapped) => return Ok(mapped),
+                Err(ResponseError::Unrelated) => {}
+                Err(ResponseError::Invalid(e)) => Err(e)?,
+            },
+            Ok(_) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock || e.kind() == std::io::ErrorKind::TimedOut => interval *= 2,
+            Err(e) => Err(e)?,
+        }
+    }
+}
+
+/// Why a response was not used
+#[derive(Debug, PartialEq)]
+enum ResponseError {
+    /// The message does not answer the request
+    Unrelated,
+    /// The response answers the request without a valid mapped address
+    Invalid(String),
+}
+
+/// Returns a binding request without attributes
+///
+/// # Arguments
+///
+/// * `transaction` - Identifier of the transaction, echoed by the response
+fn binding_request(transaction: &[u8; 12]) -> [u8; 20] {
+    let mut request = [0u8; 20];
+    request[0..2].copy_from_slice(&BINDING_REQUEST.to_be_bytes());
+    // no attributes
+    request[4..8].copy_from_slice(&MAGIC_COOKIE.to_be_bytes());
+    request[8..20].copy_from_slice(transaction);
+    request
+}
+
+/// Returns the mapped address of a binding response, preferring the masked address
+///
+/// # Arguments
+///
+/// * `response` - The response
+/// * `transaction` - Identifier of the transaction of the request
+fn parse_binding_response(response: &[u8], transaction: &[u8; 12]) -> Result<SocketAddr, ResponseError> {
+    if response.len() < 20 || response[4..8] != MAGIC_COOKIE.to_be_bytes() || response[8..20] != transaction[..] {
+        return Err(ResponseError::Unrelated);
+    }
+    let message_type = u16::from_be_bytes([response[0], response[1]]);
+    if message_type != BINDING_RESPONSE {
+        return Err(ResponseError::Invalid(format!("unexpected message type {:#06x}", message_type)));
+    }
+    let length = u16::from_be_bytes([response[2], response[3]]) as usize;
+    let attributes = response.get(20..20 + length).ok_or_else(|| ResponseError::Invalid("truncated response".to_owned()))?;
+
+    let mut mapped = None;
+    let mut offset = 0;
+    while offset + 4 <= attributes.len() {
+        let attribute = u16::from_be_bytes([attributes[offset], attributes[offset + 1]]);
+        let length = u16::from_be_bytes([attributes[offset + 2], attributes[offset + 3]]) as usize;
+        let value = attributes.get(offset + 4..offset + 4 + length).ok_or_else(|| ResponseError::Invalid("truncated attribute".to_owned()))?;
+        match attribute {
+            XOR_MAPPED_ADDRESS => return parse_address(value, Some(transaction)),
+            MAPPED_ADDRESS => mapped = Some(parse_address(value, None)?),
+            _ => {}
+        }
+        // attributes are padded to 4 bytes
+        offset += 4 + length.div_ceil(4) * 4;
+    }
+    mapped.ok_or_else(|| ResponseError::Invalid("no mapped address".to_owned()))
+}
+
+/// Returns the address of a mapped address attribute, unmasking it if masked
+///
+/// # Arguments
+///
+/// * `value` - Value of the attribute
+/// * `transaction` - Identifier of the transaction if the address is masked
+fn parse_address(value: &[u8], transaction: Option<&[u8; 12]>) -> Result<SocketAddr, ResponseError> {
+    let invalid = || ResponseError::Invalid("invalid mapped address".to_owned());
+    if value.len() < 4 {
+        return Err(invalid());
+    }
+    // masked with the magic cookie, followed by the transaction for IPv6
+    let mut mask = [0u8; 16];
+    if let Some(transaction) = transaction {
+        mask[0..4].copy_from_slice(&MAGIC_COOKIE.to_be_bytes());
+        mask[4..16].copy_from_slice(transaction);
+    }
+    let port = u16::from_be_bytes([value[2] ^ mask[0], value[3] ^ mask[1]]);
+    let mut address = [0u8; 16];
+    let ip = match (value[1], value.len()) {
+        (1, 8) => {
+            address[..4].iter_mut().zip(&value[4..8]).zip(&mask).for_each(|((byte, value), mask)| *byte = value ^ mask);
+            IpAddr::V4(Ipv4Addr::new(address[0], address[1], address[2], address[3]))
+        }
+        (2, 20) => {
+            address.iter_mut().zip(&value[4..20]).zip(&mask).for_each(|((byte, value), mask)| *byte = value ^ mask);
+            IpAddr::V6(Ipv6Addr::from(address))
+        }
+        _ => return Err(invalid()),
+    };
+    Ok(SocketAddr::new(ip, port))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_mapped_addresses() {
+        let transaction = [7u8; 12];
+        let request = binding_request(&transaction);
+        assert_eq!(&request[..8], &[0, 1, 0, 0, 0x21, 0x12, 0xA4, 0x42]);
+
+        // XOR-MAPPED-ADDRESS of 203.0.113.7:9000, after an unknown attribute padded to 4 bytes
+        let mut response = vec![0x01, 0x01, 0, 20, 0x21, 0x12, 0xA4, 0x42];
+        response.extend_from_slice(&transaction);
+        response.extend_from_slice(&[0x80, 0x22, 0, 3, b'g', b'b', b'p', 0]);
+        response.extend_from_slice(&[0, 0x20, 0, 8, 0, 1, 0x23 ^ 0x21, 0x28 ^ 0x12, 203 ^ 0x21, 0x12, 113 ^ 0xA4, 7 ^ 0x42]);
+        assert_eq!(parse_binding_response(&response, &transaction), Ok("203.0.113.7:9000".parse().unwrap()));
+        assert_eq!(parse_binding_response(&response, &[8u8; 12]), Err(ResponseError::Unrelated));
+
+        // MAPPED-ADDRESS of older servers
+        let mut response = vec![0x01, 0x01, 0, 12, 0x21, 0x12, 0xA4, 0x42];
+        response.extend_from_slice(&transaction);
+        response.extend_from_slice(&[0, 1, 0, 8, 0, 1, 0x23, 0x28, 203, 0, 113, 7]);
+        assert_eq!(parse_binding_response(&response, &transaction), Ok("203.0.113.7:9000".parse().unwrap()));
+        response[3] = 16;
+        assert!(matches!(parse_binding_response(&response, &transaction), Err(ResponseError::Invalid(_))));
+    }
+
+    #[test]
+    fn discovers_address_from_server() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let servers = vec!["127.0.0.1:1".to_owned(), server.local_addr().unwrap().to_string()];
+        let answering = std::thread::spawn(move || {
+            let mut request = [0u8; 20];
+            let (_, client) = server.recv_from(&mut request).unwrap();
+            let mut response = vec![0x01, 0x01, 0, 12];
+            response.extend_from_slice(&request[4..20]);
+            response.extend_from_slice(&[0, 1, 0, 8, 0, 1, 0x23, 0x28, 203, 0, 113, 7]);
+            server.send_to(&response, client).unwrap();
+        });
+        let local = "127.0.0.1:0".parse().unwrap();
+        assert_eq!(discover(&servers, &local, Duration::from_millis(500)).unwrap(), "203.0.113.7:9000".parse().unwrap());
+        answering.join().unwrap();
+    }
+}
@@ -0,0 +1,261 @@
+use std::any::Any;
+use std::error::Error;
+use std::fmt;
+use std::net::SocketAddr;
+use std::panic::AssertUnwindSafe;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Condvar, Mutex};
+
+use slog::{debug, Logger};
+
+use crate::network::BindError;
+
+/// Triggers the cycles of the peer sampling, shared with the handles so that they can stop it
+pub(crate) type CycleTrigger = Arc<Mutex<Option<Sender<Sender<Option<String>>>>>>;
+
+/// A failure to start the service
+#[derive(Debug)]
+pub enum InitError {
+    /// An address of the node could not be bound
+    Bind(BindError),
+    /// A task of the service could not be started, such as when the process cannot create more threads
+    Spawn { task: String, error: std::io::Error },
+}
+
+impl fmt::Display for InitError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InitError::Bind(error) => write!(f, "{}", error),
+            InitError::Spawn { task, error } => write!(f, "could not start {}: {}", task, error),
+        }
+    }
+}
+
+impl Error for InitError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            InitError::Bind(error) => Some(error),
+            InitError::Spawn { error, .. } => Some(error),
+        }
+    }
+}
+
+impl From<BindError> for InitError {
+    fn from(error: BindError) -> InitError {
+        InitError::Bind(error)
+    }
+}
+
+/// How a task of the service ended
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TaskExit {
+    /// The task stopped after the service was shut down or aborted
+    Stopped,
+    /// The task returned while the service was running, such as after a failure of its socket
+    Exited,
+    /// The task panicked, with the message of the panic
+    Panicked(String),
+}
+
+impl fmt::Display for TaskExit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TaskExit::Stopped => write!(f, "stopped"),
+            TaskExit::Exited => write!(f, "exited"),
+            TaskExit::Panicked(message) => write!(f, "panicked: {}", message),
+        }
+    }
+}
+
+/// The running tasks and the exits of those that ended, by task name
+#[derive(Default)]
+struct TaskStates {
+    running: Vec<String>,
+    exits: Vec<(String, TaskExit)>,
+}
+
+/// Records the end of the tasks started by the service
+#[derive(Default)]
+pub(crate) struct Tasks {
+    states: Mutex<TaskStates>,
+    /// Notified when a task ends
+    ended: Condvar,
+    /// Whether the tasks were asked to stop, so that their end is expected
+    stopping: AtomicBool,
+}
+
+impl Tasks {
+    /// Registers a running task, and returns its body recording how it ends
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Name of the task
+    /// * `f` - The body of the task
+    pub(crate) fn track<F: FnOnce() + Send + 'static>(self: &Arc<Self>, name: String, f: F) -> impl FnOnce() + Send + 'static {
+        self.states.lock().unwrap().running.push(name.clone());
+        let tasks = self.clone();
+        move || {
+            let result = std::panic::catch_unwind(AssertUnwindSafe(f));
+            let exit = match &result {
+                Ok(()) if tasks.stopping.load(Ordering::SeqCst) => TaskExit::Stopped,
+                Ok(()) => TaskExit::Exited,
+                Err(panic) => TaskExit::Panicked(panic_message(panic.as_ref())),
+            };
+            tasks.end(&name, Some(exit));
+            // the panic is still reported when joining the thread
+            if let Err(panic) = result {
+                std::panic::resume_unwind(panic);
+            }
+        }
+    }
+
+    /// Records the end of a task, none for a task that could not be started
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Name of the task
+    /// * `exit` - How the task ended
+    pub(crate) fn end(&self, name: &str, exit: Option<TaskExit>) {
+        let mut states = self.states.lock().unwrap();
+        if let Some(index) = states.running.iter().position(|running| running == name) {
+            states.running.remove(index);
+        }
+        if let Some(exit) = exit {
+            states.exits.push((name.to_owned(), exit));
+        }
+        self.ended.notify_all();
+    }
+}
+
+/// Returns the message of a panic, which is a string unless the panic was raised with another value
+///
+/// # Arguments
+///
+/// * `panic` - The value of the panic
+fn panic_message(panic: &(dyn Any + Send)) -> String {
+    match (panic.downcast_ref::<&str>(), panic.downcast_ref::<String>()) {
+        (Some(message), _) => (*message).to_owned(),
+        (_, Some(message)) => message.clone(),
+        _ => "unknown panic".to_owned(),
+    }
+}
+
+/// A cloneable handle to the tasks of a started service, returned by [crate::PeerSamplingService::init],
+/// for supervising them: the application learns which tasks ended and why, waits for their end,
+/// or stops them without owning the service.
+///
+/// The handle only concerns the tasks of the start that returned it: after a restart,
+/// the handle returned by [crate::PeerSamplingService::restart] must be used.
+#[derive(Clone)]
+pub struct ServiceHandle {
+    tasks: Arc<Tasks>,
+    shutdown_tcp_listener: Arc<AtomicBool>,
+    shutdown_peer_sampling: Arc<AtomicBool>,
+    cycle_trigger: CycleTrigger,
+    /// Wakes the event loop, if the service runs on a single thread
+    #[cfg(feature = "event-loop")]
+    cycle_waker: Option<Arc<mio::Waker>>,
+    /// Addresses of the listeners, woken up when the tasks are stopped
+    addresses: Vec<SocketAddr>,
+    logger: Logger,
+}
+
+impl fmt::Debug for ServiceHandle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let states = self.tasks.states.lock().unwrap();
+        f.debug_struct("ServiceHandle")
+            .field("running", &states.running)
+            .field("exits", &states.exits)
+            .finish()
+    }
+}
+
+impl ServiceHandle {
+    pub(crate) fn new(tasks: Arc<Tasks>, shutdown_tcp_listener: Arc<AtomicBool>, shutdown_peer_sampling: Arc<AtomicBool>, cycle_trigger: CycleTrigger, addresses: Vec<SocketAddr>, logger: Logger) -> ServiceHandle {
+        ServiceHandle {
+            tasks,
+            shutdown_tcp_listener,
+            shutdown_peer_sampling,
+            cycle_trigger,
+            #[cfg(feature = "event-loop")]
+            cycle_waker: None,
+            addresses,
+            logger,
+        }
+    }
+
+    /// Sets the waker of the event loop, notified when the tasks are stopped
+    #[cfg(feature = "event-loop")]
+    pub(crate) fn set_waker(&mut self, waker: Arc<mio::Waker>) {
+        self.cycle_waker = Some(waker);
+    }
+
+    /// Returns the names of the tasks still running
+    pub fn running(&self) -> Vec<String> {
+        self.tasks.states.lock().unwrap().running.clone()
+    }
+
+    /// Returns true while all the tasks of the service are running
+    pub fn is_running(&self) -> bool {
+        self.tasks.states.lock().unwrap().exits.is_empty()
+    }
+
+    /// Returns the tasks that ended, in the order they ended, with how they ended
+    pub fn exits(&self) -> Vec<(String, TaskExit)> {
+        self.tasks.states.lock().unwrap().exits.clone()
+    }
+
+    /// Waits for the end of all the tasks, and returns how they ended.
+    /// Blocks until the service is shut down or aborted, unless all the tasks fail.
+    pub fn join(&self) -> Vec<(String, TaskExit)> {
+        let states = self.tasks.states.lock().unwrap();
+        let states = self.tasks.ended.wait_while(states, |states| !states.running.is_empty()).unwrap();
+        states.exits.clone()
+    }
+
+    /// Asks all the tasks to stop without waiting for their end. The current cycle and the
+    /// exchanges in progress are completed, and the departure is not announced to the peers,
+    /// see [crate::PeerSamplingService::shutdown].
+    pub fn abort(&self) {
+        self.tasks.stopping.store(true, Ordering::SeqCst);
+        self.shutdown_peer_sampling.store(true, Ordering::SeqCst);
+        // closing the channel of the triggered cycles wakes up the peer sampling
+        self.cycle_trigger.lock().unwrap().take();
+        self.shutdown_tcp_listener.store(true, Ordering::SeqCst);
+        #[cfg(feature = "event-loop")]
+        if let Some(waker) = self.cycle_waker.as_ref() {
+            if let Err(e) = waker.wake() {
+                debug!(self.logger, "Could not wake the event loop: {}", e);
+            }
+        }
+        for address in &self.addresses {
+            if let Err(e) = crate::network::wake_listener(address) {
+                debug!(self.logger, "Could not wake the listener on {}: {}", address, e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn records_exits_of_tasks() {
+        let tasks = Arc::new(Tasks::default());
+        let handle = ServiceHandle::new(tasks.clone(), Arc::new(AtomicBool::new(false)), Arc::new(AtomicBool::new(false)), Arc::new(Mutex::new(None)), vec![], Logger::root(slog::Discard, slog::o!()));
+        let (release, released) = std::sync::mpsc::channel::<()>();
+        let waiting = std::thread::spawn(tasks.track("waiting".to_owned(), move || { let _ = released.recv(); }));
+        let failing = std::thread::spawn(tasks.track("failing".to_owned(), || panic!("no more sockets")));
+        assert!(failing.join().is_err());
+        assert_eq!(handle.exits(), vec![("failing".to_owned(), TaskExit::Panicked("no more sockets".to_owned()))]);
+        assert_eq!(handle.running(), vec!["waiting".to_owned()]);
+        assert!(!handle.is_running());
+
+        handle.abort();
+        drop(release);
+        assert_eq!(handle.join()[1], ("waiting".to_owned(), TaskExit::Stopped));
+        waiting.join().unwrap();
+    }
+}
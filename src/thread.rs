@@ -0,0 +1,249 @@
+use std::fmt;
+use std::sync::mpsc::Receiver;
+use std::sync::Arc;
+use std::thread::JoinHandle;
+
+use slog::{warn, Logger};
+
+use crate::supervisor::{InitError, Tasks};
+
+/// Runs the long-lived tasks of the service on the threads of the application, such as
+/// those of an existing runtime, instead of threads started by the service.
+///
+/// Each task blocks until the service is shut down, waiting on sockets and channels, so that
+/// it must be run where blocking is allowed, such as with `tokio::runtime::Handle::spawn_blocking`.
+/// Closures taking the name and the task implement this trait.
+pub trait Executor: Send + Sync {
+    /// Runs a task of the service
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Name of the task, such as `127.0.0.1:9000 - gbps listener`
+    /// * `task` - The task
+    fn spawn(&self, name: String, task: Box<dyn FnOnce() + Send>);
+}
+
+impl<F: Fn(String, Box<dyn FnOnce() + Send>) + Send + Sync> Executor for F {
+    fn spawn(&self, name: String, task: Box<dyn FnOnce() + Send>) {
+        self(name, task)
+    }
+}
+
+/// Options of the threads started by the service: the listener, the message handling
+/// thread and the peer sampling thread
+#[derive(Clone, Default)]
+pub struct ThreadConfig {
+    /// Prefix of the thread names, followed by the role of the thread, none for the address of the node followed by ` - gbps `
+    name_prefix: Option<String>,
+    /// Stack size in bytes, none for the default of the standard library
+    stack_size: Option<usize>,
+    /// Nice value of the threads, none to inherit that of the process, set on Linux only
+    niceness: Option<i32>,
+    /// Runs the tasks of the service instead of threads, none for starting threads
+    executor: Option<Arc<dyn Executor>>,
+}
+
+impl fmt::Debug for ThreadConfig {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ThreadConfig")
+            .field("name_prefix", &self.name_prefix)
+            .field("stack_size", &self.stack_size)
+            .field("niceness", &self.niceness)
+            .field("executor", &self.executor.is_some())
+            .finish()
+    }
+}
+
+/// Waits for the end of a task of the service
+pub(crate) enum TaskHandle {
+    /// A thread started by the service
+    Thread(JoinHandle<()>),
+    /// A task run by an executor, notifying its end unless it panicked
+    Task(Receiver<()>),
+}
+
+impl TaskHandle {
+    /// Waits for the end of the task, failing if it panicked
+    pub(crate) fn join(self) -> Result<(), String> {
+        match self {
+            TaskHandle::Thread(handle) => handle.join().map_err(|e| format!("{:?}", e)),
+            TaskHandle::Task(done) => done.recv().map_err(|_| "task panicked or was dropped by the executor".to_owned()),
+        }
+    }
+}
+
+impl ThreadConfig {
+    /// Creates a configuration with the default names and the options of the standard library
+    pub fn new() -> ThreadConfig {
+        ThreadConfig::default()
+    }
+
+    /// Names the threads with a prefix followed by their role, such as `gossip-listener`
+    /// for the prefix `gossip-`. Linux only shows the first 15 bytes of a thread name.
+    ///
+    /// # Arguments
+    ///
+    /// * `name_prefix` - Prefix of the thread names
+    pub fn with_name_prefix(mut self, name_prefix: String) -> ThreadConfig {
+        self.name_prefix = Some(name_prefix);
+        self
+    }
+
+    /// Sets the stack size of the threads
+    ///
+    /// # Arguments
+    ///
+    /// * `stack_size` - Stack size in bytes
+    pub fn with_stack_size(mut self, stack_size: usize) -> ThreadConfig {
+        self.stack_size = Some(stack_size);
+        self
+    }
+
+    /// Sets the scheduling priority of the threads, such as 10 for running the protocol in the background.
+    /// Only supported on Linux, where each thread has its own nice value; lowering it requires privileges.
+    ///
+    /// # Arguments
+    ///
+    /// * `niceness` - Nice value, from -20 for the highest priority to 19 for the lowest
+    pub fn with_niceness(mut self, niceness: i32) -> ThreadConfig {
+        self.niceness = Some(niceness);
+        self
+    }
+
+    /// Runs the tasks of the service with an executor of the application instead of starting threads.
+    /// The stack size and the nice value are then left to the executor.
+    ///
+    /// # Arguments
+    ///
+    /// * `executor` - The executor, such as a closure calling `spawn_blocking` on a runtime handle
+    pub fn with_executor(mut self, executor: Arc<dyn Executor>) -> ThreadConfig {
+        self.executor = Some(executor);
+        self
+    }
+
+    pub fn name_prefix(&self) -> Option<&str> {
+        self.name_prefix.as_deref()
+    }
+
+    pub fn stack_size(&self) -> Option<usize> {
+        self.stack_size
+    }
+
+    pub fn niceness(&self) -> Option<i32> {
+        self.niceness
+    }
+
+    /// Returns the name of a thread of the service
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - Address of the node, named in the default names
+    /// * `role` - Role of the thread, such as `listener`
+    pub(crate) fn name(&self, address: &str, role: &str) -> String {
+        match &self.name_prefix {
+            Some(prefix) => format!("{}{}", prefix, role),
+            None => format!("{} - gbps {}", address, role),
+        }
+    }
+
+    /// Starts a task of the service on the executor, or on a thread with the configured options
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - Address of the node, named in the default names
+    /// * `role` - Role of the thread, such as `listener`
+    /// * `tasks` - The tasks of the service, recording how the task ends
+    /// * `logger` - Logger, warned if the priority could not be set
+    /// * `f` - The body of the thread
+    pub(crate) fn spawn<F: FnOnce() + Send + 'static>(&self, address: &str, role: &str, tasks: &Arc<Tasks>, logger: Logger, f: F) -> Result<TaskHandle, InitError> {
+        let name = self.name(address, role);
+        let f = tasks.track(name.clone(), f);
+        if let Some(executor) = &self.executor {
+            let (done, wait) = std::sync::mpsc::sync_channel(1);
+            executor.spawn(name, Box::new(move || {
+                f();
+                let _ = done.send(());
+            }));
+            return Ok(TaskHandle::Task(wait));
+        }
+        let mut builder = std::thread::Builder::new().name(name.clone());
+        if let Some(stack_size) = self.stack_size {
+            builder = builder.stack_size(stack_size);
+        }
+        let niceness = self.niceness;
+        builder.spawn(move || {
+            if let Some(niceness) = niceness {
+                if let Err(e) = set_niceness(niceness) {
+                    warn!(logger, "Could not set the nice value of the thread to {}: {}", niceness, e);
+                }
+            }
+            f()
+        }).map(TaskHandle::Thread).map_err(|error| {
+            tasks.end(&name, None);
+            InitError::Spawn { task: name, error }
+        })
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn set_niceness(niceness: i32) -> std::io::Result<()> {
+    // with PRIO_PROCESS, a thread identifier sets the nice value of that thread only
+    let thread_id = unsafe { libc::syscall(libc::SYS_gettid) } as libc::id_t;
+    if unsafe { libc::setpriority(libc::PRIO_PROCESS, thread_id, niceness) } == 0 {
+        Ok(())
+    }
+    else {
+        Err(std::io::Error::last_os_error())
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_niceness(_niceness: i32) -> std::io::Result<()> {
+    Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "thread priorities are only supported on Linux"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_threads() {
+        let logger = Logger::root(slog::Discard, slog::o!());
+        let threads = ThreadConfig::new();
+        assert_eq!(threads.name("127.0.0.1:9000", "listener"), "127.0.0.1:9000 - gbps listener");
+
+        let threads = threads.with_name_prefix("gossip-".to_owned()).with_stack_size(256 * 1024);
+        let handle = threads.spawn("127.0.0.1:9000", "receiver", &Arc::default(), logger, || {
+            assert_eq!(std::thread::current().name(), Some("gossip-receiver"));
+        }).unwrap();
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn runs_tasks_on_executor() {
+        let logger = Logger::root(slog::Discard, slog::o!());
+        let (names, spawned) = std::sync::mpsc::channel();
+        let names = std::sync::Mutex::new(names);
+        let executor = move |name: String, task: Box<dyn FnOnce() + Send>| {
+            names.lock().unwrap().send(name).unwrap();
+            std::thread::spawn(task);
+        };
+        let threads = ThreadConfig::new().with_executor(Arc::new(executor));
+        threads.spawn("127.0.0.1:9000", "listener", &Arc::default(), logger.clone(), || ()).unwrap().join().unwrap();
+        assert_eq!(spawned.recv().unwrap(), "127.0.0.1:9000 - gbps listener");
+        assert!(threads.spawn("127.0.0.1:9000", "receiver", &Arc::default(), logger, || panic!("failed")).unwrap().join().is_err());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn sets_niceness_of_the_thread_only() {
+        let logger = Logger::root(slog::Discard, slog::o!());
+        let priority = || unsafe { libc::getpriority(libc::PRIO_PROCESS, libc::syscall(libc::SYS_gettid) as libc::id_t) };
+        let own_priority = priority();
+        let niceness = (own_priority + 5).min(19);
+        let threads = ThreadConfig::new().with_niceness(niceness);
+        let handle = threads.spawn("127.0.0.1:9000", "sampling", &Arc::default(), logger, move || assert_eq!(priority(), niceness)).unwrap();
+        handle.join().unwrap();
+        assert_eq!(priority(), own_priority);
+    }
+}
@@ -0,0 +1,89 @@
+use std::collections::{HashMap, VecDeque};
+
+use crate::peer::Peer;
+
+/// Tracks the peers returned to the application over a sliding window, for checking
+/// that the samples are uniformly distributed over the view
+pub(crate) struct UniformityTest {
+    /// Number of samples kept
+    window: usize,
+    /// Addresses of the last returned peers, oldest first
+    samples: VecDeque<String>,
+}
+
+impl UniformityTest {
+    /// Creates a test over the last samples
+    ///
+    /// # Arguments
+    ///
+    /// * `window` - Number of samples kept
+    pub(crate) fn new(window: usize) -> UniformityTest {
+        UniformityTest {
+            window,
+            samples: VecDeque::with_capacity(window),
+        }
+    }
+
+    /// Records a peer returned to the application
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - Address of the peer
+    pub(crate) fn record(&mut self, address: &str) {
+        if self.samples.len() == self.window {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(address.to_owned());
+    }
+
+    /// Returns the chi-square statistic of the samples of the peers of the view against a uniform
+    /// distribution, divided by its degrees of freedom: close to 1 for uniform samples and
+    /// much higher for biased ones. None while there are fewer samples than peers.
+    ///
+    /// # Arguments
+    ///
+    /// * `peers` - The view
+    pub(crate) fn score(&self, peers: &[Peer]) -> Option<f64> {
+        let mut counts = peers.iter().map(|peer| (peer.address(), 0usize)).collect::<HashMap<&str, usize>>();
+        if counts.len() < 2 {
+            return None;
+        }
+        let mut total = 0;
+        for address in &self.samples {
+            if let Some(count) = counts.get_mut(address.as_str()) {
+                *count += 1;
+                total += 1;
+            }
+        }
+        if total < counts.len() {
+            return None;
+        }
+        let expected = total as f64 / counts.len() as f64;
+        let chi_square = counts.values()
+            .map(|count| (*count as f64 - expected).powi(2) / expected)
+            .sum::<f64>();
+        Some(chi_square / (counts.len() - 1) as f64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn biased_samples_score_higher() {
+        let peers = (9001..=9004).map(|port| Peer::new(format!("127.0.0.1:{}", port))).collect::<Vec<Peer>>();
+        let mut test = UniformityTest::new(8);
+        test.record("127.0.0.1:9001");
+        assert!(test.score(&peers).is_none());
+
+        for _ in 0..2 {
+            peers.iter().for_each(|peer| test.record(peer.address()));
+        }
+        assert_eq!(test.score(&peers), Some(0.0));
+
+        // the window only keeps the last samples
+        (0..8).for_each(|_| test.record("127.0.0.1:9001"));
+        assert_eq!(test.score(&peers), Some(8.0));
+    }
+}
@@ -0,0 +1,834 @@
+use std::collections::{HashSet, VecDeque};
+use std::iter::FromIterator;
+use std::time::{Duration, Instant};
+
+use rand::Rng;
+use rand::seq::SliceRandom;
+
+use crate::peer::Peer;
+use crate::policy::{SampleSource, SamplingPolicy};
+use crate::quota::PrefixQuota;
+use crate::selection::Descriptor;
+use crate::uniformity::UniformityTest;
+
+/// The view at each node.
+///
+/// Contains the view selection algorithm of the paper, independently of networking,
+/// so that it can be validated and reused standalone. The methods involving randomness
+/// have a `_with` variant taking the random number generator, for deterministic runs.
+pub struct View {
+    /// The address of the node
+    host_address: String,
+    /// The other addresses the node listens to
+    aliases: Vec<String>,
+    /// The list of peers in the node view
+    peers: Vec<Peer>,
+    /// The queue from which peer are retrieved for the application layer
+    queue: VecDeque<Peer>,
+    /// Preserve the order of the view when removing duplicates, as in the paper
+    strict_selection: bool,
+    /// How the peers returned to the application layer are sampled
+    sampling_policy: SamplingPolicy,
+    /// Position in the view of the next peer returned in turn
+    next_index: usize,
+    /// The last peers returned to the application layer, if their uniformity is tested
+    uniformity: Option<UniformityTest>,
+    /// Maximum number of peers in the same network, if limited
+    prefix_quota: Option<PrefixQuota>,
+    /// The peers removed for exceeding the quota of their network at the last selection
+    over_quota: Vec<Peer>,
+    /// Maximum number of peers with the same locality, if limited
+    locality_quota: Option<usize>,
+    /// The peers removed for exceeding the quota of their locality at the last selection
+    over_locality_quota: Vec<Peer>,
+    /// When ages measure time, their unit and the instant up to which they were increased
+    age_clock: Option<(Duration, Instant)>,
+    /// Age of the node's own descriptor, increased with the ages of the peers
+    own_age: u16,
+}
+
+impl View {
+    /// Creates a new empty view with the node's address
+    ///
+    /// # Arguments
+    ///
+    /// * `host_address` - Address of the node
+    pub fn new(host_address: String) -> View {
+        View::with_peers(host_address, vec![])
+    }
+
+    /// Creates a new view with the node's address and an initial list of peers
+    ///
+    /// # Arguments
+    ///
+    /// * `host_address` - Address of the node
+    /// * `peers` - The initial peers of the view, in order
+    pub fn with_peers(host_address: String, peers: Vec<Peer>) -> View {
+        View {
+            host_address,
+            aliases: vec![],
+            peers,
+            queue: VecDeque::new(),
+            strict_selection: true,
+            sampling_policy: SamplingPolicy::Queue,
+            next_index: 0,
+            uniformity: None,
+            prefix_quota: None,
+            over_quota: vec![],
+            locality_quota: None,
+            over_locality_quota: vec![],
+            age_clock: None,
+            own_age: 0,
+        }
+    }
+
+    /// Enables or disables the strict view selection, see [crate::Config::with_strict_selection]
+    ///
+    /// # Arguments
+    ///
+    /// * `strict_selection` - Follow the view selection of the paper
+    pub fn set_strict_selection(&mut self, strict_selection: bool) {
+        self.strict_selection = strict_selection;
+    }
+
+    /// Limits the number of peers of the view in the same network, see [crate::Config::with_prefix_quota]
+    ///
+    /// # Arguments
+    ///
+    /// * `prefix_quota` - The quota, none for no limit
+    pub fn set_prefix_quota(&mut self, prefix_quota: Option<PrefixQuota>) {
+        self.prefix_quota = prefix_quota;
+    }
+
+    /// Limits the number of peers of the view with the same locality, see [crate::Config::with_locality_diversity]
+    ///
+    /// # Arguments
+    ///
+    /// * `locality_quota` - The maximum number of peers per locality, none for no limit
+    pub fn set_locality_quota(&mut self, locality_quota: Option<usize>) {
+        self.locality_quota = locality_quota;
+    }
+
+    /// Sets how the peers returned to the application layer are sampled, see [crate::Config::with_sampling_policy]
+    ///
+    /// # Arguments
+    ///
+    /// * `sampling_policy` - The sampling policy
+    pub fn set_sampling_policy(&mut self, sampling_policy: SamplingPolicy) {
+        self.sampling_policy = sampling_policy;
+    }
+
+    /// Tests the uniformity of the peers returned to the application layer, see [crate::Config::with_uniformity_test]
+    ///
+    /// # Arguments
+    ///
+    /// * `window` - Number of samples kept, 0 for no test
+    pub(crate) fn set_uniformity_window(&mut self, window: usize) {
+        self.uniformity = if window > 0 { Some(UniformityTest::new(window)) } else { None };
+    }
+
+    /// Measures the ages in units of time instead of cycles, see [crate::Config::with_time_based_age]
+    ///
+    /// # Arguments
+    ///
+    /// * `unit` - The unit of the ages, none for counting cycles
+    pub(crate) fn set_age_unit(&mut self, unit: Option<Duration>) {
+        self.age_clock = unit.map(|unit| (unit, Instant::now()));
+    }
+
+    /// Returns the uniformity score of the last peers returned, see [crate::Stats::uniformity]
+    pub(crate) fn uniformity(&self) -> Option<f64> {
+        self.uniformity.as_ref().and_then(|test| test.score(&self.peers))
+    }
+
+    /// Replaces the address of the node, once the listener is bound
+    pub(crate) fn set_host_address(&mut self, host_address: String) {
+        self.host_address = host_address;
+    }
+
+    /// Sets the other addresses of the node, which are omitted from the view like its address
+    pub(crate) fn set_aliases(&mut self, aliases: Vec<String>) {
+        self.aliases = aliases;
+    }
+
+    /// Checks if an address is one of the addresses of the node
+    pub(crate) fn is_host(&self, address: &str) -> bool {
+        address == self.host_address || self.aliases.iter().any(|alias| alias == address)
+    }
+
+    /// Returns the address of the node
+    pub fn host_address(&self) -> &str {
+        &self.host_address
+    }
+
+    /// Returns the peers of the view, in order
+    pub fn peers(&self) -> &[Peer] {
+        &self.peers
+    }
+
+    /// Returns the queue of peers not yet returned to the application layer
+    pub fn queue(&self) -> &VecDeque<Peer> {
+        &self.queue
+    }
+
+    /// Appends peers at the end of the view, without any selection.
+    /// The node's own address and the peers already in the view are skipped, unless of a later
+    /// incarnation replacing their descriptor, as well as the peers exceeding the quota of their network or of their locality.
+    ///
+    /// # Arguments
+    ///
+    /// * `peers` - The peers to be added
+    pub fn add_peers(&mut self, peers: Vec<Peer>) {
+        for peer in peers {
+            if let Some(entry) = self.peers.iter_mut().find(|entry| **entry == peer) {
+                if peer.incarnation() > entry.incarnation() {
+                    *entry = peer;
+                }
+            }
+            else if !self.is_host(peer.address()) {
+                self.peers.push(peer);
+                if self.prefix_quota.as_ref().is_some_and(|quota| !quota.excess(&self.peers).is_empty())
+                    || self.locality_quota.is_some_and(|quota| !Self::locality_excess(&self.peers, quota).is_empty()) {
+                    self.peers.pop();
+                }
+            }
+        }
+    }
+
+    /// Randomly select a peer for exchanging views at each cycle
+    pub fn select_peer(&self) -> Option<Peer> {
+        self.select_peer_with(&mut rand::thread_rng())
+    }
+
+    /// Randomly select a peer for exchanging views using the provided generator
+    pub fn select_peer_with<R: Rng>(&self, rng: &mut R) -> Option<Peer> {
+        if self.peers.is_empty() {
+            None
+        }
+        else {
+            let selected_peer = rng.gen_range(0..self.peers.len());
+            Some(self.peers[selected_peer].clone())
+        }
+    }
+
+    /// Randomly select a peer for exchanging views among the peers accepted by a filter
+    ///
+    /// # Arguments
+    ///
+    /// * `filter` - Returns true for the peers that can be selected
+    pub fn select_peer_filtered<F: FnMut(&Peer) -> bool>(&self, filter: F) -> Option<Peer> {
+        self.select_peer_filtered_with(filter, &mut rand::thread_rng())
+    }
+
+    /// Randomly select a peer among the peers accepted by a filter using the provided generator
+    pub fn select_peer_filtered_with<F: FnMut(&Peer) -> bool, R: Rng>(&self, mut filter: F, rng: &mut R) -> Option<Peer> {
+        let candidates = self.peers.iter().filter(|peer| filter(peer)).collect::<Vec<&Peer>>();
+        candidates.choose(rng).map(|peer| (*peer).clone())
+    }
+
+    /// Randomly reorder the current view
+    pub fn permute(&mut self) {
+        self.permute_with(&mut rand::thread_rng());
+    }
+
+    /// Randomly reorder the current view using the provided generator
+    pub fn permute_with<R: Rng>(&mut self, rng: &mut R) {
+        crate::selection::permute(&mut self.peers, rng);
+    }
+
+    /// Move the oldest peers to the end of the view if the size
+    /// of the view is larger than the healing factor
+    ///
+    /// # Arguments
+    ///
+    /// * `h` - The number of peer that should be moved
+    pub fn move_oldest_to_end(&mut self, h: usize) {
+        crate::selection::move_oldest_to_end(&mut self.peers, h);
+    }
+
+    /// Returns the peers at the beginning of the view
+    ///
+    /// # Arguments
+    ///
+    /// * `count` - The number of peers
+    pub fn head(&self, count: usize) -> Vec<Peer> {
+        let count = std::cmp::min(count, self.peers.len());
+        let mut head = Vec::new();
+        for i in 0..count {
+            head.push(self.peers[i].clone());
+        }
+        head
+    }
+
+    /// Increases by one the age of each peer in the view, or by the time elapsed
+    /// since the ages were last increased when they measure time
+    pub fn increase_age(&mut self) {
+        if self.age_clock.is_some() {
+            self.update_age(Instant::now());
+            return;
+        }
+        crate::selection::increase_age(&mut self.peers);
+        self.own_age = self.own_age.saturating_add(1);
+    }
+
+    /// Returns the age of the node's own descriptor, increased with the ages of the peers
+    /// since the node started, see [crate::SelfAgePolicy::Preserve]
+    pub fn own_age(&self) -> u16 {
+        self.own_age
+    }
+
+    /// Resets the age of the node's own descriptor, such as for a new incarnation
+    pub(crate) fn reset_own_age(&mut self) {
+        self.own_age = 0;
+    }
+
+    /// When ages measure time, increases the age of each peer by the whole units elapsed since
+    /// the ages were last increased, the remainder being counted at the next update
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - The current instant
+    pub(crate) fn update_age(&mut self, now: Instant) {
+        if let Some((unit, since)) = self.age_clock.as_mut() {
+            let units = (now.saturating_duration_since(*since).as_nanos() / unit.as_nanos().max(1)).min(u16::MAX as u128) as u16;
+            if units > 0 {
+                for peer in self.peers.iter_mut() {
+                    peer.add_age(units);
+                }
+                self.own_age = self.own_age.saturating_add(units);
+                *since += *unit * units as u32;
+            }
+        }
+    }
+
+    /// Merge a view received received from a peer with the current view
+    ///
+    /// # Arguments
+    ///
+    /// * `c` - The size of the view
+    /// * `h` - The healing parameter
+    /// * `s` - The swap parameter
+    /// * `buffer` - The view received
+    pub fn select(&mut self, c: usize, h: usize, s: usize, buffer: &[Peer]) {
+        self.select_with(c, h, s, buffer, &mut rand::thread_rng());
+    }
+
+    /// Merge a view received received from a peer with the current view,
+    /// using the provided generator for random removals
+    ///
+    /// # Arguments
+    ///
+    /// * `c` - The size of the view
+    /// * `h` - The healing parameter
+    /// * `s` - The swap parameter
+    /// * `buffer` - The view received
+    /// * `rng` - The random number generator
+    pub fn select_with<R: Rng>(&mut self, c: usize, h: usize, s: usize, buffer: &[Peer], rng: &mut R) {
+        // received ages are current, so are those of the view when they measure time
+        self.update_age(Instant::now());
+        self.merge(buffer);
+        self.trim_with(c, h, s, rng);
+        // Update peer queue for application layer
+        self.update_queue();
+        #[cfg(feature = "strict-invariants")]
+        self.check_invariants(c);
+    }
+
+    /// Asserts the invariants of the view after a merge in debug builds: the view does not contain
+    /// the node's own address nor duplicates, does not exceed its size, and contains the queue
+    ///
+    /// # Arguments
+    ///
+    /// * `c` - The size of the view
+    #[cfg(feature = "strict-invariants")]
+    fn check_invariants(&self, c: usize) {
+        debug_assert!(self.peers.iter().all(|peer| !self.is_host(peer.address())), "the view of {} contains its own address", self.host_address);
+        debug_assert!(self.peers.iter().enumerate().all(|(index, peer)| !self.peers[..index].contains(peer)),
+            "the view of {} contains duplicates: {:?}", self.host_address, self.peers);
+        debug_assert!(self.peers.len() <= c, "the view of {} has {} peers, more than {}", self.host_address, self.peers.len(), c);
+        debug_assert!(self.queue.iter().all(|peer| self.peers.contains(peer)),
+            "the queue of {} contains peers not in the view: {:?}", self.host_address, self.queue);
+    }
+
+    /// Adds received peers to the end of the current view, omitting the node's own address
+    ///
+    /// # Arguments
+    ///
+    /// * `buffer` - The view received
+    pub fn merge(&mut self, buffer: &[Peer]) {
+        for peer in buffer {
+            if !self.is_host(peer.address()) {
+                self.peers.push(peer.clone());
+            }
+        }
+    }
+
+    /// Reduces the view to its size parameter: removes duplicates, then the peers
+    /// exceeding the quota of their network or of their locality, then old items, then the head of the view, then peers at random
+    ///
+    /// # Arguments
+    ///
+    /// * `c` - The size of the view
+    /// * `h` - The healing parameter
+    /// * `s` - The swap parameter
+    pub fn trim(&mut self, c: usize, h: usize, s: usize) {
+        self.trim_with(c, h, s, &mut rand::thread_rng());
+    }
+
+    /// Reduces the view to its size parameter using the provided generator for random removals
+    ///
+    /// # Arguments
+    ///
+    /// * `c` - The size of the view
+    /// * `h` - The healing parameter
+    /// * `s` - The swap parameter
+    /// * `rng` - The random number generator
+    pub fn trim_with<R: Rng>(&mut self, c: usize, h: usize, s: usize, rng: &mut R) {
+        self.remove_duplicates();
+        self.remove_over_quota();
+        self.remove_old_items(c, h);
+        self.remove_head(c, s);
+        self.remove_at_random(c, rng);
+    }
+
+    /// Removes duplicates peers from the view and keep the most recent one,
+    /// of the latest incarnation and then the youngest. In strict mode the order of the view is preserved, the most recent
+    /// descriptor taking the position of the first occurrence of the peer.
+    fn remove_duplicates(&mut self) {
+        if !self.strict_selection {
+            self.remove_duplicates_unordered();
+            return;
+        }
+        crate::selection::remove_duplicates(&mut self.peers);
+    }
+
+    /// Removes duplicates peers from the view and keep the most recent one,
+    /// of the latest incarnation and then the youngest, without preserving the order of the view
+    fn remove_duplicates_unordered(&mut self) {
+        let mut unique_peers: HashSet<Peer> = HashSet::new();
+        self.peers.iter().for_each(|peer| {
+            if let Some(entry) = unique_peers.get(peer) {
+                // duplicate peer, check incarnation and age
+                if peer.is_fresher_than(entry) {
+                    unique_peers.replace(peer.clone());
+                }
+            }
+            else {
+                // unique peer
+                unique_peers.insert(peer.clone());
+            }
+        });
+        let new_view = Vec::from_iter(unique_peers);
+        let _ = std::mem::replace(&mut self.peers, new_view);
+    }
+
+    /// Removes the oldest peers of the networks and of the localities having more peers than their quota
+    fn remove_over_quota(&mut self) {
+        self.over_quota.clear();
+        if let Some(quota) = &self.prefix_quota {
+            for index in quota.excess(&self.peers).into_iter().rev() {
+                self.over_quota.push(self.peers.remove(index));
+            }
+        }
+        self.over_locality_quota.clear();
+        if let Some(quota) = self.locality_quota {
+            for index in Self::locality_excess(&self.peers, quota).into_iter().rev() {
+                self.over_locality_quota.push(self.peers.remove(index));
+            }
+        }
+    }
+
+    /// Returns the positions of the peers exceeding the quota of their locality, the peers without locality not being limited
+    ///
+    /// # Arguments
+    ///
+    /// * `peers` - The peers of a view
+    /// * `quota` - The maximum number of peers per locality
+    fn locality_excess(peers: &[Peer], quota: usize) -> Vec<usize> {
+        crate::quota::excess_by(peers, quota, |peer| peer.locality().map(str::to_owned))
+    }
+
+    /// Returns the peers removed for exceeding the quota of their network at the last selection
+    pub(crate) fn over_quota(&self) -> &[Peer] {
+        &self.over_quota
+    }
+
+    /// Returns the peers removed for exceeding the quota of their locality at the last selection
+    pub(crate) fn over_locality_quota(&self) -> &[Peer] {
+        &self.over_locality_quota
+    }
+
+    /// Removes the oldest items from the view based on the healing parameter
+    ///
+    /// # Arguments
+    ///
+    /// * `c` - The size of the view
+    /// * `h` - The healing parameter
+    fn remove_old_items(&mut self, c: usize, h: usize) {
+        crate::selection::remove_old_items(&mut self.peers, c, h);
+    }
+
+    /// Removes peers at the beginning of the current view based on the swap parameter
+    ///
+    /// # Arguments
+    ///
+    /// * `c` - The size of the view
+    /// * `s` - The swap parameter
+    fn remove_head(&mut self, c: usize, s: usize) {
+        crate::selection::remove_head(&mut self.peers, c, s);
+    }
+
+    /// Removes peers at random to match the view size parameter
+    ///
+    /// # Arguments
+    ///
+    /// * `c` - The size of the view
+    /// * `rng` - The random number generator
+    fn remove_at_random<R: Rng>(&mut self, c: usize, rng: &mut R) {
+        crate::selection::remove_at_random(&mut self.peers, c, rng);
+    }
+
+    /// Update peer queue by adding peers that appeared in the view
+    /// and removing those that were removed.
+    fn update_queue(&mut self) {
+
+        // compute index of removed peers
+        let removed_peers = self.queue.iter().enumerate()
+            .filter(|(_, peer)| !self.peers.contains(peer))
+            .map(|(index, _)| index)
+            .collect::<Vec<usize>>();
+
+        // compute new peers
+        let added_peers = self.peers.iter()
+            .filter(|peer| !self.queue.contains(peer))
+            .map(|peer| peer.to_owned())
+            .collect::<Vec<Peer>>();
+
+        // removed old peers by descending index
+        removed_peers.iter().rev().for_each(|index| { self.queue.remove(*index); });
+
+        // add new peers
+        for peer in added_peers {
+            self.queue.push_back(peer);
+        }
+    }
+
+    /// Replaces the view and the queue, such as with those of an exported state
+    ///
+    /// # Arguments
+    ///
+    /// * `peers` - The view, in order
+    /// * `queue` - The peers not yet returned to the application layer
+    pub(crate) fn restore(&mut self, peers: Vec<Peer>, queue: Vec<Peer>) {
+        self.peers = peers;
+        self.queue = queue.into_iter().collect();
+    }
+
+    /// Removes a peer from the view and from the queue
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - Address of the peer to be removed
+    pub fn remove_peer(&mut self, address: &str) {
+        self.peers.retain(|peer| peer.address() != address);
+        self.queue.retain(|peer| peer.address() != address);
+    }
+
+    /// Returns a random peer for use in the application layer, sampled according to the sampling policy.
+    /// By default, the peer is selected from the queue of newly added peers if available,
+    /// otherwise at random from the view.
+    pub fn get_peer(&mut self) -> Option<Peer> {
+        self.sample_peer(&[]).map(|(peer, _)| peer)
+    }
+
+    /// Returns a random peer for use in the application layer, other than the excluded peers,
+    /// such as the partners already contacted during a round of the application
+    ///
+    /// # Arguments
+    ///
+    /// * `excluded` - The peers not to be returned
+    pub fn get_peer_excluding(&mut self, excluded: &[Peer]) -> Option<Peer> {
+        self.sample_peer(excluded).map(|(peer, _)| peer)
+    }
+
+    /// Returns a random peer for use in the application layer with the given locality,
+    /// such as a partner in the same zone for bulk transfers, see [crate::Config::with_locality]
+    ///
+    /// # Arguments
+    ///
+    /// * `locality` - The locality of the peer
+    pub fn get_peer_in(&mut self, locality: &str) -> Option<Peer> {
+        self.sample_peer_filtered(|peer| peer.locality() == Some(locality)).map(|(peer, _)| peer)
+    }
+
+    /// Returns a random peer for use in the application layer with a locality other than the given one,
+    /// such as a partner in another zone for resilience. The peers without locality are not returned.
+    ///
+    /// # Arguments
+    ///
+    /// * `locality` - The locality the peer is not in
+    pub fn get_peer_outside(&mut self, locality: &str) -> Option<Peer> {
+        self.sample_peer_filtered(|peer| peer.locality().is_some_and(|other| other != locality)).map(|(peer, _)| peer)
+    }
+
+    /// Returns a peer for use in the application layer, sampled according to the sampling policy,
+    /// along with how it was sampled
+    ///
+    /// # Arguments
+    ///
+    /// * `excluded` - The peers not to be returned
+    pub fn sample_peer(&mut self, excluded: &[Peer]) -> Option<(Peer, SampleSource)> {
+        self.sample_peer_filtered(|peer| !excluded.contains(peer))
+    }
+
+    /// Returns a peer accepted by a filter, sampled according to the sampling policy
+    ///
+    /// # Arguments
+    ///
+    /// * `filter` - Returns whether a peer may be returned
+    fn sample_peer_filtered<F: Fn(&Peer) -> bool>(&mut self, filter: F) -> Option<(Peer, SampleSource)> {
+        let sample = self.sample_peer_with_policy(filter);
+        if let (Some((peer, _)), Some(test)) = (sample.as_ref(), self.uniformity.as_mut()) {
+            test.record(peer.address());
+        }
+        sample
+    }
+
+    fn sample_peer_with_policy<F: Fn(&Peer) -> bool>(&mut self, filter: F) -> Option<(Peer, SampleSource)> {
+        match self.sampling_policy {
+            SamplingPolicy::Queue => match self.queue.iter().position(&filter) {
+                Some(index) => self.queue.remove(index).map(|peer| (peer, SampleSource::Queue)),
+                None => self.select_peer_filtered(filter).map(|peer| (peer, SampleSource::Random)),
+            },
+            SamplingPolicy::Uniform => self.select_peer_filtered(filter).map(|peer| (peer, SampleSource::Random)),
+            SamplingPolicy::RoundRobin => {
+                let count = self.peers.len();
+                let offset = (0..count).find(|offset| filter(&self.peers[(self.next_index + offset) % count]))?;
+                let index = (self.next_index + offset) % count;
+                self.next_index = index + 1;
+                Some((self.peers[index].clone(), SampleSource::RoundRobin))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    fn peer(port: u16, age: u16) -> Peer {
+        Peer::with_age(format!("127.0.0.1:{}", port), age)
+    }
+
+    fn addresses_and_ages(peers: &[Peer]) -> Vec<(String, u16)> {
+        peers.iter().map(|peer| (peer.address().to_owned(), peer.age())).collect()
+    }
+
+    #[test]
+    fn select_follows_paper() {
+        let mut view = View::with_peers("127.0.0.1:9000".to_owned(),
+            vec![peer(9001, 5), peer(9002, 1), peer(9003, 3), peer(9004, 0), peer(9005, 7), peer(9006, 2)]);
+        let buffer = vec![peer(9000, 0), peer(9002, 0), peer(9007, 0)];
+
+        view.select(5, 1, 1, &buffer);
+
+        // own address ignored, duplicate 9002 refreshed in place,
+        // oldest 9005 removed by healing, head 9001 removed by swapping
+        let expected = vec![peer(9002, 0), peer(9003, 3), peer(9004, 0), peer(9006, 2), peer(9007, 0)];
+        assert_eq!(addresses_and_ages(view.peers()), addresses_and_ages(&expected));
+        assert_eq!(view.queue().len(), 5);
+    }
+
+    #[test]
+    fn select_removal_counts_are_bounded_by_excess() {
+        let mut view = View::with_peers("127.0.0.1:9000".to_owned(), vec![peer(9001, 9), peer(9002, 8), peer(9003, 1)]);
+
+        // one peer in excess: only the oldest is removed by healing, swapping removes nothing
+        view.select(3, 2, 2, &[peer(9004, 0)]);
+        let expected = vec![peer(9002, 8), peer(9003, 1), peer(9004, 0)];
+        assert_eq!(addresses_and_ages(view.peers()), addresses_and_ages(&expected));
+
+        // no healing: the head is swapped out
+        view.select(3, 0, 2, &[peer(9005, 0)]);
+        let expected = vec![peer(9003, 1), peer(9004, 0), peer(9005, 0)];
+        assert_eq!(addresses_and_ages(view.peers()), addresses_and_ages(&expected));
+
+        // neither healing nor swapping: removal at random down to the view size
+        view.select(3, 0, 0, &[peer(9006, 0), peer(9007, 0)]);
+        assert_eq!(view.peers().len(), 3);
+    }
+
+    #[cfg(all(feature = "strict-invariants", debug_assertions))]
+    #[test]
+    #[should_panic(expected = "contains duplicates")]
+    fn duplicates_break_invariants() {
+        let mut view = View::with_peers("127.0.0.1:9000".to_owned(), vec![peer(9001, 0), peer(9002, 0)]);
+        view.check_invariants(2);
+        view.select(3, 1, 1, &[peer(9003, 0), peer(9001, 1), peer(9000, 0)]);
+        view.peers.push(peer(9002, 4));
+        view.check_invariants(20);
+    }
+
+    #[test]
+    fn seeded_runs_are_deterministic() {
+        let run = || {
+            let mut rng = StdRng::seed_from_u64(7);
+            let mut view = View::with_peers("127.0.0.1:9000".to_owned(), (1..=10).map(|i| peer(9000 + i, i)).collect());
+            view.permute_with(&mut rng);
+            view.select_with(4, 0, 0, &[peer(9020, 0), peer(9021, 0)], &mut rng);
+            addresses_and_ages(view.peers())
+        };
+        assert_eq!(run(), run());
+    }
+
+    #[test]
+    fn filtered_selection_skips_excluded_peers() {
+        let view = View::with_peers("127.0.0.1:9000".to_owned(), vec![peer(9001, 0), peer(9002, 0), peer(9003, 0)]);
+        for _ in 0..20 {
+            let selected = view.select_peer_filtered(|peer| peer.address() != "127.0.0.1:9002").unwrap();
+            assert_ne!(selected.address(), "127.0.0.1:9002");
+        }
+        assert!(view.select_peer_filtered(|_| false).is_none());
+    }
+
+    #[test]
+    fn excluded_peers_are_not_returned() {
+        let mut view = View::new("127.0.0.1:9000".to_owned());
+        view.select(20, 2, 8, &[peer(9001, 0), peer(9002, 0), peer(9003, 0)]);
+        let excluded = [peer(9001, 0), peer(9003, 0)];
+        // from the queue, then at random from the view
+        for _ in 0..5 {
+            assert_eq!(view.get_peer_excluding(&excluded), Some(peer(9002, 0)));
+        }
+        assert_eq!(view.queue().len(), 2);
+        assert_eq!(view.get_peer_excluding(&[peer(9001, 0), peer(9002, 0), peer(9003, 0)]), None);
+    }
+
+    #[test]
+    fn sampling_follows_policy() {
+        let peers = vec![peer(9001, 0), peer(9002, 0), peer(9003, 0)];
+        let mut view = View::new("127.0.0.1:9000".to_owned());
+        view.select(20, 2, 8, &peers);
+        assert_eq!(view.sample_peer(&[]), Some((peer(9001, 0), SampleSource::Queue)));
+        assert_eq!(view.sample_peer(&[peer(9002, 0)]), Some((peer(9003, 0), SampleSource::Queue)));
+        assert_eq!(view.sample_peer(&[]), Some((peer(9002, 0), SampleSource::Queue)));
+        assert_eq!(view.sample_peer(&[]).unwrap().1, SampleSource::Random);
+
+        view.set_sampling_policy(SamplingPolicy::Uniform);
+        view.select(20, 2, 8, &[peer(9004, 0)]);
+        let queued = view.queue().len();
+        assert_eq!(view.sample_peer(&[]).unwrap().1, SampleSource::Random);
+        assert_eq!(view.queue().len(), queued);
+
+        view.set_sampling_policy(SamplingPolicy::RoundRobin);
+        let returned = (0..5).map(|_| view.sample_peer(&[peer(9002, 0)]).unwrap()).collect::<Vec<(Peer, SampleSource)>>();
+        let addresses = returned.iter().map(|(peer, _)| peer.address().to_owned()).collect::<Vec<String>>();
+        let expected = view.peers().iter().filter(|peer| peer.address() != "127.0.0.1:9002").map(|peer| peer.address().to_owned()).collect::<Vec<String>>();
+        assert_eq!(&addresses[..3], &expected[..]);
+        assert_eq!(&addresses[3..], &expected[..2]);
+        assert!(returned.iter().all(|(_, source)| *source == SampleSource::RoundRobin));
+    }
+
+    #[test]
+    fn networks_are_limited_to_their_quota() {
+        let mut view = View::new("127.0.0.1:9000".to_owned());
+        view.set_prefix_quota(Some(PrefixQuota::new(2)));
+        view.add_peers(vec![peer(9001, 0), peer(9002, 0), peer(9003, 0), Peer::new("10.0.0.1:9000".to_owned())]);
+        assert_eq!(view.peers().len(), 3);
+        view.increase_age();
+
+        // the freshest peers of the network are kept
+        view.select(20, 2, 8, &[peer(9004, 0), peer(9005, 1), Peer::new("10.0.0.2:9000".to_owned())]);
+        view.select(20, 2, 8, &[peer(9001, 5)]);
+        let addresses = view.peers().iter().filter(|peer| peer.address().starts_with("127.")).count();
+        assert_eq!(addresses, 2);
+        assert!(view.peers().contains(&peer(9004, 0)));
+        assert_eq!(view.peers().len(), 4);
+    }
+
+    #[test]
+    fn added_peers_are_not_duplicated() {
+        let mut view = View::with_peers("127.0.0.1:9000".to_owned(), vec![peer(9001, 3)]);
+        view.add_peers(vec![peer(9000, 0), peer(9001, 0), peer(9002, 0), peer(9002, 1)]);
+        assert_eq!(addresses_and_ages(view.peers()), addresses_and_ages(&[peer(9001, 3), peer(9002, 0)]));
+    }
+
+    #[test]
+    fn later_incarnations_are_preferred() {
+        for strict in [true, false] {
+            let mut view = View::with_peers("127.0.0.1:9000".to_owned(), vec![peer(9001, 0).with_incarnation(1)]);
+            view.set_strict_selection(strict);
+            // the restarted node, although older, replaces its previous life
+            view.select(20, 2, 8, &[peer(9001, 4).with_incarnation(2), peer(9002, 0)]);
+            view.select(20, 2, 8, &[peer(9001, 0).with_incarnation(1)]);
+            view.add_peers(vec![peer(9001, 0)]);
+            let restarted = view.peers().iter().find(|entry| entry.address() == "127.0.0.1:9001").unwrap();
+            assert_eq!((restarted.incarnation(), restarted.age()), (2, 4));
+
+            view.add_peers(vec![peer(9001, 6).with_incarnation(3)]);
+            assert_eq!(view.peers().iter().find(|entry| entry.address() == "127.0.0.1:9001").unwrap().incarnation(), 3);
+        }
+    }
+
+    #[test]
+    fn ages_measure_time() {
+        let mut view = View::with_peers("127.0.0.1:9000".to_owned(), vec![peer(9001, 0), peer(9002, 5)]);
+        let start = Instant::now();
+        view.set_age_unit(Some(Duration::from_millis(10)));
+        // independent of the cycles
+        view.increase_age();
+        assert_eq!(addresses_and_ages(view.peers()), addresses_and_ages(&[peer(9001, 0), peer(9002, 5)]));
+        view.update_age(start + Duration::from_millis(35));
+        assert_eq!(addresses_and_ages(view.peers()), addresses_and_ages(&[peer(9001, 3), peer(9002, 8)]));
+        // the remainder is counted at the next update
+        view.update_age(start + Duration::from_millis(45));
+        assert_eq!(addresses_and_ages(view.peers()), addresses_and_ages(&[peer(9001, 4), peer(9002, 9)]));
+        // the node's own descriptor ages with the peers
+        assert_eq!(view.own_age(), 4);
+    }
+
+    #[test]
+    fn localities_are_limited_to_their_quota() {
+        let located = |port: u16, age: u16, locality: &str| peer(port, age).with_locality(locality.to_owned());
+        let mut view = View::new("127.0.0.1:9000".to_owned());
+        view.set_locality_quota(Some(2));
+        view.add_peers(vec![located(9001, 0, "eu"), located(9002, 0, "eu"), located(9003, 0, "eu"), peer(9004, 0), peer(9005, 0), peer(9006, 0)]);
+        assert_eq!(view.peers().len(), 5);
+
+        // the freshest peers of the locality are kept, the peers without locality are not limited
+        view.select(20, 2, 8, &[located(9007, 0, "eu"), located(9008, 0, "us"), peer(9009, 0)]);
+        view.increase_age();
+        view.select(20, 2, 8, &[located(9010, 0, "eu")]);
+        let localities = view.peers().iter().filter(|peer| peer.locality() == Some("eu")).count();
+        assert_eq!(localities, 2);
+        assert!(view.peers().contains(&peer(9010, 0)));
+        assert_eq!(view.over_locality_quota().len(), 1);
+        assert_eq!(view.peers().len(), 7);
+    }
+
+    #[test]
+    fn peers_are_sampled_by_locality() {
+        let located = |port: u16, locality: &str| peer(port, 0).with_locality(locality.to_owned());
+        for policy in [SamplingPolicy::Queue, SamplingPolicy::Uniform, SamplingPolicy::RoundRobin] {
+            let mut view = View::new("127.0.0.1:9000".to_owned());
+            view.set_sampling_policy(policy);
+            view.select(20, 2, 8, &[located(9001, "eu"), located(9002, "us"), located(9003, "eu"), peer(9004, 0)]);
+            for _ in 0..10 {
+                assert_eq!(view.get_peer_in("eu").unwrap().locality(), Some("eu"));
+                assert_eq!(view.get_peer_outside("eu").unwrap().address(), "127.0.0.1:9002");
+            }
+            assert!(view.get_peer_in("ap").is_none());
+        }
+    }
+
+    #[test]
+    fn aliases_of_the_node_are_omitted() {
+        let mut view = View::new("127.0.0.1:9000".to_owned());
+        view.set_aliases(vec!["127.0.0.1:9001".to_owned()]);
+        view.add_peers(vec![peer(9001, 0), peer(9002, 0)]);
+        view.merge(&[peer(9000, 0), peer(9001, 0), peer(9003, 0)]);
+        assert_eq!(addresses_and_ages(view.peers()), addresses_and_ages(&[peer(9002, 0), peer(9003, 0)]));
+    }
+}
@@ -1,10 +1,9 @@
 use gbps::terminal_logger;
-use slog::{o, Logger};
 
-// logger for integration tests
-struct IntegrationTestLogger;
-
-static LOGGER: IntegrationTestLogger = IntegrationTestLogger;
+// configuration of a node listening on a port assigned by the system, with the parameters of the paper
+fn local_config(sampling_period: u64) -> gbps::Config {
+    gbps::Config::new("127.0.0.1:0".parse().unwrap(), true, true, sampling_period, 0, 20, 2, 8, None)
+}
 
 #[test]
 fn peer_sampling_smoke_test() {
@@ -19,7 +18,7 @@ fn peer_sampling_smoke_test() {
     let d = 5;
     let c = 4;
     let h = 1;
-    let s = 2;
+    let s = 1;
 
     let monitoring_config = MonitoringConfig::new(true, "http://127.0.0.1:8080/peers", logger.clone());
 
@@ -27,50 +26,46 @@ fn peer_sampling_smoke_test() {
     let mut instances = vec![];
 
     // create first peer with no contact peer
-    let init_address = "127.0.0.1:9000";
-    // configuration
-    let first_config = Config::new(init_address.parse().unwrap(), push, pull, t, d, c, h, s, Some(monitoring_config.clone()));
+    // configuration, the first peer is contacted by all others and limits its load
+    let first_config = Config::new("127.0.0.1:0".parse().unwrap(), push, pull, t, d, c, h, s, Some(monitoring_config.clone()))
+        .with_indegree_cap(2)
+        .with_max_requests_per_second(5);
     // no contact peer for first node
     let no_peer_handler = Box::new(move|| { None });
 
     // create and initiate the peer sampling service
     let mut service = PeerSamplingService::new(first_config, logger.clone());
-    service.init(no_peer_handler);
+    service.init(no_peer_handler).unwrap();
+    let init_address = service.local_address().to_string();
     instances.push(service);
 
     // create peers using IPv4 addresses
-    let mut port = 9001;
     for _ in 1..peers_per_protocol {
-        // peer socket address
-        let address = format!("127.0.0.1:{}", port);
         // configuration
-        let config = Config::new(address.parse().unwrap(), push, pull, t, d, c, h, s, Some(monitoring_config.clone()));
+        let config = Config::new("127.0.0.1:0".parse().unwrap(), push, pull, t, d, c, h, s, Some(monitoring_config.clone()));
         // closure for retrieving the address of the first contact peer
-        let init_handler = Box::new(move|| { Some(vec![Peer::new(init_address.to_owned())]) });
+        let contact = init_address.clone();
+        let init_handler = Box::new(move|| { Some(vec![Peer::new(contact.clone())]) });
 
         // create and initiate the peer sampling service
         let mut ipv4_service = PeerSamplingService::new(config, logger.clone());
-        ipv4_service.init(init_handler);
+        ipv4_service.init(init_handler).unwrap();
         instances.push(ipv4_service);
-
-        port += 1;
     }
 
-    // create peers using IPv6 addresses
+    // create peers using IPv6 addresses, with replay protection
     for _ in 1..peers_per_protocol {
-        // peer socket address
-        let address = format!("[::1]:{}", port);
         // configuration
-        let config = Config::new(address.parse().unwrap(), push, pull, t, d, c, h, s, Some(monitoring_config.clone()));
+        let config = Config::new("[::1]:0".parse().unwrap(), push, pull, t, d, c, h, s, Some(monitoring_config.clone()))
+            .with_message_ttl(60);
         // closure for retrieving the address of the first contact peer
-        let init_handler = Box::new(move|| { Some(vec![Peer::new(init_address.to_owned())]) });
+        let contact = init_address.clone();
+        let init_handler = Box::new(move|| { Some(vec![Peer::new(contact.clone())]) });
 
         // create and initiate the peer sampling service
         let mut ipv6_service = PeerSamplingService::new(config, logger.clone());
-        ipv6_service.init(init_handler);
+        ipv6_service.init(init_handler).unwrap();
         instances.push(ipv6_service);
-
-        port += 1;
     }
 
     std::thread::sleep(std::time::Duration::from_secs(11));
@@ -84,19 +79,1574 @@ fn peer_sampling_smoke_test() {
 
 #[test]
 fn does_shutdown() {
-    use gbps::{Config, PeerSamplingService};
+    use gbps::PeerSamplingService;
 
     let logger = terminal_logger();
 
     // configuration
-    let config = Config::new("127.0.0.1:9000".parse().unwrap(), true, true, 1, 0, 20, 2, 8, None);
+    let config = local_config(1);
     // closure for retrieving the address of the first contact peer
     let init_handler = Box::new(move|| { None });
 
     // create and initiate the peer sampling service
     let mut service = PeerSamplingService::new(config, logger.clone());
-    service.init(init_handler);
+    service.init(init_handler).unwrap();
+
+    std::thread::sleep(std::time::Duration::from_secs(3));
+    service.shutdown().unwrap();
+}
+#[test]
+fn answers_ping() {
+    use gbps::{PeerSamplingService, Peer};
+
+    let logger = terminal_logger();
+
+    let mut first = PeerSamplingService::new(local_config(1), logger.clone());
+    first.init(Box::new(move|| { None })).unwrap();
+    let mut second = PeerSamplingService::new(local_config(1), logger.clone());
+    second.init(Box::new(move|| { None })).unwrap();
+
+    let rtt = second.ping(&Peer::new(first.local_address().to_string()), std::time::Duration::from_secs(2));
+    assert!(rtt.is_ok());
+    // no node listening
+    let dead_address = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().to_string();
+    assert!(second.ping(&Peer::new(dead_address), std::time::Duration::from_secs(2)).is_err());
+
+    first.shutdown().unwrap();
+    second.shutdown().unwrap();
+}
+
+#[test]
+fn answers_ping_on_its_connection() {
+    use gbps::{PeerSamplingService, Peer};
+
+    let logger = terminal_logger();
+
+    let config = || local_config(60).with_direct_answers(true);
+    let mut first = PeerSamplingService::new(config(), logger.clone());
+    first.init(Box::new(move|| { None })).unwrap();
+    let mut second = PeerSamplingService::new(config(), logger.clone());
+    second.init(Box::new(move|| { None })).unwrap();
+    // the second node no longer accepts connections
+    second.shutdown().unwrap();
+
+    assert!(second.ping(&Peer::new(first.local_address().to_string()), std::time::Duration::from_secs(2)).is_ok());
+
+    first.shutdown().unwrap();
+}
+
+#[test]
+fn learns_external_address_from_stun() {
+    use gbps::{PeerSamplingService};
+
+    let logger = terminal_logger();
+
+    // answers the binding requests with the mapped address of a gateway whose address changes
+    let server = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+    let stun_server = server.local_addr().unwrap().to_string();
+    std::thread::spawn(move || {
+        let mut request = [0u8; 20];
+        for gateway in 7u8.. {
+            let (_, client) = match server.recv_from(&mut request) { Ok(received) => received, Err(_) => return };
+            let mut response = vec![0x01, 0x01, 0, 12];
+            response.extend_from_slice(&request[4..20]);
+            response.extend_from_slice(&[0, 1, 0, 8, 0, 1, 0x23, 0x28, 203, 0, 113, gateway]);
+            let _ = server.send_to(&response, client);
+        }
+    });
+
+    let config = local_config(60)
+        .with_stun_servers(vec![stun_server], 1);
+    let mut service = PeerSamplingService::new(config, logger.clone());
+    service.init(Box::new(move|| { None })).unwrap();
+    // the port of the node is advertised with the learned IP address
+    let port = service.local_address().port();
+    assert_eq!(service.external_address(), Some(std::net::SocketAddr::new([203, 0, 113, 7].into(), port)));
+
+    std::thread::sleep(std::time::Duration::from_millis(1500));
+    assert_eq!(service.external_address(), Some(std::net::SocketAddr::new([203, 0, 113, 8].into(), port)));
+
+    service.shutdown().unwrap();
+}
+
+#[test]
+fn exchanges_through_relay() {
+    use gbps::{Message, MessageType, PeerSampler, PeerSamplingService, Peer};
+
+    let logger = terminal_logger();
+
+    let mut relay = PeerSamplingService::new(local_config(60)
+        .with_relaying(4096, 100), logger.clone());
+    relay.init(Box::new(move|| { None })).unwrap();
+    let relay_address = relay.local_address();
+
+    // a peer that does not accept connections, played by the test through the relay
+    let unreachable = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().to_string();
+    let config = local_config(60)
+        .with_relay(relay_address, 50);
+    let mut node = PeerSamplingService::new(config, logger);
+    let contact = unreachable.clone();
+    node.init(Box::new(move|| { Some(vec![Peer::new(contact.clone())]) })).unwrap();
+    node.trigger_exchange().unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(200));
+
+    // the request of the node is held by the relay until polled
+    let poll = |sender: &str| {
+        let mut stream = std::net::TcpStream::connect(relay_address).unwrap();
+        std::io::Write::write_all(&mut stream, &Message::new_poll(sender.to_owned()).as_bytes()).unwrap();
+        stream.shutdown(std::net::Shutdown::Write).unwrap();
+        let mut bytes = vec![];
+        std::io::Read::read_to_end(&mut stream, &mut bytes).unwrap();
+        bytes
+    };
+    let bytes = poll(&unreachable);
+    let relayed = Message::from_bytes(&bytes).unwrap();
+    assert_eq!(relayed.sender(), node.local_address().to_string());
+    let request = Message::from_bytes(relayed.relayed().unwrap().1).unwrap();
+    assert_eq!(*request.message_type(), MessageType::Request);
+    assert!(poll(&unreachable).is_empty());
+
+    // the response sent through the relay is merged by the node
+    let response = Message::new_response(unreachable.clone(), Some(vec![Peer::new("10.0.0.1:9000".to_owned())])).in_reply_to(request.nonce());
+    let relayed = Message::new_relay(unreachable.clone(), &node.local_address().to_string(), &response).unwrap();
+    let mut stream = std::net::TcpStream::connect(relay_address).unwrap();
+    std::io::Write::write_all(&mut stream, &relayed.as_bytes()).unwrap();
+    drop(stream);
+    std::thread::sleep(std::time::Duration::from_millis(500));
+    assert!(node.view_snapshot().contains(&Peer::new("10.0.0.1:9000".to_owned())));
+
+    node.shutdown().unwrap();
+    relay.shutdown().unwrap();
+}
+
+#[test]
+fn sends_heartbeats_to_recent_partners() {
+    use std::io::Read;
+    use gbps::{Config, Message, PeerSamplingService, Peer};
+
+    let logger = terminal_logger();
+
+    // a partner recording the types of the messages it receives
+    let partner = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let partner_address = partner.local_addr().unwrap().to_string();
+    let received = std::thread::spawn(move || {
+        let mut types = vec![];
+        for stream in partner.incoming().take(3) {
+            let mut bytes = vec![];
+            stream.unwrap().read_to_end(&mut bytes).unwrap();
+            types.push(format!("{:?}", Message::from_bytes(&bytes).unwrap().message_type()));
+        }
+        types
+    });
+
+    // push only, the partner not answering
+    let config = Config::new("127.0.0.1:0".parse().unwrap(), true, false, 60, 0, 20, 2, 8, None)
+        .with_heartbeat(200, 4, 1000);
+    let mut node = PeerSamplingService::new(config, logger);
+    let contact = partner_address.clone();
+    node.init(Box::new(move|| { Some(vec![Peer::new(contact.clone())]) })).unwrap();
+    node.trigger_exchange().unwrap();
+
+    assert_eq!(received.join().unwrap(), vec!["Request", "Ping", "Ping"]);
+
+    node.shutdown().unwrap();
+}
+
+#[test]
+fn tracks_last_seen_peers() {
+    use gbps::{PeerSamplingService, Peer};
+
+    let logger = terminal_logger();
+
+    let mut first = PeerSamplingService::new(local_config(1), logger.clone());
+    first.init(Box::new(move|| { None })).unwrap();
+    let first_address = first.local_address().to_string();
+    let mut second = PeerSamplingService::new(local_config(1), logger.clone());
+    let contact = first_address.clone();
+    second.init(Box::new(move|| { Some(vec![Peer::new(contact.clone())]) })).unwrap();
+    let second_address = second.local_address().to_string();
+
+    std::thread::sleep(std::time::Duration::from_secs(3));
+    let seen = first.last_seen();
+    assert!(seen.get(&second_address).unwrap().elapsed().unwrap() < std::time::Duration::from_secs(2));
+    assert!(!seen.contains_key(&first_address));
+    assert_eq!(first.get_recent_peer(std::time::Duration::from_secs(2)).unwrap().address(), second_address);
+    assert!(second.handle().last_seen().contains_key(&first_address));
+
+    second.shutdown().unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(500));
+    assert!(first.last_seen().is_empty());
+
+    first.shutdown().unwrap();
+}
+
+#[test]
+fn returns_peers_not_suspected_dead() {
+    use gbps::{PeerSampler, PeerSamplingService, Peer};
+
+    let logger = terminal_logger();
+
+    // no node listening
+    let dead = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().to_string();
+    let config = local_config(60)
+        .with_send_backoff(60_000, 60_000);
+    let mut node = PeerSamplingService::new(config, logger);
+    let contact = dead.clone();
+    node.init(Box::new(move|| { Some(vec![Peer::new(contact.clone())]) })).unwrap();
+    node.trigger_exchange().unwrap();
+    assert_eq!(node.view_snapshot(), vec![Peer::new(dead.clone())]);
+    assert_eq!(node.get_alive_peer(), None);
+
+    node.add_peer_hint(Peer::new("127.0.0.1:9".to_owned()));
+    for _ in 0..10 {
+        assert_eq!(node.get_alive_peer().unwrap().address(), "127.0.0.1:9");
+    }
+
+    node.shutdown().unwrap();
+}
+
+#[test]
+fn removes_leaving_peer() {
+    use gbps::{PeerSamplingService, Peer};
+
+    let logger = terminal_logger();
+
+    let mut first = PeerSamplingService::new(local_config(1), logger.clone());
+    first.init(Box::new(move|| { None })).unwrap();
+    let first_address = first.local_address().to_string();
+    let mut second = PeerSamplingService::new(local_config(1), logger.clone());
+    second.init(Box::new(move|| { Some(vec![Peer::new(first_address.clone())]) })).unwrap();
+
+    std::thread::sleep(std::time::Duration::from_secs(3));
+    assert!(first.get_peer().is_some());
+
+    second.shutdown().unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(500));
+    assert!(first.get_peer().is_none());
+
+    first.shutdown().unwrap();
+}
+
+#[test]
+fn ignores_departure_of_another_address() {
+    use gbps::{Message, PeerSampler, PeerSamplingService, Peer};
+    use std::io::Write;
+
+    let logger = terminal_logger();
+
+    let config = local_config(60)
+        .with_exchange_timeout(500);
+    let mut node = PeerSamplingService::new(config, logger);
+    // a local peer, and a third party on another host
+    let (local, remote) = (Peer::new("127.0.0.1:9".to_owned()), Peer::new("192.0.2.1:9000".to_owned()));
+    let peers = vec![local.clone(), remote.clone()];
+    node.init(Box::new(move|| { Some(peers.clone()) })).unwrap();
+    let leave = |sender: &str| {
+        let mut stream = std::net::TcpStream::connect(node.local_address()).unwrap();
+        stream.write_all(&Message::new_leave(sender.to_owned()).as_bytes()).unwrap();
+    };
+
+    // sent from the loopback interface, naming the third party
+    leave(remote.address());
+    std::thread::sleep(std::time::Duration::from_millis(300));
+    assert!(node.view_snapshot().contains(&remote));
+
+    leave(local.address());
+    std::thread::sleep(std::time::Duration::from_millis(300));
+    assert!(!node.view_snapshot().contains(&local));
+    assert!(node.view_snapshot().contains(&remote));
+
+    node.shutdown().unwrap();
+}
+
+#[cfg(feature = "noise")]
+#[test]
+fn exchanges_over_noise() {
+    use gbps::{NoiseConfig, PeerSamplingService, Peer};
+
+    let logger = terminal_logger();
+
+    let first_config = local_config(1)
+        .with_noise(NoiseConfig::generate().unwrap());
+    let mut first = PeerSamplingService::new(first_config, logger.clone());
+    first.init(Box::new(move|| { None })).unwrap();
+    let first_address = first.local_address().to_string();
+    let second_config = local_config(1)
+        .with_noise(NoiseConfig::generate().unwrap());
+    let mut second = PeerSamplingService::new(second_config, logger.clone());
+    second.init(Box::new(move|| { Some(vec![Peer::new(first_address.clone())]) })).unwrap();
 
     std::thread::sleep(std::time::Duration::from_secs(3));
+    assert_eq!(first.get_peer().unwrap().address(), second.local_address().to_string());
+
+    second.shutdown().unwrap();
+    first.shutdown().unwrap();
+}
+
+#[cfg(feature = "impairment")]
+#[test]
+fn exchanges_over_impaired_network() {
+    use gbps::{Impairment, PeerSampler, PeerSamplingService, Peer};
+
+    let logger = terminal_logger();
+
+    let mut first = PeerSamplingService::new(local_config(60), logger.clone());
+    first.init(Box::new(move|| { None })).unwrap();
+    let first_address = first.local_address().to_string();
+
+    // the request is delayed, and the exchange completes
+    let config = local_config(60)
+        .with_impairment(Impairment::new().with_latency(100).with_jitter(20).with_seed(1));
+    let mut second = PeerSamplingService::new(config, logger.clone());
+    let contact = first_address.clone();
+    second.init(Box::new(move|| { Some(vec![Peer::new(contact.clone())]) })).unwrap();
+    let start = std::time::Instant::now();
+    second.trigger_exchange().unwrap();
+    assert!(start.elapsed() >= std::time::Duration::from_millis(100));
+    std::thread::sleep(std::time::Duration::from_millis(200));
+    assert!(first.view_snapshot().contains(&Peer::new(second.local_address().to_string())));
+
+    // every message is lost
+    let config = local_config(60)
+        .with_impairment(Impairment::new().with_loss(1.0));
+    let mut third = PeerSamplingService::new(config, logger);
+    third.init(Box::new(move|| { Some(vec![Peer::new(first_address.clone())]) })).unwrap();
+    third.trigger_exchange().unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(200));
+    assert!(!first.view_snapshot().contains(&Peer::new(third.local_address().to_string())));
+
+    third.shutdown().unwrap();
+    second.shutdown().unwrap();
+    first.shutdown().unwrap();
+}
+
+#[cfg(feature = "chaos")]
+#[test]
+fn reconverges_after_chaos() {
+    use gbps::{Chaos, Cluster, Config, Fault, Impairment};
+    use std::time::Duration;
+
+    let logger = terminal_logger();
+
+    let mut chaos = Chaos::new(42).with_fault_rate(0.8).with_intensity(0.5);
+    // the impairment of a node is kept, and shares the faults injected into the node
+    let second = Impairment::new();
+    // views smaller than the cluster, so that healing evicts the addresses garbled by corruption
+    let config = Config::new("127.0.0.1:0".parse().unwrap(), true, true, 1, 0, 4, 1, 1, None);
+    let cluster = Cluster::start_with(5, config, logger, |index, config| {
+        let config = if index == 1 { config.with_impairment(second.clone()) } else { config };
+        chaos.configure(index, config)
+    }).unwrap();
+    cluster.await_overlay(Duration::from_secs(15)).unwrap();
+
+    // a dropped link fails the exchanges between the two nodes
+    chaos.inject(&cluster, Fault::DropLink { node: 1, other: 2 }, 1).unwrap();
+    assert_eq!(second.blocked_links(), vec![cluster.nodes()[2].local_address()]);
+    assert!(chaos.inject(&cluster, Fault::Pause { node: 5 }, 1).is_err());
+
+    let faults = chaos.run(&cluster, 8, Duration::from_millis(500)).unwrap();
+    assert!(!faults.is_empty());
+    assert!(chaos.active().is_empty());
+    assert!(second.blocked_links().is_empty());
+
+    cluster.await_overlay(Duration::from_secs(20)).unwrap();
+    cluster.stop().unwrap();
+}
+
+#[test]
+fn notifies_view_updates() {
+    use gbps::{PeerSampler, PeerSamplingService, Peer};
+
+    let logger = terminal_logger();
+
+    let mut first = PeerSamplingService::new(local_config(1), logger.clone());
+    let updates = first.subscribe();
+    first.init(Box::new(move|| { None })).unwrap();
+    let first_address = first.local_address().to_string();
+    let mut second = PeerSamplingService::new(local_config(1), logger.clone());
+    second.init(Box::new(move|| { Some(vec![Peer::new(first_address.clone())]) })).unwrap();
+
+    let update = updates.recv_timeout(std::time::Duration::from_secs(3)).unwrap();
+    assert_eq!(update, vec![Peer::new(second.local_address().to_string())]);
+    assert_eq!(first.view_snapshot(), update);
+
+    second.shutdown().unwrap();
+    first.shutdown().unwrap();
+}
+
+#[test]
+fn suspects_partition() {
+    use gbps::{Event, PeerSamplingService, Peer};
+
+    let logger = terminal_logger();
+
+    // the contact peers are not running
+    let mut contacts = (0..2)
+        .map(|_| std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().to_string())
+        .collect::<Vec<String>>();
+    contacts.sort();
+    let config = local_config(1)
+        .with_partition_detection(3);
+    let mut service = PeerSamplingService::new(config, logger.clone());
+    let events = service.subscribe_events();
+    let peers = contacts.iter().map(|contact| Peer::new(contact.clone())).collect::<Vec<Peer>>();
+    service.init(Box::new(move|| { Some(peers.clone()) })).unwrap();
+
+    match events.recv_timeout(std::time::Duration::from_secs(12)).unwrap() {
+        Event::PartitionSuspected { mut unreachable } => {
+            unreachable.sort();
+            assert_eq!(unreachable, contacts);
+        },
+        event => panic!("unexpected event {:?}", event),
+    }
+
+    service.shutdown().unwrap();
+}
+
+#[test]
+fn runs_synchronized_rounds() {
+    use gbps::{Cluster, Peer, RecordFormat, RecordingConfig};
+
+    let logger = terminal_logger();
+
+    let data_file = std::env::temp_dir().join(format!("gbps-rounds-{}.csv", std::process::id()));
+    let _ = std::fs::remove_file(&data_file);
+    let config = local_config(1)
+        .with_synchronized_rounds(true);
+    let cluster = Cluster::start_with(3, config, logger, |index, config| {
+        if index == 1 {
+            config.with_recording(RecordingConfig::new(&data_file, RecordFormat::Csv))
+        }
+        else {
+            config
+        }
+    }).unwrap();
+    let services = cluster.nodes();
+    let addresses = cluster.addresses();
+
+    // no cycle without rounds
+    std::thread::sleep(std::time::Duration::from_millis(2500));
+    assert!(cluster.views()[0].is_empty());
+
+    for _ in 0..3 {
+        cluster.step().unwrap();
+    }
+    std::thread::sleep(std::time::Duration::from_millis(200));
+    let views = cluster.views();
+    assert_eq!(views[0].len(), 2);
+    assert!(views[1].contains(&Peer::new(addresses[2].clone())));
+
+    // the first node is in the views of the others
+    assert_eq!(services[0].stats().cycles(), 3);
+    assert!(services[0].stats().self_occurrences() > 0);
+    assert!(services[0].stats().indegree() > 0.0);
+    // the received requests waited for the handling thread and were merged
+    assert!(services[0].stats().queue_latency().count() > 0);
+    assert!(services[0].stats().merge_duration().count() > 0);
+
+    // header and one sample per round
+    let samples = std::fs::read_to_string(&data_file).unwrap();
+    assert_eq!(samples.lines().count(), 4);
+    assert!(samples.lines().nth(1).unwrap().contains(&format!(",{},{},response,", addresses[1], addresses[0])));
+    std::fs::remove_file(&data_file).unwrap();
+
+    cluster.stop().unwrap();
+}
+
+#[test]
+fn removes_peers_with_low_score() {
+    use gbps::{PeerSampler, PeerSamplingService, Peer};
+
+    let logger = terminal_logger();
+
+    let mut live = PeerSamplingService::new(local_config(1).with_synchronized_rounds(true), logger.clone());
+    live.init(Box::new(move|| { None })).unwrap();
+    let live_address = live.local_address().to_string();
+    let dead_address = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().to_string();
+    let config = local_config(1)
+        .with_synchronized_rounds(true)
+        .with_score_threshold(0.5);
+    let mut service = PeerSamplingService::new(config, logger.clone());
+    let peers = vec![Peer::new(live_address.clone()), Peer::new(dead_address.clone())];
+    service.init(Box::new(move|| { Some(peers.clone()) })).unwrap();
+
+    // until the dead peer was contacted often enough for its score to be significant
+    for _ in 0..30 {
+        service.run_round().unwrap();
+        let scores = service.scores();
+        if scores.contains_key(&live_address) && scores.get(&dead_address).map(|score| score.failures() >= 3).unwrap_or(false) {
+            break;
+        }
+    }
+    let scores = service.scores();
+    assert!(scores[&dead_address].score() < 0.5);
+    assert!(scores[&live_address].score() > 0.5);
+    assert_eq!(service.view_snapshot(), vec![Peer::new(live_address)]);
+
+    service.shutdown().unwrap();
+    live.shutdown().unwrap();
+}
+
+#[test]
+fn quarantines_malformed_messages() {
+    use std::io::Write;
+    use gbps::{Event, PeerSamplingService};
+
+    let logger = terminal_logger();
+
+    let config = local_config(1)
+        .with_quarantine(2, 60);
+    let mut service = PeerSamplingService::new(config, logger.clone());
+    let events = service.subscribe_events();
+    service.init(Box::new(move|| { None })).unwrap();
+
+    for _ in 0..2 {
+        let mut stream = std::net::TcpStream::connect(service.local_address()).unwrap();
+        stream.write_all(&[0x80, 0xff, 0xff]).unwrap();
+    }
+
+    match events.recv_timeout(std::time::Duration::from_secs(5)).unwrap() {
+        Event::PeerQuarantined { peer, violations } => {
+            assert_eq!(peer, "127.0.0.1");
+            assert_eq!(violations, 2);
+        },
+        event => panic!("unexpected event {:?}", event),
+    }
+
+    service.shutdown().unwrap();
+}
+
+#[test]
+fn ignores_unsolicited_responses() {
+    use std::io::Write;
+    use gbps::{Message, PeerSampler, PeerSamplingService, Peer, RejectionReason};
+
+    let logger = terminal_logger();
+
+    let config = local_config(60)
+        .with_audit(8);
+    let mut service = PeerSamplingService::new(config, logger.clone());
+    service.init(Box::new(move|| { None })).unwrap();
+
+    // a response pushed without request is not merged
+    let response = Message::new_response("127.0.0.1:9999".to_owned(), Some(vec![Peer::new("10.0.0.1:9000".to_owned())]));
+    let mut stream = std::net::TcpStream::connect(service.local_address()).unwrap();
+    stream.write_all(&response.as_bytes()).unwrap();
+    drop(stream);
+    std::thread::sleep(std::time::Duration::from_millis(200));
+
+    assert!(service.view_snapshot().is_empty());
+    assert_eq!(service.rejections().iter().map(|rejection| rejection.reason()).collect::<Vec<RejectionReason>>(), vec![RejectionReason::UnsolicitedResponse]);
+
+    // the response to a request echoes its nonce
+    let contact = service.local_address().to_string();
+    let config = local_config(60)
+        .with_strict_responses(true);
+    let mut node = PeerSamplingService::new(config, logger);
+    let bootstrap_address = contact.clone();
+    node.init(Box::new(move|| { Some(vec![Peer::new(bootstrap_address.clone())]) })).unwrap();
+    node.trigger_exchange().unwrap();
+    assert_eq!(node.view_snapshot(), vec![Peer::new(contact)]);
+
+    node.shutdown().unwrap();
+    service.shutdown().unwrap();
+}
+
+#[test]
+fn rejects_oversized_messages() {
+    use std::io::Write;
+    use gbps::{Event, Message, Peer, PeerSampler, PeerSamplingService};
+
+    let logger = terminal_logger();
+
+    let config = local_config(1)
+        .with_max_frame_size(256)
+        .with_quarantine(1, 60);
+    let mut service = PeerSamplingService::new(config, logger.clone());
+    let events = service.subscribe_events();
+    service.init(Box::new(move|| { None })).unwrap();
+
+    // a valid request with a buffer larger than the frame size
+    let buffer = (0..50).map(|i| Peer::new(format!("127.0.0.1:{}", 10000 + i))).collect();
+    let mut stream = std::net::TcpStream::connect(service.local_address()).unwrap();
+    stream.write_all(&Message::new_request("127.0.0.1:9".to_owned(), Some(buffer)).as_bytes()).unwrap();
+    drop(stream);
+
+    match events.recv_timeout(std::time::Duration::from_secs(5)).unwrap() {
+        Event::PeerQuarantined { peer, .. } => assert_eq!(peer, "127.0.0.1"),
+        event => panic!("unexpected event {:?}", event),
+    }
+    assert!(service.view_snapshot().is_empty());
+
     service.shutdown().unwrap();
-}
\ No newline at end of file
+}
+
+#[test]
+fn stops_attempting_peers_that_time_out() {
+    use gbps::{Config, PeerSamplingService, Peer};
+
+    let logger = terminal_logger();
+
+    // a peer accepting connections without ever answering
+    let unresponsive = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let unresponsive_address = unresponsive.local_addr().unwrap().to_string();
+
+    let config = Config::new("127.0.0.1:0".parse().unwrap(), false, true, 1, 0, 20, 2, 8, None)
+        .with_synchronized_rounds(true)
+        .with_exchange_timeout(200)
+        .with_circuit_breaker(2, 60);
+    let mut service = PeerSamplingService::new(config, logger.clone());
+    service.init(Box::new(move|| { Some(vec![Peer::new(unresponsive_address.clone())]) })).unwrap();
+
+    for _ in 0..4 {
+        service.run_round().unwrap();
+    }
+
+    // only the first two rounds attempted the peer
+    unresponsive.set_nonblocking(true).unwrap();
+    let attempts = std::iter::from_fn(|| unresponsive.accept().ok()).count();
+    assert_eq!(attempts, 2);
+
+    service.shutdown().unwrap();
+}
+
+// runs three nodes bootstrapped in a ring, and checks that every node learns the two others
+fn spreads_addresses(push: bool, pull: bool) {
+    use gbps::{Config, PeerSampler, PeerSamplingService, Peer};
+
+    let logger = terminal_logger();
+
+    let mut services = vec![];
+    for _ in 0..3 {
+        let config = Config::new("127.0.0.1:0".parse().unwrap(), push, pull, 1, 0, 20, 2, 8, None)
+            .with_synchronized_rounds(true);
+        let mut service = PeerSamplingService::new(config, logger.clone());
+        service.init(Box::new(move|| { None })).unwrap();
+        services.push(service);
+    }
+    let addresses = services.iter().map(|service| service.local_address().to_string()).collect::<Vec<String>>();
+    for (index, service) in services.iter().enumerate() {
+        service.add_peer_hint(Peer::new(addresses[(index + 1) % 3].clone()));
+    }
+
+    for _ in 0..6 {
+        PeerSamplingService::run_synchronized_round(&services).unwrap();
+    }
+    // pushed buffers are merged after the round of the sender completes
+    std::thread::sleep(std::time::Duration::from_millis(200));
+
+    for (service, address) in services.iter_mut().zip(addresses.iter()) {
+        let mut known = service.view_snapshot().iter().map(|peer| peer.address().to_owned()).collect::<Vec<String>>();
+        known.sort();
+        let mut others = addresses.iter().filter(|other| *other != address).cloned().collect::<Vec<String>>();
+        others.sort();
+        assert_eq!(known, others);
+        // pushing without waiting for an answer is not a failure
+        assert!(service.scores().values().all(|score| score.failures() == 0));
+    }
+
+    for mut service in services {
+        service.shutdown().unwrap();
+    }
+}
+
+#[test]
+fn spreads_addresses_with_push_only() {
+    spreads_addresses(true, false);
+}
+
+#[test]
+fn spreads_addresses_with_pull_only() {
+    spreads_addresses(false, true);
+}
+
+#[test]
+fn spreads_addresses_with_push_pull() {
+    spreads_addresses(true, true);
+}
+
+#[test]
+fn recovers_from_saved_view() {
+    use gbps::{PeerSampler, PeerSamplingService, Peer};
+
+    let logger = terminal_logger();
+
+    let snapshot = std::env::temp_dir().join(format!("gbps-view-{}.peers", std::process::id()));
+    let _ = std::fs::remove_file(&snapshot);
+
+    let mut contact = PeerSamplingService::new(local_config(1)
+        .with_synchronized_rounds(true), logger.clone());
+    contact.init(Box::new(move|| { None })).unwrap();
+    let contact_address = contact.local_address().to_string();
+
+    // the node saves its view, then restarts with a bootstrap source that has no peers
+    let config = local_config(1)
+        .with_synchronized_rounds(true)
+        .with_view_snapshot(&snapshot);
+    let mut node = PeerSamplingService::new(config.clone(), logger.clone());
+    let bootstrap_address = contact_address.clone();
+    node.init(Box::new(move|| { Some(vec![Peer::new(bootstrap_address.clone())]) })).unwrap();
+    node.run_round().unwrap();
+    node.shutdown().unwrap();
+    assert_eq!(std::fs::read_to_string(&snapshot).unwrap(), format!("{}\n", contact_address));
+
+    let mut node = PeerSamplingService::new(config, logger.clone());
+    node.init(Box::new(move|| { None })).unwrap();
+    assert_eq!(node.view_snapshot(), vec![Peer::new(contact_address.clone())]);
+
+    // the contact learns the restarted node, whose view is then emptied by the departure of the contact
+    node.run_round().unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(200));
+    contact.shutdown().unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(200));
+    assert!(node.view_snapshot().is_empty());
+    // and recovered at the next cycle
+    node.run_round().unwrap();
+    assert_eq!(node.view_snapshot(), vec![Peer::new(contact_address)]);
+
+    node.shutdown().unwrap();
+    std::fs::remove_file(&snapshot).unwrap();
+}
+
+#[test]
+fn falls_back_to_recent_senders() {
+    use gbps::{Config, PeerSampler, PeerSamplingService, Peer};
+
+    let logger = terminal_logger();
+
+    // the only peer known to the node is not running
+    let dead_address = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().to_string();
+    let config = local_config(1)
+        .with_synchronized_rounds(true)
+        .with_recent_senders(4);
+    let mut node = PeerSamplingService::new(config, logger.clone());
+    node.init(Box::new(move|| { Some(vec![Peer::new(dead_address.clone())]) })).unwrap();
+    let node_address = node.local_address().to_string();
+
+    // a pull-only peer contacts the node without pushing its address
+    let config = Config::new("127.0.0.1:0".parse().unwrap(), false, true, 1, 0, 20, 2, 8, None)
+        .with_synchronized_rounds(true);
+    let mut sender = PeerSamplingService::new(config, logger.clone());
+    sender.init(Box::new(move|| { Some(vec![Peer::new(node_address.clone())]) })).unwrap();
+    let sender_address = sender.local_address().to_string();
+    sender.run_round().unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(200));
+    assert!(node.view_snapshot().iter().all(|peer| peer.address() != sender_address));
+
+    // the failed exchange makes the node try the recent sender
+    node.run_round().unwrap();
+    assert!(node.view_snapshot().iter().any(|peer| peer.address() == sender_address));
+
+    sender.shutdown().unwrap();
+    node.shutdown().unwrap();
+}
+
+#[test]
+fn avoids_last_partners() {
+    use gbps::{PeerSamplingService, Peer, RecordFormat, RecordingConfig};
+
+    let logger = terminal_logger();
+
+    let mut partners = vec![];
+    for _ in 0..2 {
+        let config = local_config(1)
+            .with_synchronized_rounds(true);
+        let mut partner = PeerSamplingService::new(config, logger.clone());
+        partner.init(Box::new(move|| { None })).unwrap();
+        partners.push(partner);
+    }
+
+    let data_file = std::env::temp_dir().join(format!("gbps-partners-{}.csv", std::process::id()));
+    let config = local_config(1)
+        .with_synchronized_rounds(true)
+        .with_partner_history(1)
+        .with_recording(RecordingConfig::new(&data_file, RecordFormat::Csv));
+    let mut node = PeerSamplingService::new(config, logger.clone());
+    let peers = partners.iter().map(|partner| Peer::new(partner.local_address().to_string())).collect::<Vec<Peer>>();
+    node.init(Box::new(move|| { Some(peers.clone()) })).unwrap();
+    for _ in 0..6 {
+        node.run_round().unwrap();
+    }
+    node.shutdown().unwrap();
+
+    // with two peers, the partners alternate
+    let data = std::fs::read_to_string(&data_file).unwrap();
+    let selected = data.lines().skip(1).map(|line| line.split(',').nth(3).unwrap().to_owned()).collect::<Vec<String>>();
+    assert_eq!(selected.len(), 6);
+    assert!(selected.windows(2).all(|pair| pair[0] != pair[1]));
+
+    for mut partner in partners {
+        partner.shutdown().unwrap();
+    }
+    std::fs::remove_file(&data_file).unwrap();
+}
+
+#[test]
+fn triggers_cycles_from_application() {
+    use gbps::{Cluster, CycleSchedule, Peer};
+
+    let logger = terminal_logger();
+
+    let config = local_config(1)
+        .with_schedule(CycleSchedule::Triggered);
+    let cluster = Cluster::start(2, config, logger).unwrap();
+
+    // no cycle is performed until triggered
+    std::thread::sleep(std::time::Duration::from_millis(1500));
+    assert!(cluster.views()[0].is_empty());
+
+    cluster.nodes()[1].trigger_cycle().unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(300));
+    assert_eq!(cluster.views()[0], vec![Peer::new(cluster.addresses()[1].clone())]);
+
+    cluster.stop().unwrap();
+}
+
+#[test]
+fn exchanges_on_demand() {
+    use gbps::{PeerSampler, PeerSamplingService, Peer};
+
+    let logger = terminal_logger();
+
+    // periodic cycles too far apart to happen during the test
+    let mut contact = PeerSamplingService::new(local_config(60), logger.clone());
+    contact.init(Box::new(move|| { None })).unwrap();
+    let contact_address = contact.local_address().to_string();
+    let mut node = PeerSamplingService::new(local_config(60), logger.clone());
+    let bootstrap_address = contact_address.clone();
+    node.init(Box::new(move|| { Some(vec![Peer::new(bootstrap_address.clone())]) })).unwrap();
+    let node_address = node.local_address().to_string();
+
+    assert_eq!(node.trigger_exchange().unwrap(), Some(contact_address));
+    std::thread::sleep(std::time::Duration::from_millis(200));
+    assert_eq!(contact.view_snapshot(), vec![Peer::new(node_address.clone())]);
+    assert_eq!(contact.trigger_exchange().unwrap(), Some(node_address));
+
+    node.shutdown().unwrap();
+    contact.shutdown().unwrap();
+}
+
+#[test]
+fn warms_up_at_shorter_period() {
+    use gbps::{PeerSamplingService, RecordFormat, RecordingConfig};
+
+    let logger = terminal_logger();
+
+    let data_file = std::env::temp_dir().join(format!("gbps-warm-up-{}.csv", std::process::id()));
+    let config = local_config(60)
+        .with_warm_up(3, 100)
+        .with_recording(RecordingConfig::new(&data_file, RecordFormat::Csv));
+    let mut node = PeerSamplingService::new(config, logger.clone());
+    node.init(Box::new(move|| { None })).unwrap();
+
+    // three warm-up cycles, then the normal period
+    std::thread::sleep(std::time::Duration::from_millis(1000));
+    node.shutdown().unwrap();
+    let data = std::fs::read_to_string(&data_file).unwrap();
+    assert_eq!(data.lines().count(), 1 + 3);
+
+    std::fs::remove_file(&data_file).unwrap();
+}
+
+#[test]
+fn binds_to_assigned_port() {
+    use gbps::{PeerSampler, PeerSamplingService, Peer};
+
+    let logger = terminal_logger();
+
+    let config = local_config(60);
+    let mut contact = PeerSamplingService::new(config, logger.clone());
+    contact.init(Box::new(move|| { None })).unwrap();
+    let contact_address = contact.local_address();
+    assert_ne!(contact_address.port(), 0);
+
+    let config = local_config(60);
+    let mut node = PeerSamplingService::new(config, logger.clone());
+    node.init(Box::new(move|| { Some(vec![Peer::new(contact_address.to_string())]) })).unwrap();
+
+    // the contact learns the address actually bound by the node
+    assert_eq!(node.trigger_exchange().unwrap(), Some(contact_address.to_string()));
+    std::thread::sleep(std::time::Duration::from_millis(200));
+    assert_eq!(contact.view_snapshot(), vec![Peer::new(node.local_address().to_string())]);
+
+    node.shutdown().unwrap();
+    contact.shutdown().unwrap();
+}
+
+#[test]
+fn reports_ports_in_use() {
+    use gbps::{BindError, Config, InitError, PeerSamplingService};
+
+    let logger = terminal_logger();
+
+    let mut node = PeerSamplingService::new(local_config(60), logger.clone());
+    node.init(Box::new(move|| { None })).unwrap();
+
+    // the address of the running node is in use
+    let mut other = PeerSamplingService::new(Config::new(node.local_address(), true, true, 60, 0, 20, 2, 8, None), logger.clone());
+    match other.init(Box::new(move|| { None })) {
+        Err(InitError::Bind(BindError::AddressInUse { address })) => assert_eq!(address, node.local_address()),
+        other => panic!("unexpected result {:?}", other),
+    }
+    // retried with another address
+    let mut other = PeerSamplingService::new(local_config(60), logger.clone());
+    other.init(Box::new(move|| { None })).unwrap();
+
+    other.shutdown().unwrap();
+    node.shutdown().unwrap();
+}
+
+#[test]
+fn supervises_tasks() {
+    use gbps::{PeerSamplingService, TaskExit};
+
+    let logger = terminal_logger();
+
+    let mut service = PeerSamplingService::new(local_config(60), logger);
+    let handle = service.init(Box::new(move|| { None })).unwrap();
+    assert_eq!(handle.running().len(), 3);
+    assert!(handle.is_running());
+
+    // stopped without the service
+    let supervisor = handle.clone();
+    let joined = std::thread::spawn(move || supervisor.join());
+    handle.abort();
+    let exits = joined.join().unwrap();
+    assert_eq!(exits.len(), 3);
+    assert!(exits.iter().all(|(_, exit)| *exit == TaskExit::Stopped));
+    assert!(handle.running().is_empty());
+
+    service.shutdown().unwrap();
+}
+
+#[test]
+fn listens_to_several_addresses() {
+    use gbps::{Config, PeerSampler, PeerSamplingService, Peer};
+
+    let logger = terminal_logger();
+
+    let config = local_config(60)
+        .with_additional_address("[::1]:0".parse().unwrap());
+    let mut node = PeerSamplingService::new(config, logger.clone());
+    node.init(Box::new(move|| { None })).unwrap();
+    let addresses = node.local_addresses();
+    assert!(addresses[0].is_ipv4() && addresses[1].is_ipv6());
+    let (ipv4_address, ipv6_address) = (addresses[0].to_string(), addresses[1].to_string());
+
+    // peers of each family contact the node on the address of their family
+    let config = local_config(60);
+    let mut ipv4_peer = PeerSamplingService::new(config, logger.clone());
+    let contact = ipv4_address.clone();
+    ipv4_peer.init(Box::new(move|| { Some(vec![Peer::new(contact.clone())]) })).unwrap();
+    let config = Config::new("[::1]:0".parse().unwrap(), true, true, 60, 0, 20, 2, 8, None);
+    let mut ipv6_peer = PeerSamplingService::new(config, logger.clone());
+    let contact = ipv6_address.clone();
+    ipv6_peer.init(Box::new(move|| { Some(vec![Peer::new(contact.clone())]) })).unwrap();
+
+    assert_eq!(ipv4_peer.trigger_exchange().unwrap(), Some(ipv4_address.clone()));
+    assert_eq!(ipv6_peer.trigger_exchange().unwrap(), Some(ipv6_address.clone()));
+    std::thread::sleep(std::time::Duration::from_millis(200));
+    let mut view = node.view_snapshot().iter().map(|peer| peer.address().to_owned()).collect::<Vec<String>>();
+    view.sort();
+    let mut expected = vec![ipv4_peer.local_address().to_string(), ipv6_peer.local_address().to_string()];
+    expected.sort();
+    assert_eq!(view, expected);
+
+    // the node answered the IPv6 peer with its IPv6 address
+    let view = ipv6_peer.view_snapshot();
+    assert!(view.contains(&Peer::new(ipv6_address)));
+    assert!(!view.contains(&Peer::new(ipv4_address)));
+
+    ipv6_peer.shutdown().unwrap();
+    ipv4_peer.shutdown().unwrap();
+    node.shutdown().unwrap();
+}
+
+#[test]
+fn keeps_recent_events() {
+    use gbps::{Event, PeerSamplingService, Peer};
+
+    let logger = terminal_logger();
+
+    let config = local_config(60)
+        .with_event_history(8);
+    let mut contact = PeerSamplingService::new(config, logger.clone());
+    contact.init(Box::new(move|| { None })).unwrap();
+    let contact_address = contact.local_address().to_string();
+    let config = local_config(60)
+        .with_event_history(8);
+    let mut node = PeerSamplingService::new(config, logger.clone());
+    let bootstrap_address = contact_address.clone();
+    node.init(Box::new(move|| { Some(vec![Peer::new(bootstrap_address.clone())]) })).unwrap();
+
+    node.trigger_exchange().unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(200));
+    let events = node.recent_events().into_iter().map(|(_, event)| event).collect::<Vec<Event>>();
+    assert_eq!(events, vec![Event::Exchanged { peer: contact_address, outcome: "response".to_owned() }]);
+    // the pushed buffer holds the node's descriptor and the contact
+    let events = contact.recent_events().into_iter().map(|(_, event)| event).collect::<Vec<Event>>();
+    assert_eq!(events, vec![Event::Merged { peer: node.local_address().to_string(), peers: 2 }]);
+
+    node.shutdown().unwrap();
+    contact.shutdown().unwrap();
+}
+
+#[test]
+fn shares_handle_between_threads() {
+    use gbps::{PeerSamplingService, Peer};
+
+    let logger = terminal_logger();
+
+    let config = local_config(60);
+    let mut contact = PeerSamplingService::new(config, logger.clone());
+    contact.init(Box::new(move|| { None })).unwrap();
+    let contact_address = contact.local_address().to_string();
+    let config = local_config(60);
+    let mut node = PeerSamplingService::new(config, logger.clone());
+    let bootstrap_address = contact_address.clone();
+    node.init(Box::new(move|| { Some(vec![Peer::new(bootstrap_address.clone())]) })).unwrap();
+
+    let handle = node.handle();
+    let updates = handle.subscribe();
+    let samplers = (0..4).map(|_| {
+        let handle = handle.clone();
+        std::thread::spawn(move || handle.get_peer())
+    }).collect::<Vec<_>>();
+    for sampler in samplers {
+        assert_eq!(sampler.join().unwrap(), Some(Peer::new(contact_address.clone())));
+    }
+
+    node.trigger_exchange().unwrap();
+    assert!(updates.recv_timeout(std::time::Duration::from_secs(1)).is_ok());
+    assert_eq!(handle.stats().cycles(), 1);
+
+    node.shutdown().unwrap();
+    contact.shutdown().unwrap();
+}
+
+#[test]
+fn observers_are_not_advertised() {
+    use gbps::{PeerSampler, PeerSamplingService, Peer};
+
+    let logger = terminal_logger();
+
+    let config = local_config(60);
+    let mut contact = PeerSamplingService::new(config, logger.clone());
+    contact.init(Box::new(move|| { None })).unwrap();
+    let contact_address = contact.local_address().to_string();
+    let config = local_config(60);
+    let mut node = PeerSamplingService::new(config, logger.clone());
+    let bootstrap_address = contact_address.clone();
+    node.init(Box::new(move|| { Some(vec![Peer::new(bootstrap_address.clone())]) })).unwrap();
+    node.trigger_exchange().unwrap();
+
+    let config = local_config(60)
+        .with_observer(true);
+    let mut observer = PeerSamplingService::new(config, logger.clone());
+    let bootstrap_address = contact_address.clone();
+    observer.init(Box::new(move|| { Some(vec![Peer::new(bootstrap_address.clone())]) })).unwrap();
+    observer.trigger_exchange().unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(200));
+
+    // the observer obtains the peers of the contact without being added to any view
+    let observer_address = observer.local_address().to_string();
+    assert!(observer.view_snapshot().iter().any(|peer| peer.address() == node.local_address().to_string()));
+    node.trigger_exchange().unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(200));
+    assert!(contact.view_snapshot().iter().all(|peer| peer.address() != observer_address));
+    assert!(node.view_snapshot().iter().all(|peer| peer.address() != observer_address));
+
+    observer.shutdown().unwrap();
+    node.shutdown().unwrap();
+    contact.shutdown().unwrap();
+}
+
+#[test]
+fn crawls_the_overlay() {
+    use gbps::{Cluster, Config, Crawler};
+
+    let logger = terminal_logger();
+
+    let config = local_config(60);
+    let cluster = Cluster::start(4, config, logger.clone()).unwrap();
+    let contact_address = cluster.addresses()[0].clone();
+    for node in cluster.nodes().iter().skip(1) {
+        node.trigger_exchange().unwrap();
+    }
+    std::thread::sleep(std::time::Duration::from_millis(200));
+
+    let config = Config::new("127.0.0.1:0".parse().unwrap(), false, true, 60, 0, 20, 2, 8, None);
+    let overlay = Crawler::new(config, logger.clone()).with_interval(10).with_pulls_per_node(2).crawl(&contact_address);
+    assert_eq!(overlay.node_count(), 4);
+    assert!(overlay.edge_count() >= 3);
+
+    cluster.stop().unwrap();
+}
+
+#[test]
+fn estimates_network_size() {
+    use gbps::Cluster;
+
+    let logger = terminal_logger();
+
+    let config = local_config(60)
+        .with_size_estimation(64, 3600);
+    let cluster = Cluster::start(5, config, logger).unwrap();
+
+    // the first node collects the minima of every node, then spreads them
+    for _ in 0..2 {
+        for node in cluster.nodes().iter().skip(1) {
+            node.trigger_exchange().unwrap();
+        }
+    }
+    for node in cluster.nodes() {
+        let estimate = node.estimated_network_size().unwrap();
+        assert!((2.5..10.0).contains(&estimate), "estimate {}", estimate);
+    }
+
+    cluster.stop().unwrap();
+}
+
+#[test]
+fn piggybacks_application_payloads() {
+    use gbps::{PeerSamplingService, Peer, Piggyback};
+    use std::sync::mpsc::Sender;
+
+    struct Version {
+        version: &'static str,
+        received: Sender<(String, Vec<u8>)>,
+    }
+
+    impl Piggyback for Version {
+        fn payload(&mut self, _partner: &str) -> Vec<u8> {
+            self.version.as_bytes().to_vec()
+        }
+
+        fn receive(&mut self, sender: &str, payload: &[u8]) {
+            self.received.send((sender.to_owned(), payload.to_vec())).unwrap();
+        }
+    }
+
+    let logger = terminal_logger();
+
+    let config = local_config(60)
+        .with_piggyback(16);
+    let mut contact = PeerSamplingService::new(config, logger.clone());
+    contact.init(Box::new(move|| { None })).unwrap();
+    let (contact_tx, contact_rx) = std::sync::mpsc::channel();
+    contact.set_piggyback(Box::new(Version { version: "v2", received: contact_tx }));
+    let contact_address = contact.local_address().to_string();
+
+    let config = local_config(60)
+        .with_piggyback(16);
+    let mut node = PeerSamplingService::new(config, logger.clone());
+    let bootstrap_address = contact_address.clone();
+    node.init(Box::new(move|| { Some(vec![Peer::new(bootstrap_address.clone())]) })).unwrap();
+    let (node_tx, node_rx) = std::sync::mpsc::channel();
+    node.set_piggyback(Box::new(Version { version: "v1", received: node_tx }));
+
+    node.trigger_exchange().unwrap();
+    let timeout = std::time::Duration::from_secs(1);
+    assert_eq!(contact_rx.recv_timeout(timeout).unwrap(), (node.local_address().to_string(), b"v1".to_vec()));
+    assert_eq!(node_rx.recv_timeout(timeout).unwrap(), (contact_address, b"v2".to_vec()));
+
+    node.shutdown().unwrap();
+    contact.shutdown().unwrap();
+}
+
+#[test]
+fn requires_proof_of_work_from_unknown_senders() {
+    use gbps::{Message, PeerSampler, PeerSamplingService, Peer};
+    use std::io::Write;
+
+    let logger = terminal_logger();
+
+    let config = local_config(60)
+        .with_proof_of_work(8);
+    let mut contact = PeerSamplingService::new(config, logger.clone());
+    contact.init(Box::new(move|| { None })).unwrap();
+    let contact_address = contact.local_address();
+
+    // a request pushing spoofed addresses without proof of work is ignored
+    let spoofed = vec![Peer::new("127.0.0.1:9661".to_owned()), Peer::new("127.0.0.1:9662".to_owned())];
+    let request = Message::new_request("127.0.0.1:9660".to_owned(), Some(spoofed));
+    std::net::TcpStream::connect(contact_address).unwrap().write_all(&request.as_bytes()).unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(200));
+    assert!(contact.view_snapshot().is_empty());
+
+    // a request sent in the name of a peer of the view, from another IP address, also needs a proof of work
+    let mut known = PeerSamplingService::new(local_config(60).with_proof_of_work(8), logger.clone());
+    let known_peer = Peer::new("192.0.2.1:9000".to_owned());
+    let peers = vec![known_peer.clone()];
+    known.init(Box::new(move|| { Some(peers.clone()) })).unwrap();
+    let request = Message::new_request(known_peer.address().to_owned(), Some(vec![Peer::new("127.0.0.1:9663".to_owned())]));
+    std::net::TcpStream::connect(known.local_address()).unwrap().write_all(&request.as_bytes()).unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(200));
+    assert_eq!(known.view_snapshot(), vec![known_peer]);
+    known.shutdown().unwrap();
+
+    // a node with the same difficulty proves its work
+    let config = local_config(60)
+        .with_proof_of_work(8);
+    let mut node = PeerSamplingService::new(config, logger.clone());
+    node.init(Box::new(move|| { Some(vec![Peer::new(contact_address.to_string())]) })).unwrap();
+    node.trigger_exchange().unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(200));
+    assert!(contact.view_snapshot().iter().any(|peer| peer.address() == node.local_address().to_string()));
+
+    node.shutdown().unwrap();
+    contact.shutdown().unwrap();
+}
+
+#[test]
+fn tolerates_different_view_sizes() {
+    use std::io::{Read, Write};
+    use gbps::{Config, Event, Message, PeerSampler, PeerSamplingService, Peer, ProtocolParameters};
+
+    let logger = terminal_logger();
+
+    let config = local_config(60)
+        .with_advertised_parameters(true);
+    let mut large = PeerSamplingService::new(config, logger.clone());
+    let events = large.subscribe_events();
+    large.init(Box::new(move|| { Some((1..=15).map(|port| Peer::new(format!("127.0.0.1:{}", port))).collect()) })).unwrap();
+    let large_address = large.local_address().to_string();
+
+    let config = Config::new("127.0.0.1:0".parse().unwrap(), true, true, 60, 0, 4, 1, 1, None)
+        .with_advertised_parameters(true);
+    let mut small = PeerSamplingService::new(config, logger.clone());
+    let bootstrap_address = large_address.clone();
+    small.init(Box::new(move|| { Some(vec![Peer::new(bootstrap_address.clone())]) })).unwrap();
+    small.trigger_exchange().unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(200));
+
+    // the small node keeps as many peers as the buffer size of its view: the large node and one of its peers
+    assert!(small.view_snapshot().len() <= 2);
+    assert_eq!(small.parameter_mismatches()[&large_address].view_size(), 20);
+    let small_address = small.local_address().to_string();
+    assert_eq!(large.parameter_mismatches()[&small_address].view_size(), 4);
+    assert!(events.try_iter().any(|event| matches!(event, Event::ParameterMismatch { peer, .. } if peer == small_address)));
+
+    // the answer to a node with a smaller view is truncated to half of its view size
+    let request = Message::new_request("127.0.0.1:9".to_owned(), None).with_parameters(ProtocolParameters::new(6, 1, 1));
+    let mut stream = std::net::TcpStream::connect(&large_address).unwrap();
+    stream.write_all(&request.as_bytes()).unwrap();
+    stream.shutdown(std::net::Shutdown::Write).unwrap();
+    let mut answer = vec![];
+    stream.read_to_end(&mut answer).unwrap();
+    let response = Message::from_bytes(&answer).unwrap();
+    assert_eq!(response.view().as_ref().unwrap().len(), 3);
+    assert_eq!(response.parameters(), Some(ProtocolParameters::new(20, 2, 8)));
+
+    small.shutdown().unwrap();
+    large.shutdown().unwrap();
+}
+
+#[test]
+fn collects_monitoring_reports() {
+    use gbps::{Config, MonitoringCollector, MonitoringConfig, PeerSamplingService, Peer, REPORT_VERSION};
+
+    let logger = terminal_logger();
+
+    let collector = MonitoringCollector::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/peers", collector.local_address().unwrap());
+    let monitoring = MonitoringConfig::new(true, &url, logger.clone());
+    let config = Config::new("127.0.0.1:0".parse().unwrap(), true, true, 60, 0, 20, 2, 8, Some(monitoring));
+    let mut service = PeerSamplingService::new(config, logger.clone());
+    service.init(Box::new(move|| { None })).unwrap();
+    service.add_peer_hint(Peer::new("127.0.0.1:9".to_owned()));
+
+    let report = collector.receive().unwrap();
+    assert_eq!(report.version(), REPORT_VERSION);
+    assert_eq!(report.id(), service.local_address().to_string());
+    assert_eq!(report.peers(), &["127.0.0.1:9".to_owned()]);
+
+    service.shutdown().unwrap();
+}
+
+#[test]
+fn runs_on_executor() {
+    use std::sync::{Arc, Mutex};
+    use gbps::{PeerSampler, PeerSamplingService, Peer, ThreadConfig};
+
+    let logger = terminal_logger();
+
+    // an executor of the application, recording the tasks it runs
+    let tasks = Arc::new(Mutex::new(vec![]));
+    let recorded = tasks.clone();
+    let executor = move |name: String, task: Box<dyn FnOnce() + Send>| {
+        recorded.lock().unwrap().push(name);
+        std::thread::spawn(task);
+    };
+    let threads = ThreadConfig::new().with_name_prefix("app-".to_owned()).with_executor(Arc::new(executor));
+
+    let mut contact = PeerSamplingService::new(local_config(60), logger.clone());
+    contact.init(Box::new(move|| { None })).unwrap();
+    let contact_address = contact.local_address().to_string();
+    let config = local_config(60)
+        .with_threads(threads);
+    let mut node = PeerSamplingService::new(config, logger.clone());
+    node.init(Box::new(move|| { Some(vec![Peer::new(contact_address.clone())]) })).unwrap();
+    assert_eq!(node.trigger_exchange().unwrap(), Some(contact.local_address().to_string()));
+    assert!(!node.view_snapshot().is_empty());
+
+    node.shutdown().unwrap();
+    assert_eq!(*tasks.lock().unwrap(), vec!["app-listener", "app-receiver", "app-sampling"]);
+    contact.shutdown().unwrap();
+}
+
+#[test]
+fn drains_before_shutdown() {
+    use std::io::{Read, Write};
+    use gbps::{Message, MessageType, PeerSampler, PeerSamplingService, Peer};
+
+    let logger = terminal_logger();
+
+    let mut contact = PeerSamplingService::new(local_config(60), logger.clone());
+    contact.init(Box::new(move|| { None })).unwrap();
+    let contact_address = contact.local_address().to_string();
+    let mut node = PeerSamplingService::new(local_config(60), logger.clone());
+    node.init(Box::new(move|| { Some(vec![Peer::new(contact_address.clone())]) })).unwrap();
+    node.trigger_exchange().unwrap();
+    let node_address = node.local_address().to_string();
+    std::thread::sleep(std::time::Duration::from_millis(100));
+    assert!(contact.view_snapshot().iter().any(|peer| peer.address() == node_address));
+
+    let draining = std::thread::spawn(move || node.drain(std::time::Duration::from_millis(1000)).map_err(|e| e.to_string()));
+    std::thread::sleep(std::time::Duration::from_millis(300));
+
+    // the departure is announced, and pulls are still answered without the descriptor of the node
+    assert!(contact.view_snapshot().iter().all(|peer| peer.address() != node_address));
+    let mut stream = std::net::TcpStream::connect(&node_address).unwrap();
+    stream.write_all(&Message::new_request("127.0.0.1:9".to_owned(), None).as_bytes()).unwrap();
+    stream.shutdown(std::net::Shutdown::Write).unwrap();
+    let mut answer = vec![];
+    stream.read_to_end(&mut answer).unwrap();
+    let response = Message::from_bytes(&answer).unwrap();
+    assert_eq!(*response.message_type(), MessageType::Response);
+    assert!(response.is_observer());
+    assert!(response.view().as_ref().unwrap().iter().all(|peer| peer.address() != node_address));
+
+    draining.join().unwrap().unwrap();
+    assert!(std::net::TcpStream::connect(&node_address).is_err());
+    contact.shutdown().unwrap();
+}
+
+#[test]
+fn restarts_with_the_same_instance() {
+    use gbps::{PeerSampler, PeerSamplingService, Peer};
+
+    let logger = terminal_logger();
+
+    let mut contact = PeerSamplingService::new(local_config(60), logger.clone());
+    contact.init(Box::new(move|| { None })).unwrap();
+    let contact_address = contact.local_address().to_string();
+    let mut node = PeerSamplingService::new(local_config(60), logger.clone());
+    assert!(node.restart().is_err());
+    let bootstrap_address = contact_address.clone();
+    node.init(Box::new(move|| { Some(vec![Peer::new(bootstrap_address.clone())]) })).unwrap();
+    assert!(node.restart().is_err());
+    let node_address = node.local_address();
+    let updates = node.subscribe();
+    node.trigger_exchange().unwrap();
+    assert!(updates.recv_timeout(std::time::Duration::from_secs(2)).is_ok());
+    node.shutdown().unwrap();
+    let cycles = node.stats().cycles();
+    assert!(node.trigger_exchange().is_err());
+
+    // same address, same view, same subscriptions and statistics
+    node.restart().unwrap();
+    assert_eq!(node.local_address(), node_address);
+    assert!(node.view_snapshot().iter().any(|peer| peer.address() == contact_address));
+    assert_eq!(node.trigger_exchange().unwrap(), Some(contact_address.clone()));
+    assert!(updates.recv_timeout(std::time::Duration::from_secs(2)).is_ok());
+    assert_eq!(node.stats().cycles(), cycles + 1);
+    assert!(contact.ping(&Peer::new(node_address.to_string()), std::time::Duration::from_secs(2)).is_ok());
+
+    node.shutdown().unwrap();
+    contact.shutdown().unwrap();
+}
+
+#[test]
+fn advertises_incarnation() {
+    use gbps::{PeerSampler, PeerSamplingService, Peer};
+
+    let logger = terminal_logger();
+
+    let mut contact = PeerSamplingService::new(local_config(60), logger.clone());
+    contact.init(Box::new(move|| { None })).unwrap();
+    let contact_address = contact.local_address().to_string();
+    let mut node = PeerSamplingService::new(local_config(60).with_incarnation(1), logger.clone());
+    node.init(Box::new(move|| { Some(vec![Peer::new(contact_address.clone())]) })).unwrap();
+    let node_address = node.local_address().to_string();
+    let incarnation = |contact: &PeerSamplingService| contact.view_snapshot().iter()
+        .find(|peer| peer.address() == node_address)
+        .map(|peer| peer.incarnation());
+
+    node.trigger_exchange().unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(100));
+    assert_eq!(incarnation(&contact), Some(1));
+
+    node.shutdown().unwrap();
+    node.restart().unwrap();
+    node.trigger_exchange().unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(100));
+    assert_eq!(incarnation(&contact), Some(2));
+
+    node.shutdown().unwrap();
+    contact.shutdown().unwrap();
+}
+
+#[test]
+fn samples_peers_by_locality() {
+    use gbps::{PeerSamplingService, Peer};
+
+    let logger = terminal_logger();
+
+    let mut contact = PeerSamplingService::new(local_config(60).with_locality("eu".to_owned()), logger.clone());
+    contact.init(Box::new(move|| { None })).unwrap();
+    let contact_address = contact.local_address().to_string();
+    let mut nodes = vec![];
+    for locality in ["eu", "us"] {
+        let bootstrap_address = contact_address.clone();
+        let mut node = PeerSamplingService::new(local_config(60).with_locality(locality.to_owned()), logger.clone());
+        node.init(Box::new(move|| { Some(vec![Peer::new(bootstrap_address.clone())]) })).unwrap();
+        node.trigger_exchange().unwrap();
+        nodes.push(node);
+    }
+    std::thread::sleep(std::time::Duration::from_millis(100));
+
+    let handle = contact.handle();
+    assert_eq!(handle.get_peer_in("eu").map(|peer| peer.address().to_owned()), Some(nodes[0].local_address().to_string()));
+    assert_eq!(contact.get_peer_outside("eu").map(|peer| peer.address().to_owned()), Some(nodes[1].local_address().to_string()));
+    // the locality of the contact is learned from its answer
+    assert_eq!(nodes[1].get_peer_outside("us").and_then(|peer| peer.locality().map(str::to_owned)), Some("eu".to_owned()));
+
+    for mut node in nodes {
+        node.shutdown().unwrap();
+    }
+    contact.shutdown().unwrap();
+}
+
+#[test]
+fn backs_off_unreachable_peers() {
+    use gbps::{PeerSamplingService, Peer};
+
+    let logger = terminal_logger();
+
+    let mut contact = PeerSamplingService::new(local_config(60), logger.clone());
+    contact.init(Box::new(move|| { None })).unwrap();
+    let contact_address = contact.local_address().to_string();
+    // a port nobody listens to
+    let dead_address = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().to_string();
+    let mut node = PeerSamplingService::new(local_config(60).with_send_backoff(60_000, 600_000), logger.clone());
+    let initial_peers = vec![Peer::new(contact_address.clone()), Peer::new(dead_address.clone())];
+    node.init(Box::new(move|| { Some(initial_peers.clone()) })).unwrap();
+
+    let partners = (0..8).map(|_| node.trigger_exchange().unwrap()).collect::<Vec<Option<String>>>();
+    assert!(partners.iter().filter(|partner| partner.as_deref() == Some(dead_address.as_str())).count() <= 1);
+    assert!(partners.iter().filter(|partner| partner.as_deref() == Some(contact_address.as_str())).count() >= 7);
+
+    node.shutdown().unwrap();
+    contact.shutdown().unwrap();
+}
+
+#[cfg(feature = "event-loop")]
+#[test]
+fn runs_on_a_single_thread() {
+    use gbps::{PeerSampler, PeerSamplingService, Peer, ThreadConfig};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    let logger = terminal_logger();
+
+    let tasks = Arc::new(AtomicUsize::new(0));
+    let counted = tasks.clone();
+    let threads = ThreadConfig::new().with_executor(Arc::new(move |name: String, task: Box<dyn FnOnce() + Send>| {
+        counted.fetch_add(1, Ordering::SeqCst);
+        std::thread::Builder::new().name(name).spawn(task).unwrap();
+    }));
+    let config = || local_config(60)
+        .with_exchange_timeout(1000)
+        .with_threads(threads.clone())
+        .with_event_loop();
+
+    let mut contact = PeerSamplingService::new(config(), logger.clone());
+    contact.init(Box::new(move|| { None })).unwrap();
+    let contact_address = contact.local_address().to_string();
+    let mut node = PeerSamplingService::new(config(), logger.clone());
+    let bootstrap_address = contact_address.clone();
+    node.init(Box::new(move|| { Some(vec![Peer::new(bootstrap_address.clone())]) })).unwrap();
+    assert_eq!(tasks.load(Ordering::SeqCst), 2);
+
+    assert_eq!(node.trigger_exchange().unwrap(), Some(contact_address));
+    assert!(contact.ping(&Peer::new(node.local_address().to_string()), std::time::Duration::from_secs(2)).is_ok());
+    // the pong was handled after the request
+    assert!(contact.view_snapshot().iter().any(|peer| peer.address() == node.local_address().to_string()));
+
+    node.shutdown().unwrap();
+    node.restart().unwrap();
+    assert_eq!(tasks.load(Ordering::SeqCst), 3);
+    assert!(node.trigger_exchange().unwrap().is_some());
+
+    node.shutdown().unwrap();
+    contact.shutdown().unwrap();
+}
+
+#[cfg(feature = "event-loop")]
+#[test]
+fn drops_idle_connections_on_a_single_thread() {
+    use gbps::{PeerSampler, PeerSamplingService, Peer};
+    use std::net::TcpStream;
+    use std::time::Duration;
+
+    let logger = terminal_logger();
+
+    let config = || local_config(60)
+        .with_exchange_timeout(300)
+        .with_event_loop();
+
+    let mut contact = PeerSamplingService::new(config(), logger.clone());
+    contact.init(Box::new(move|| { None })).unwrap();
+    let contact_address = contact.local_address().to_string();
+
+    // connections that never write fill the table of pending connections
+    let idle = (0..300).map(|_| TcpStream::connect(contact.local_address()).unwrap()).collect::<Vec<TcpStream>>();
+    std::thread::sleep(Duration::from_millis(600));
+
+    let mut node = PeerSamplingService::new(config(), logger.clone());
+    let bootstrap_address = contact_address.clone();
+    node.init(Box::new(move|| { Some(vec![Peer::new(bootstrap_address.clone())]) })).unwrap();
+    assert_eq!(node.trigger_exchange().unwrap(), Some(contact_address));
+    assert!(contact.view_snapshot().iter().any(|peer| peer.address() == node.local_address().to_string()));
+
+    drop(idle);
+    node.shutdown().unwrap();
+    contact.shutdown().unwrap();
+}
+
+#[cfg(feature = "compression")]
+#[test]
+fn exchanges_compressed_messages() {
+    use gbps::{PeerSampler, PeerSamplingService, Peer};
+
+    let logger = terminal_logger();
+
+    let mut contact = PeerSamplingService::new(local_config(60), logger.clone());
+    contact.init(Box::new(move|| { None })).unwrap();
+    let contact_address = contact.local_address().to_string();
+
+    // only the node compresses its messages, which the contact still reads
+    let config = local_config(60)
+        .with_compression(true);
+    let mut node = PeerSamplingService::new(config, logger.clone());
+    let bootstrap_address = contact_address.clone();
+    node.init(Box::new(move|| { Some(vec![Peer::new(bootstrap_address.clone())]) })).unwrap();
+
+    assert_eq!(node.trigger_exchange().unwrap(), Some(contact_address.clone()));
+    assert!(contact.view_snapshot().iter().any(|peer| peer.address() == node.local_address().to_string()));
+    assert!(node.view_snapshot().iter().any(|peer| peer.address() == contact_address));
+
+    node.shutdown().unwrap();
+    contact.shutdown().unwrap();
+}
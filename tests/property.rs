@@ -0,0 +1,184 @@
+use std::cmp::Reverse;
+use std::collections::HashSet;
+
+use gbps::{Message, Peer, View};
+use proptest::prelude::*;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+
+const HOST_ADDRESS: &str = "127.0.0.1:9000";
+
+fn address() -> impl Strategy<Value = String> {
+    (any::<[u8; 4]>(), any::<u16>())
+        .prop_map(|(ip, port)| format!("{}.{}.{}.{}:{}", ip[0], ip[1], ip[2], ip[3], port))
+}
+
+fn peer() -> impl Strategy<Value = Peer> {
+    (address(), 0u16..64).prop_map(|(address, age)| Peer::with_age(address, age))
+}
+
+// peers among a small set of addresses, so that duplicates are frequent
+fn local_peer(first_port: u16) -> impl Strategy<Value = Peer> {
+    (first_port..9040, 0u16..20).prop_map(|(port, age)| Peer::with_age(format!("127.0.0.1:{}", port), age))
+}
+
+// local peers restarted a few times, so that duplicates differ by incarnation as well as by age
+fn restarted_peer(first_port: u16) -> impl Strategy<Value = Peer> {
+    (local_peer(first_port), 0u32..3).prop_map(|(peer, incarnation)| peer.with_incarnation(incarnation))
+}
+
+fn message() -> impl Strategy<Value = Message> {
+    (0..6u8, address(), proptest::collection::vec(peer(), 1..=255), any::<u64>()).prop_map(|(kind, sender, view, nonce)| {
+        match kind {
+            0 => Message::new_request(sender, Some(view)),
+            1 => Message::new_response(sender, Some(view)),
+            2 => Message::new_busy(sender),
+            3 => Message::new_ping(sender),
+            4 => Message::new_pong(sender, nonce),
+            _ => Message::new_leave(sender),
+        }
+    })
+}
+
+fn addresses_and_ages(peers: &[Peer]) -> Vec<(String, u16)> {
+    peers.iter().map(|peer| (peer.address().to_owned(), peer.age())).collect()
+}
+
+fn descriptors(peers: &[Peer]) -> Vec<(String, u16, u32)> {
+    peers.iter().map(|peer| (peer.address().to_owned(), peer.age(), peer.incarnation())).collect()
+}
+
+/// The view selection of the paper written as plainly as possible, as the reference for the strict
+/// selection of View: the buffer is appended to the view, the duplicates are removed keeping the most
+/// recent descriptor at the position of the first one, then the min(H, size - c) oldest descriptors,
+/// the min(S, size - c) first descriptors, and descriptors at random down to c are removed.
+fn reference_select<R: Rng>(view: &[Peer], c: usize, h: usize, s: usize, buffer: &[Peer], rng: &mut R) -> Vec<Peer> {
+    let mut merged = view.to_vec();
+    merged.extend(buffer.iter().filter(|peer| peer.address() != HOST_ADDRESS).cloned());
+
+    let mut view: Vec<Peer> = vec![];
+    for peer in merged {
+        match view.iter_mut().find(|kept| kept.address() == peer.address()) {
+            Some(kept) => {
+                // the latest incarnation, then the youngest
+                if (peer.incarnation(), Reverse(peer.age())) > (kept.incarnation(), Reverse(kept.age())) {
+                    *kept = peer;
+                }
+            },
+            None => view.push(peer),
+        }
+    }
+
+    for _ in 0..h.min(view.len().saturating_sub(c)) {
+        // the last of the oldest descriptors
+        let oldest = view.iter().map(|peer| peer.age()).max().unwrap();
+        let index = view.iter().rposition(|peer| peer.age() == oldest).unwrap();
+        view.remove(index);
+    }
+
+    for _ in 0..s.min(view.len().saturating_sub(c)) {
+        view.remove(0);
+    }
+
+    while view.len() > c {
+        let index = rng.gen_range(0..view.len());
+        view.remove(index);
+    }
+    view
+}
+
+fn assert_same(message: &Message, decoded: &Message) {
+    assert_eq!(decoded.message_type(), message.message_type());
+    assert_eq!(decoded.sender(), message.sender());
+    assert_eq!(decoded.timestamp(), message.timestamp());
+    assert_eq!(decoded.nonce(), message.nonce());
+    assert_eq!(
+        decoded.view().as_ref().map(|view| addresses_and_ages(view)),
+        message.view().as_ref().map(|view| addresses_and_ages(view)));
+}
+
+proptest! {
+    #[test]
+    fn peer_round_trip(peer in peer()) {
+        let decoded = Peer::from_bytes(&peer.as_bytes()).unwrap();
+        prop_assert_eq!(decoded.address(), peer.address());
+        prop_assert_eq!(decoded.age(), peer.age());
+    }
+
+    #[test]
+    fn peer_string_round_trip(peer in peer()) {
+        let parsed = peer.to_string().parse::<Peer>().unwrap();
+        prop_assert_eq!(parsed.address(), peer.address());
+        prop_assert_eq!(parsed.age(), peer.age());
+    }
+
+    #[test]
+    fn message_round_trip(message in message()) {
+        assert_same(&message, &Message::from_bytes(&message.as_bytes()).unwrap());
+        #[cfg(feature = "compression")]
+        assert_same(&message, &Message::from_bytes(&message.as_compressed_bytes()).unwrap());
+    }
+
+    #[test]
+    fn select_keeps_view_invariants(
+        initial in proptest::collection::vec(local_peer(9001), 0..40),
+        // buffers may contain the host address
+        buffers in proptest::collection::vec(proptest::collection::vec(local_peer(9000), 0..20), 1..10),
+        c in 1usize..30,
+        h in 0usize..15,
+        s in 0usize..15,
+        strict in any::<bool>(),
+        seed in any::<u64>()) {
+
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut view = View::with_peers(HOST_ADDRESS.to_owned(), initial);
+        view.set_strict_selection(strict);
+        for buffer in buffers {
+            view.select_with(c, h, s, &buffer, &mut rng);
+
+            prop_assert!(view.peers().len() <= c);
+            prop_assert!(view.peers().iter().all(|peer| peer.address() != HOST_ADDRESS));
+            let unique = view.peers().iter().map(|peer| peer.address()).collect::<HashSet<&str>>();
+            prop_assert_eq!(unique.len(), view.peers().len());
+            view.increase_age();
+        }
+    }
+
+    #[test]
+    fn select_matches_reference(
+        initial in proptest::collection::vec(restarted_peer(9001), 0..40),
+        buffers in proptest::collection::vec(proptest::collection::vec(restarted_peer(9000), 0..20), 1..10),
+        c in 1usize..30,
+        h in 0usize..15,
+        s in 0usize..15,
+        seed in any::<u64>()) {
+
+        let mut view = View::with_peers(HOST_ADDRESS.to_owned(), initial.clone());
+        let mut reference = initial;
+        // the same random removals on both sides
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut reference_rng = StdRng::seed_from_u64(seed);
+        for buffer in buffers {
+            view.select_with(c, h, s, &buffer, &mut rng);
+            reference = reference_select(&reference, c, h, s, &buffer, &mut reference_rng);
+
+            prop_assert_eq!(descriptors(view.peers()), descriptors(&reference));
+            view.increase_age();
+            reference.iter_mut().for_each(|peer| peer.increment_age());
+        }
+    }
+}
+
+proptest! {
+    #[test]
+    fn decoding_arbitrary_bytes_does_not_panic(bytes in proptest::collection::vec(any::<u8>(), 0..512)) {
+        let _ = Message::from_bytes(&bytes);
+        let _ = Peer::from_bytes(&bytes);
+    }
+
+    #[test]
+    fn decoding_truncated_message_does_not_panic(message in message(), cut in any::<prop::sample::Index>()) {
+        let bytes = message.as_bytes();
+        let _ = Message::from_bytes(&bytes[..cut.index(bytes.len())]);
+    }
+}
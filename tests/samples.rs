@@ -1,10 +1,5 @@
 use gbps::{terminal_logger, Config, Peer, PeerSamplingService};
 
-// logger for integration tests
-struct IntegrationTestLogger;
-
-static LOGGER: IntegrationTestLogger = IntegrationTestLogger;
-
 #[test]
 fn sample_code() {
 
@@ -20,7 +15,7 @@ fn sample_code() {
 
         // create and initiate the peer sampling service
         let mut sampling_service = PeerSamplingService::new(config, logger_clone);
-        sampling_service.init(no_initial_peer);
+        sampling_service.init(no_initial_peer).unwrap();
         std::thread::sleep(std::time::Duration::from_secs(20));
 
         // terminate peer sampling
@@ -37,7 +32,7 @@ fn sample_code() {
 
         // create and initiate the peer sampling service
         let mut sampling_service = PeerSamplingService::new(config, logger_clone);
-        sampling_service.init(initial_peer);
+        sampling_service.init(initial_peer).unwrap();
         std::thread::sleep(std::time::Duration::from_secs(20));
 
         // terminate peer sampling